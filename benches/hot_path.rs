@@ -0,0 +1,135 @@
+//! Criterion benchmarks for the per-window hot path: McLeod detection, pulling windows out of
+//! the audio queue, and the full engine pipeline (gate, detect, smooth, map).
+//!
+//! Run with `cargo bench`; `cargo bench -- detect` runs one group. Each benchmark runs on
+//! three fixtures, since the detector's cost depends on the signal: a pure sine, a
+//! harmonically rich tone, and noise.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pitch_detection::detector::mcleod::McLeodDetector;
+use pitch_detection::detector::PitchDetector;
+use pitchu::audio_queue::audio_queue;
+use pitchu::clock::ManualClock;
+use pitchu::engine::{EngineConfig, PitchToKeyEngine};
+use pitchu::mapping::Mapping;
+use pitchu::smoothing::SmoothingKind;
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+const WINDOW: usize = 2048;
+const SAMPLE_RATES: [usize; 2] = [44_100, 48_000];
+// Inside the default mapping's 'a' band
+const TONE_HZ: f32 = 250.0;
+const AMPLITUDE: f32 = 0.4;
+// Pushed per simulated audio callback
+const CALLBACK_FRAMES: usize = 512;
+
+#[derive(Clone, Copy)]
+enum Fixture {
+    Sine,
+    /// The first eight harmonics at 1/n amplitude, close to a sawtooth or a bright voice.
+    Harmonic,
+    /// White noise, which the detector rejects only after a full search.
+    Noise,
+}
+
+const FIXTURES: [(&str, Fixture); 3] = [("sine", Fixture::Sine), ("harmonic", Fixture::Harmonic), ("noise", Fixture::Noise)];
+
+fn fixture(kind: Fixture, sample_rate: usize, len: usize) -> Vec<f32> {
+    // A fixed-seed xorshift keeps the noise identical between runs
+    let mut seed = 0x2545_f491_u32;
+    (0..len)
+        .map(|i| {
+            let t = i as f32 / sample_rate as f32;
+            match kind {
+                Fixture::Sine => AMPLITUDE * (TAU * TONE_HZ * t).sin(),
+                Fixture::Harmonic => {
+                    let sum: f32 = (1..=8).map(|n| (TAU * TONE_HZ * n as f32 * t).sin() / n as f32).sum();
+                    AMPLITUDE * 0.6 * sum
+                }
+                Fixture::Noise => {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 17;
+                    seed ^= seed << 5;
+                    AMPLITUDE * (seed as f32 / u32::MAX as f32 * 2.0 - 1.0)
+                }
+            }
+        })
+        .collect()
+}
+
+fn config(sample_rate: usize) -> EngineConfig {
+    EngineConfig {
+        window_size: WINDOW,
+        sample_rate,
+        adaptive_ratio: Some(4.0),
+        smoothing: SmoothingKind::Median,
+        ..EngineConfig::default()
+    }
+}
+
+fn detection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("detect");
+    group.throughput(Throughput::Elements(WINDOW as u64));
+    for sample_rate in SAMPLE_RATES {
+        let defaults = config(sample_rate);
+        for (name, kind) in FIXTURES {
+            let window = fixture(kind, sample_rate, WINDOW);
+            let mut detector = McLeodDetector::new(WINDOW, WINDOW / 2);
+            group.bench_with_input(BenchmarkId::new(name, sample_rate), &window, |b, window| {
+                b.iter(|| {
+                    detector.get_pitch(
+                        black_box(window),
+                        sample_rate,
+                        defaults.power_threshold,
+                        defaults.clarity_threshold,
+                    )
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn window_extraction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("queue");
+    group.throughput(Throughput::Elements(WINDOW as u64));
+    let samples = fixture(Fixture::Harmonic, SAMPLE_RATES[0], WINDOW);
+    let (mut producer, mut consumer) = audio_queue(4 * WINDOW);
+    let mut window = vec![0.0; WINDOW];
+    group.bench_function("push_and_pop_window", |b| {
+        b.iter(|| {
+            for chunk in samples.chunks(CALLBACK_FRAMES) {
+                producer.push(black_box(chunk));
+            }
+            assert!(consumer.pop_window(&mut window));
+            black_box(&window);
+        })
+    });
+    group.finish();
+}
+
+// With the adaptive gate on, the noise fixture soon counts as the noise floor and is gated out
+// before detection, so it measures the rejection path
+fn pipeline(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pipeline");
+    group.throughput(Throughput::Elements(WINDOW as u64));
+    for sample_rate in SAMPLE_RATES {
+        let window_duration = Duration::from_secs_f64(WINDOW as f64 / sample_rate as f64);
+        for (name, kind) in FIXTURES {
+            let window = fixture(kind, sample_rate, WINDOW);
+            let clock = ManualClock::new();
+            let mut engine = PitchToKeyEngine::new(config(sample_rate), Mapping::default(), clock.clone());
+            group.bench_with_input(BenchmarkId::new(name, sample_rate), &window, |b, window| {
+                b.iter(|| {
+                    clock.advance(window_duration);
+                    engine.process(black_box(window))
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, detection, window_extraction, pipeline);
+criterion_main!(benches);
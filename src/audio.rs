@@ -0,0 +1,474 @@
+use pitchu::audio_queue::{audio_queue, capacity_for_latency, AudioConsumer, AudioProducer, WindowSignal};
+use pitchu::decimate::Decimator;
+use pitchu::error::{PitchuError, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use log::{error, info, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// Names of backends cpal supports somewhere, for a helpful message when one isn't compiled in
+const KNOWN_HOSTS: &[(&str, &str)] = &[
+    ("JACK", "rebuild with `--features jack`"),
+    ("ASIO", "rebuild on Windows with `--features asio` (requires the ASIO SDK)"),
+    ("ALSA", "only available on Linux/BSD"),
+    ("WASAPI", "only available on Windows"),
+    ("CoreAudio", "only available on macOS/iOS"),
+];
+
+/// Picks the audio backend named `name` (case-insensitive), or the platform default when `None`.
+pub fn select_host(name: Option<&str>) -> Result<cpal::Host> {
+    let Some(name) = name else {
+        return Ok(cpal::default_host());
+    };
+
+    let compiled = cpal::ALL_HOSTS.iter().find(|id| id.name().eq_ignore_ascii_case(name));
+    let Some(&id) = compiled else {
+        let hint = KNOWN_HOSTS
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(name))
+            .map_or("unknown host name", |(_, hint)| *hint);
+        return Err(PitchuError::Host {
+            name: name.to_string(),
+            reason: format!(
+                "not compiled into this build ({}). Available hosts: {}",
+                hint,
+                host_names(&cpal::available_hosts())
+            ),
+        });
+    };
+
+    let host = cpal::host_from_id(id).map_err(|_| PitchuError::Host {
+        name: id.name().to_string(),
+        reason: format!(
+            "compiled in but not available on this system{}. Available hosts: {}",
+            if id.name() == "JACK" { " (is the JACK server running?)" } else { "" },
+            host_names(&cpal::available_hosts())
+        ),
+    })?;
+    if let Some(quirk) = host_quirk(id) {
+        info!("Audio host {}: {}", id.name(), quirk);
+    }
+    Ok(host)
+}
+
+/// Backend-specific behavior worth knowing before a stream fails to build.
+pub fn host_quirk(id: cpal::HostId) -> Option<&'static str> {
+    match id.name() {
+        "JACK" => Some("the buffer size and sample rate are fixed by the JACK server, not by pitchu."),
+        "ASIO" => Some("ASIO drivers usually grant exclusive access; close other audio applications using the device."),
+        _ => None,
+    }
+}
+
+fn host_names(ids: &[cpal::HostId]) -> String {
+    ids.iter().map(|id| id.name()).collect::<Vec<_>>().join(", ")
+}
+
+/// The input device named `name`, or the host's default input device when `None`.
+pub fn select_input_device(host: &cpal::Host, name: Option<&str>) -> Result<cpal::Device> {
+    let host_name = host.id().name();
+    match name {
+        Some(name) => input_devices(host)?
+            .find(|d| d.name().is_ok_and(|n| n == name))
+            .ok_or_else(|| PitchuError::NoDevice(format!("no input device named '{}' on host {}", name, host_name))),
+        None => host
+            .default_input_device()
+            .ok_or_else(|| PitchuError::NoDevice(format!("no input device available on host {}", host_name))),
+    }
+}
+
+fn input_devices(host: &cpal::Host) -> Result<cpal::InputDevices<cpal::Devices>> {
+    host.input_devices()
+        .map_err(|source| PitchuError::DeviceEnumeration { host: host.id().name().to_string(), source })
+}
+
+fn output_devices(host: &cpal::Host) -> Result<cpal::OutputDevices<cpal::Devices>> {
+    host.output_devices()
+        .map_err(|source| PitchuError::DeviceEnumeration { host: host.id().name().to_string(), source })
+}
+
+/// A device's name, for use in messages and for finding it again after a reconnect.
+pub fn device_name(device: &cpal::Device) -> Result<String> {
+    device.name().map_err(|source| PitchuError::UnsupportedConfig {
+        device: "<unnamed>".to_string(),
+        source: Box::new(source),
+    })
+}
+
+/// Prints every input device of every available host, marking the defaults.
+pub fn list_devices() {
+    for id in cpal::available_hosts() {
+        println!("Host: {}", id.name());
+        let host = match cpal::host_from_id(id) {
+            Ok(host) => host,
+            Err(e) => {
+                println!("  (unavailable: {})", e);
+                continue;
+            }
+        };
+        let default_name = host.default_input_device().and_then(|d| d.name().ok());
+        let devices: Vec<cpal::Device> = match host.input_devices() {
+            Ok(devices) => devices.collect(),
+            Err(e) => {
+                println!("  (could not enumerate devices: {})", e);
+                continue;
+            }
+        };
+        if devices.is_empty() {
+            println!("  (no input devices)");
+        }
+        for device in devices {
+            let name = device.name().unwrap_or_else(|_| "<unnamed>".to_string());
+            let marker = if default_name.as_deref() == Some(name.as_str()) { " (default)" } else { "" };
+            match device.default_input_config() {
+                Ok(config) => println!(
+                    "  {}{}: {} ch, {} Hz, {:?}",
+                    name,
+                    marker,
+                    config.channels(),
+                    config.sample_rate().0,
+                    config.sample_format()
+                ),
+                Err(_) => println!("  {}{}", name, marker),
+            }
+        }
+    }
+}
+
+/// Picks the device to capture system audio from for `--loopback`.
+///
+/// Returns the device and whether it is an output device captured in loopback (WASAPI),
+/// as opposed to an input device that carries the system mix (PulseAudio/PipeWire monitors).
+pub fn select_loopback_device(host: &cpal::Host, name: Option<&str>) -> Result<(cpal::Device, bool)> {
+    if cfg!(windows) && host.id().name() == "WASAPI" {
+        let device = match name {
+            Some(name) => output_devices(host)?.find(|d| d.name().is_ok_and(|n| n == name)),
+            None => host.default_output_device(),
+        };
+        return device
+            .map(|device| (device, true))
+            .ok_or_else(|| PitchuError::NoDevice("no output device available to capture in loopback mode".to_string()));
+    }
+
+    if let Some(name) = name {
+        return select_input_device(host, Some(name)).map(|device| (device, false));
+    }
+    input_devices(host)?
+        .find(|d| d.name().is_ok_and(|n| n.to_lowercase().contains("monitor")))
+        .map(|device| (device, false))
+        .ok_or_else(|| {
+            PitchuError::NoDevice(format!(
+                "no loopback source found on host {}. Loopback capture only works directly with WASAPI on Windows. \
+                 Elsewhere pitchu needs an input device carrying the system mix: a PulseAudio/PipeWire \
+                 \"monitor\" source (or make it the default source, e.g. `pactl set-default-source <sink>.monitor`), \
+                 or a virtual loopback device such as BlackHole on macOS. Pass its name with --device.",
+                host.id().name()
+            ))
+        })
+}
+
+// Sample formats the capture stream converts, best first
+const CAPTURE_FORMATS: [cpal::SampleFormat; 4] =
+    [cpal::SampleFormat::F32, cpal::SampleFormat::I32, cpal::SampleFormat::I16, cpal::SampleFormat::U16];
+/// Rate aimed for when a device has no default config.
+const FALLBACK_SAMPLE_RATE: u32 = 48_000;
+// Below this a voice's harmonics don't fit
+const MIN_SAMPLE_RATE: u32 = 16_000;
+
+/// How the captured stream is turned into analysis queues.
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureSettings {
+    /// Number of queues. With 1 every device channel is downmixed into it; with more, the first
+    /// `pipelines` device channels are kept apart, one per queue.
+    pub pipelines: usize,
+    pub max_latency_ms: u64,
+    pub window_size: usize,
+    /// Factor the device's sample rate is reduced by before the samples are queued.
+    pub decimate: usize,
+}
+
+/// A running capture stream and the queues it feeds.
+///
+/// Whatever the device's sample format and channel count, the queues receive mono `f32` samples.
+pub struct AudioInput {
+    pub device_name: String,
+    /// True when capturing an output device in loopback mode.
+    pub loopback: bool,
+    /// Rate of the queued samples: the device's, divided by [`CaptureSettings::decimate`].
+    pub sample_rate: usize,
+    /// Maximum number of queued samples before the oldest windows are discarded.
+    pub latency_cap: usize,
+    /// One mono queue per pipeline (see [`CaptureSettings::pipelines`]).
+    pub consumers: Vec<AudioConsumer>,
+    window_ready: WindowSignal,
+    failed: Arc<AtomicBool>,
+    _stream: cpal::Stream,
+}
+
+impl AudioInput {
+    pub fn open(
+        host: &cpal::Host,
+        device: &cpal::Device,
+        loopback: bool,
+        settings: &CaptureSettings,
+    ) -> Result<Self> {
+        let device_name = device_name(device)?;
+        let config = stream_config(device, &device_name, loopback)?;
+
+        let device_rate = config.sample_rate().0 as usize;
+        let sample_rate = device_rate / settings.decimate;
+        if settings.decimate > 1 {
+            info!("Detecting at {} Hz ({} Hz decimated by {}).", sample_rate, device_rate, settings.decimate);
+        } else {
+            info!("Detecting at {} Hz.", sample_rate);
+        }
+        let sample_format = config.sample_format();
+        let channels = config.channels() as usize;
+        if settings.pipelines > 1 {
+            if channels < settings.pipelines {
+                return Err(PitchuError::UnsupportedConfig {
+                    source: format!(
+                        "{} channel(s), but detecting {} channels separately needs at least that many",
+                        channels, settings.pipelines
+                    )
+                    .into(),
+                    device: device_name,
+                });
+            }
+            info!("Detecting channels 0-{} of {} separately.", settings.pipelines - 1, channels);
+        } else if channels > 1 {
+            info!("Downmixing {} channels to mono for detection.", channels);
+        }
+
+        // The queue itself has headroom beyond the latency cap, so the callback never has to drop
+        // fresh audio; stale audio beyond the cap is discarded by the processing loop instead.
+        let latency_cap = capacity_for_latency(sample_rate, 1, settings.max_latency_ms, settings.window_size);
+        let (producers, consumers): (Vec<_>, Vec<_>) =
+            (0..settings.pipelines.max(1)).map(|_| audio_queue(2 * latency_cap)).unzip();
+        info!("Audio queue capped at {} samples (~{}ms).", latency_cap, settings.max_latency_ms);
+
+        let failed = Arc::new(AtomicBool::new(false));
+        let window_ready = WindowSignal::new();
+        let sink = CaptureSink {
+            producers,
+            window_size: settings.window_size,
+            decimate: settings.decimate,
+            window_ready: window_ready.clone(),
+        };
+
+        info!("Building audio input stream...");
+        let config: cpal::StreamConfig = config.into();
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => build_capture_stream::<f32>(device, &config, sink, Arc::clone(&failed)),
+            cpal::SampleFormat::I16 => build_capture_stream::<i16>(device, &config, sink, Arc::clone(&failed)),
+            cpal::SampleFormat::U16 => build_capture_stream::<u16>(device, &config, sink, Arc::clone(&failed)),
+            cpal::SampleFormat::I32 => build_capture_stream::<i32>(device, &config, sink, Arc::clone(&failed)),
+            other => {
+                return Err(PitchuError::UnsupportedConfig {
+                    device: device_name,
+                    source: format!("sample format {:?}", other).into(),
+                });
+            }
+        }
+        .map_err(|source| PitchuError::StreamBuild { device: device_name.clone(), quirk: quirk_of(host), source })?;
+        stream
+            .play()
+            .map_err(|source| PitchuError::StreamPlay { device: device_name.clone(), quirk: quirk_of(host), source })?;
+
+        Ok(AudioInput {
+            device_name,
+            loopback,
+            sample_rate,
+            latency_cap,
+            consumers,
+            window_ready,
+            failed,
+            _stream: stream,
+        })
+    }
+
+    /// True once the device has gone away; the stream won't deliver any more audio.
+    pub fn has_failed(&self) -> bool {
+        self.failed.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until the callback has queued another full window, or `timeout` passes.
+    pub fn wait_for_window(&self, timeout: Duration) -> bool {
+        self.window_ready.wait(timeout)
+    }
+
+    /// Queued samples per millisecond, for converting queue sizes to durations.
+    pub fn samples_per_ms(&self) -> f32 {
+        self.sample_rate as f32 / 1000.0
+    }
+}
+
+// Frames converted per push; the scratch buffers are allocated once, outside the callback
+const DOWNMIX_CHUNK_FRAMES: usize = 1024;
+
+/// Everything the capture callback writes to.
+struct CaptureSink {
+    producers: Vec<AudioProducer>,
+    window_size: usize,
+    decimate: usize,
+    window_ready: WindowSignal,
+}
+
+/// With a single producer every channel is downmixed into it; otherwise producer `i` gets
+/// device channel `i`.
+fn build_capture_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    sink: CaptureSink,
+    failed: Arc<AtomicBool>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    let channels = config.channels.max(1) as usize;
+    let CaptureSink { mut producers, window_size, decimate, window_ready } = sink;
+    let downmix = producers.len() == 1;
+    let mut scratch = vec![vec![0.0f32; DOWNMIX_CHUNK_FRAMES]; producers.len()];
+    let mut decimators: Vec<Decimator> = (0..producers.len()).map(|_| Decimator::new(decimate)).collect();
+    let mut decimated = vec![0.0f32; DOWNMIX_CHUNK_FRAMES];
+    // Frames queued since the last wakeup
+    let mut pending = 0;
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            for chunk in data.chunks(DOWNMIX_CHUNK_FRAMES * channels) {
+                let frames = chunk.len() / channels;
+                if downmix {
+                    for (out, frame) in scratch[0][..frames].iter_mut().zip(chunk.chunks_exact(channels)) {
+                        let sum: f32 = frame.iter().map(|&s| s.to_sample::<f32>()).sum();
+                        *out = sum / channels as f32;
+                    }
+                } else {
+                    for (channel, buffer) in scratch.iter_mut().enumerate() {
+                        for (out, frame) in buffer[..frames].iter_mut().zip(chunk.chunks_exact(channels)) {
+                            *out = frame[channel].to_sample::<f32>();
+                        }
+                    }
+                }
+                let mut kept = 0;
+                for ((producer, decimator), buffer) in producers.iter_mut().zip(&mut decimators).zip(&scratch) {
+                    kept = decimator.process(&buffer[..frames], &mut decimated);
+                    producer.push(&decimated[..kept]);
+                }
+                pending += kept;
+            }
+            if pending >= window_size {
+                pending %= window_size;
+                window_ready.notify();
+            }
+        },
+        move |err| {
+            error!("Stream error: {:?}", err);
+            if matches!(err, cpal::StreamError::DeviceNotAvailable) {
+                failed.store(true, Ordering::SeqCst);
+            }
+        },
+        None,
+    )
+}
+
+/// The device's default stream config or, when it advertises none, the best of the configs it
+/// supports: a sample format the capture stream converts (`f32` first) at a rate as close to
+/// [`FALLBACK_SAMPLE_RATE`] as its range allows.
+fn stream_config(device: &cpal::Device, device_name: &str, loopback: bool) -> Result<cpal::SupportedStreamConfig> {
+    let kind = if loopback { "loopback" } else { "input" };
+    let default = if loopback { device.default_output_config() } else { device.default_input_config() };
+    let error = match default {
+        Ok(config) => {
+            info!("Using default {} stream config: {:?}", kind, config);
+            return Ok(config);
+        }
+        Err(error) => error,
+    };
+    warn!("'{}' has no default {} stream config ({}); looking through the ones it supports.", device_name, kind, error);
+    let unsupported = |source: Box<dyn std::error::Error + Send + Sync>| PitchuError::UnsupportedConfig {
+        device: device_name.to_string(),
+        source,
+    };
+    let ranges: Vec<cpal::SupportedStreamConfigRange> = if loopback {
+        device.supported_output_configs().map_err(|e| unsupported(Box::new(e)))?.collect()
+    } else {
+        device.supported_input_configs().map_err(|e| unsupported(Box::new(e)))?.collect()
+    };
+    let config = ranges
+        .into_iter()
+        .filter_map(|range| {
+            let format_rank = CAPTURE_FORMATS.iter().position(|&format| format == range.sample_format())?;
+            let rate = FALLBACK_SAMPLE_RATE.clamp(range.min_sample_rate().0, range.max_sample_rate().0);
+            (rate >= MIN_SAMPLE_RATE).then(|| (format_rank, rate.abs_diff(FALLBACK_SAMPLE_RATE), range, rate))
+        })
+        .min_by_key(|&(format_rank, distance, _, _)| (format_rank, distance))
+        .map(|(_, _, range, rate)| range.with_sample_rate(cpal::SampleRate(rate)))
+        .ok_or_else(|| unsupported(Box::new(error)))?;
+    info!("Using supported {} stream config: {:?}", kind, config);
+    Ok(config)
+}
+
+/// The host's quirk phrased for an error message, e.g. "JACK the buffer size ...".
+pub fn quirk_of(host: &cpal::Host) -> Option<String> {
+    host_quirk(host.id()).map(|quirk| format!("{} {}", host.id().name(), quirk))
+}
+
+/// Device named `name` if it is present, otherwise the host's default device.
+/// Looks among output devices when `loopback` is set.
+pub fn find_capture_device(host: &cpal::Host, name: &str, loopback: bool) -> Option<cpal::Device> {
+    let devices = if loopback { host.output_devices() } else { host.input_devices() };
+    let by_name = devices
+        .ok()
+        .and_then(|mut devices| devices.find(|d| d.name().is_ok_and(|n| n == name)));
+    match by_name {
+        Some(device) => Some(device),
+        None if loopback => host.default_output_device(),
+        None => host.default_input_device(),
+    }
+}
+
+/// How to behave when the input device disappears.
+pub struct ReconnectPolicy {
+    pub interval: Duration,
+    /// `None` retries forever; `Some(0)` gives up immediately.
+    pub max_attempts: Option<u32>,
+}
+
+/// Waits for `device_name` (or a default input device) to come back and reopens it.
+///
+/// Returns `None` if the retry budget runs out or `running` is cleared while waiting.
+pub fn reconnect(
+    host: &cpal::Host,
+    device_name: &str,
+    loopback: bool,
+    policy: &ReconnectPolicy,
+    settings: &CaptureSettings,
+    running: &AtomicBool,
+) -> Option<AudioInput> {
+    let mut attempt = 0;
+    while policy.max_attempts.is_none_or(|max| attempt < max) {
+        attempt += 1;
+        thread::sleep(policy.interval);
+        if !running.load(Ordering::SeqCst) {
+            return None;
+        }
+
+        let Some(device) = find_capture_device(host, device_name, loopback) else {
+            info!("Audio: reconnect attempt {}: no input device available yet.", attempt);
+            continue;
+        };
+        match AudioInput::open(host, &device, loopback, settings) {
+            Ok(input) => {
+                info!("Audio: reconnected to '{}' after {} attempt(s).", input.device_name, attempt);
+                return Some(input);
+            }
+            Err(e) => warn!("Audio: reconnect attempt {} failed: {}", attempt, e),
+        }
+    }
+    error!("Audio: giving up after {} reconnect attempt(s).", attempt);
+    None
+}
@@ -0,0 +1,264 @@
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+// Capture timestamps kept for windows not yet popped; when full, the newest are dropped
+const TIMESTAMP_CAPACITY: usize = 1024;
+
+/// Number of samples the queue must hold to buffer `max_latency_ms` of interleaved audio.
+///
+/// Never less than two analysis windows, so a full window can always be queued while
+/// the previous one is being processed.
+pub fn capacity_for_latency(sample_rate: usize, channels: usize, max_latency_ms: u64, window_size: usize) -> usize {
+    let samples = sample_rate * channels * max_latency_ms as usize / 1000;
+    samples.max(2 * window_size)
+}
+
+/// Creates a single-producer single-consumer audio queue holding up to `capacity` samples.
+///
+/// The producer half lives in the cpal callback, the consumer half in the processing loop.
+/// Neither side ever blocks the other.
+pub fn audio_queue(capacity: usize) -> (AudioProducer, AudioConsumer) {
+    let (producer, consumer) = HeapRb::<f32>::new(capacity).split();
+    let (stamp_producer, stamp_consumer) = HeapRb::new(TIMESTAMP_CAPACITY).split();
+    let overruns = Arc::new(AtomicUsize::new(0));
+    (
+        AudioProducer { inner: producer, overruns: Arc::clone(&overruns), stamps: stamp_producer, pushed: 0 },
+        AudioConsumer { inner: consumer, overruns, stamps: stamp_consumer, popped: 0, captured_at: None },
+    )
+}
+
+pub struct AudioProducer {
+    inner: HeapProd<f32>,
+    overruns: Arc<AtomicUsize>,
+    // (total samples queued after a push, when that push happened)
+    stamps: HeapProd<(u64, Instant)>,
+    pushed: u64,
+}
+
+impl AudioProducer {
+    /// Copies `samples` into the queue in one go and notes when it happened. Whatever doesn't
+    /// fit is dropped and counted. Allocation-free, so it is safe to call from the audio callback.
+    pub fn push(&mut self, samples: &[f32]) {
+        let pushed = self.inner.push_slice(samples);
+        if pushed < samples.len() {
+            self.overruns.fetch_add(samples.len() - pushed, Ordering::Relaxed);
+        }
+        if pushed > 0 {
+            self.pushed += pushed as u64;
+            let _ = self.stamps.try_push((self.pushed, Instant::now()));
+        }
+    }
+}
+
+pub struct AudioConsumer {
+    inner: HeapCons<f32>,
+    overruns: Arc<AtomicUsize>,
+    stamps: HeapCons<(u64, Instant)>,
+    popped: u64,
+    captured_at: Option<Instant>,
+}
+
+impl AudioConsumer {
+    /// Fills `window` with the oldest queued samples if a whole window is available.
+    /// Returns `false` (leaving `window` untouched) otherwise.
+    pub fn pop_window(&mut self, window: &mut [f32]) -> bool {
+        if self.inner.occupied_len() < window.len() {
+            return false;
+        }
+        self.inner.pop_slice(window);
+        self.popped += window.len() as u64;
+        self.captured_at = self.take_stamp();
+        true
+    }
+
+    /// When the last sample of the window most recently popped arrived from the device, if
+    /// known. This is where capture-to-key-press latency is measured from.
+    pub fn captured_at(&self) -> Option<Instant> {
+        self.captured_at
+    }
+
+    // The time of the push that queued sample `popped - 1`. Older stamps are dropped; that one
+    // is kept, as the next window may end within the same push.
+    fn take_stamp(&mut self) -> Option<Instant> {
+        while let Some(&(end, at)) = self.stamps.first() {
+            if end >= self.popped {
+                return Some(at);
+            }
+            self.stamps.try_pop();
+        }
+        None
+    }
+
+    /// Pops a single sample, if any is queued.
+    pub fn pop_sample(&mut self) -> Option<f32> {
+        self.inner.try_pop()
+    }
+
+    /// Drops the oldest queued audio, in whole-window steps, until no more than
+    /// `max_samples` remain. Returns the number of samples discarded.
+    pub fn discard_backlog(&mut self, max_samples: usize, window_size: usize) -> usize {
+        let excess = self.inner.occupied_len().saturating_sub(max_samples);
+        if excess == 0 {
+            return 0;
+        }
+        let windows = excess.div_ceil(window_size);
+        let skipped = self.inner.skip(windows * window_size);
+        self.popped += skipped as u64;
+        skipped
+    }
+
+    /// Number of samples dropped by the producer since the last call.
+    pub fn take_overruns(&self) -> usize {
+        self.overruns.swap(0, Ordering::Relaxed)
+    }
+}
+
+/// Wakes the processing loop when the audio callback has queued another full window.
+///
+/// Clones share the same signal: one goes into the callback, one stays with the loop.
+#[derive(Clone, Default)]
+pub struct WindowSignal {
+    inner: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl WindowSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks a window as ready and wakes the waiting loop. Allocation-free and never panics,
+    /// so it is safe to call from the audio callback.
+    pub fn notify(&self) {
+        let (ready, condvar) = &*self.inner;
+        if let Ok(mut ready) = ready.lock() {
+            *ready = true;
+        }
+        condvar.notify_one();
+    }
+
+    /// Blocks until [`notify`](Self::notify) is called or `timeout` passes, whichever is first.
+    /// Returns whether a window became ready.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let (ready, condvar) = &*self.inner;
+        let Ok(guard) = ready.lock() else {
+            return false;
+        };
+        match condvar.wait_timeout_while(guard, timeout, |ready| !*ready) {
+            Ok((mut ready, _)) => std::mem::take(&mut *ready),
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn holds_samples_up_to_capacity() {
+        let (mut producer, mut consumer) = audio_queue(4096);
+        let samples: Vec<f32> = (0..4096).map(|i| i as f32).collect();
+        producer.push(&samples);
+
+        let mut window = vec![0.0; 1024];
+        let mut received = Vec::new();
+        while consumer.pop_window(&mut window) {
+            received.extend_from_slice(&window);
+        }
+        assert_eq!(received, samples);
+        assert_eq!(consumer.take_overruns(), 0);
+    }
+
+    #[test]
+    fn counts_samples_pushed_past_capacity() {
+        let (mut producer, consumer) = audio_queue(1000);
+        producer.push(&[0.0; 1500]);
+        assert_eq!(consumer.take_overruns(), 500);
+        assert_eq!(consumer.take_overruns(), 0);
+    }
+
+    #[test]
+    fn discards_oldest_whole_windows_beyond_the_cap() {
+        let (mut producer, mut consumer) = audio_queue(8192);
+        let samples: Vec<f32> = (0..5000).map(|i| i as f32).collect();
+        producer.push(&samples);
+
+        // 5000 queued, cap 2500: 2500 excess rounds up to three 1000-sample windows.
+        assert_eq!(consumer.discard_backlog(2500, 1000), 3000);
+        let mut window = vec![0.0; 1000];
+        assert!(consumer.pop_window(&mut window));
+        assert_eq!(window[0], 3000.0);
+        assert_eq!(consumer.discard_backlog(2500, 1000), 0);
+    }
+
+    #[test]
+    fn windows_carry_the_time_their_last_sample_arrived() {
+        let (mut producer, mut consumer) = audio_queue(4096);
+        let mut window = vec![0.0; 1000];
+        producer.push(&[0.0; 600]);
+        let first_push = Instant::now();
+        thread::sleep(Duration::from_millis(5));
+        producer.push(&[0.0; 600]);
+        let second_push = Instant::now();
+
+        assert!(consumer.pop_window(&mut window));
+        let captured = consumer.captured_at().unwrap();
+        // Sample 999 came with the second push
+        assert!(captured > first_push && captured <= second_push);
+        assert_eq!(consumer.captured_at(), Some(captured));
+    }
+
+    #[test]
+    fn window_signal_wakes_the_waiter() {
+        let signal = WindowSignal::new();
+        assert!(!signal.wait(Duration::from_millis(1)));
+
+        let notifier = signal.clone();
+        let writer = thread::spawn(move || notifier.notify());
+        assert!(signal.wait(Duration::from_secs(5)));
+        writer.join().unwrap();
+
+        // The wakeup is consumed by the wait that saw it
+        assert!(!signal.wait(Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn faster_than_real_time_producer_loses_nothing() {
+        const WINDOW: usize = 2048;
+        const CHUNKS: usize = 2000;
+        const CHUNK_SIZE: usize = 512;
+        let (mut producer, mut consumer) = audio_queue(8 * WINDOW);
+
+        // Push as fast as the queue has room, which is far faster than any audio device would.
+        let writer = thread::spawn(move || {
+            let mut next = 0u32;
+            for _ in 0..CHUNKS {
+                let chunk: Vec<f32> = (0..CHUNK_SIZE as u32).map(|i| (next + i) as f32).collect();
+                next += CHUNK_SIZE as u32;
+                while producer.inner.vacant_len() < CHUNK_SIZE {
+                    thread::yield_now();
+                }
+                producer.push(&chunk);
+            }
+        });
+
+        let mut window = vec![0.0; WINDOW];
+        let mut expected = 0u32;
+        while (expected as usize) < CHUNKS * CHUNK_SIZE {
+            if consumer.pop_window(&mut window) {
+                for &sample in &window {
+                    assert_eq!(sample, expected as f32);
+                    expected += 1;
+                }
+            } else {
+                thread::yield_now();
+            }
+        }
+        writer.join().unwrap();
+        assert_eq!(consumer.take_overruns(), 0);
+    }
+}
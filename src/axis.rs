@@ -0,0 +1,166 @@
+//! `[gamepad.axis]`: pitch steering an analog stick of the virtual controller. A range of
+//! pitches is spread over the axis from -1.0 to +1.0, so sliding the voice up and down moves
+//! the stick smoothly, e.g. to steer in a racing game, instead of stepping between bands.
+
+use crate::notes::cents;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// An analog stick axis of the virtual controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StickAxis {
+    /// Left stick, left (-1.0) to right (+1.0).
+    LeftX,
+    /// Left stick, down (-1.0) to up (+1.0).
+    LeftY,
+    RightX,
+    RightY,
+}
+
+/// How pitches are spread over the axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AxisScale {
+    /// Evenly in cents, so every semitone moves the stick as far, as it sounds.
+    #[default]
+    Cents,
+    /// Evenly in Hz, which stretches the top of the range.
+    Linear,
+}
+
+// Smallest change of the axis value worth sending
+const AXIS_STEP: f32 = 0.001;
+
+/// A pitch range spread over an axis: `low_hz` is -1.0, `high_hz` is +1.0, and pitches beyond
+/// stop at the ends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AxisMapping {
+    pub axis: StickAxis,
+    pub low_hz: f32,
+    pub high_hz: f32,
+    pub scale: AxisScale,
+    /// Where the stick rests without a pitch.
+    pub center: f32,
+    /// How long the stick takes to glide back to `center` once the pitch is lost; zero snaps
+    /// back at once.
+    pub decay: Duration,
+}
+
+impl AxisMapping {
+    /// The axis value for `freq`.
+    pub fn value(&self, freq: f32) -> f32 {
+        let position = match self.scale {
+            AxisScale::Cents => cents(freq, self.low_hz) / cents(self.high_hz, self.low_hz),
+            AxisScale::Linear => (freq - self.low_hz) / (self.high_hz - self.low_hz),
+        };
+        (2.0 * position - 1.0).clamp(-1.0, 1.0)
+    }
+}
+
+/// Follows the pitch window by window and says when the axis should move.
+pub struct AxisFollower {
+    mapping: AxisMapping,
+    value: f32,
+    // The value and time the pitch was lost at, while gliding back to center
+    lost: Option<(f32, Instant)>,
+    sent: Option<f32>,
+}
+
+impl AxisFollower {
+    pub fn new(mapping: AxisMapping) -> Self {
+        AxisFollower { value: mapping.center, mapping, lost: None, sent: None }
+    }
+
+    pub fn mapping(&self) -> &AxisMapping {
+        &self.mapping
+    }
+
+    /// Feeds one window's (smoothed) pitch, if any, at `now`. Returns the new axis value when
+    /// it has moved since the last one returned.
+    pub fn update(&mut self, freq: Option<f32>, now: Instant) -> Option<f32> {
+        let center = self.mapping.center;
+        self.value = match freq {
+            Some(freq) => {
+                self.lost = None;
+                self.mapping.value(freq)
+            }
+            None => {
+                let (from, since) = *self.lost.get_or_insert((self.value, now));
+                let elapsed = now.saturating_duration_since(since);
+                if elapsed >= self.mapping.decay {
+                    center
+                } else {
+                    let left = 1.0 - elapsed.as_secs_f32() / self.mapping.decay.as_secs_f32();
+                    center + (from - center) * left
+                }
+            }
+        };
+        let moved = match self.sent {
+            None => true,
+            // However small the last step, land exactly on the center
+            Some(sent) => (sent - self.value).abs() >= AXIS_STEP || (self.value == center && sent != center),
+        };
+        if !moved {
+            return None;
+        }
+        self.sent = Some(self.value);
+        Some(self.value)
+    }
+
+    /// Puts the stick back on its center at once.
+    pub fn reset(&mut self) {
+        self.value = self.mapping.center;
+        self.lost = None;
+        self.sent = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steering(scale: AxisScale, decay_ms: u64) -> AxisMapping {
+        AxisMapping {
+            axis: StickAxis::LeftX,
+            low_hz: 110.0,
+            high_hz: 440.0,
+            scale,
+            center: 0.0,
+            decay: Duration::from_millis(decay_ms),
+        }
+    }
+
+    #[test]
+    fn pitches_spread_over_the_axis() {
+        let cents = steering(AxisScale::Cents, 0);
+        assert_eq!(cents.value(110.0), -1.0);
+        assert_eq!(cents.value(440.0), 1.0);
+        // An octave up is halfway in cents, a third of the way in Hz
+        assert!(cents.value(220.0).abs() < 1e-5);
+        let linear = steering(AxisScale::Linear, 0);
+        assert!((linear.value(220.0) + 1.0 / 3.0).abs() < 1e-5);
+        // Beyond the range, the stick stays at the end
+        assert_eq!(cents.value(50.0), -1.0);
+        assert_eq!(cents.value(1000.0), 1.0);
+    }
+
+    #[test]
+    fn the_stick_glides_back_to_center_when_the_pitch_is_lost() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut follower = AxisFollower::new(steering(AxisScale::Cents, 200));
+        assert_eq!(follower.update(Some(440.0), at(0)), Some(1.0));
+        // The same pitch again doesn't move it
+        assert_eq!(follower.update(Some(440.0), at(20)), None);
+        // Losing the pitch starts the glide from where the stick is
+        assert_eq!(follower.update(None, at(40)), None);
+        let halfway = follower.update(None, at(140)).unwrap();
+        assert!((halfway - 0.5).abs() < 1e-5, "{}", halfway);
+        assert_eq!(follower.update(None, at(240)), Some(0.0));
+        assert_eq!(follower.update(None, at(260)), None);
+
+        let mut snapping = AxisFollower::new(steering(AxisScale::Cents, 0));
+        assert_eq!(snapping.update(Some(110.0), at(0)), Some(-1.0));
+        assert_eq!(snapping.update(None, at(20)), Some(0.0));
+    }
+}
@@ -0,0 +1,118 @@
+//! Where key presses end up. The engine only decides on [`KeyEvent`](crate::keys::KeyEvent)s;
+//! the `pitchu` binary hands them to one [`OutputBackend`] per `--output` on a thread of its
+//! own, so a slow backend never holds up detection. A new kind of output is a new
+//! implementation of this trait, without changes to the engine or its loop.
+
+use crate::axis::StickAxis;
+use enigo::Key;
+
+/// One place key presses go: the keyboard, a virtual controller, a MIDI port, ... Every
+/// method but [`name`](Self::name), [`press`](Self::press) and [`release`](Self::release) has
+/// a default, so a backend only implements what it can do. An `Err` stops the backend: it is
+/// closed and the others carry on.
+pub trait OutputBackend {
+    /// What `--output` calls it, for messages.
+    fn name(&self) -> String;
+
+    /// Puts `key` down until [`release`](Self::release); how latch mode holds a key.
+    fn press(&mut self, key: Key) -> Result<(), String>;
+
+    fn release(&mut self, key: Key) -> Result<(), String>;
+
+    /// A press or repeat in repeat mode: down and straight back up.
+    fn tap(&mut self, key: Key) -> Result<(), String> {
+        self.press(key)?;
+        self.release(key)
+    }
+
+    /// Types a text band's text. Backends without a way to type skip it.
+    fn type_text(&mut self, _text: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Moves a `[gamepad.axis]` stick, from -1.0 to 1.0. Only a controller has one.
+    fn axis(&mut self, _axis: StickAxis, _value: f32) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Moves the mouse pointer by `dx` and `dy` pixels, for `[[curves]]`. Only the keyboard
+    /// has a pointer.
+    fn mouse_move(&mut self, _dx: i32, _dy: i32) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called on every pass of the output thread, after a command or a wait of up to a second
+    /// for one, e.g. to keep a connection alive. The backend decides whether anything is due.
+    fn idle(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called once when the output stops, after every key still down has been released.
+    fn shutdown(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Shares what it's called with, so a test can look after boxing it
+    struct Recorder {
+        calls: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl OutputBackend for Recorder {
+        fn name(&self) -> String {
+            "recorder".to_string()
+        }
+
+        fn press(&mut self, key: Key) -> Result<(), String> {
+            self.calls.borrow_mut().push(format!("press {:?}", key));
+            Ok(())
+        }
+
+        fn release(&mut self, key: Key) -> Result<(), String> {
+            self.calls.borrow_mut().push(format!("release {:?}", key));
+            Ok(())
+        }
+    }
+
+    struct Broken;
+
+    impl OutputBackend for Broken {
+        fn name(&self) -> String {
+            "broken".to_string()
+        }
+
+        fn press(&mut self, _key: Key) -> Result<(), String> {
+            Err("unplugged".to_string())
+        }
+
+        fn release(&mut self, _key: Key) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn a_backend_only_needs_press_and_release() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut backend: Box<dyn OutputBackend> = Box::new(Recorder { calls: Rc::clone(&calls) });
+        backend.tap(Key::Space).unwrap();
+        assert_eq!(*calls.borrow(), ["press Space", "release Space"]);
+        // The defaults do nothing, and don't fail
+        backend.type_text("gg").unwrap();
+        backend.axis(StickAxis::LeftX, 0.5).unwrap();
+        backend.mouse_move(3, -2).unwrap();
+        backend.idle().unwrap();
+        backend.shutdown();
+        assert_eq!(*calls.borrow(), ["press Space", "release Space"]);
+        assert_eq!(backend.name(), "recorder");
+    }
+
+    #[test]
+    fn a_tap_stops_at_a_failed_press() {
+        let mut broken = Broken;
+        assert_eq!(broken.tap(Key::Space), Err("unplugged".to_string()));
+    }
+}
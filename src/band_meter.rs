@@ -0,0 +1,78 @@
+use crate::mapping::Mapping;
+
+/// Renders where `freq` sits across the bands of `mapping` as one line, e.g.
+/// `[---|---|=^=|---]  251.3 Hz 'a'`.
+///
+/// The bar spans the lowest band edge to the highest on a log scale, `width` cells wide. `-`
+/// marks a band, `|` the start of the next one, a space a gap between bands. The band the pitch
+/// falls in is drawn with `=` and the pitch itself with `^`, or `<`/`>` at the ends when it is
+/// outside the bar.
+pub fn render(mapping: &Mapping, freq: Option<f32>, width: usize) -> String {
+    let bands = mapping.bands();
+    let low = bands.iter().map(|band| band.low_hz).fold(f32::INFINITY, f32::min);
+    let high = bands.iter().map(|band| band.high_hz).fold(0.0, f32::max);
+    if bands.is_empty() || width == 0 || low >= high {
+        return String::from("[no bands]");
+    }
+    let span = (high / low).ln();
+    let band_at = |f: f32| bands.iter().position(|band| band.contains(f));
+    let active = freq.and_then(band_at);
+    let marker = freq.map(|f| {
+        if f < low {
+            (0, '<')
+        } else if f > high {
+            (width - 1, '>')
+        } else {
+            let cell = ((f / low).ln() / span * width as f32) as usize;
+            (cell.min(width - 1), '^')
+        }
+    });
+
+    let mut bar = String::with_capacity(width + 2);
+    bar.push('[');
+    let mut previous = None;
+    for cell in 0..width {
+        let center = low * (span * (cell as f32 + 0.5) / width as f32).exp();
+        let band = band_at(center);
+        let c = match marker {
+            Some((at, c)) if at == cell => c,
+            _ if band.is_some() && previous.is_some() && band != previous => '|',
+            _ if band.is_some() && band == active => '=',
+            _ if band.is_some() => '-',
+            _ => ' ',
+        };
+        bar.push(c);
+        previous = band;
+    }
+    bar.push(']');
+
+    match (freq, active) {
+        (Some(f), Some(index)) => format!("{} {:6.1} Hz '{}'", bar, f, bands[index].label()),
+        (Some(f), None) => format!("{} {:6.1} Hz", bar, f),
+        (None, _) => format!("{}      - Hz", bar),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::Band;
+    use enigo::Key;
+
+    fn two_bands() -> Mapping {
+        Mapping::new(vec![Band::new(100.0, 200.0, Key::Layout('a')), Band::new(200.1, 400.0, Key::Layout('b'))])
+    }
+
+    #[test]
+    fn marks_the_pitch_and_its_band() {
+        assert_eq!(render(&two_bands(), Some(150.0), 10), "[==^==|----]  150.0 Hz 'a'");
+        assert_eq!(render(&two_bands(), Some(300.0), 10), "[-----|=^==]  300.0 Hz 'b'");
+    }
+
+    #[test]
+    fn pitches_outside_the_bar_point_off_the_end() {
+        assert_eq!(render(&two_bands(), Some(50.0), 10), "[<----|----]   50.0 Hz");
+        assert_eq!(render(&two_bands(), Some(900.0), 10), "[-----|--->]  900.0 Hz");
+        assert_eq!(render(&two_bands(), None, 10), "[-----|----]      - Hz");
+    }
+}
@@ -0,0 +1,75 @@
+//! Band-limiting before detection: only the frequencies the mapping can use, with some margin,
+//! reach the pitch detector, so strong content far outside them (keyboard clatter, the
+//! harmonics of a fan) doesn't compete with the voice.
+
+use crate::decimate::{Biquad, BUTTERWORTH_Q};
+use std::f32::consts::PI;
+
+/// How far beyond the mapped range the filter starts to cut: half an octave each way.
+pub const MARGIN: f32 = std::f32::consts::SQRT_2;
+
+// Cutoffs this close to Nyquist are left out; the filter can't place them
+const MAX_CUTOFF: f32 = 0.45;
+// Q of the four sections of an 8th-order Butterworth filter
+const BUTTERWORTH_8_Q: [f32; 4] = [0.509_795_6, 0.601_344_9, 0.899_976_1, 2.562_915_5];
+
+/// A band-pass around `low_hz..high_hz`, cutting from [`MARGIN`] beyond each end: a 4th-order
+/// Butterworth high-pass against rumble and a steeper, 8th-order low-pass, since what competes
+/// with a voice is mostly above it. State carries over from one window to the next, so
+/// consecutive windows are filtered as the stream they came from.
+#[derive(Debug, Clone)]
+pub struct BandLimit {
+    low_hz: f32,
+    high_hz: f32,
+    sections: Vec<Biquad>,
+}
+
+impl BandLimit {
+    pub fn new(low_hz: f32, high_hz: f32, sample_rate: usize) -> Self {
+        let nyquist = sample_rate as f32 / 2.0;
+        let w0 = |hz: f32| 2.0 * PI * hz / sample_rate as f32;
+        let mut sections = Vec::new();
+        let high_pass_hz = (low_hz / MARGIN).min(nyquist * MAX_CUTOFF);
+        sections.extend(BUTTERWORTH_Q.map(|q| Biquad::high_pass(w0(high_pass_hz), q)));
+        let low_pass_hz = high_hz * MARGIN;
+        if low_pass_hz < nyquist * MAX_CUTOFF {
+            sections.extend(BUTTERWORTH_8_Q.map(|q| Biquad::low_pass(w0(low_pass_hz), q)));
+        }
+        BandLimit { low_hz, high_hz, sections }
+    }
+
+    /// The range the filter was made for, before the margin.
+    pub fn range_hz(&self) -> (f32, f32) {
+        (self.low_hz, self.high_hz)
+    }
+
+    /// Filters `window` in place.
+    pub fn process(&mut self, window: &mut [f32]) {
+        for sample in window {
+            *sample = self.sections.iter_mut().fold(*sample, |x, section| section.process(x));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Peak gain for a sine at `hz` through a filter for 100-200 Hz, once it has settled
+    fn gain(hz: f32) -> f32 {
+        let rate = 48_000;
+        let mut filter = BandLimit::new(100.0, 200.0, rate);
+        let mut signal: Vec<f32> = (0..rate).map(|n| (2.0 * PI * hz * n as f32 / rate as f32).sin()).collect();
+        filter.process(&mut signal);
+        signal[rate / 2..].iter().fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn the_mapped_range_passes_and_far_content_is_cut() {
+        assert!(gain(150.0) > 0.95, "{}", gain(150.0));
+        assert!(gain(100.0) > 0.9 && gain(200.0) > 0.9, "{} {}", gain(100.0), gain(200.0));
+        // Over an octave out, down by 20 dB and more
+        assert!(gain(500.0) < 0.02, "{}", gain(500.0));
+        assert!(gain(30.0) < 0.1, "{}", gain(30.0));
+    }
+}
@@ -0,0 +1,82 @@
+use pitch_detection::detector::autocorrelation::AutocorrelationDetector;
+use pitch_detection::detector::mcleod::McLeodDetector;
+use pitch_detection::detector::yin::YINDetector;
+use pitch_detection::detector::PitchDetector;
+use pitchu::detector::HpsDetector;
+use std::f32::consts::TAU;
+use std::time::{Duration, Instant};
+
+// Synthetic test signal: a 220 Hz tone with a second harmonic, as a sung note might look
+const SIGNAL_HZ: f32 = 220.0;
+const SAMPLE_RATE: usize = 48_000;
+const AMPLITUDE: f32 = 0.5;
+// Thresholds matching the CLI defaults, so the measured path is the one used at runtime
+const POWER_THRESHOLD: f32 = 0.7;
+const CLARITY_THRESHOLD: f32 = 0.2;
+
+const DETECTORS: [&str; 4] = ["mcleod", "autocorrelation", "yin", "hps"];
+
+fn signal(window_size: usize) -> Vec<f32> {
+    (0..window_size)
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            AMPLITUDE * ((TAU * SIGNAL_HZ * t).sin() + 0.3 * (2.0 * TAU * SIGNAL_HZ * t).sin())
+        })
+        .collect()
+}
+
+fn detector(name: &str, window_size: usize) -> Box<dyn PitchDetector<f32>> {
+    match name {
+        "mcleod" => Box::new(McLeodDetector::new(window_size, window_size / 2)),
+        "autocorrelation" => Box::new(AutocorrelationDetector::new(window_size, window_size / 2)),
+        "hps" => Box::new(HpsDetector::new(window_size)),
+        _ => Box::new(YINDetector::new(window_size, window_size / 2)),
+    }
+}
+
+/// Times every detector on a fixed synthetic signal at each window size and prints one CSV
+/// row per combination to stdout.
+///
+/// `max_sample_rate` is how many samples per second the detector keeps up with when every
+/// sample is analysed exactly once (windows back to back), i.e. `window / mean time`.
+pub fn run(window_sizes: &[usize], iterations: u32) {
+    println!("detector,window,iterations,mean_us,min_us,max_us,max_sample_rate,detected_hz");
+    for &window_size in window_sizes {
+        let samples = signal(window_size);
+        for name in DETECTORS {
+            let mut detector = detector(name, window_size);
+            // Warm caches and the FFT planner before timing
+            let detected = detector.get_pitch(&samples, SAMPLE_RATE, POWER_THRESHOLD, CLARITY_THRESHOLD);
+
+            let mut total = Duration::ZERO;
+            let mut min = Duration::MAX;
+            let mut max = Duration::ZERO;
+            for _ in 0..iterations {
+                let start = Instant::now();
+                std::hint::black_box(detector.get_pitch(
+                    std::hint::black_box(&samples),
+                    SAMPLE_RATE,
+                    POWER_THRESHOLD,
+                    CLARITY_THRESHOLD,
+                ));
+                let elapsed = start.elapsed();
+                total += elapsed;
+                min = min.min(elapsed);
+                max = max.max(elapsed);
+            }
+
+            let mean = total.as_secs_f64() / iterations as f64;
+            println!(
+                "{},{},{},{:.1},{:.1},{:.1},{:.0},{}",
+                name,
+                window_size,
+                iterations,
+                mean * 1e6,
+                min.as_secs_f64() * 1e6,
+                max.as_secs_f64() * 1e6,
+                window_size as f64 / mean,
+                detected.map_or_else(String::new, |pitch| format!("{:.2}", pitch.frequency))
+            );
+        }
+    }
+}
@@ -0,0 +1,805 @@
+use crate::hotkey::Hotkey;
+use crate::output::OutputKind;
+use pitchu::config::{band_configs, ChannelConfig, Config};
+use pitchu::decimate::MAX_DECIMATION;
+use pitchu::detector::DetectorKind;
+use pitchu::held_keys::DEFAULT_MAX_HOLD;
+use pitchu::key_state::{GridFrom, SwitchConsensus, BPM_RANGE, DIVISION_RANGE};
+use pitchu::keys::{GapBehavior, KeyMode, LowClarity, Trigger};
+use pitchu::layout::Layout;
+use pitchu::mapping::Mapping;
+use pitchu::osc::{parse_address, DEFAULT_OSC_ADDRESS, DEFAULT_OSC_TARGET};
+use pitchu::notes::{parse_note_name, A4_RANGE_HZ, DEFAULT_A4_HZ};
+use pitchu::practice::DEFAULT_PRACTICE_SECS;
+use pitchu::presets::{Preset, VocalRange};
+use pitchu::register::{DEFAULT_TRANSPOSE_STEP, TRANSPOSE_RANGE, TRANSPOSE_STEP_RANGE};
+use pitchu::scope::Scope;
+use pitchu::smoothing::SmoothingKind;
+use pitchu::vibrato::MAX_VIBRATO_TOLERANCE_CENTS;
+use pitchu::whistle::{DEFAULT_WHISTLE_OCTAVES, WHISTLE_CLARITY, WHISTLE_MIN_PRESS_CLARITY, WHISTLE_OCTAVE_RANGE};
+use clap::parser::ValueSource;
+use log::LevelFilter;
+use clap::{ArgAction, ArgMatches, Parser, Subcommand, ValueEnum};
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Control a GBA emulator (or anything else) by singing into your microphone.
+///
+/// Every option can also be set through a `PITCHU_*` environment variable (shown in `--help`).
+/// Command-line flags take precedence over environment variables, which take precedence over
+/// the `--config` file, which takes precedence over the built-in defaults.
+#[derive(Parser, Debug)]
+#[command(name = "pitchu", version, about)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Show more log output (-v for debug, -vv for trace). `RUST_LOG`, when set, overrides this.
+    #[arg(short, long, action = ArgAction::Count, global = true, env = "PITCHU_VERBOSE")]
+    pub verbose: u8,
+
+    /// Only log warnings and errors.
+    #[arg(short, long, global = true, conflicts_with = "verbose", env = "PITCHU_QUIET")]
+    pub quiet: bool,
+
+    /// Log the detected pitch of every analysis window. These lines are hidden by default
+    /// because they flood the output.
+    #[arg(long, env = "PITCHU_LOG_PITCH")]
+    pub log_pitch: bool,
+
+    /// Also write the log to this file, from a background thread. Its level is set with
+    /// `--log-file-level`, independently of the console's.
+    #[arg(long, global = true, env = "PITCHU_LOG_FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// Most detailed messages written to `--log-file`: error, warn, info, debug or trace.
+    #[arg(long, default_value = "debug", global = true, env = "PITCHU_LOG_FILE_LEVEL")]
+    pub log_file_level: LevelFilter,
+
+    /// Rotate `--log-file` once it reaches this many megabytes: it becomes `<file>.1`, the
+    /// previous `.1` becomes `.2`, and so on.
+    #[arg(long, default_value_t = 10, global = true, env = "PITCHU_LOG_FILE_MAX_MB")]
+    pub log_file_max_mb: u64,
+
+    /// How many rotated log files to keep.
+    #[arg(long, default_value_t = 3, global = true, env = "PITCHU_LOG_FILE_KEEP")]
+    pub log_file_keep: usize,
+
+    /// Write one JSON line per detected pitch, key event and typed text to this file, with
+    /// millisecond timestamps since startup, for analysing a session afterwards.
+    #[arg(long, env = "PITCHU_EVENT_LOG")]
+    pub event_log: Option<PathBuf>,
+
+    /// Record every analysis window's detection and every key decision to this file, to
+    /// replay later with `pitchu replay` (e.g. with another config).
+    #[arg(long, value_name = "FILE", env = "PITCHU_RECORD_SESSION")]
+    pub record_session: Option<PathBuf>,
+
+    /// Drive the mapping from a CSV of `time_ms,frequency,clarity` rows (an empty frequency is
+    /// silence) instead of audio, on a virtual clock taken from the time column, and print the
+    /// resulting key events as CSV.
+    #[arg(long, value_name = "CSV", env = "PITCHU_SIMULATE")]
+    pub simulate: Option<PathBuf>,
+
+    /// Also write the `--simulate` key events to this file.
+    #[arg(long, value_name = "FILE", requires = "simulate", env = "PITCHU_SIMULATE_OUTPUT")]
+    pub simulate_output: Option<PathBuf>,
+
+    /// Press the keys of a TOML sequence of `[[step]]`s (`key`, `duration_ms`, `delay_ms`) on
+    /// the output, one after another, without listening to any input; then exit.
+    #[arg(long, value_name = "FILE", conflicts_with = "simulate", env = "PITCHU_PLAY")]
+    pub play: Option<PathBuf>,
+
+    /// TOML file with the pitch-to-key bands (see the readme). Repeat it to layer files, e.g.
+    /// shared timings then a per-game mapping; later files win. Without it, the config file in
+    /// the platform's config directory is read if there is one (see `pitchu config-path`).
+    #[arg(long, action = ArgAction::Append, env = "PITCHU_CONFIG")]
+    pub config: Vec<PathBuf>,
+
+    /// Use a built-in mapping instead of the default table: `mgba-default`, `retroarch-default`
+    /// or `menu-navigation`. Replaces the config file's bands.
+    #[arg(long, value_enum, env = "PITCHU_PRESET")]
+    pub preset: Option<Preset>,
+
+    /// Vocal range the `--preset` bands are laid out for; `high` moves them up an octave.
+    #[arg(long, value_enum, default_value_t = VocalRange::Low, env = "PITCHU_RANGE")]
+    pub range: VocalRange,
+
+    /// Print a built-in preset (for `--range`) as a config file to start customizing from, and
+    /// exit.
+    #[arg(long, value_enum, value_name = "PRESET")]
+    pub print_preset: Option<Preset>,
+
+    /// Print the effective settings and mapping in config-file form (TOML unless `json` is
+    /// given) and exit. The output can be loaded again with `--config`.
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "toml", env = "PITCHU_DUMP_CONFIG")]
+    pub dump_config: Option<DumpFormat>,
+
+    /// Detect input channels 0 and 1 separately, each with its own mapping from the config
+    /// file's `[channel0]` and `[channel1]` sections, instead of downmixing them.
+    #[arg(long, env = "PITCHU_DUAL_CHANNEL")]
+    pub dual_channel: bool,
+
+    /// Where pitches come from: `audio` (the microphone or `--device`), or `midi` to play the
+    /// mapping from a MIDI keyboard instead, optionally naming the port as `midi:<name>`
+    /// (see `pitchu list-midi-ports`).
+    #[arg(long, default_value = "audio", env = "PITCHU_INPUT")]
+    pub input: InputSource,
+
+    /// Audio backend to capture from (e.g. ALSA, JACK, WASAPI, ASIO, CoreAudio).
+    /// Defaults to the platform's default host; see `pitchu list-devices`.
+    #[arg(long, env = "PITCHU_HOST")]
+    pub host: Option<String>,
+
+    /// Name of the input device to capture from. Defaults to the host's default input device.
+    #[arg(long, env = "PITCHU_DEVICE")]
+    pub device: Option<String>,
+
+    /// Track audio playing on this computer instead of the microphone. Uses WASAPI loopback on
+    /// Windows; elsewhere picks a "monitor" input source (or the one named by `--device`).
+    #[arg(long, env = "PITCHU_LOOPBACK")]
+    pub loopback: bool,
+
+    /// Reduce the device's sample rate by this factor before detection, e.g. `4` to detect
+    /// 192 kHz input at 48 kHz: less work per window, and a window that spans more time for
+    /// better low notes. Content above 40% of the reduced rate is filtered out.
+    #[arg(long, default_value_t = 1, value_parser = parse_decimation, env = "PITCHU_DECIMATE")]
+    pub decimate: usize,
+
+    /// Minimum window power (sum of squared samples) before pitch detection is attempted.
+    #[arg(long, default_value_t = 0.7, env = "PITCHU_POWER")]
+    pub power: f32,
+
+    /// Clarity threshold handed to the pitch detector (0.0 - 1.0).
+    #[arg(long, default_value_t = 0.2, env = "PITCHU_CLARITY")]
+    pub clarity: f32,
+
+    /// The pitch detection algorithm. `mcleod` is precise and cheap; `yin` is an alternative
+    /// with a different take on noise; `hps` (harmonic product spectrum) finds the note from
+    /// its overtones, so it rarely reads an octave off and copes with a weak fundamental,
+    /// but reads a few cents coarser and costs more per window.
+    #[arg(long, value_enum, default_value_t = DetectorKind::Mcleod, env = "PITCHU_DETECTOR")]
+    pub detector: DetectorKind,
+
+    /// Minimum clarity (0.0 - 1.0) a detected pitch needs to press a key. Stricter values keep
+    /// loud unpitched sounds (claps, consonants) from pressing anything; such windows are
+    /// still logged with `--log-pitch`. Unlike `--clarity` this doesn't change the detector.
+    #[arg(long, default_value_t = 0.0, env = "PITCHU_MIN_CLARITY_FOR_PRESS")]
+    pub min_clarity_for_press: f32,
+
+    /// What a pitch below `--min-clarity-for-press` does: `hold` keeps the previous key
+    /// active, `release` lets it go as if the pitch were lost, `ignore` leaves the key state
+    /// untouched.
+    #[arg(long, value_enum, default_value_t = LowClarity::Release, env = "PITCHU_LOW_CLARITY_BEHAVIOR")]
+    pub low_clarity_behavior: LowClarity,
+
+    /// What a pitch in a gap between two bands does: `release` lets go of the active key,
+    /// `hold` keeps it, so brief slides through a gap don't drop the key. Pitches outside
+    /// every band and silence always release.
+    #[arg(long, value_enum, default_value_t = GapBehavior::Release, env = "PITCHU_GAP_BEHAVIOR")]
+    pub gap_behavior: GapBehavior,
+
+    /// Follow the background noise floor during silence and move the power gate with it.
+    /// `--power` is used until a noise floor estimate is available.
+    #[arg(long, env = "PITCHU_ADAPTIVE_GATE")]
+    pub adaptive_gate: bool,
+
+    /// How far above the estimated noise floor the adaptive gate sits (as a power ratio).
+    #[arg(long, default_value_t = 4.0, env = "PITCHU_ADAPTIVE_RATIO")]
+    pub adaptive_ratio: f32,
+
+    /// Level gate with two thresholds: no pitch counts until a window's RMS (0.0 - 1.0)
+    /// reaches this level, e.g. 0.05. Once open, it stays open down to `--gate-release`, and
+    /// after that nothing presses until the level is back up here. 0 is off.
+    #[arg(long, default_value_t = 0.0, value_parser = parse_gate_level, env = "PITCHU_GATE_ATTACK")]
+    pub gate_attack: f32,
+
+    /// RMS below which the `--gate-attack` gate closes and the note is released; lower than
+    /// the attack level. 0 is half the attack level.
+    #[arg(long, default_value_t = 0.0, value_parser = parse_gate_level, env = "PITCHU_GATE_RELEASE")]
+    pub gate_release: f32,
+
+    /// Scale each analysis window that passes the power gate to the same level before
+    /// detection, so singing softly or loudly detects alike. Silence is still gated out on
+    /// the raw level.
+    #[arg(long, env = "PITCHU_NORMALIZE")]
+    pub normalize: bool,
+
+    /// Detect on the whole spectrum. By default the audio is band-passed to the bands' range
+    /// (with a stick or curves, theirs too) and half an octave beyond, so loud sounds far
+    /// outside it don't mask the voice.
+    #[arg(long, env = "PITCHU_NO_BANDLIMIT")]
+    pub no_bandlimit: bool,
+
+    /// Snap readings at half or double the previous stable pitch back to its octave.
+    /// Sustained octave jumps are accepted after a few windows.
+    #[arg(long, env = "PITCHU_OCTAVE_CORRECT")]
+    pub octave_correct: bool,
+
+    /// How close (in cents) a reading must be to exactly half or double the previous pitch
+    /// to count as an octave error.
+    #[arg(long, default_value_t = 50.0, env = "PITCHU_OCTAVE_TOLERANCE_CENTS")]
+    pub octave_tolerance_cents: f32,
+
+    /// How to smooth the detected frequency across consecutive windows.
+    /// `ema` reacts fastest, `median` rejects single-window glitches best but lags more.
+    #[arg(long, value_enum, default_value_t = SmoothingKind::None, env = "PITCHU_SMOOTHING")]
+    pub smoothing: SmoothingKind,
+
+    /// Weight of the newest reading for `--smoothing ema` (0.0 - 1.0, higher reacts faster).
+    #[arg(long, default_value_t = 0.5, env = "PITCHU_EMA_ALPHA")]
+    pub ema_alpha: f32,
+
+    /// Number of windows in the median for `--smoothing median`.
+    #[arg(long, default_value_t = 3, env = "PITCHU_MEDIAN_WINDOW")]
+    pub median_window: usize,
+
+    /// Hold a sustained note sung with vibrato on its center frequency, so the swing doesn't
+    /// cross band edges. The pitch may swing this many cents either way (0 - 200, 0 is off);
+    /// around 50 suits most singers. A larger jump is a new note and is followed at once.
+    #[arg(long, default_value_t = 0.0, value_parser = parse_vibrato_tolerance, env = "PITCHU_VIBRATO_TOLERANCE")]
+    pub vibrato_tolerance: f32,
+
+    /// How notes drive keys: `repeat` presses and auto-repeats while a note is held,
+    /// `latch` toggles the key down/up on each fresh note.
+    #[arg(long, value_enum, default_value_t = KeyMode::Repeat, env = "PITCHU_MODE")]
+    pub mode: KeyMode,
+
+    /// When a note presses its key in repeat mode: `onset` as soon as it starts, `offset` once
+    /// when it ends (for rhythm games that judge the release). Ignored in latch mode.
+    #[arg(long, value_enum, default_value_t = Trigger::Onset, env = "PITCHU_TRIGGER")]
+    pub trigger: Trigger,
+
+    /// How long (ms) the same band must be heard before its key is pressed (or, in latch mode,
+    /// toggled), so squeaks and consonants don't press keys. Repeat timing still counts from
+    /// the first window; 0 presses at once.
+    #[arg(long, default_value_t = 70, env = "PITCHU_MIN_NOTE_MS")]
+    pub min_note_ms: u64,
+
+    /// How many of the latest windows a different band must win, as `needed/windows`, before
+    /// a pressed key switches to it, so one stray window at a band edge doesn't switch keys.
+    /// `1/1` switches at once. Presses from silence and releases aren't affected.
+    #[arg(long, default_value = "2/3", env = "PITCHU_SWITCH_CONSENSUS")]
+    pub switch_consensus: SwitchConsensus,
+
+    /// For bands with `tap_key` and `hold_key`: a note released sooner than this (ms) presses
+    /// the tap key once when it ends, one held this long presses and repeats the hold key.
+    /// The hold key's press waits this long.
+    #[arg(long, default_value_t = 200, env = "PITCHU_TAP_HOLD_MS")]
+    pub tap_hold_ms: u64,
+
+    /// How long (ms) a note must be held before its key starts repeating.
+    #[arg(long, default_value_t = 250, env = "PITCHU_HOLD_MS")]
+    pub hold_ms: u64,
+
+    /// Interval (ms) between repeats of a held key.
+    #[arg(long, default_value_t = 100, env = "PITCHU_REPEAT_MS")]
+    pub repeat_ms: u64,
+
+    /// Shorten the repeat interval the longer a note is held, like OS key repeat, so a held
+    /// note scrolls through menus faster and faster.
+    #[arg(long, env = "PITCHU_REPEAT_ACCEL")]
+    pub repeat_accel: bool,
+
+    /// Shortest repeat interval (ms) `--repeat-accel` ramps down to.
+    #[arg(long, default_value_t = 30, env = "PITCHU_REPEAT_FLOOR_MS")]
+    pub repeat_floor_ms: u64,
+
+    /// How much (ms) `--repeat-accel` shortens the repeat interval per second of repeating.
+    #[arg(long, default_value_t = 60, env = "PITCHU_REPEAT_RAMP_MS")]
+    pub repeat_ramp_ms: u64,
+
+    /// Rhythm mode: repeat held keys on a tempo grid at this many beats per minute (20-400)
+    /// instead of every `--repeat-ms`, with no drift however late a window comes in. 0 is off.
+    #[arg(long, default_value_t = 0.0, value_parser = parse_bpm, env = "PITCHU_BPM")]
+    pub bpm: f32,
+
+    /// With `--bpm`: repeats per beat (1-16), e.g. 2 for eighth notes or 4 for sixteenths.
+    #[arg(long, default_value_t = 2, value_parser = parse_repeat_division, env = "PITCHU_REPEAT_DIVISION")]
+    pub repeat_division: u32,
+
+    /// With `--bpm`: count the grid from the start of the session, so every note repeats on
+    /// the same beat, or from each note's first press.
+    #[arg(long, value_enum, default_value_t = GridFrom::Session, env = "PITCHU_GRID_FROM")]
+    pub grid_from: GridFrom,
+
+    /// With `--bpm`: click every beat on the default output device. Use headphones, or the
+    /// microphone hears the clicks.
+    #[arg(long, env = "PITCHU_METRONOME")]
+    pub metronome: bool,
+
+    /// Consecutive windows a note must be detected before it toggles a key in latch mode, or
+    /// types the text of a text band.
+    #[arg(long, default_value_t = 2, env = "PITCHU_LATCH_DEBOUNCE")]
+    pub latch_debounce: u32,
+
+    /// With `[[voices]]` in the config file: how long (ms) a voice keeps its key while the
+    /// detector is hearing another voice, or nothing.
+    #[arg(long, default_value_t = 150, env = "PITCHU_VOICE_HOLD_MS")]
+    pub voice_hold_ms: u64,
+
+    /// Maximum amount of audio (in milliseconds) allowed to queue up before the oldest is
+    /// discarded, which caps how far key presses can lag behind your voice.
+    #[arg(long, default_value_t = 500, env = "PITCHU_MAX_LATENCY_MS")]
+    pub max_latency_ms: u64,
+
+    /// Reference pitch of A4 in Hz (400-466), for note names, MIDI input and script helpers.
+    /// Use e.g. 415 for baroque pitch or 442 if that's what you tune to.
+    #[arg(long, default_value_t = DEFAULT_A4_HZ, value_parser = parse_a4, env = "PITCHU_A4")]
+    pub a4: f32,
+
+    /// Practice holding a note, e.g. `A3` or `F#4`, instead of pressing keys: from the first
+    /// pitch heard, for `--practice-secs`, then print how close and how steadily it was held.
+    #[arg(long, value_name = "NOTE", value_parser = parse_practice_note, env = "PITCHU_PRACTICE")]
+    pub practice: Option<String>,
+
+    /// How long a `--practice` run lasts, in seconds.
+    #[arg(long, default_value_t = DEFAULT_PRACTICE_SECS, env = "PITCHU_PRACTICE_SECS")]
+    pub practice_secs: u64,
+
+    /// Play by whistling: moves every band up by `--whistle-octaves`, so a table laid out for
+    /// singing lands where whistles are (roughly 800-2500 Hz). Also uses a shorter analysis
+    /// window, ignores pitches below 500 Hz and, unless set, raises `--clarity` and
+    /// `--min-clarity-for-press`, since a whistle is nearly a pure sine.
+    #[arg(long, env = "PITCHU_WHISTLE")]
+    pub whistle: bool,
+
+    /// How many octaves `--whistle` moves the bands up (1-4).
+    #[arg(long, default_value_t = DEFAULT_WHISTLE_OCTAVES, value_parser = parse_whistle_octaves, env = "PITCHU_WHISTLE_OCTAVES")]
+    pub whistle_octaves: i32,
+
+    /// Move every band by this many semitones, e.g. `+12` an octave up or `-5` a fourth down,
+    /// so a mapping fits a higher or lower voice without editing it.
+    #[arg(long, value_name = "SEMITONES", allow_hyphen_values = true, value_parser = parse_transpose, env = "PITCHU_TRANSPOSE")]
+    pub transpose: Option<i32>,
+
+    /// Listen to the first few seconds of singing and move the bands, by whole
+    /// `--transpose-step`s, so their middle lands on the median pitch sung. No key is pressed
+    /// until then. `--transpose` skips it.
+    #[arg(long, conflicts_with = "transpose", env = "PITCHU_AUTO_TRANSPOSE")]
+    pub auto_transpose: bool,
+
+    /// Semitones `--auto-transpose` moves the bands in (1-12); 12 keeps them in whole octaves.
+    #[arg(long, default_value_t = DEFAULT_TRANSPOSE_STEP, value_parser = parse_transpose_step, env = "PITCHU_TRANSPOSE_STEP")]
+    pub transpose_step: i32,
+
+    /// After this many seconds without a detected pitch, let go of every key: latched keys,
+    /// the active note and anything the output still holds. With `--input midi` it counts
+    /// from the last MIDI message. Off when unset.
+    #[arg(long, value_name = "SECONDS", env = "PITCHU_IDLE_TIMEOUT")]
+    pub idle_timeout: Option<u64>,
+
+    /// How often to look for the input device again after it disconnects.
+    #[arg(long, default_value_t = 2000, env = "PITCHU_RECONNECT_INTERVAL_MS")]
+    pub reconnect_interval_ms: u64,
+
+    /// Give up after this many reconnect attempts (0 exits as soon as the device is lost).
+    /// Retries forever when unset.
+    #[arg(long, env = "PITCHU_MAX_RECONNECT_ATTEMPTS")]
+    pub max_reconnect_attempts: Option<u32>,
+
+    /// Play the analysed audio back through an output device to hear what the detector hears.
+    /// Use headphones: speakers will feed back into the microphone.
+    #[arg(long, env = "PITCHU_MONITOR")]
+    pub monitor: bool,
+
+    /// Output device for `--monitor`. Defaults to the host's default output device.
+    #[arg(long, env = "PITCHU_MONITOR_DEVICE")]
+    pub monitor_device: Option<String>,
+
+    /// Log a tuner readout for every detection: offset in cents from the nearest band's
+    /// center, or the distance to each edge when inside a band.
+    #[arg(long, env = "PITCHU_TUNER")]
+    pub tuner: bool,
+
+    /// Log, for every detected window, the nearest band, its center and how far (in Hz and
+    /// cents) the pitch landed from it. Unlike `--log-pitch` this shows where to retrain or
+    /// widen bands.
+    #[arg(long, env = "PITCHU_DIAGNOSTICS")]
+    pub diagnostics: bool,
+
+    /// Discard this much audio after the stream starts (or reconnects, or resumes from a pause
+    /// or the enable key), so the mic and buffers settle before any key can be pressed.
+    #[arg(long, default_value_t = 300, env = "PITCHU_WARMUP_MS")]
+    pub warmup_ms: u64,
+
+    /// Global hotkey that pauses and resumes key presses, e.g. `LControl+LShift+P` or `F9`.
+    /// `none` disables it.
+    #[arg(long, default_value = "LControl+LShift+P", env = "PITCHU_PAUSE_HOTKEY")]
+    pub pause_hotkey: Hotkey,
+
+    /// With a `[relative]` mapping: forget the tonic and sing a new one. `none` disables it.
+    #[arg(long, default_value = "LControl+LShift+T", env = "PITCHU_CALIBRATE_HOTKEY")]
+    pub calibrate_hotkey: Hotkey,
+
+    /// Logs the capture-to-key-press latency percentiles so far. `none` disables it.
+    #[arg(long, default_value = "LControl+LShift+L", env = "PITCHU_LATENCY_HOTKEY")]
+    pub latency_hotkey: Hotkey,
+
+    /// Lets go of every key and button held down, e.g. one stuck after a lost release.
+    /// `none` disables it.
+    #[arg(long, default_value = "LControl+LShift+R", env = "PITCHU_PANIC_HOTKEY")]
+    pub panic_hotkey: Hotkey,
+
+    /// Push-to-talk: keys are only pressed while this key (or combination) is held down, e.g.
+    /// `F8` or `RAlt`. Detection carries on while it is up. `none`, the default, always presses.
+    #[arg(long, default_value = "none", env = "PITCHU_ENABLE_KEY")]
+    pub enable_key: Hotkey,
+
+    /// Where key presses go; repeat the option (or separate with commas) for several at once.
+    /// `keyboard` (or `enigo`) is simulated keyboard input, `gamepad` the buttons of a virtual
+    /// controller (uinput on Linux, ViGEm on Windows) picked by the config's
+    /// `[gamepad.buttons]` table, falling back to the keyboard if none can be created.
+    /// `uinput` presses keys on a virtual Linux keyboard that needs no display server.
+    /// `midi[:<port name>]` plays each key's band as a note on a MIDI output port. `ws`
+    /// sends the key presses as JSON on the `--ws-port` WebSocket. `osc[:<host:port>]`
+    /// presses nothing and sends every detected frequency and clarity to `--osc-target` (or
+    /// the given target). An output that fails is closed and the others carry on.
+    #[arg(long, value_delimiter = ',', default_value = "keyboard", env = "PITCHU_OUTPUT")]
+    pub output: Vec<OutputKind>,
+
+    /// The keyboard layout of this machine. Character keys in the mapping are taken to mean
+    /// their position on QWERTY and are pressed as whatever this layout has there, e.g. `z`
+    /// presses `w` with `azerty`. Only the letter rows move; text is typed as written.
+    #[arg(long, value_enum, default_value_t = Layout::Qwerty, env = "PITCHU_LAYOUT")]
+    pub layout: Layout,
+
+    /// Where `--output osc` sends its UDP messages (`host:port`).
+    #[arg(long, default_value = DEFAULT_OSC_TARGET, env = "PITCHU_OSC_TARGET")]
+    pub osc_target: String,
+
+    /// OSC address of the pitch messages, whose arguments are the frequency and clarity as
+    /// floats, then the channel as an int with `--dual-channel`.
+    #[arg(long, default_value = DEFAULT_OSC_ADDRESS, value_parser = parse_address, env = "PITCHU_OSC_ADDRESS")]
+    pub osc_address: String,
+
+    /// Send key presses over TCP to `pitchu receive` on another machine (`host:port`) instead
+    /// of pressing them here. Reconnects when the link drops.
+    #[arg(long, value_name = "HOST:PORT", env = "PITCHU_REMOTE")]
+    pub remote: Option<String>,
+
+    /// Warn when several key presses in a row land more than this many milliseconds after
+    /// the audio that caused them was captured.
+    #[arg(long, default_value_t = 150, env = "PITCHU_LATENCY_BUDGET_MS")]
+    pub latency_budget_ms: u64,
+
+    /// Let go of a key (or button) held down for longer than this many milliseconds, as
+    /// stuck: its release was probably lost. Raise it for mappings that hold keys for long; 0
+    /// never lets go.
+    #[arg(long, default_value_t = DEFAULT_MAX_HOLD.as_millis() as u64, env = "PITCHU_MAX_HOLD_MS")]
+    pub max_hold_ms: u64,
+
+    /// Most keys (and buttons) down at once, e.g. across `[[voices]]` or both channels. Keys
+    /// already down come first: one more is not pressed until another is let go. 0 is no limit.
+    #[arg(long, default_value_t = 0, env = "PITCHU_MAX_SIMULTANEOUS_KEYS")]
+    pub max_simultaneous_keys: usize,
+
+    /// Log a one-line input level readout (peak and RMS) every second and, when stdout is a
+    /// terminal, keep a line on stderr showing the pitch's position across the bands.
+    #[arg(long, env = "PITCHU_METER")]
+    pub meter: bool,
+
+    /// Feedback on each initial key press (not repeats): `bell` rings the terminal bell,
+    /// `color` prints a line on stderr colored by the detection's clarity.
+    #[arg(long, value_enum, default_value = "none", env = "PITCHU_CUE")]
+    pub cue: Cue,
+
+    /// Also write the end-of-session frequency histogram to this file.
+    #[arg(long, env = "PITCHU_HISTOGRAM_FILE")]
+    pub histogram_file: Option<PathBuf>,
+
+    /// Show a live terminal display of pitch, clarity, input level and key activity instead of
+    /// scrolling logs. Falls back to plain logging when stdout is not a terminal.
+    #[arg(long, env = "PITCHU_TUI")]
+    pub tui: bool,
+
+    /// Add a panel to `--tui`: `trace` plots the detected pitch of the last ten seconds against
+    /// the band edges, `spectrum` a rough spectrogram of the input. Left out when the terminal
+    /// is too small.
+    #[arg(long, value_enum, requires = "tui", env = "PITCHU_SCOPE")]
+    pub scope: Option<Scope>,
+
+    /// Broadcast every detected pitch as JSON (`{freq, clarity, key}`) over a WebSocket
+    /// on this port. Only accepts connections from localhost.
+    #[arg(long, env = "PITCHU_WS_PORT")]
+    pub ws_port: Option<u16>,
+
+    /// Serve a live dashboard on this address (e.g. `127.0.0.1:8080`, or `0.0.0.0:8080` to
+    /// open it from a phone): a rolling chart of the detected pitch over the bands, and the
+    /// latest key presses.
+    #[arg(long, env = "PITCHU_HTTP")]
+    pub http: Option<SocketAddr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputSource {
+    Audio,
+    /// Port name to look for, or the first port.
+    Midi(Option<String>),
+}
+
+impl FromStr for InputSource {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        match spec.split_once(':') {
+            _ if spec.eq_ignore_ascii_case("audio") => Ok(InputSource::Audio),
+            _ if spec.eq_ignore_ascii_case("midi") => Ok(InputSource::Midi(None)),
+            Some((kind, port)) if kind.eq_ignore_ascii_case("midi") && !port.is_empty() => {
+                Ok(InputSource::Midi(Some(port.to_string())))
+            }
+            _ => Err(format!("unknown input '{}' (expected `audio`, `midi` or `midi:<port name>`)", spec)),
+        }
+    }
+}
+
+impl fmt::Display for InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputSource::Audio => write!(f, "audio"),
+            InputSource::Midi(None) => write!(f, "midi"),
+            InputSource::Midi(Some(port)) => write!(f, "midi:{}", port),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Cue {
+    None,
+    Bell,
+    Color,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DumpFormat {
+    Toml,
+    Json,
+}
+
+fn parse_a4(value: &str) -> Result<f32, String> {
+    let hz: f32 = value.parse().map_err(|e| format!("{}", e))?;
+    if !A4_RANGE_HZ.contains(&hz) {
+        return Err(format!("{} Hz is outside {}-{} Hz", hz, A4_RANGE_HZ.start(), A4_RANGE_HZ.end()));
+    }
+    Ok(hz)
+}
+
+fn parse_practice_note(value: &str) -> Result<String, String> {
+    match parse_note_name(value) {
+        Some(_) => Ok(value.to_string()),
+        None => Err(format!("'{}' is not a note name such as A3, F#4 or Bb2", value)),
+    }
+}
+
+fn parse_decimation(value: &str) -> Result<usize, String> {
+    let factor: usize = value.parse().map_err(|e| format!("{}", e))?;
+    if !(1..=MAX_DECIMATION).contains(&factor) {
+        return Err(format!("{} is outside 1-{}", factor, MAX_DECIMATION));
+    }
+    Ok(factor)
+}
+
+fn parse_transpose(value: &str) -> Result<i32, String> {
+    let semitones: i32 = value.parse().map_err(|e| format!("{}", e))?;
+    if !TRANSPOSE_RANGE.contains(&semitones) {
+        return Err(format!("{} is outside {}-{}", semitones, TRANSPOSE_RANGE.start(), TRANSPOSE_RANGE.end()));
+    }
+    Ok(semitones)
+}
+
+fn parse_transpose_step(value: &str) -> Result<i32, String> {
+    let semitones: i32 = value.parse().map_err(|e| format!("{}", e))?;
+    if !TRANSPOSE_STEP_RANGE.contains(&semitones) {
+        return Err(format!("{} is outside {}-{}", semitones, TRANSPOSE_STEP_RANGE.start(), TRANSPOSE_STEP_RANGE.end()));
+    }
+    Ok(semitones)
+}
+
+fn parse_vibrato_tolerance(value: &str) -> Result<f32, String> {
+    let cents: f32 = value.parse().map_err(|_| format!("'{}' is not a number of cents", value))?;
+    if !(0.0..=MAX_VIBRATO_TOLERANCE_CENTS).contains(&cents) {
+        return Err(format!("must be between 0 and {} cents", MAX_VIBRATO_TOLERANCE_CENTS));
+    }
+    Ok(cents)
+}
+
+fn parse_gate_level(value: &str) -> Result<f32, String> {
+    let rms: f32 = value.parse().map_err(|_| format!("'{}' is not an RMS level", value))?;
+    if !(0.0..=1.0).contains(&rms) {
+        return Err("must be between 0.0 and 1.0".to_string());
+    }
+    Ok(rms)
+}
+
+fn parse_step_hz(value: &str) -> Result<f32, String> {
+    let hz: f32 = value.parse().map_err(|_| format!("'{}' is not a frequency", value))?;
+    if hz.is_nan() || hz <= 0.0 {
+        return Err("must be above 0 Hz".to_string());
+    }
+    Ok(hz)
+}
+
+fn parse_bpm(value: &str) -> Result<f32, String> {
+    let bpm: f32 = value.parse().map_err(|_| format!("'{}' is not a tempo", value))?;
+    if bpm != 0.0 && !BPM_RANGE.contains(&bpm) {
+        return Err(format!("{} is outside {}-{} (or 0 for off)", bpm, BPM_RANGE.start(), BPM_RANGE.end()));
+    }
+    Ok(bpm)
+}
+
+fn parse_repeat_division(value: &str) -> Result<u32, String> {
+    let division: u32 = value.parse().map_err(|e| format!("{}", e))?;
+    if !DIVISION_RANGE.contains(&division) {
+        return Err(format!("{} is outside {}-{}", division, DIVISION_RANGE.start(), DIVISION_RANGE.end()));
+    }
+    Ok(division)
+}
+
+fn parse_whistle_octaves(value: &str) -> Result<i32, String> {
+    let octaves: i32 = value.parse().map_err(|e| format!("{}", e))?;
+    if !WHISTLE_OCTAVE_RANGE.contains(&octaves) {
+        return Err(format!("{} is outside {}-{}", octaves, WHISTLE_OCTAVE_RANGE.start(), WHISTLE_OCTAVE_RANGE.end()));
+    }
+    Ok(octaves)
+}
+
+// Fills each listed field from the config file unless it was given on the command line or
+// through its environment variable.
+macro_rules! fill_from_config {
+    ($args:expr, $matches:expr, $config:expr, $($field:ident),* $(,)?) => {
+        $(
+            if !is_explicit($matches, stringify!($field))
+                && let Some(value) = $config.$field
+            {
+                $args.$field = value;
+            }
+        )*
+    };
+}
+
+fn is_explicit(matches: &ArgMatches, id: &str) -> bool {
+    matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable))
+}
+
+impl Args {
+    /// Applies the settings of `config` that weren't set explicitly.
+    pub fn apply_config(&mut self, matches: &ArgMatches, config: &Config) {
+        fill_from_config!(
+            self, matches, config,
+            power, clarity, detector, min_clarity_for_press, low_clarity_behavior, gap_behavior, adaptive_gate, adaptive_ratio, gate_attack, gate_release, normalize, octave_correct, octave_tolerance_cents,
+            smoothing, ema_alpha, median_window, vibrato_tolerance, mode, trigger, min_note_ms, switch_consensus, tap_hold_ms, hold_ms, repeat_ms, repeat_accel, repeat_floor_ms,
+            repeat_ramp_ms, bpm, repeat_division, grid_from, metronome, latch_debounce, voice_hold_ms, warmup_ms,
+            max_latency_ms, a4, whistle, whistle_octaves, layout,
+        );
+    }
+
+    /// With `--whistle`, tightens the clarity thresholds that weren't set on the command line,
+    /// through their environment variables or in `config`.
+    pub fn apply_whistle(&mut self, matches: &ArgMatches, config: &Config) {
+        if !self.whistle {
+            return;
+        }
+        if !is_explicit(matches, "clarity") && config.clarity.is_none() {
+            self.clarity = WHISTLE_CLARITY;
+        }
+        if !is_explicit(matches, "min_clarity_for_press") && config.min_clarity_for_press.is_none() {
+            self.min_clarity_for_press = WHISTLE_MIN_PRESS_CLARITY;
+        }
+    }
+
+    /// The settings in effect, with `mappings` (one per channel), as a config file.
+    pub fn to_config(&self, mappings: &[Mapping]) -> Config {
+        let mut config = Config {
+            power: Some(self.power),
+            clarity: Some(self.clarity),
+            detector: Some(self.detector),
+            min_clarity_for_press: Some(self.min_clarity_for_press),
+            low_clarity_behavior: Some(self.low_clarity_behavior),
+            gap_behavior: Some(self.gap_behavior),
+            adaptive_gate: Some(self.adaptive_gate),
+            adaptive_ratio: Some(self.adaptive_ratio),
+            gate_attack: Some(self.gate_attack),
+            gate_release: Some(self.gate_release),
+            normalize: Some(self.normalize),
+            octave_correct: Some(self.octave_correct),
+            octave_tolerance_cents: Some(self.octave_tolerance_cents),
+            smoothing: Some(self.smoothing),
+            ema_alpha: Some(self.ema_alpha),
+            median_window: Some(self.median_window),
+            vibrato_tolerance: Some(self.vibrato_tolerance),
+            mode: Some(self.mode),
+            trigger: Some(self.trigger),
+            min_note_ms: Some(self.min_note_ms),
+            switch_consensus: Some(self.switch_consensus),
+            tap_hold_ms: Some(self.tap_hold_ms),
+            hold_ms: Some(self.hold_ms),
+            repeat_ms: Some(self.repeat_ms),
+            repeat_accel: Some(self.repeat_accel),
+            repeat_floor_ms: Some(self.repeat_floor_ms),
+            repeat_ramp_ms: Some(self.repeat_ramp_ms),
+            bpm: Some(self.bpm),
+            repeat_division: Some(self.repeat_division),
+            grid_from: Some(self.grid_from),
+            metronome: Some(self.metronome),
+            latch_debounce: Some(self.latch_debounce),
+            voice_hold_ms: Some(self.voice_hold_ms),
+            warmup_ms: Some(self.warmup_ms),
+            max_latency_ms: Some(self.max_latency_ms),
+            a4: Some(self.a4),
+            whistle: Some(self.whistle),
+            whistle_octaves: Some(self.whistle_octaves),
+            layout: Some(self.layout),
+            ..Config::default()
+        };
+        match mappings {
+            [channel0, channel1] => {
+                config.channel0 = Some(ChannelConfig { bands: band_configs(channel0) });
+                config.channel1 = Some(ChannelConfig { bands: band_configs(channel1) });
+            }
+            [mapping, ..] => config.bands = band_configs(mapping),
+            [] => {}
+        }
+        config
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List the input devices of every available audio host and exit.
+    ListDevices,
+    /// List the MIDI input ports usable with `--input midi` and exit.
+    ListMidiPorts,
+    /// Press the keys sent by `pitchu --remote` from another machine. `--output` picks the
+    /// keyboard or a virtual gamepad here, as usual.
+    Receive {
+        /// Address to listen on, e.g. `0.0.0.0:7878` to accept connections from the network.
+        #[arg(long, value_name = "ADDRESS:PORT")]
+        listen: String,
+    },
+    /// Run a `--record-session` file through the mapping and key logic of the current settings
+    /// and config, and print the keys that result. Needs no audio device.
+    Replay {
+        /// The recorded session.
+        session: PathBuf,
+        /// Replay as fast as possible instead of at the recorded pace.
+        #[arg(long)]
+        instant: bool,
+    },
+    /// Show what is detected (frequency, nearest note, clarity, level) on one updating line,
+    /// without pressing keys, and print the range heard on Ctrl-C. Uses the device, threshold
+    /// and buffer options as usual; the config file's bands aren't needed.
+    Tune,
+    /// Print the band every semitone across the config's mapping goes to, as transposed by the
+    /// current settings, then the gaps and overlaps between bands. Needs no audio device.
+    TestMapping {
+        /// Sweep the range in steps of this many Hz instead of by semitone.
+        #[arg(long, value_parser = parse_step_hz)]
+        step_hz: Option<f32>,
+        /// Afterwards, read note names or frequencies from stdin and print what each maps to.
+        #[arg(long)]
+        interactive: bool,
+    },
+    /// Print the mapping in effect as a table (band, range, notes, key, mode, overrides), after
+    /// transposition and presets, and exit. Needs no audio device.
+    ShowMapping,
+    /// Write a commented config with the built-in bands to the platform's config directory,
+    /// where pitchu looks for one when no `--config` is given.
+    Init {
+        /// Replace the config file if there already is one.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print where pitchu looks for its config file when no `--config` is given.
+    ConfigPath,
+    /// Time each pitch detector on a synthetic signal and print the results as CSV.
+    Bench {
+        /// Window sizes (in samples) to measure.
+        #[arg(long, value_delimiter = ',', default_value = "1024,2048,4096")]
+        window_sizes: Vec<usize>,
+        /// Timed runs per detector and window size.
+        #[arg(long, default_value_t = 500)]
+        iterations: u32,
+    },
+}
@@ -0,0 +1,94 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Source of the current time for the engine's hold/repeat timing.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to, for driving the engine from tests and replays.
+/// Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed_nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock { start: Instant::now(), elapsed_nanos: Arc::new(AtomicU64::new(0)) }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.elapsed_nanos.fetch_add(by.as_nanos() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + Duration::from_nanos(self.elapsed_nanos.load(Ordering::SeqCst))
+    }
+}
+
+/// The time of the audio itself: it only moves as samples go by, so hold and repeat timing
+/// follow the audio however late the processing loop wakes up, and the same audio always
+/// times the same. Advance it by every sample taken from the input, including any dropped.
+/// Clones share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct AudioClock(ManualClock);
+
+impl AudioClock {
+    pub fn new() -> Self {
+        AudioClock(ManualClock::new())
+    }
+
+    /// Moves the clock on by `samples` at `sample_rate`.
+    pub fn advance_samples(&self, samples: usize, sample_rate: usize) {
+        if sample_rate > 0 {
+            self.0.advance(Duration::from_nanos(samples as u64 * 1_000_000_000 / sample_rate as u64));
+        }
+    }
+}
+
+impl Clock for AudioClock {
+    fn now(&self) -> Instant {
+        self.0.now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audio_time_counts_samples_not_wakeups() {
+        let clock = AudioClock::new();
+        let shared = clock.clone();
+        let start = clock.now();
+        // However long the loop took to get to them, two windows of 22050 samples are a second
+        std::thread::sleep(Duration::from_millis(5));
+        clock.advance_samples(22_050, 44_100);
+        clock.advance_samples(22_050, 44_100);
+        assert_eq!(shared.now() - start, Duration::from_secs(1));
+        // A reopened input at another rate carries on from there
+        clock.advance_samples(4_800, 48_000);
+        assert_eq!(shared.now() - start, Duration::from_millis(1100));
+    }
+}
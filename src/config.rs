@@ -0,0 +1,1401 @@
+use crate::error::{PitchuError, Result};
+use crate::key_state::{BandTiming, GridFrom, KeyBehavior, SwitchConsensus, BPM_RANGE, DIVISION_RANGE};
+use crate::keys::{key_kind, key_name, parse_key, GapBehavior, KeyMode, LowClarity, Trigger};
+use crate::axis::{AxisMapping, AxisScale, StickAxis};
+use crate::detector::DetectorKind;
+use crate::curve::{Curve, CurveOutput, CurveShape};
+use crate::gamepad::{parse_button, ButtonMap};
+use crate::intervals::{IntervalDirection, IntervalMapping, IntervalRule};
+use crate::layout::{key_scancode, physical_name, scancode_key, Layout};
+use crate::mapping::{Band, BandAction, Mapping};
+use crate::notes::{midi_to_frequency, note_name, note_window, parse_note_name, Scale, A4_RANGE_HZ};
+use crate::patterns::{Pattern, PatternAction};
+use crate::percussive::DEFAULT_REFRACTORY_MS;
+use crate::relative::RelativeMapping;
+use crate::vibrato::MAX_VIBRATO_TOLERANCE_CENTS;
+use crate::voices::Voice;
+use crate::whistle::WHISTLE_OCTAVE_RANGE;
+use crate::smoothing::SmoothingKind;
+use directories::BaseDirs;
+use enigo::Key;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The `--config` file (TOML).
+///
+/// Every setting is optional. Detection and timing settings are named after the command-line
+/// options they stand in for and rank below them (and their `PITCHU_*` variables).
+///
+/// ```toml
+/// power = 0.5
+/// hold_ms = 300
+/// # A beatboxed "p", "t" or "k" presses Space
+/// percussive_key = "Space"
+///
+/// bands = [
+///     # Low notes come out murkier; let them through at a lower clarity
+///     { low_hz = 100.0, high_hz = 115.0, key = "DownArrow", min_clarity = 0.5 },
+///     # Presses once per note, never repeating (also `repeat = false`)
+///     { low_hz = 230.1, high_hz = 270.0, key = "a", action = "one_shot" },
+///     # Held down for as long as the note lasts, e.g. to run
+///     { low_hz = 270.1, high_hz = 299.9, key = "Shift", action = "hold" },
+///     # Typed once per note rather than held
+///     { low_hz = 300.0, high_hz = 340.0, text = "gg" },
+///     # A short stab presses z once, a sustained note presses and repeats x
+///     { low_hz = 200.1, high_hz = 230.0, tap_key = "z", hold_key = "x" },
+///     # The key QWERTY calls Z, whatever the layout; `key = { scancode = 44 }` also works
+///     { low_hz = 340.1, high_hz = 380.0, key = "KeyZ" },
+///     # A steady hum to leave alone; wins over any band it overlaps
+///     { low_hz = 175.0, high_hz = 185.0, action = "ignore" },
+/// ]
+///
+/// # Generated bands: a C major scale from C4, one note per key, each band named after its
+/// # note ("C4", "D4", ...). `semitones = [0, 3, 7]` lists the notes instead of `scale`
+/// [[scales]]
+/// start = "C4"
+/// scale = "major"
+/// keys = ["a", "s", "d", "f", "g", "h", "j"]
+///
+/// # With --dual-channel, each input channel gets its own section
+/// [channel1]
+/// bands = [{ low_hz = 100.0, high_hz = 130.0, key = "Space" }]
+///
+/// # Or split the range between singers, each with their own bands
+/// [[voices]]
+/// name = "low"
+/// low_hz = 80.0
+/// high_hz = 180.0
+/// bands = [{ low_hz = 100.0, high_hz = 130.0, key = "LeftArrow" }]
+///
+/// # Or, instead of bands: keys relative to a tonic sung at startup
+/// [relative]
+/// steps = [{ semitones = 0, key = "z" }, { semitones = 2, key = "x" }]
+///
+/// # Or keys for the jump from one note to the next: up a fourth or more, any way down, or the
+/// # same note again
+/// [intervals]
+/// rules = [
+///     { direction = "up", min_semitones = 5, key = "a" },
+///     { direction = "down", key = "z" },
+///     { direction = "same", key = "x" },
+/// ]
+///
+/// # Three bands sung in order within 1.5 s press Return instead of their own keys. Steps
+/// # are band names (a band's `name = "..."`) or the keys the bands press
+/// [[patterns]]
+/// name = "rise"
+/// steps = ["DownArrow", "x", "a"]
+/// within_ms = 1500
+/// key = "Return"
+/// suppress = true
+/// ```
+///
+/// `[[curves]]` drive continuous outputs from the pitch on top of the bands, e.g. the mouse
+/// pointer, here from 300 pixels per second left at 110 Hz to as fast right at 440 Hz:
+///
+/// ```toml
+/// [[curves]]
+/// output = "mouse-x"
+/// curve = "log"
+/// freq_min = 110.0
+/// freq_max = 440.0
+/// out_min = -300.0
+/// out_max = 300.0
+/// ```
+///
+/// With `--output gamepad`, keys press controller buttons, e.g. `[gamepad.buttons]` with
+/// `x = "A"` and `UpArrow = "DpadUp"` (see [`Button`](crate::gamepad::Button)).
+///
+/// A top-level `script = "mapping.rhai"` hands the choice of key to a script (see
+/// [`ScriptMapper`](crate::script::ScriptMapper)).
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub power: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub clarity: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detector: Option<DetectorKind>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub min_clarity_for_press: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub low_clarity_behavior: Option<LowClarity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gap_behavior: Option<GapBehavior>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adaptive_gate: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub adaptive_ratio: Option<f32>,
+    /// RMS levels of the two-threshold level gate; 0 is off (attack) or half the attack
+    /// (release).
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub gate_attack: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub gate_release: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalize: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub octave_correct: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub octave_tolerance_cents: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smoothing: Option<SmoothingKind>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub ema_alpha: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub median_window: Option<usize>,
+    /// Cents of vibrato held on the note's center; 0 is off.
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub vibrato_tolerance: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<KeyMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<Trigger>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_note_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub switch_consensus: Option<SwitchConsensus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tap_hold_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hold_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_accel: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_floor_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_ramp_ms: Option<u64>,
+    /// Tempo of the repeat grid; 0 is off.
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub bpm: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_division: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grid_from: Option<GridFrom>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metronome: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latch_debounce: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voice_hold_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warmup_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_latency_ms: Option<u64>,
+    /// Reference pitch of A4 for note names and MIDI notes, in Hz.
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub a4: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whistle: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whistle_octaves: Option<i32>,
+    /// The `--layout` character keys are moved to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<Layout>,
+    /// Pressed for beatboxed sounds like "p", "t" or "k", which have no pitch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percussive_key: Option<String>,
+    /// How long after one percussive sound another can fire.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percussive_refractory_ms: Option<u64>,
+
+    /// The mapping used when a section doesn't define its own; the built-in table if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bands: Vec<BandConfig>,
+    /// Runs of notes that expand into more top-level bands, after `bands`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scales: Vec<ScaleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel0: Option<ChannelConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel1: Option<ChannelConfig>,
+    /// Replaces the bands with several voices sharing the input, each owning a range.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub voices: Vec<VoiceConfig>,
+    /// Replaces the bands with steps relative to a calibrated tonic.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative: Option<RelativeConfig>,
+    /// Replaces the bands with keys for the intervals between notes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intervals: Option<IntervalsConfig>,
+    /// A Rhai script that picks the key for each detection instead of the bands (ignore bands
+    /// still apply). Relative paths are relative to the config file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script: Option<PathBuf>,
+    /// Controller buttons for `--output gamepad`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gamepad: Option<GamepadConfig>,
+    /// Sequences of bands that fire a key or text of their own.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<PatternConfig>,
+    /// Continuous outputs driven by the pitch.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub curves: Vec<CurveConfig>,
+}
+
+/// A `[[curves]]` entry: `freq_min` to `freq_max` Hz spread over `out_min` to `out_max`, in
+/// pixels per second for the mouse and -1.0 to 1.0 for a stick.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CurveConfig {
+    pub output: CurveOutput,
+    /// `linear` (the default) or `log`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub curve: Option<CurveShape>,
+    #[serde(serialize_with = "short_f32")]
+    pub freq_min: f32,
+    #[serde(serialize_with = "short_f32")]
+    pub freq_max: f32,
+    #[serde(serialize_with = "short_f32")]
+    pub out_min: f32,
+    #[serde(serialize_with = "short_f32")]
+    pub out_max: f32,
+}
+
+/// The `[gamepad]` section.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GamepadConfig {
+    /// Key name to button name, e.g. `x = "A"`. Adds to or overrides the default table.
+    #[serde(default)]
+    pub buttons: BTreeMap<String, String>,
+    /// A stick axis the pitch steers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub axis: Option<AxisConfig>,
+}
+
+/// The `[gamepad.axis]` section: a pitch range spread over a stick axis, from `low_hz` (or
+/// `low_note`) at -1.0 to `high_hz` (or `high_note`) at +1.0.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AxisConfig {
+    pub axis: StickAxis,
+    #[serde(default, skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub low_hz: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub high_hz: Option<f32>,
+    /// Instead of `low_hz`, e.g. `"C3"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low_note: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub high_note: Option<String>,
+    /// `cents` (the default) or `linear`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<AxisScale>,
+    /// Where the stick rests without a pitch, -1.0 to 1.0; 0.0 by default.
+    #[serde(default, skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub center: Option<f32>,
+    /// How long the stick takes to glide back to `center`; 0 (the default) snaps back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decay_ms: Option<u64>,
+}
+
+/// A `[[voices]]` entry: a singer's frequency range and the bands within it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VoiceConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(serialize_with = "short_f32")]
+    pub low_hz: f32,
+    #[serde(serialize_with = "short_f32")]
+    pub high_hz: f32,
+    pub bands: Vec<BandConfig>,
+}
+
+/// Default for [`ScaleConfig::tolerance_cents`]: half a semitone, so neighbouring notes of a
+/// chromatic scale just meet.
+pub const DEFAULT_SCALE_TOLERANCE_CENTS: f32 = 50.0;
+
+/// A `[[scales]]` entry: one band per key, on successive notes from `start`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScaleConfig {
+    /// The first note, e.g. `"C4"` or `"F#3"`.
+    pub start: String,
+    /// Which notes follow `start`; chromatic if neither this nor `semitones` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<Scale>,
+    /// The notes as semitones above `start`, one per key, instead of `scale`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub semitones: Option<Vec<f32>>,
+    pub keys: Vec<String>,
+    /// How far from each note still counts as that note. Defaults to 50 cents.
+    #[serde(default, skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub tolerance_cents: Option<f32>,
+}
+
+/// A `[[patterns]]` entry: bands sung in order, quickly enough, that press a key or type a
+/// text of their own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PatternConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Band names, or the keys the bands press (the hold key of a tap/hold band).
+    pub steps: Vec<String>,
+    /// Time allowed from the first step's press to the last one's.
+    pub within_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Drops the keys of the steps when the pattern completes. They are held back until it
+    /// completes or fails, so they are late when it fails.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suppress: Option<bool>,
+}
+
+/// Default for [`RelativeConfig::tolerance_cents`].
+pub const DEFAULT_TOLERANCE_CENTS: f32 = 40.0;
+
+/// The `[relative]` section: keys by semitone offset from a tonic.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelativeConfig {
+    /// How far from each step still counts as that step. Defaults to 40 cents.
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub tolerance_cents: Option<f32>,
+    pub steps: Vec<StepConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StepConfig {
+    /// Offset from the tonic, e.g. 0 for "do", 2 for "re", 4 for "mi".
+    #[serde(serialize_with = "short_f32")]
+    pub semitones: f32,
+    pub key: String,
+}
+
+/// Default for [`IntervalsConfig::tolerance_cents`].
+pub const DEFAULT_INTERVAL_TOLERANCE_CENTS: f32 = 50.0;
+
+/// The `[intervals]` section: keys by the jump from the note before.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IntervalsConfig {
+    /// How far a jump may be off and still count; also how close to the note before is the
+    /// same note. Defaults to 50 cents.
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub tolerance_cents: Option<f32>,
+    pub rules: Vec<IntervalRuleConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IntervalRuleConfig {
+    pub direction: IntervalDirection,
+    /// Smallest jump the rule covers, for `up` and `down`. Defaults to 1.
+    #[serde(default, skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub min_semitones: Option<f32>,
+    /// Largest jump the rule covers; no limit by default.
+    #[serde(default, skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub max_semitones: Option<f32>,
+    pub key: String,
+}
+
+/// Settings for one input channel in `--dual-channel` mode.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelConfig {
+    #[serde(default)]
+    pub bands: Vec<BandConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BandConfig {
+    #[serde(serialize_with = "short_f32")]
+    pub low_hz: f32,
+    #[serde(serialize_with = "short_f32")]
+    pub high_hz: f32,
+    /// Lets `[[patterns]]` steps refer to the band by name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// A single character, a key name such as `DownArrow`, a physical key such as `KeyZ`, or
+    /// `{ scancode = 44 }`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<KeyConfig>,
+    /// Typed once per note instead of pressing a key, e.g. `text = "gg"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// `action = "ignore"` makes the band a dead zone instead. With `key`, `"hold"` keeps the
+    /// key down for the whole note and `"one_shot"` presses it once per note, instead of the
+    /// default `"click_repeat"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<BandActionConfig>,
+    /// With `hold_key`, instead of `key`: a short note presses `tap_key` once, a sustained one
+    /// presses and repeats `hold_key`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tap_key: Option<KeyConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hold_key: Option<KeyConfig>,
+    /// Clarity (0.0 - 1.0) a pitch needs in this band, instead of `clarity` and
+    /// `min_clarity_for_press`.
+    #[serde(default, skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub min_clarity: Option<f32>,
+    /// Window power a pitch needs in this band, instead of `power` (or the adaptive gate).
+    #[serde(default, skip_serializing_if = "Option::is_none", serialize_with = "short_f32_opt")]
+    pub min_power: Option<f32>,
+    /// This band's `hold_ms`, instead of the global one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hold_ms: Option<u64>,
+    /// This band's `repeat_ms`, instead of the global one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_ms: Option<u64>,
+    /// With `bpm`: this band's repeats per beat, instead of the global `repeat_division`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_division: Option<u32>,
+    /// `repeat = false` is short for `action = "one_shot"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<bool>,
+}
+
+/// A band's key: a name as [`parse_key`] reads it, or a raw scancode as
+/// [`scancode_key`] reads it. Physical keys and scancodes press the same key on any layout.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KeyConfig {
+    Name(String),
+    Scancode { scancode: u16 },
+}
+
+impl KeyConfig {
+    pub fn to_key(&self) -> Result<Key> {
+        match self {
+            KeyConfig::Name(name) => parse_config_key(name),
+            KeyConfig::Scancode { scancode } => scancode_key(*scancode).ok_or_else(|| {
+                PitchuError::InvalidConfig(format!("scancode {} can't be pressed on this platform", scancode))
+            }),
+        }
+    }
+}
+
+impl From<Key> for KeyConfig {
+    fn from(key: Key) -> Self {
+        match key_scancode(key) {
+            Some(scancode) if physical_name(key).is_none() => KeyConfig::Scancode { scancode },
+            _ => KeyConfig::Name(key_name(key)),
+        }
+    }
+}
+
+/// A band's `action`: a dead zone, or what its `key` does over a note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BandActionConfig {
+    Ignore,
+    ClickRepeat,
+    Hold,
+    OneShot,
+}
+
+impl BandActionConfig {
+    fn behavior(self) -> Option<KeyBehavior> {
+        match self {
+            BandActionConfig::Ignore => None,
+            BandActionConfig::ClickRepeat => Some(KeyBehavior::ClickRepeat),
+            BandActionConfig::Hold => Some(KeyBehavior::Hold),
+            BandActionConfig::OneShot => Some(KeyBehavior::OneShot),
+        }
+    }
+}
+
+/// What `pitchu init` writes: the built-in bands and the main settings, commented out at their
+/// defaults.
+pub const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
+/// The config file read when no `--config` is given: `pitchu/config.toml` in the platform's
+/// config directory (`~/.config` on Linux, `%APPDATA%` on Windows, `~/Library/Application
+/// Support` on macOS). `None` if the system has no home directory to put it under.
+pub fn default_config_path() -> Option<PathBuf> {
+    BaseDirs::new().map(|dirs| dirs.config_dir().join("pitchu").join("config.toml"))
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|source| PitchuError::ConfigRead { path: path.to_path_buf(), source })?;
+        let mut config: Config =
+            toml::from_str(&text).map_err(|source| PitchuError::ConfigParse { path: path.to_path_buf(), source })?;
+        if let Some(script) = config.script.as_mut()
+            && script.is_relative()
+            && let Some(dir) = path.parent()
+        {
+            *script = dir.join(&*script);
+        }
+        if let Some(a4) = config.a4
+            && !A4_RANGE_HZ.contains(&a4)
+        {
+            return Err(PitchuError::InvalidConfig(format!(
+                "a4 = {} Hz is outside {}-{} Hz",
+                a4,
+                A4_RANGE_HZ.start(),
+                A4_RANGE_HZ.end()
+            )));
+        }
+        for (name, level) in [("gate_attack", config.gate_attack), ("gate_release", config.gate_release)] {
+            if let Some(level) = level
+                && !(0.0..=1.0).contains(&level)
+            {
+                return Err(PitchuError::InvalidConfig(format!("{} = {} is outside 0.0-1.0", name, level)));
+            }
+        }
+        if let Some(bpm) = config.bpm
+            && bpm != 0.0
+            && !BPM_RANGE.contains(&bpm)
+        {
+            return Err(PitchuError::InvalidConfig(format!(
+                "bpm = {} is outside {}-{} (or 0 for off)",
+                bpm,
+                BPM_RANGE.start(),
+                BPM_RANGE.end()
+            )));
+        }
+        if let Some(division) = config.repeat_division
+            && !DIVISION_RANGE.contains(&division)
+        {
+            return Err(PitchuError::InvalidConfig(format!(
+                "repeat_division = {} is outside {}-{}",
+                division,
+                DIVISION_RANGE.start(),
+                DIVISION_RANGE.end()
+            )));
+        }
+        if let Some(cents) = config.vibrato_tolerance
+            && !(0.0..=MAX_VIBRATO_TOLERANCE_CENTS).contains(&cents)
+        {
+            return Err(PitchuError::InvalidConfig(format!(
+                "vibrato_tolerance = {} is outside 0-{} cents",
+                cents, MAX_VIBRATO_TOLERANCE_CENTS
+            )));
+        }
+        if let Some(octaves) = config.whistle_octaves
+            && !WHISTLE_OCTAVE_RANGE.contains(&octaves)
+        {
+            return Err(PitchuError::InvalidConfig(format!(
+                "whistle_octaves = {} is outside {}-{}",
+                octaves,
+                WHISTLE_OCTAVE_RANGE.start(),
+                WHISTLE_OCTAVE_RANGE.end()
+            )));
+        }
+        Ok(config)
+    }
+
+    /// Loads each file in turn and merges it over the ones before (see [`merge`](Self::merge)).
+    pub fn load_all(paths: &[PathBuf]) -> Result<Self> {
+        let mut merged = Config::default();
+        for path in paths {
+            merged.merge(Config::load(path)?);
+        }
+        Ok(merged)
+    }
+
+    /// Merges `later` over `self`, later wins:
+    ///
+    /// - Settings (`power`, `hold_ms`, ...), `script`, `relative`, `intervals` and `voices` are replaced
+    ///   when `later` sets them.
+    /// - `bands` (and each `[channelN]`'s) combine: `later`'s bands go first, so they win
+    ///   wherever they overlap an earlier band, and an earlier band with exactly the same
+    ///   `low_hz` and `high_hz` as a later one is dropped.
+    /// - `[gamepad.buttons]` entries are added, replacing the buttons of keys already listed.
+    /// - `[[scales]]`, `[[patterns]]` and `[[curves]]` are replaced when `later` lists any.
+    pub fn merge(&mut self, later: Config) {
+        let Config {
+            power, clarity, detector, min_clarity_for_press, low_clarity_behavior, gap_behavior, adaptive_gate,
+            adaptive_ratio, gate_attack, gate_release, normalize, octave_correct, octave_tolerance_cents, smoothing, ema_alpha,
+            median_window, vibrato_tolerance, mode, trigger, min_note_ms, switch_consensus, tap_hold_ms, hold_ms, repeat_ms,
+            repeat_accel, repeat_floor_ms, repeat_ramp_ms, bpm, repeat_division, grid_from, metronome, latch_debounce,
+            voice_hold_ms, warmup_ms, max_latency_ms, a4, whistle, whistle_octaves, layout, percussive_key, percussive_refractory_ms,
+            bands, scales, channel0, channel1, voices, relative, intervals, script, gamepad, patterns, curves,
+        } = later;
+        self.power = power.or(self.power);
+        self.clarity = clarity.or(self.clarity);
+        self.detector = detector.or(self.detector);
+        self.min_clarity_for_press = min_clarity_for_press.or(self.min_clarity_for_press);
+        self.low_clarity_behavior = low_clarity_behavior.or(self.low_clarity_behavior);
+        self.gap_behavior = gap_behavior.or(self.gap_behavior);
+        self.adaptive_gate = adaptive_gate.or(self.adaptive_gate);
+        self.adaptive_ratio = adaptive_ratio.or(self.adaptive_ratio);
+        self.gate_attack = gate_attack.or(self.gate_attack);
+        self.gate_release = gate_release.or(self.gate_release);
+        self.normalize = normalize.or(self.normalize);
+        self.octave_correct = octave_correct.or(self.octave_correct);
+        self.octave_tolerance_cents = octave_tolerance_cents.or(self.octave_tolerance_cents);
+        self.smoothing = smoothing.or(self.smoothing);
+        self.ema_alpha = ema_alpha.or(self.ema_alpha);
+        self.median_window = median_window.or(self.median_window);
+        self.vibrato_tolerance = vibrato_tolerance.or(self.vibrato_tolerance);
+        self.mode = mode.or(self.mode);
+        self.trigger = trigger.or(self.trigger);
+        self.min_note_ms = min_note_ms.or(self.min_note_ms);
+        self.switch_consensus = switch_consensus.or(self.switch_consensus);
+        self.tap_hold_ms = tap_hold_ms.or(self.tap_hold_ms);
+        self.hold_ms = hold_ms.or(self.hold_ms);
+        self.repeat_ms = repeat_ms.or(self.repeat_ms);
+        self.repeat_accel = repeat_accel.or(self.repeat_accel);
+        self.repeat_floor_ms = repeat_floor_ms.or(self.repeat_floor_ms);
+        self.repeat_ramp_ms = repeat_ramp_ms.or(self.repeat_ramp_ms);
+        self.bpm = bpm.or(self.bpm);
+        self.repeat_division = repeat_division.or(self.repeat_division);
+        self.grid_from = grid_from.or(self.grid_from);
+        self.metronome = metronome.or(self.metronome);
+        self.latch_debounce = latch_debounce.or(self.latch_debounce);
+        self.voice_hold_ms = voice_hold_ms.or(self.voice_hold_ms);
+        self.warmup_ms = warmup_ms.or(self.warmup_ms);
+        self.max_latency_ms = max_latency_ms.or(self.max_latency_ms);
+        self.a4 = a4.or(self.a4);
+        self.whistle = whistle.or(self.whistle);
+        self.whistle_octaves = whistle_octaves.or(self.whistle_octaves);
+        self.layout = layout.or(self.layout);
+        self.percussive_key = percussive_key.or(self.percussive_key.take());
+        self.percussive_refractory_ms = percussive_refractory_ms.or(self.percussive_refractory_ms);
+        self.bands = merge_bands(std::mem::take(&mut self.bands), bands);
+        if !scales.is_empty() {
+            self.scales = scales;
+        }
+        for (section, later) in [(&mut self.channel0, channel0), (&mut self.channel1, channel1)] {
+            if let Some(later) = later {
+                let earlier = section.take().unwrap_or_default();
+                *section = Some(ChannelConfig { bands: merge_bands(earlier.bands, later.bands) });
+            }
+        }
+        if !voices.is_empty() {
+            self.voices = voices;
+        }
+        self.relative = relative.or(self.relative.take());
+        self.intervals = intervals.or(self.intervals.take());
+        self.script = script.or(self.script.take());
+        if let Some(later) = gamepad {
+            let section = self.gamepad.get_or_insert_with(GamepadConfig::default);
+            section.buttons.extend(later.buttons);
+            if later.axis.is_some() {
+                section.axis = later.axis;
+            }
+        }
+        if !patterns.is_empty() {
+            self.patterns = patterns;
+        }
+        if !curves.is_empty() {
+            self.curves = curves;
+        }
+    }
+
+    /// The top-level mapping, with the `[[scales]]` placed for an A4 of `a4_hz`.
+    pub fn mapping(&self, a4_hz: f32) -> Result<Mapping> {
+        if self.scales.is_empty() {
+            return build_mapping(&self.bands);
+        }
+        let mut bands = if self.bands.is_empty() { Vec::new() } else { build_mapping(&self.bands)?.bands().to_vec() };
+        for scale in &self.scales {
+            bands.extend(scale_bands(scale, a4_hz)?);
+        }
+        Ok(Mapping::new(bands))
+    }
+
+    /// The mapping for input `channel` in dual-channel mode, falling back to the top-level one
+    /// when the channel has no section or its section lists no bands.
+    pub fn channel_mapping(&self, channel: usize, a4_hz: f32) -> Result<Mapping> {
+        let section = match channel {
+            0 => self.channel0.as_ref(),
+            1 => self.channel1.as_ref(),
+            _ => None,
+        };
+        match section {
+            Some(section) if !section.bands.is_empty() => build_mapping(&section.bands),
+            _ => self.mapping(a4_hz),
+        }
+    }
+
+    /// The `[[voices]]`, in priority order; empty if the file has none.
+    pub fn voices(&self) -> Result<Vec<Voice>> {
+        if !self.voices.is_empty() && self.relative.is_some() {
+            return Err(PitchuError::InvalidConfig("use either [[voices]] or [relative], not both".to_string()));
+        }
+        self.voices
+            .iter()
+            .enumerate()
+            .map(|(index, voice)| {
+                let name = voice.name.clone().unwrap_or_else(|| format!("voice {}", index + 1));
+                if voice.low_hz > voice.high_hz {
+                    return Err(PitchuError::InvalidConfig(format!(
+                        "{} has low_hz {} above high_hz {}",
+                        name, voice.low_hz, voice.high_hz
+                    )));
+                }
+                if voice.bands.is_empty() {
+                    return Err(PitchuError::InvalidConfig(format!("{} lists no bands", name)));
+                }
+                Ok(Voice { name, low_hz: voice.low_hz, high_hz: voice.high_hz, mapping: build_mapping(&voice.bands)? })
+            })
+            .collect()
+    }
+
+    /// The `[[patterns]]`, with their steps resolved against the bands of `mapping`.
+    pub fn patterns(&self, mapping: &Mapping) -> Result<Vec<Pattern>> {
+        self.patterns
+            .iter()
+            .enumerate()
+            .map(|(index, pattern)| {
+                let name = pattern.name.clone().unwrap_or_else(|| format!("pattern {}", index + 1));
+                if pattern.steps.len() < 2 {
+                    return Err(PitchuError::InvalidConfig(format!("{} needs at least two steps", name)));
+                }
+                if pattern.within_ms == 0 {
+                    return Err(PitchuError::InvalidConfig(format!("{} has within_ms = 0", name)));
+                }
+                let action = match (&pattern.key, &pattern.text) {
+                    (Some(key), None) => PatternAction::Key(parse_config_key(key)?),
+                    (None, Some(text)) if !text.is_empty() => PatternAction::Text(text.clone()),
+                    _ => {
+                        return Err(PitchuError::InvalidConfig(format!(
+                            "{} needs exactly one of key or a non-empty text",
+                            name
+                        )));
+                    }
+                };
+                let steps = pattern
+                    .steps
+                    .iter()
+                    .map(|step| match mapping.bands().iter().find(|band| band.name.as_deref() == Some(step.as_str())) {
+                        Some(band) => band.key().ok_or_else(|| {
+                            PitchuError::InvalidConfig(format!("{}: band '{}' presses no key", name, step))
+                        }),
+                        None => parse_key(step).ok_or_else(|| {
+                            PitchuError::InvalidConfig(format!("{}: step '{}' is neither a band name nor a key", name, step))
+                        }),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Pattern {
+                    name,
+                    steps,
+                    within: Duration::from_millis(pattern.within_ms),
+                    action,
+                    suppress: pattern.suppress.unwrap_or(false),
+                })
+            })
+            .collect()
+    }
+
+    /// The key percussive sounds press and their refractory period, if `percussive_key` is set.
+    pub fn percussive(&self) -> Result<Option<(Key, Duration)>> {
+        let Some(key) = &self.percussive_key else {
+            return Ok(None);
+        };
+        let refractory_ms = self.percussive_refractory_ms.unwrap_or(DEFAULT_REFRACTORY_MS);
+        Ok(Some((parse_config_key(key)?, Duration::from_millis(refractory_ms))))
+    }
+
+    /// The `[relative]` mapping, if the file has one.
+    pub fn relative_mapping(&self) -> Result<Option<RelativeMapping>> {
+        let Some(relative) = &self.relative else {
+            return Ok(None);
+        };
+        if relative.steps.is_empty() {
+            return Err(PitchuError::InvalidConfig("[relative] lists no steps".to_string()));
+        }
+        let tolerance_cents = relative.tolerance_cents.unwrap_or(DEFAULT_TOLERANCE_CENTS);
+        if !(tolerance_cents > 0.0 && tolerance_cents <= 600.0) {
+            return Err(PitchuError::InvalidConfig(format!(
+                "[relative] tolerance_cents {} must be above 0 and at most 600",
+                tolerance_cents
+            )));
+        }
+        let steps = relative
+            .steps
+            .iter()
+            .map(|step| Ok((step.semitones, parse_config_key(&step.key)?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(RelativeMapping { steps, tolerance_cents }))
+    }
+
+    /// The `[intervals]` mapping, if the file has one.
+    pub fn interval_mapping(&self) -> Result<Option<IntervalMapping>> {
+        let Some(intervals) = &self.intervals else {
+            return Ok(None);
+        };
+        if !self.voices.is_empty() || self.relative.is_some() {
+            return Err(PitchuError::InvalidConfig(
+                "[intervals] can't be combined with [[voices]] or [relative]".to_string(),
+            ));
+        }
+        if intervals.rules.is_empty() {
+            return Err(PitchuError::InvalidConfig("[intervals] lists no rules".to_string()));
+        }
+        let tolerance_cents = intervals.tolerance_cents.unwrap_or(DEFAULT_INTERVAL_TOLERANCE_CENTS);
+        if !(tolerance_cents > 0.0 && tolerance_cents < 100.0) {
+            return Err(PitchuError::InvalidConfig(format!(
+                "[intervals] tolerance_cents {} must be above 0 and below 100",
+                tolerance_cents
+            )));
+        }
+        let rules = intervals
+            .rules
+            .iter()
+            .map(|rule| {
+                let min_semitones = rule.min_semitones.unwrap_or(1.0);
+                if min_semitones < 0.0 || rule.max_semitones.is_some_and(|max| max < min_semitones) {
+                    return Err(PitchuError::InvalidConfig(format!(
+                        "[intervals] rule for '{}' needs 0 <= min_semitones <= max_semitones",
+                        rule.key
+                    )));
+                }
+                Ok(IntervalRule {
+                    direction: rule.direction,
+                    min_semitones,
+                    max_semitones: rule.max_semitones,
+                    key: parse_config_key(&rule.key)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(IntervalMapping { rules, tolerance_cents }))
+    }
+
+    /// The key-to-button table for `--output gamepad`: the defaults with the `[gamepad]`
+    /// section's entries applied.
+    pub fn button_map(&self) -> Result<ButtonMap> {
+        let mut map = ButtonMap::default();
+        for (key, button) in self.gamepad.iter().flat_map(|gamepad| &gamepad.buttons) {
+            let button = parse_button(button)
+                .ok_or_else(|| PitchuError::InvalidConfig(format!("unknown gamepad button '{}' for key '{}'", button, key)))?;
+            map.set(parse_config_key(key)?, button);
+        }
+        Ok(map)
+    }
+
+    /// The `[gamepad.axis]` section, if any, with its notes turned into frequencies.
+    pub fn axis_mapping(&self, a4_hz: f32) -> Result<Option<AxisMapping>> {
+        let Some(axis) = self.gamepad.as_ref().and_then(|gamepad| gamepad.axis.as_ref()) else {
+            return Ok(None);
+        };
+        let end = |hz: Option<f32>, note: &Option<String>, name: &str| -> Result<f32> {
+            match (hz, note) {
+                (Some(hz), None) => Ok(hz),
+                (None, Some(note)) => parse_note_name(note)
+                    .map(|midi| midi_to_frequency(midi as f32, a4_hz))
+                    .ok_or_else(|| PitchuError::InvalidConfig(format!("[gamepad.axis] {}_note '{}' is not a note such as C4 or F#3", name, note))),
+                (Some(_), Some(_)) => {
+                    Err(PitchuError::InvalidConfig(format!("[gamepad.axis] sets both {0}_hz and {0}_note; pick one", name)))
+                }
+                (None, None) => Err(PitchuError::InvalidConfig(format!("[gamepad.axis] needs {0}_hz or {0}_note", name))),
+            }
+        };
+        let low_hz = end(axis.low_hz, &axis.low_note, "low")?;
+        let high_hz = end(axis.high_hz, &axis.high_note, "high")?;
+        if !(low_hz > 0.0 && low_hz < high_hz) {
+            return Err(PitchuError::InvalidConfig(format!(
+                "[gamepad.axis] runs from {:.1} to {:.1} Hz; the low end must be above 0 and below the high end",
+                low_hz, high_hz
+            )));
+        }
+        let center = axis.center.unwrap_or(0.0);
+        if !(-1.0..=1.0).contains(&center) {
+            return Err(PitchuError::InvalidConfig(format!("[gamepad.axis] center = {} is outside -1.0-1.0", center)));
+        }
+        Ok(Some(AxisMapping {
+            axis: axis.axis,
+            low_hz,
+            high_hz,
+            scale: axis.scale.unwrap_or_default(),
+            center,
+            decay: Duration::from_millis(axis.decay_ms.unwrap_or(0)),
+        }))
+    }
+
+    /// The `[[curves]]`, checked.
+    pub fn curves(&self) -> Result<Vec<Curve>> {
+        self.curves
+            .iter()
+            .map(|curve| {
+                if !(curve.freq_min > 0.0 && curve.freq_min < curve.freq_max) {
+                    return Err(PitchuError::InvalidConfig(format!(
+                        "[[curves]] for {} runs from {} to {} Hz; freq_min must be above 0 and below freq_max",
+                        curve_output_name(curve.output),
+                        curve.freq_min,
+                        curve.freq_max
+                    )));
+                }
+                if curve.output.stick().is_some()
+                    && ![curve.out_min, curve.out_max].iter().all(|value| (-1.0..=1.0).contains(value))
+                {
+                    return Err(PitchuError::InvalidConfig(format!(
+                        "[[curves]] for {} needs out_min and out_max within -1.0-1.0",
+                        curve_output_name(curve.output)
+                    )));
+                }
+                Ok(Curve {
+                    output: curve.output,
+                    shape: curve.curve.unwrap_or_default(),
+                    freq_min: curve.freq_min,
+                    freq_max: curve.freq_max,
+                    out_min: curve.out_min,
+                    out_max: curve.out_max,
+                })
+            })
+            .collect()
+    }
+
+    /// True if the file lists top-level bands or scales.
+    pub fn has_bands(&self) -> bool {
+        !self.bands.is_empty() || !self.scales.is_empty()
+    }
+
+    /// True if `channel` has bands of its own.
+    pub fn has_channel_bands(&self, channel: usize) -> bool {
+        let section = match channel {
+            0 => self.channel0.as_ref(),
+            1 => self.channel1.as_ref(),
+            _ => None,
+        };
+        section.is_some_and(|section| !section.bands.is_empty())
+    }
+}
+
+impl From<&Band> for BandConfig {
+    fn from(band: &Band) -> Self {
+        let behavior = match band.timing.behavior {
+            KeyBehavior::ClickRepeat => None,
+            KeyBehavior::Hold => Some(BandActionConfig::Hold),
+            KeyBehavior::OneShot => Some(BandActionConfig::OneShot),
+        };
+        let (key, text, action) = match &band.action {
+            BandAction::Key(key) => (Some(KeyConfig::from(*key)), None, behavior),
+            BandAction::Text(text) => (None, Some(text.clone()), None),
+            BandAction::Ignore => (None, None, Some(BandActionConfig::Ignore)),
+            BandAction::TapHold { .. } => (None, None, None),
+        };
+        let (tap_key, hold_key) = match band.action {
+            BandAction::TapHold { tap, hold } => (Some(KeyConfig::from(tap)), Some(KeyConfig::from(hold))),
+            _ => (None, None),
+        };
+        BandConfig {
+            low_hz: band.low_hz,
+            high_hz: band.high_hz,
+            name: band.name.clone(),
+            key,
+            text,
+            action,
+            tap_key,
+            hold_key,
+            min_clarity: band.min_clarity,
+            min_power: band.min_power,
+            hold_ms: band.timing.hold_threshold.map(|hold| hold.as_millis() as u64),
+            repeat_ms: band.timing.repeat_interval.map(|interval| interval.as_millis() as u64),
+            repeat_division: band.timing.repeat_division,
+            repeat: None,
+        }
+    }
+}
+
+/// The bands of `mapping` as they would be written in a config file.
+pub fn band_configs(mapping: &Mapping) -> Vec<BandConfig> {
+    mapping.bands().iter().map(BandConfig::from).collect()
+}
+
+// f32 values are written as their shortest decimal form (0.2, not 0.20000000298023224)
+fn short_f32<S: Serializer>(value: &f32, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(value.to_string().parse().unwrap_or(*value as f64))
+}
+
+fn short_f32_opt<S: Serializer>(value: &Option<f32>, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => short_f32(value, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn parse_config_key(name: &str) -> Result<Key> {
+    parse_key(name).ok_or_else(|| {
+        PitchuError::InvalidConfig(format!(
+            "unknown key '{}': a key is a single character (\"z\"), a named key (\"DownArrow\"), a physical \
+             key (\"KeyZ\") or a scancode (\"scancode:44\", or {{ scancode = 44 }} for a band)",
+            name
+        ))
+    })
+}
+
+// A curve's output as the config file writes it
+fn curve_output_name(output: CurveOutput) -> String {
+    toml::Value::try_from(output).ok().and_then(|value| value.as_str().map(str::to_string)).unwrap_or_default()
+}
+
+// How errors name a band: by its key, and how that key was read, or by its label
+fn band_subject(band: &Band) -> String {
+    match band.key() {
+        Some(key) if matches!(band.action, BandAction::Key(_)) => format!("'{}' ({})", key_name(key), key_kind(key)),
+        _ => format!("'{}'", band.label()),
+    }
+}
+
+fn scale_bands(scale: &ScaleConfig, a4_hz: f32) -> Result<Vec<Band>> {
+    let start = parse_note_name(&scale.start).ok_or_else(|| {
+        PitchuError::InvalidConfig(format!("[[scales]] start '{}' is not a note such as C4 or F#3", scale.start))
+    })?;
+    let semitones: Vec<f32> = match (&scale.semitones, scale.scale) {
+        (Some(_), Some(_)) => {
+            return Err(PitchuError::InvalidConfig(format!(
+                "the scale from {} has both scale and semitones; use one",
+                scale.start
+            )));
+        }
+        (Some(semitones), None) if semitones.len() != scale.keys.len() => {
+            return Err(PitchuError::InvalidConfig(format!(
+                "the scale from {} lists {} semitones for {} keys",
+                scale.start,
+                semitones.len(),
+                scale.keys.len()
+            )));
+        }
+        (Some(semitones), None) => semitones.clone(),
+        (None, kind) => {
+            let kind = kind.unwrap_or(Scale::Chromatic);
+            kind.semitones(scale.keys.len()).into_iter().map(|n| n as f32).collect()
+        }
+    };
+    let tolerance_cents = scale.tolerance_cents.unwrap_or(DEFAULT_SCALE_TOLERANCE_CENTS);
+    if !(tolerance_cents > 0.0 && tolerance_cents <= 600.0) {
+        return Err(PitchuError::InvalidConfig(format!(
+            "the scale from {} has tolerance_cents {}; it must be above 0 and at most 600",
+            scale.start, tolerance_cents
+        )));
+    }
+    let mut sorted = semitones.clone();
+    sorted.sort_by(f32::total_cmp);
+    if sorted.windows(2).any(|pair| (pair[1] - pair[0]) * 100.0 < 2.0 * tolerance_cents - 0.001) {
+        return Err(PitchuError::InvalidConfig(format!(
+            "the scale from {} has notes closer than twice its tolerance_cents ({}), so their bands overlap",
+            scale.start, tolerance_cents
+        )));
+    }
+    scale
+        .keys
+        .iter()
+        .zip(semitones)
+        .map(|(key, semitones)| {
+            let midi = start as f32 + semitones;
+            let (low_hz, high_hz) = note_window(midi_to_frequency(midi, a4_hz), tolerance_cents);
+            let name = (midi.fract() == 0.0).then(|| note_name(midi as i32));
+            Ok(Band { name, ..Band::new(low_hz, high_hz, parse_config_key(key)?) })
+        })
+        .collect()
+}
+
+// `later` first, then the `earlier` bands whose range `later` doesn't repeat
+fn merge_bands(earlier: Vec<BandConfig>, mut later: Vec<BandConfig>) -> Vec<BandConfig> {
+    let replaced = |band: &BandConfig| later.iter().any(|l| l.low_hz == band.low_hz && l.high_hz == band.high_hz);
+    let kept: Vec<_> = earlier.into_iter().filter(|band| !replaced(band)).collect();
+    later.extend(kept);
+    later
+}
+
+fn build_mapping(bands: &[BandConfig]) -> Result<Mapping> {
+    if bands.is_empty() {
+        return Ok(Mapping::default());
+    }
+    let bands = bands
+        .iter()
+        .map(|band| {
+            let action = match (&band.key, &band.text, band.action, &band.tap_key, &band.hold_key) {
+                (None, None, None, Some(tap), Some(hold)) => {
+                    BandAction::TapHold { tap: tap.to_key()?, hold: hold.to_key()? }
+                }
+                (_, _, _, Some(_), None) | (_, _, _, None, Some(_)) => {
+                    return Err(PitchuError::InvalidConfig(format!(
+                        "band {}-{} Hz needs both tap_key and hold_key",
+                        band.low_hz, band.high_hz
+                    )));
+                }
+                (_, _, _, Some(_), Some(_)) => {
+                    return Err(PitchuError::InvalidConfig(format!(
+                        "band {}-{} Hz has tap_key and hold_key, so it can't also have key, text or action",
+                        band.low_hz, band.high_hz
+                    )));
+                }
+                (Some(key), None, None, ..) => BandAction::Key(key.to_key()?),
+                (Some(key), None, Some(action), ..) if action.behavior().is_some() => BandAction::Key(key.to_key()?),
+                (None, Some(text), None, ..) if !text.is_empty() => BandAction::Text(text.clone()),
+                (None, None, Some(BandActionConfig::Ignore), ..) => BandAction::Ignore,
+                (None, Some(_), None, ..) => {
+                    return Err(PitchuError::InvalidConfig(format!(
+                        "band {}-{} Hz has an empty text",
+                        band.low_hz, band.high_hz
+                    )));
+                }
+                (None, None, Some(action), ..) if action.behavior().is_some() => {
+                    return Err(PitchuError::InvalidConfig(format!(
+                        "band {}-{} Hz has an action for its key, but no key",
+                        band.low_hz, band.high_hz
+                    )));
+                }
+                _ => {
+                    return Err(PitchuError::InvalidConfig(format!(
+                        "band {}-{} Hz needs exactly one of key, text, tap_key/hold_key or action = \"ignore\"",
+                        band.low_hz, band.high_hz
+                    )));
+                }
+            };
+            let behavior = match (band.action.and_then(BandActionConfig::behavior), band.repeat) {
+                (Some(KeyBehavior::ClickRepeat | KeyBehavior::Hold), Some(false)) => {
+                    return Err(PitchuError::InvalidConfig(format!(
+                        "band {}-{} Hz has repeat = false, which only goes with action = \"one_shot\"",
+                        band.low_hz, band.high_hz
+                    )));
+                }
+                (Some(behavior), _) => behavior,
+                (None, Some(false)) => KeyBehavior::OneShot,
+                (None, _) => KeyBehavior::ClickRepeat,
+            };
+            let band = Band {
+                low_hz: band.low_hz,
+                high_hz: band.high_hz,
+                action,
+                name: band.name.clone(),
+                min_clarity: band.min_clarity,
+                min_power: band.min_power,
+                timing: BandTiming {
+                    hold_threshold: band.hold_ms.map(Duration::from_millis),
+                    repeat_interval: band.repeat_ms.map(Duration::from_millis),
+                    repeat_division: band.repeat_division,
+                    behavior,
+                },
+            };
+            if band.timing.repeat_interval == Some(Duration::ZERO) {
+                return Err(PitchuError::InvalidConfig(format!("band for {} has repeat_ms = 0", band_subject(&band))));
+            }
+            if let Some(division) = band.timing.repeat_division
+                && !DIVISION_RANGE.contains(&division)
+            {
+                return Err(PitchuError::InvalidConfig(format!(
+                    "band for {} has repeat_division = {}, outside {}-{}",
+                    band_subject(&band),
+                    division,
+                    DIVISION_RANGE.start(),
+                    DIVISION_RANGE.end()
+                )));
+            }
+            if band.low_hz > band.high_hz {
+                return Err(PitchuError::InvalidConfig(format!(
+                    "band for {} has low_hz {} above high_hz {}",
+                    band_subject(&band),
+                    band.low_hz,
+                    band.high_hz
+                )));
+            }
+            if let Some(clarity) = band.min_clarity
+                && !(0.0..=1.0).contains(&clarity)
+            {
+                return Err(PitchuError::InvalidConfig(format!(
+                    "band for {} has min_clarity {} outside 0.0-1.0",
+                    band_subject(&band),
+                    clarity
+                )));
+            }
+            if let Some(power) = band.min_power
+                && power < 0.0
+            {
+                return Err(PitchuError::InvalidConfig(format!(
+                    "band for {} has a negative min_power {}",
+                    band_subject(&band),
+                    power
+                )));
+            }
+            Ok(band)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Mapping::new(bands))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::DEFAULT_A4_HZ;
+
+    fn parse(text: &str) -> Config {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn the_init_template_is_the_built_in_mapping() {
+        let config = parse(DEFAULT_CONFIG);
+        assert_eq!(config.mapping(DEFAULT_A4_HZ).unwrap().bands(), Mapping::default().bands());
+        assert_eq!(config.power, None);
+        assert!(default_config_path().is_none_or(|path| path.ends_with("pitchu/config.toml")));
+    }
+
+    #[test]
+    fn later_settings_win_and_unset_ones_are_kept() {
+        let mut config = parse("power = 0.5\nhold_ms = 300");
+        config.merge(parse("hold_ms = 400\nrepeat_ms = 80"));
+        assert_eq!((config.power, config.hold_ms, config.repeat_ms), (Some(0.5), Some(400), Some(80)));
+    }
+
+    #[test]
+    fn later_bands_go_first_and_replace_identical_ranges() {
+        let mut config = parse(
+            r#"bands = [
+                { low_hz = 100.0, high_hz = 150.0, key = "a" },
+                { low_hz = 150.1, high_hz = 200.0, key = "b" },
+            ]"#,
+        );
+        config.merge(parse(
+            r#"bands = [
+                { low_hz = 150.1, high_hz = 200.0, key = "c" },
+                { low_hz = 120.0, high_hz = 130.0, key = "d" },
+            ]"#,
+        ));
+        let mapping = config.mapping(DEFAULT_A4_HZ).unwrap();
+        let labels: Vec<String> = mapping.bands().iter().map(Band::label).collect();
+        assert_eq!(labels, ["c", "d", "a"]);
+        // The later band wins inside the earlier one
+        assert_eq!(mapping.key_for(125.0), Some(Key::Layout('d')));
+        assert_eq!(mapping.key_for(110.0), Some(Key::Layout('a')));
+    }
+
+    #[test]
+    fn band_keys_can_be_physical_keys_or_scancodes() {
+        let config = parse(
+            r#"bands = [
+                { low_hz = 100.0, high_hz = 150.0, key = "KeyZ" },
+                { low_hz = 150.1, high_hz = 200.0, key = { scancode = 45 } },
+                { low_hz = 200.1, high_hz = 250.0, key = "z", repeat_ms = 0 },
+            ]"#,
+        );
+        let error = config.mapping(DEFAULT_A4_HZ).unwrap_err().to_string();
+        assert!(error.contains("'z' (character)"), "{}", error);
+
+        let config = parse(r#"bands = [{ low_hz = 100.0, high_hz = 150.0, key = "Kez" }]"#);
+        let error = config.mapping(DEFAULT_A4_HZ).unwrap_err().to_string();
+        assert!(error.contains("physical key"), "{}", error);
+
+        if cfg!(target_os = "linux") {
+            let config = parse(r#"bands = [{ low_hz = 100.0, high_hz = 150.0, key = { scancode = 44 } }]"#);
+            let mapping = config.mapping(DEFAULT_A4_HZ).unwrap();
+            assert_eq!(mapping.key_for(120.0), parse_key("KeyZ"));
+            // Written back by name
+            assert_eq!(band_configs(&mapping)[0].key, Some(KeyConfig::Name("KeyZ".to_string())));
+        }
+    }
+
+    #[test]
+    fn band_actions_choose_what_the_key_does() {
+        let config = parse(
+            r#"bands = [
+                { low_hz = 100.0, high_hz = 150.0, key = "a", action = "hold" },
+                { low_hz = 150.1, high_hz = 200.0, key = "b", action = "one_shot" },
+                { low_hz = 200.1, high_hz = 250.0, key = "c", repeat = false },
+                { low_hz = 250.1, high_hz = 300.0, key = "d", action = "click_repeat" },
+            ]"#,
+        );
+        let mapping = config.mapping(DEFAULT_A4_HZ).unwrap();
+        let behaviors: Vec<_> = mapping.bands().iter().map(|band| band.timing.behavior).collect();
+        assert_eq!(behaviors, [KeyBehavior::Hold, KeyBehavior::OneShot, KeyBehavior::OneShot, KeyBehavior::ClickRepeat]);
+        // Written back as actions
+        let written: Vec<_> = band_configs(&mapping).iter().map(|band| band.action).collect();
+        assert_eq!(written, [Some(BandActionConfig::Hold), Some(BandActionConfig::OneShot), Some(BandActionConfig::OneShot), None]);
+
+        for bands in [
+            r#"{ low_hz = 100.0, high_hz = 150.0, action = "hold" }"#,
+            r#"{ low_hz = 100.0, high_hz = 150.0, key = "a", action = "hold", repeat = false }"#,
+            r#"{ low_hz = 100.0, high_hz = 150.0, text = "gg", action = "one_shot" }"#,
+        ] {
+            assert!(parse(&format!("bands = [{}]", bands)).mapping(DEFAULT_A4_HZ).is_err(), "{}", bands);
+        }
+    }
+
+    #[test]
+    fn curves_are_checked_against_their_output() {
+        let config = parse("[[curves]]\noutput = \"mouse-x\"\ncurve = \"log\"\nfreq_min = 110.0\nfreq_max = 440.0\nout_min = -300.0\nout_max = 300.0\n");
+        let curves = config.curves().unwrap();
+        assert_eq!((curves[0].output, curves[0].shape), (CurveOutput::MouseX, CurveShape::Log));
+        // A stick only goes from -1.0 to 1.0
+        let stick = parse("[[curves]]\noutput = \"left-x\"\nfreq_min = 110.0\nfreq_max = 440.0\nout_min = 0.0\nout_max = 300.0\n");
+        let error = stick.curves().unwrap_err().to_string();
+        assert!(error.contains("left-x"), "{}", error);
+        let backwards = parse("[[curves]]\noutput = \"mouse-y\"\nfreq_min = 440.0\nfreq_max = 110.0\nout_min = 0.0\nout_max = 1.0\n");
+        assert!(backwards.curves().is_err());
+    }
+
+    #[test]
+    fn the_axis_range_takes_hz_or_notes() {
+        let config = parse("[gamepad.axis]\naxis = \"LeftX\"\nlow_note = \"A2\"\nhigh_hz = 440.0\ndecay_ms = 150\n");
+        let axis = config.axis_mapping(DEFAULT_A4_HZ).unwrap().unwrap();
+        assert_eq!((axis.axis, axis.low_hz, axis.high_hz), (StickAxis::LeftX, 110.0, 440.0));
+        assert_eq!((axis.scale, axis.center, axis.decay), (AxisScale::Cents, 0.0, Duration::from_millis(150)));
+
+        let mut later = config.clone();
+        later.merge(parse("[gamepad.buttons]\nx = \"B\"\n"));
+        assert!(later.axis_mapping(DEFAULT_A4_HZ).unwrap().is_some());
+
+        let backwards = parse("[gamepad.axis]\naxis = \"RightY\"\nlow_hz = 400.0\nhigh_hz = 200.0\n");
+        assert!(backwards.axis_mapping(DEFAULT_A4_HZ).is_err());
+        let open = parse("[gamepad.axis]\naxis = \"RightY\"\nlow_hz = 100.0\n");
+        let error = open.axis_mapping(DEFAULT_A4_HZ).unwrap_err().to_string();
+        assert!(error.contains("high_hz or high_note"), "{}", error);
+        assert!(parse("").axis_mapping(DEFAULT_A4_HZ).unwrap().is_none());
+    }
+
+    #[test]
+    fn pattern_steps_name_bands_or_keys() {
+        let config = parse(
+            r#"bands = [{ low_hz = 100.0, high_hz = 150.0, key = "a", name = "low" }]
+
+            [[patterns]]
+            steps = ["low", "DownArrow"]
+            within_ms = 1000
+            key = "Return""#,
+        );
+        let patterns = config.patterns(&config.mapping(DEFAULT_A4_HZ).unwrap()).unwrap();
+        assert_eq!(patterns[0].steps, [Key::Layout('a'), Key::DownArrow]);
+        assert_eq!(patterns[0].name, "pattern 1");
+
+        let unknown = parse("[[patterns]]\nsteps = [\"low\", \"nowhere\"]\nwithin_ms = 1000\nkey = \"a\"");
+        assert!(matches!(unknown.patterns(&Mapping::default()), Err(PitchuError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn scales_expand_into_named_bands_after_the_explicit_ones() {
+        let config = parse(
+            r#"bands = [{ low_hz = 100.0, high_hz = 110.0, key = "Space" }]
+
+            [[scales]]
+            start = "C4"
+            scale = "major"
+            keys = ["a", "s", "d", "f", "g", "h", "j", "k"]"#,
+        );
+        let mapping = config.mapping(DEFAULT_A4_HZ).unwrap();
+        let names: Vec<_> = mapping.bands().iter().map(|band| band.name.as_deref().unwrap_or("-")).collect();
+        assert_eq!(names, ["-", "C4", "D4", "E4", "F4", "G4", "A4", "B4", "C5"]);
+        assert_eq!(mapping.key_for(440.0), Some(Key::Layout('h')));
+        assert_eq!(mapping.key_for(523.25), Some(Key::Layout('k')));
+        // C#4 falls between the C4 and D4 bands
+        assert_eq!(mapping.key_for(277.18), None);
+
+        let crowded = parse("[[scales]]\nstart = \"C4\"\nsemitones = [0, 0.5]\nkeys = [\"a\", \"s\"]");
+        assert!(matches!(crowded.mapping(DEFAULT_A4_HZ), Err(PitchuError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn interval_rules_default_to_any_jump_of_a_semitone_or_more() {
+        let config = parse(
+            r#"[intervals]
+            rules = [
+                { direction = "up", min_semitones = 5, key = "a" },
+                { direction = "down", key = "z" },
+            ]"#,
+        );
+        let intervals = config.interval_mapping().unwrap().unwrap();
+        assert_eq!(intervals.tolerance_cents, DEFAULT_INTERVAL_TOLERANCE_CENTS);
+        assert_eq!(intervals.rules[1].min_semitones, 1.0);
+        assert_eq!(intervals.key_for(-12.0), Some(Key::Layout('z')));
+
+        let backwards = parse("[intervals]\nrules = [{ direction = \"up\", min_semitones = 7, max_semitones = 5, key = \"a\" }]");
+        assert!(matches!(backwards.interval_mapping(), Err(PitchuError::InvalidConfig(_))));
+    }
+}
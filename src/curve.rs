@@ -0,0 +1,186 @@
+//! `[[curves]]`: pitch driving a continuous control through a function rather than bands,
+//! e.g. the mouse pointer moving faster the higher the note. Each curve maps a frequency range
+//! onto an output range, evenly in Hz or in cents, and pitches beyond stop at the ends.
+
+use crate::axis::StickAxis;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+// Smallest change of a stick value worth sending
+const STICK_STEP: f32 = 0.001;
+
+/// What a curve drives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CurveOutput {
+    /// The mouse pointer's speed to the right (or left, below zero), in pixels per second.
+    MouseX,
+    /// The mouse pointer's speed down (or up, below zero), in pixels per second.
+    MouseY,
+    /// A stick axis of the virtual controller, from -1.0 to 1.0.
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+}
+
+impl CurveOutput {
+    /// The stick axis, for the controller outputs.
+    pub fn stick(self) -> Option<StickAxis> {
+        match self {
+            CurveOutput::MouseX | CurveOutput::MouseY => None,
+            CurveOutput::LeftX => Some(StickAxis::LeftX),
+            CurveOutput::LeftY => Some(StickAxis::LeftY),
+            CurveOutput::RightX => Some(StickAxis::RightX),
+            CurveOutput::RightY => Some(StickAxis::RightY),
+        }
+    }
+}
+
+/// How a curve spreads its frequency range over its output range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CurveShape {
+    /// Evenly in Hz.
+    #[default]
+    Linear,
+    /// Evenly in cents, so every semitone moves the output as far.
+    Log,
+}
+
+/// One frequency-to-value function: `freq_min` gives `out_min` and `freq_max` gives `out_max`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    pub output: CurveOutput,
+    pub shape: CurveShape,
+    pub freq_min: f32,
+    pub freq_max: f32,
+    pub out_min: f32,
+    pub out_max: f32,
+}
+
+impl Curve {
+    /// The output value for `freq`.
+    pub fn value(&self, freq: f32) -> f32 {
+        let position = match self.shape {
+            CurveShape::Linear => (freq - self.freq_min) / (self.freq_max - self.freq_min),
+            CurveShape::Log => (freq / self.freq_min).ln() / (self.freq_max / self.freq_min).ln(),
+        };
+        self.out_min + (self.out_max - self.out_min) * position.clamp(0.0, 1.0)
+    }
+}
+
+/// A change a window's pitch makes to the curves' outputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurveMove {
+    /// Move the mouse pointer by this many pixels.
+    Mouse { dx: i32, dy: i32 },
+    /// Move a stick axis to this value.
+    Stick(StickAxis, f32),
+}
+
+/// Evaluates the curves window by window. Mouse speeds are turned into the pixels to move
+/// since the last window, keeping the fractions for the next; sticks only move when their
+/// value changes. Without a pitch the mouse stops and the sticks go back to the center.
+pub struct CurveFollower {
+    curves: Vec<Curve>,
+    last_window: Option<Instant>,
+    // Fractions of a pixel left over, in x and y
+    carry: (f32, f32),
+    // The last value sent for each curve, for the sticks
+    sent: Vec<Option<f32>>,
+}
+
+impl CurveFollower {
+    pub fn new(curves: Vec<Curve>) -> Self {
+        let sent = vec![None; curves.len()];
+        CurveFollower { curves, last_window: None, carry: (0.0, 0.0), sent }
+    }
+
+    pub fn curves(&self) -> &[Curve] {
+        &self.curves
+    }
+
+    /// Feeds one window's (smoothed) pitch, if any, at `now`.
+    pub fn update(&mut self, freq: Option<f32>, now: Instant) -> Vec<CurveMove> {
+        let elapsed = self.last_window.map_or(0.0, |last| now.saturating_duration_since(last).as_secs_f32());
+        self.last_window = Some(now);
+        let mut moves = Vec::new();
+        let (mut speed_x, mut speed_y) = (0.0, 0.0);
+        for (curve, sent) in self.curves.iter().zip(&mut self.sent) {
+            let value = freq.map(|freq| curve.value(freq));
+            match curve.output.stick() {
+                Some(axis) => {
+                    let value = value.unwrap_or(0.0);
+                    if sent.is_none_or(|sent| (sent - value).abs() >= STICK_STEP || (value == 0.0 && sent != 0.0)) {
+                        *sent = Some(value);
+                        moves.push(CurveMove::Stick(axis, value));
+                    }
+                }
+                None if curve.output == CurveOutput::MouseX => speed_x += value.unwrap_or(0.0),
+                None => speed_y += value.unwrap_or(0.0),
+            }
+        }
+        if freq.is_none() {
+            self.carry = (0.0, 0.0);
+            return moves;
+        }
+        let x = self.carry.0 + speed_x * elapsed;
+        let y = self.carry.1 + speed_y * elapsed;
+        let (dx, dy) = (x.trunc(), y.trunc());
+        self.carry = (x - dx, y - dy);
+        if dx != 0.0 || dy != 0.0 {
+            moves.push(CurveMove::Mouse { dx: dx as i32, dy: dy as i32 });
+        }
+        moves
+    }
+
+    /// Stops the mouse and forgets what was sent, so the sticks are sent again.
+    pub fn reset(&mut self) {
+        self.last_window = None;
+        self.carry = (0.0, 0.0);
+        self.sent.iter_mut().for_each(|sent| *sent = None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn curve(output: CurveOutput, shape: CurveShape, out_min: f32, out_max: f32) -> Curve {
+        Curve { output, shape, freq_min: 110.0, freq_max: 440.0, out_min, out_max }
+    }
+
+    #[test]
+    fn curves_spread_the_range_in_hz_or_cents() {
+        let linear = curve(CurveOutput::MouseX, CurveShape::Linear, -300.0, 300.0);
+        assert_eq!(linear.value(110.0), -300.0);
+        assert_eq!(linear.value(440.0), 300.0);
+        // A third of the way in Hz, halfway in cents
+        assert!((linear.value(220.0) + 100.0).abs() < 1e-3);
+        let log = curve(CurveOutput::MouseX, CurveShape::Log, -300.0, 300.0);
+        assert!(log.value(220.0).abs() < 1e-3);
+        // Beyond the range, the output stays at the end
+        assert_eq!(log.value(50.0), -300.0);
+        assert_eq!(log.value(2000.0), 300.0);
+    }
+
+    #[test]
+    fn mouse_speed_becomes_whole_pixels_per_window() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut follower = CurveFollower::new(vec![
+            // 440 Hz moves right at 100 pixels per second
+            curve(CurveOutput::MouseX, CurveShape::Linear, 0.0, 100.0),
+            curve(CurveOutput::LeftY, CurveShape::Linear, -1.0, 1.0),
+        ]);
+        assert_eq!(follower.update(Some(440.0), at(0)), vec![CurveMove::Stick(StickAxis::LeftY, 1.0)]);
+        // 25ms at 100 px/s is 2.5 pixels: 2 now, and the half carried over to the next window
+        assert_eq!(follower.update(Some(440.0), at(25)), vec![CurveMove::Mouse { dx: 2, dy: 0 }]);
+        assert_eq!(follower.update(Some(440.0), at(50)), vec![CurveMove::Mouse { dx: 3, dy: 0 }]);
+        // Losing the pitch stops the pointer and centers the stick
+        assert_eq!(follower.update(None, at(75)), vec![CurveMove::Stick(StickAxis::LeftY, 0.0)]);
+        assert_eq!(follower.update(None, at(100)), vec![]);
+    }
+}
@@ -0,0 +1,204 @@
+use log::{debug, info, warn};
+use pitchu::keys::{key_name, KeyEvent};
+use pitchu::mapping::Mapping;
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The whole page, served at `/`
+const PAGE: &str = include_str!("dashboard.html");
+// Events buffered for the server thread before new ones are dropped
+const EVENT_QUEUE_DEPTH: usize = 256;
+// How often the server thread checks for new connections when no events arrive
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// How long a browser may take to send its request, and to take a message
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_millis(50);
+// Comment lines sent to idle event streams, so closed tabs are noticed and proxies stay open
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// One message on the dashboard's `/events` stream.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DashboardEvent {
+    /// The bands the chart draws behind the pitch; sent again whenever the mapping changes.
+    Bands { bands: Vec<DashboardBand> },
+    Pitch { freq: f32, clarity: f32, key: Option<String> },
+    Key { action: &'static str, key: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DashboardBand {
+    low_hz: f32,
+    high_hz: f32,
+    label: String,
+}
+
+impl DashboardEvent {
+    pub fn bands(mapping: &Mapping) -> Self {
+        let bands = mapping
+            .bands()
+            .iter()
+            .map(|band| DashboardBand { low_hz: band.low_hz, high_hz: band.high_hz, label: band.label() })
+            .collect();
+        DashboardEvent::Bands { bands }
+    }
+
+    pub fn key(event: KeyEvent) -> Self {
+        let (action, key) = match event {
+            KeyEvent::Press(key) => ("press", key),
+            KeyEvent::Repeat(key) => ("repeat", key),
+            KeyEvent::Release(key) => ("release", key),
+            KeyEvent::Down(key) => ("down", key),
+            KeyEvent::Up(key) => ("up", key),
+        };
+        DashboardEvent::Key { action, key: key_name(key) }
+    }
+}
+
+/// Handle used by the processing loop to publish events to `--http`. Never blocks: the page
+/// and every open tab are served from the dashboard's own thread.
+pub struct Dashboard {
+    sender: SyncSender<DashboardEvent>,
+}
+
+impl Dashboard {
+    /// Binds `address` and starts serving the page and its event stream, charting `mapping`.
+    pub fn start(address: SocketAddr, mapping: &Mapping) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        info!("Dashboard: serving http://{}", listener.local_addr()?);
+        if !address.ip().is_loopback() {
+            warn!("Dashboard: anyone who can reach {} can watch what you sing and press.", address);
+        }
+
+        let (sender, receiver) = mpsc::sync_channel(EVENT_QUEUE_DEPTH);
+        let bands = DashboardEvent::bands(mapping);
+        thread::spawn(move || serve_loop(listener, receiver, bands));
+        Ok(Dashboard { sender })
+    }
+
+    pub fn publish(&self, event: DashboardEvent) {
+        match self.sender.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => debug!("Dashboard: event queue full, dropping event."),
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+// An open `/events` stream
+struct Client {
+    stream: TcpStream,
+    addr: SocketAddr,
+    last_write: Instant,
+}
+
+impl Client {
+    fn send(&mut self, chunk: &str) -> bool {
+        match self.stream.write_all(chunk.as_bytes()) {
+            Ok(()) => {
+                self.last_write = Instant::now();
+                true
+            }
+            Err(e) => {
+                info!("Dashboard: {} disconnected ({}).", self.addr, e);
+                false
+            }
+        }
+    }
+}
+
+fn serve_loop(listener: TcpListener, receiver: Receiver<DashboardEvent>, mut bands: DashboardEvent) {
+    let mut clients: Vec<Client> = Vec::new();
+    loop {
+        accept_clients(&listener, &mut clients, &bands);
+
+        let event = match receiver.recv_timeout(ACCEPT_POLL_INTERVAL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => {
+                clients.retain_mut(|client| client.last_write.elapsed() < KEEPALIVE_INTERVAL || client.send(": keepalive\n\n"));
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let chunk = match sse_message(&event) {
+            Some(chunk) => chunk,
+            None => continue,
+        };
+        if matches!(event, DashboardEvent::Bands { .. }) {
+            bands = event;
+        }
+        clients.retain_mut(|client| client.send(&chunk));
+    }
+}
+
+fn sse_message(event: &DashboardEvent) -> Option<String> {
+    match serde_json::to_string(event) {
+        Ok(payload) => Some(format!("data: {}\n\n", payload)),
+        Err(e) => {
+            warn!("Dashboard: failed to serialize event: {}", e);
+            None
+        }
+    }
+}
+
+fn accept_clients(listener: &TcpListener, clients: &mut Vec<Client>, bands: &DashboardEvent) {
+    while let Ok((stream, addr)) = listener.accept() {
+        let setup = stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.set_read_timeout(Some(REQUEST_TIMEOUT)))
+            .and_then(|_| stream.set_write_timeout(Some(REQUEST_TIMEOUT)));
+        if let Err(e) = setup {
+            warn!("Dashboard: could not configure connection from {}: {}", addr, e);
+            continue;
+        }
+        match answer(stream, addr, bands) {
+            Ok(Some(client)) => {
+                info!("Dashboard: {} is watching.", addr);
+                clients.push(client);
+            }
+            Ok(None) => {}
+            Err(e) => debug!("Dashboard: request from {} failed: {}", addr, e),
+        }
+    }
+}
+
+// Serves one request; an `/events` request becomes a client
+fn answer(mut stream: TcpStream, addr: SocketAddr, bands: &DashboardEvent) -> std::io::Result<Option<Client>> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let path = path.split('?').next().unwrap_or("");
+    match (method, path) {
+        ("GET", "/") => {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                PAGE.len(),
+                PAGE
+            )?;
+            Ok(None)
+        }
+        ("GET", "/events") => {
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n")?;
+            stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+            let mut client = Client { stream, addr, last_write: Instant::now() };
+            Ok(sse_message(bands).filter(|chunk| client.send(chunk)).map(|_| client))
+        }
+        _ => {
+            stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")?;
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::f32::consts::PI;
+
+/// Largest factor `--decimate` accepts.
+pub const MAX_DECIMATION: usize = 16;
+/// Fraction of the reduced sample rate that passes the anti-aliasing filter; the rest of the
+/// way to its Nyquist frequency is the filter's roll-off.
+pub const PASSBAND: f32 = 0.4;
+
+/// Q of the two sections of a 4th-order Butterworth filter.
+pub const BUTTERWORTH_Q: [f32; 2] = [0.541_196_1, 1.306_563];
+
+/// Reduces the sample rate by an integer factor: a low-pass at [`PASSBAND`] of the new rate
+/// against aliasing, then every `factor`-th sample. State carries over between calls, so a
+/// stream can be fed in blocks of any size.
+pub struct Decimator {
+    factor: usize,
+    sections: [Biquad; 2],
+    // Input samples until the next one is kept
+    phase: usize,
+}
+
+impl Decimator {
+    pub fn new(factor: usize) -> Self {
+        let factor = factor.max(1);
+        let w0 = 2.0 * PI * PASSBAND / factor as f32;
+        Decimator { factor, sections: BUTTERWORTH_Q.map(|q| Biquad::low_pass(w0, q)), phase: 0 }
+    }
+
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Filters `input` and writes the kept samples to the start of `output`, returning how many
+    /// there are: at most `input.len() / factor`, rounded up.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) -> usize {
+        if self.factor == 1 {
+            output[..input.len()].copy_from_slice(input);
+            return input.len();
+        }
+        let mut written = 0;
+        for &sample in input {
+            let filtered = self.sections.iter_mut().fold(sample, |x, section| section.process(x));
+            if self.phase == 0 {
+                output[written] = filtered;
+                written += 1;
+            }
+            self.phase = (self.phase + 1) % self.factor;
+        }
+        written
+    }
+}
+
+/// One second-order filter section (RBJ cookbook), direct form I. `w0` is the cutoff in
+/// radians per sample.
+#[derive(Debug, Clone)]
+pub struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl Biquad {
+    pub fn low_pass(w0: f32, q: f32) -> Self {
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let a0 = 1.0 + alpha;
+        let b1 = (1.0 - cos) / a0;
+        Biquad {
+            b: [b1 / 2.0, b1, b1 / 2.0],
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    pub fn high_pass(w0: f32, q: f32) -> Self {
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let a0 = 1.0 + alpha;
+        let b1 = (1.0 + cos) / a0;
+        Biquad {
+            b: [b1 / 2.0, -b1, b1 / 2.0],
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1] - self.a[0] * self.y[0] - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RMS of a sine at `hz`, sampled at `rate`, after decimating by `factor`; skips the
+    // filter's settling time
+    fn gain(hz: f32, rate: f32, factor: usize) -> f32 {
+        let input: Vec<f32> = (0..factor * 4096).map(|n| (2.0 * PI * hz * n as f32 / rate).sin()).collect();
+        let mut output = vec![0.0; input.len()];
+        let mut decimator = Decimator::new(factor);
+        // In odd-sized blocks, to exercise the carried-over phase
+        let mut written = 0;
+        for block in input.chunks(1000) {
+            written += decimator.process(block, &mut output[written..]);
+        }
+        assert_eq!(written, input.len() / factor);
+        let settled = &output[1024..written];
+        (settled.iter().map(|s| s * s).sum::<f32>() / settled.len() as f32).sqrt() * 2f32.sqrt()
+    }
+
+    #[test]
+    fn notes_pass_and_content_above_the_new_nyquist_is_removed() {
+        // 192 kHz down to 48 kHz
+        assert!((gain(220.0, 192_000.0, 4) - 1.0).abs() < 0.02);
+        assert!(gain(5_000.0, 192_000.0, 4) > 0.95);
+        // 40 kHz would alias to 8 kHz; an octave past the passband it is down about 30 dB
+        assert!(gain(40_000.0, 192_000.0, 4) < 0.05);
+    }
+
+    #[test]
+    fn a_factor_of_one_passes_samples_through() {
+        let mut decimator = Decimator::new(1);
+        let mut output = [0.0; 3];
+        assert_eq!(decimator.process(&[0.1, -0.2, 0.3], &mut output), 3);
+        assert_eq!(output, [0.1, -0.2, 0.3]);
+    }
+}
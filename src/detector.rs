@@ -0,0 +1,182 @@
+//! `--detector`: which pitch detection algorithm the engine runs on each window. McLeod and YIN
+//! come from the `pitch-detection` crate; [`HpsDetector`] is pitchu's own.
+
+use crate::gate::window_power;
+use clap::ValueEnum;
+use pitch_detection::detector::mcleod::McLeodDetector;
+use pitch_detection::detector::yin::YINDetector;
+use pitch_detection::detector::PitchDetector;
+use pitch_detection::Pitch;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DetectorKind {
+    /// McLeod's normalized square difference; precise and quick, the default.
+    #[default]
+    Mcleod,
+    /// YIN's cumulative mean difference.
+    Yin,
+    /// Harmonic product spectrum (see [`HpsDetector`]).
+    Hps,
+}
+
+pub fn build_detector(kind: DetectorKind, window_size: usize) -> Box<dyn PitchDetector<f32>> {
+    match kind {
+        DetectorKind::Mcleod => Box::new(McLeodDetector::new(window_size, window_size / 2)),
+        DetectorKind::Yin => Box::new(YINDetector::new(window_size, window_size / 2)),
+        DetectorKind::Hps => Box::new(HpsDetector::new(window_size)),
+    }
+}
+
+/// Spectra multiplied together, the fundamental's included.
+pub const HPS_HARMONICS: usize = 5;
+// The window is zero-padded to this many times its length, for finer bins
+const PADDING: usize = 4;
+
+/// Finds the fundamental in the spectrum instead of the waveform: the spectrum of the
+/// Hann-windowed buffer is multiplied by copies of itself squeezed 2, 3, ... times, so only
+/// the frequency whose harmonics all line up stands out. A fundamental that is weak or missing,
+/// as in a breathy low note, is still found from its overtones, and a strong second harmonic
+/// doesn't win an octave too high. The price is resolution: bins are
+/// `sample_rate / (4 * window_size)` Hz wide before interpolation, so it reads a few cents
+/// coarser than McLeod, and nothing below two bins of the unpadded window is found.
+///
+/// Its clarity is the share of the spectrum's energy that lies on the fundamental and its
+/// harmonics: near 1 for a clean tone, low for noise.
+pub struct HpsDetector {
+    fft: Arc<dyn Fft<f32>>,
+    hann: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    power: Vec<f32>,
+    // Log of the harmonic product, per bin
+    product: Vec<f32>,
+}
+
+impl HpsDetector {
+    pub fn new(window_size: usize) -> Self {
+        let size = window_size.max(2) * PADDING;
+        let fft = FftPlanner::new().plan_fft_forward(size);
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+        let hann = (0..window_size).map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / window_size as f32).cos()).collect();
+        HpsDetector {
+            fft,
+            hann,
+            buffer: vec![Complex::default(); size],
+            scratch,
+            power: vec![0.0; size / 2],
+            product: vec![0.0; size / 2],
+        }
+    }
+}
+
+impl PitchDetector<f32> for HpsDetector {
+    fn get_pitch(
+        &mut self,
+        signal: &[f32],
+        sample_rate: usize,
+        power_threshold: f32,
+        clarity_threshold: f32,
+    ) -> Option<Pitch<f32>> {
+        if window_power(signal) < power_threshold {
+            return None;
+        }
+        for (i, slot) in self.buffer.iter_mut().enumerate() {
+            let sample = match (signal.get(i), self.hann.get(i)) {
+                (Some(sample), Some(weight)) => sample * weight,
+                _ => 0.0,
+            };
+            *slot = Complex::new(sample, 0.0);
+        }
+        self.fft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+        let half = self.power.len();
+        for (power, bin) in self.power.iter_mut().zip(&self.buffer) {
+            *power = bin.norm_sqr();
+        }
+
+        // Two bins of the unpadded window: below that the main lobe reaches DC
+        let lowest = 2 * PADDING;
+        let highest = half / HPS_HARMONICS;
+        if lowest + 1 >= highest {
+            return None;
+        }
+        let floor = self.power.iter().copied().fold(0.0, f32::max) * 1e-12 + f32::MIN_POSITIVE;
+        for bin in lowest..highest {
+            self.product[bin] = (1..=HPS_HARMONICS).map(|harmonic| (self.power[bin * harmonic] + floor).ln()).sum();
+        }
+        let peak = (lowest + 1..highest - 1).max_by(|&a, &b| self.product[a].total_cmp(&self.product[b]))?;
+
+        // A parabola through the peak of the (smooth) log product places it between bins
+        let (left, center, right) = (self.product[peak - 1], self.product[peak], self.product[peak + 1]);
+        let curvature = left - 2.0 * center + right;
+        let offset = if curvature < 0.0 { (0.5 * (left - right) / curvature).clamp(-0.5, 0.5) } else { 0.0 };
+        let bin = peak as f32 + offset;
+
+        // Energy within the main lobe of each harmonic, against all of it
+        let total: f32 = self.power[lowest / 2..].iter().sum();
+        let mut harmonic_energy = 0.0;
+        let mut harmonic = 1;
+        while let Some(range) = lobe(bin * harmonic as f32, half) {
+            harmonic_energy += self.power[range].iter().sum::<f32>();
+            harmonic += 1;
+        }
+        let clarity = if total > 0.0 { (harmonic_energy / total).min(1.0) } else { 0.0 };
+        (clarity >= clarity_threshold)
+            .then(|| Pitch { frequency: bin * sample_rate as f32 / self.buffer.len() as f32, clarity })
+    }
+}
+
+// The bins of the main lobe around `center`, while it fits below `half`
+fn lobe(center: f32, half: usize) -> Option<std::ops::RangeInclusive<usize>> {
+    let center = center.round() as usize;
+    (center + PADDING < half).then(|| center - PADDING..=center + PADDING)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: usize = 44_100;
+
+    fn tone(harmonics: &[(f32, f32)]) -> Vec<f32> {
+        (0..2048)
+            .map(|i| {
+                let t = i as f32 / RATE as f32;
+                harmonics.iter().map(|&(hz, amplitude)| amplitude * (2.0 * PI * hz * t).sin()).sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_weak_fundamental_is_found_from_its_harmonics() {
+        let mut detector = HpsDetector::new(2048);
+        // 110 Hz barely there under its next four harmonics, the second the loudest
+        let breathy = tone(&[(110.0, 0.02), (220.0, 0.5), (330.0, 0.3), (440.0, 0.2), (550.0, 0.1)]);
+        let pitch = detector.get_pitch(&breathy, RATE, 0.0, 0.5).expect("a pitch");
+        assert!((pitch.frequency - 110.0).abs() < 2.0, "detected {} Hz", pitch.frequency);
+        assert!(pitch.clarity > 0.9, "clarity {}", pitch.clarity);
+        // The power gate applies as with the other detectors
+        assert!(detector.get_pitch(&breathy, RATE, 1e6, 0.0).is_none());
+    }
+
+    #[test]
+    fn noise_has_little_clarity() {
+        let mut detector = HpsDetector::new(2048);
+        // A cheap deterministic noise
+        let mut state = 1u32;
+        let noise: Vec<f32> = (0..2048)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+            })
+            .collect();
+        let clarity = detector.get_pitch(&noise, RATE, 0.0, 0.0).map_or(0.0, |pitch| pitch.clarity);
+        assert!(clarity < 0.3, "clarity {}", clarity);
+        assert!(detector.get_pitch(&noise, RATE, 0.0, 0.5).is_none());
+    }
+}
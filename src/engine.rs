@@ -0,0 +1,1348 @@
+use crate::clock::{Clock, SystemClock};
+use crate::axis::{AxisFollower, AxisMapping};
+use crate::bandlimit::BandLimit;
+use crate::stats::SessionStats;
+use crate::detector::{build_detector, DetectorKind};
+use crate::curve::{Curve, CurveFollower, CurveMove};
+use crate::gate::{normalize, window_power, window_rms, AdaptiveGate, HysteresisGate, NORMALIZED_RMS};
+use crate::intervals::{IntervalMapping, IntervalTracker};
+use crate::key_state::{BandTiming, KeyStateMachine, KeyTiming};
+use crate::keys::{GapBehavior, KeyEvent, KeyMode, LowClarity, Trigger};
+use crate::latch::{LatchState, LatchToggle};
+use crate::mapper::{Action, FrequencyMapper, MapContext, RECENT_WINDOWS};
+use crate::mapping::{Band, BandAction, Mapping};
+use crate::octave::OctaveCorrector;
+use crate::patterns::{Pattern, PatternAction, PatternMatcher};
+use crate::percussive::PercussiveDetector;
+use crate::smoothing::{build_smoother, Smoother, SmoothingKind};
+use crate::vibrato::VibratoLock;
+use crate::tuner;
+use crate::voices::{combined_mapping, Voice, VoiceSet};
+#[cfg(feature = "scripting")]
+use crate::script::ScriptMapper;
+use crate::PITCH_TARGET;
+use enigo::Key;
+use log::{debug, info, warn};
+use std::time::{Duration, Instant};
+use pitch_detection::detector::PitchDetector;
+
+/// Detection and key-handling settings. The defaults match the command-line defaults.
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// Samples per analysis window.
+    pub window_size: usize,
+    /// Sample rate of the audio handed to the engine.
+    pub sample_rate: usize,
+    pub power_threshold: f32,
+    pub clarity_threshold: f32,
+    /// Detections below this frequency count as no pitch, e.g. voice and breath under a
+    /// whistle.
+    pub min_hz: Option<f32>,
+    /// Detections below this clarity are reported but never map to a key.
+    pub min_press_clarity: f32,
+    /// What a detection below `min_press_clarity` does to the active key.
+    pub low_clarity: LowClarity,
+    /// What a pitch between two bands does to the active key.
+    pub gap: GapBehavior,
+    /// Enables the adaptive gate, placing the threshold this many times above the noise floor.
+    pub adaptive_ratio: Option<f32>,
+    /// `(attack, release)` RMS levels: no pitch counts until a window's RMS reaches the first,
+    /// and none again once it has fallen below the second, until it reaches the first again.
+    pub level_gate: Option<(f32, f32)>,
+    /// Scale every window that passes the power gate to the same level before detection.
+    pub normalize: bool,
+    /// Band-pass the audio to the range the pitch is used in before detection (see
+    /// [`BandLimit`]).
+    pub band_limit: bool,
+    /// The pitch detection algorithm.
+    pub detector: DetectorKind,
+    /// Enables octave-jump correction with this tolerance.
+    pub octave_tolerance_cents: Option<f32>,
+    pub smoothing: SmoothingKind,
+    pub ema_alpha: f32,
+    pub median_window: usize,
+    /// Holds sustained notes with vibrato up to this many cents either way on their center.
+    pub vibrato_tolerance_cents: Option<f32>,
+    pub mode: KeyMode,
+    /// Repeat mode only: press at the start of a note, or once when it ends.
+    pub trigger: Trigger,
+    pub timing: KeyTiming,
+    pub latch_debounce: u32,
+    /// Consecutive windows in a text band before its text is typed.
+    pub text_debounce: u32,
+    /// With voices: how long a voice keeps its key while the pitch is elsewhere or lost.
+    pub voice_hold: Duration,
+    /// Log a tuner reading for every detection.
+    pub tuner: bool,
+    /// Log how far every detection lands from the nearest band's center.
+    pub diagnostics: bool,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            window_size: 2048,
+            sample_rate: 44_100,
+            power_threshold: 0.7,
+            clarity_threshold: 0.2,
+            min_hz: None,
+            min_press_clarity: 0.0,
+            low_clarity: LowClarity::Release,
+            gap: GapBehavior::Release,
+            adaptive_ratio: None,
+            level_gate: None,
+            normalize: false,
+            band_limit: true,
+            detector: DetectorKind::Mcleod,
+            octave_tolerance_cents: None,
+            smoothing: SmoothingKind::None,
+            ema_alpha: 0.5,
+            median_window: 3,
+            vibrato_tolerance_cents: None,
+            mode: KeyMode::Repeat,
+            trigger: Trigger::Onset,
+            timing: KeyTiming::default(),
+            latch_debounce: 2,
+            text_debounce: 2,
+            voice_hold: Duration::from_millis(150),
+            tuner: false,
+            diagnostics: false,
+        }
+    }
+}
+
+// How long a pitch in an ignore band leaves the active key alone before counting as silence
+const IGNORED_PITCH_HOLD: Duration = Duration::from_millis(150);
+
+/// Lowest frequency a window of `window_size` samples can resolve: one full period has to fit
+/// in the window, i.e. `sample_rate / window_size`.
+pub fn min_detectable_hz(sample_rate: usize, window_size: usize) -> f32 {
+    sample_rate as f32 / window_size as f32
+}
+
+/// What the engine made of one analysis window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowOutcome {
+    /// The detected `(frequency, clarity)` after correction and smoothing, if any. Reported
+    /// even in an ignore band, which maps to no key.
+    pub pitch: Option<(f32, f32)>,
+    /// The key the pitch maps to, if any.
+    pub key: Option<Key>,
+    /// Key events to carry out. In repeat mode a press or repeat is a single click and a
+    /// release needs no action; in latch mode a press holds the key down and a release lets go.
+    pub events: Vec<KeyEvent>,
+    /// Text to type, once per note, when the pitch settles in a text band.
+    pub text: Option<String>,
+    /// What the detector measured, when the window went through it; enough to run the window
+    /// again with [`process_measured`](PitchToKeyEngine::process_measured).
+    pub measurement: Option<Measurement>,
+    /// Where the `[gamepad.axis]` stick moves to, when it moves.
+    pub axis: Option<f32>,
+    /// What the `[[curves]]` move in this window.
+    pub curves: Vec<CurveMove>,
+}
+
+/// One window as the pitch detector saw it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    /// The raw `(frequency, clarity)`, found at the loosest thresholds in effect, before band
+    /// thresholds, octave correction and smoothing.
+    pub detection: Option<(f32, f32)>,
+    /// Sum of squared samples.
+    pub power: f32,
+}
+
+// Fires once per stay in a text band, after `debounce` consecutive windows in it. A band is
+// told apart by its index; a custom mapper's texts, which have none, by the text itself.
+struct TextTrigger {
+    debounce: u32,
+    target: Option<(Option<usize>, String)>,
+    windows: u32,
+}
+
+impl TextTrigger {
+    fn new(debounce: u32) -> Self {
+        TextTrigger { debounce: debounce.max(1), target: None, windows: 0 }
+    }
+
+    /// Feed the text band of one window; its text on the window it should be typed.
+    fn update(&mut self, target: Option<(Option<usize>, String)>) -> Option<String> {
+        if target != self.target {
+            self.target = target;
+            self.windows = 0;
+        }
+        let (_, text) = self.target.as_ref()?;
+        self.windows = self.windows.saturating_add(1);
+        (self.windows == self.debounce).then(|| text.clone())
+    }
+}
+
+/// Turns analysis windows into key events: detection, gating, octave correction, smoothing,
+/// mapping and the hold/repeat (or latch) logic. Knows nothing about audio devices or how keys
+/// get pressed, and reads the time only through its [`Clock`].
+///
+/// Feed it either whole windows with [`process`](Self::process), or audio in chunks of any size
+/// with [`push_samples`](Self::push_samples) and collect the resulting key events with
+/// [`poll`](Self::poll).
+///
+/// The engine does no locking and never blocks: all calls must come from the thread that owns
+/// it. It is `Send` (given a `Send` clock), so it can be moved to a dedicated processing thread,
+/// but it should not be called from an audio callback since detection allocates. To get audio
+/// out of a callback, push it into an [`audio_queue`](crate::audio_queue) and feed the engine
+/// from the consumer side.
+pub struct PitchToKeyEngine<C: Clock = SystemClock> {
+    config: EngineConfig,
+    mapping: Mapping,
+    clock: C,
+    detector: Box<dyn PitchDetector<f32>>,
+    adaptive_gate: Option<AdaptiveGate>,
+    level_gate: Option<HysteresisGate>,
+    octave_corrector: Option<OctaveCorrector>,
+    smoother: Box<dyn Smoother>,
+    vibrato: Option<VibratoLock>,
+    latch: Option<LatchState>,
+    keys: KeyStateMachine,
+    voices: Option<VoiceSet>,
+    patterns: Option<PatternMatcher>,
+    // The key beatboxed sounds press, and their detector
+    percussive: Option<(Key, PercussiveDetector)>,
+    axis: Option<AxisFollower>,
+    curves: Option<CurveFollower>,
+    intervals: Option<IntervalTracker>,
+    text: TextTrigger,
+    // When the pitch entered an ignore band, while it stays in one
+    ignored_since: Option<Instant>,
+    // Frequency and key of the last clear window since the pitch was last lost, for
+    // LowClarity::Hold
+    last_clear: Option<(f32, Option<Key>)>,
+    mapper: Option<Box<dyn FrequencyMapper>>,
+    // The frequencies since the pitch was last lost, for the mapper
+    recent: Vec<f32>,
+    #[cfg(feature = "scripting")]
+    script: Option<ScriptMapper>,
+    // RMS of the last window, only tracked for the script
+    #[cfg(feature = "scripting")]
+    window_rms: f32,
+    // Samples from push_samples not yet making up a whole window
+    pending: Vec<f32>,
+    band_limit: Option<BandLimit>,
+    stats: SessionStats,
+    // The window as detected: band-limited, scaled by --normalize, or both
+    prepared: Vec<f32>,
+}
+
+impl<C: Clock> PitchToKeyEngine<C> {
+    pub fn new(config: EngineConfig, mapping: Mapping, clock: C) -> Self {
+        let mut engine = PitchToKeyEngine {
+            detector: build_detector(config.detector, config.window_size),
+            adaptive_gate: config.adaptive_ratio.map(|ratio| AdaptiveGate::new(config.power_threshold, ratio)),
+            level_gate: config.level_gate.map(|(attack, release)| HysteresisGate::new(attack, release)),
+            octave_corrector: config.octave_tolerance_cents.map(OctaveCorrector::new),
+            smoother: build_smoother(config.smoothing, config.ema_alpha, config.median_window),
+            vibrato: config.vibrato_tolerance_cents.map(VibratoLock::new),
+            latch: (config.mode == KeyMode::Latch).then(|| LatchState::new(config.latch_debounce, config.timing.min_note)),
+            keys: KeyStateMachine::new(config.timing),
+            voices: None,
+            patterns: None,
+            percussive: None,
+            axis: None,
+            curves: None,
+            intervals: None,
+            text: TextTrigger::new(config.text_debounce),
+            ignored_since: None,
+            last_clear: None,
+            mapper: None,
+            recent: Vec::new(),
+            #[cfg(feature = "scripting")]
+            script: None,
+            #[cfg(feature = "scripting")]
+            window_rms: 0.0,
+            pending: Vec::with_capacity(2 * config.window_size),
+            band_limit: None,
+            stats: SessionStats::new(),
+            prepared: Vec::with_capacity(config.window_size),
+            config,
+            mapping,
+            clock,
+        };
+        engine.refresh_band_limit();
+        engine
+    }
+
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    pub fn mapping(&self) -> &Mapping {
+        &self.mapping
+    }
+
+    /// Swaps in a new pitch-to-key table, e.g. after calibrating a relative mapping. The
+    /// active key, if any, is released on the next window that maps differently.
+    pub fn set_mapping(&mut self, mapping: Mapping) {
+        self.mapping = mapping;
+        self.refresh_band_limit();
+    }
+
+    /// The range detection is band-limited to, if it is.
+    pub fn band_limit(&self) -> Option<(f32, f32)> {
+        self.band_limit.as_ref().map(BandLimit::range_hz)
+    }
+
+    // Fits the band-pass to what the pitch can drive: the bands, the stick and the curves. Off
+    // when the keys don't come from the bands' ranges (a script, a mapper or intervals).
+    fn refresh_band_limit(&mut self) {
+        let custom = self.mapper.is_some() || self.intervals.is_some();
+        #[cfg(feature = "scripting")]
+        let custom = custom || self.script.is_some();
+        let range = self
+            .mapping
+            .range_hz()
+            .into_iter()
+            .chain(self.axis().map(|axis| (axis.low_hz, axis.high_hz)))
+            .chain(self.curves().iter().map(|curve| (curve.freq_min, curve.freq_max)))
+            .reduce(|(low, high), (other_low, other_high)| (low.min(other_low), high.max(other_high)))
+            .filter(|_| self.config.band_limit && !custom);
+        // The same range keeps the filter, and where it is in the stream
+        if self.band_limit() != range {
+            if let Some((low, high)) = range {
+                debug!("Info: band-limiting detection to {:.0}-{:.0} Hz.", low, high);
+            }
+            self.band_limit = range.map(|(low, high)| BandLimit::new(low, high, self.config.sample_rate));
+        }
+    }
+
+    /// The power threshold currently in effect (fixed, or tracked by the adaptive gate).
+    pub fn power_threshold(&self) -> f32 {
+        self.adaptive_gate.as_ref().map_or(self.config.power_threshold, AdaptiveGate::threshold)
+    }
+
+    /// Splits the range between several voices, each with its own mapping and key state (see
+    /// [`VoiceSet`]). Replaces the mapping with the voices' combined bands. Voices always use
+    /// repeat mode.
+    pub fn set_voices(&mut self, voices: Vec<Voice>) {
+        self.mapping = combined_mapping(&voices);
+        self.voices = Some(VoiceSet::new(voices, self.config.timing, self.config.voice_hold));
+        self.refresh_band_limit();
+    }
+
+    /// Watches the key presses for `patterns`, adding each completed pattern's key or text to
+    /// the window's outcome (see [`PatternMatcher`]).
+    pub fn set_patterns(&mut self, patterns: Vec<Pattern>) {
+        self.patterns = (!patterns.is_empty()).then(|| PatternMatcher::new(patterns));
+    }
+
+    /// Lets `mapper` choose the action of each detection instead of the mapping's bands, which
+    /// still set the per-band thresholds and timing and feed the tuner. A tap/hold action
+    /// presses its hold key. Voices keep their own mappings, and a script, when set, wins.
+    pub fn set_mapper(&mut self, mapper: Box<dyn FrequencyMapper>) {
+        self.recent = Vec::with_capacity(RECENT_WINDOWS);
+        self.mapper = Some(mapper);
+        self.refresh_band_limit();
+    }
+
+    /// Presses `key` for unpitched transients, such as a beatboxed "p" or "k" (see
+    /// [`PercussiveDetector`]). Only audio windows given to [`process`](Self::process) are
+    /// checked.
+    pub fn set_percussive(&mut self, key: Key, refractory: Duration) {
+        self.percussive = Some((key, PercussiveDetector::new(refractory)));
+    }
+
+    /// The `[gamepad.axis]` the pitch steers, if any.
+    pub fn axis(&self) -> Option<&AxisMapping> {
+        self.axis.as_ref().map(AxisFollower::mapping)
+    }
+
+    /// Steers a controller axis with the smoothed pitch as well (see [`AxisFollower`]); the
+    /// bands keep pressing their keys.
+    pub fn set_axis(&mut self, axis: AxisMapping) {
+        self.axis = Some(AxisFollower::new(axis));
+        self.refresh_band_limit();
+    }
+
+    /// The `[[curves]]` the pitch drives, if any.
+    pub fn curves(&self) -> &[Curve] {
+        self.curves.as_ref().map_or(&[], CurveFollower::curves)
+    }
+
+    /// Drives continuous outputs with the smoothed pitch as well (see [`CurveFollower`]); the
+    /// bands keep pressing their keys.
+    pub fn set_curves(&mut self, curves: Vec<Curve>) {
+        self.curves = Some(CurveFollower::new(curves));
+        self.refresh_band_limit();
+    }
+
+    /// Every stick the pitch moves, back where it rests: the `[gamepad.axis]` on its center and
+    /// the `[[curves]]` sticks on zero. [`reset`](Self::reset) doesn't send anything, so send
+    /// these when letting go of everything, or the sticks stay where they were pushed.
+    pub fn stick_centers(&self) -> Vec<CurveMove> {
+        self.axis()
+            .map(|axis| CurveMove::Stick(axis.axis, axis.center))
+            .into_iter()
+            .chain(self.curves().iter().filter_map(|curve| curve.output.stick()).map(|axis| CurveMove::Stick(axis, 0.0)))
+            .collect()
+    }
+
+    /// Presses keys for the jumps between notes instead of the bands' keys (see
+    /// [`IntervalTracker`]), with the minimum note duration and switch consensus of the key
+    /// timing, telling notes apart with A4 at `a4_hz`. Ignore bands still apply.
+    pub fn set_intervals(&mut self, intervals: IntervalMapping, a4_hz: f32) {
+        self.intervals = Some(IntervalTracker::new(intervals, self.config.timing, a4_hz));
+        self.refresh_band_limit();
+    }
+
+    /// Lets `script` pick the key of each detection instead of the mapping's bands. Ignore
+    /// bands still apply; voices, when set, keep their own mappings.
+    #[cfg(feature = "scripting")]
+    pub fn set_script(&mut self, script: ScriptMapper) {
+        self.script = Some(script);
+        self.refresh_band_limit();
+    }
+
+    /// Picks up edits to the script file, if there is a script.
+    #[cfg(feature = "scripting")]
+    pub fn reload_script_if_changed(&mut self) {
+        if let Some(script) = self.script.as_mut() {
+            script.reload_if_changed();
+        }
+    }
+
+    /// For when the input is reopened at a different rate.
+    pub fn set_sample_rate(&mut self, sample_rate: usize) {
+        self.config.sample_rate = sample_rate;
+        // The filter's coefficients depend on the rate
+        self.band_limit = None;
+        self.refresh_band_limit();
+    }
+
+    /// Logs the lowest detectable frequency and warns about bands below it.
+    pub fn check_frequency_range(&self) {
+        let sample_rate = self.config.sample_rate;
+        let min_hz = min_detectable_hz(sample_rate, self.config.window_size);
+        info!(
+            "Lowest detectable frequency: {:.1} Hz (sample_rate / window_size = {} / {}).",
+            min_hz, sample_rate, self.config.window_size
+        );
+        for band in self.mapping.bands() {
+            if band.high_hz < min_hz {
+                warn!(
+                    "Band {:.1}-{:.1} Hz ('{}') lies entirely below the {:.1} Hz limit (sample_rate / window_size) and can never trigger.",
+                    band.low_hz, band.high_hz, band.label(), min_hz
+                );
+            } else if band.low_hz < min_hz {
+                warn!(
+                    "Band {:.1}-{:.1} Hz ('{}') starts below the {:.1} Hz limit (sample_rate / window_size); its lower part can never trigger.",
+                    band.low_hz, band.high_hz, band.label(), min_hz
+                );
+            }
+        }
+    }
+
+    /// Queues mono samples at `config.sample_rate`; [`poll`](Self::poll) analyses them.
+    pub fn push_samples(&mut self, samples: &[f32]) {
+        self.pending.extend_from_slice(samples);
+    }
+
+    /// Runs every whole window queued by [`push_samples`](Self::push_samples) and returns the
+    /// key events they caused, in order. A partial window stays queued for the next call.
+    /// Text bands are not reported here; use [`process`](Self::process) to get their text.
+    pub fn poll(&mut self) -> Vec<KeyEvent> {
+        let mut pending = std::mem::take(&mut self.pending);
+        let mut events = Vec::new();
+        let mut consumed = 0;
+        for window in pending.chunks_exact(self.config.window_size) {
+            events.extend(self.process(window).events);
+            consumed += window.len();
+        }
+        pending.drain(..consumed);
+        self.pending = pending;
+        events
+    }
+
+    /// Runs pitch detection on one window of `config.window_size` mono samples.
+    pub fn process(&mut self, window: &[f32]) -> WindowOutcome {
+        let power = window_power(window);
+        let (detect_clarity, detect_power) = self.detection_thresholds();
+        let detection = if self.band_limit.is_none() && !self.config.normalize {
+            self.detector.get_pitch(window, self.config.sample_rate, detect_power, detect_clarity)
+        } else {
+            self.prepared.clear();
+            self.prepared.extend_from_slice(window);
+            // Even a window below the gate, so the filter follows the whole stream
+            if let Some(filter) = self.band_limit.as_mut() {
+                filter.process(&mut self.prepared);
+            }
+            // Gate on the raw level, so silence still stays silent and a loud sound filtered
+            // away doesn't open the gate either way
+            if power < detect_power {
+                None
+            } else {
+                if self.config.normalize {
+                    normalize(&mut self.prepared, NORMALIZED_RMS);
+                }
+                self.detector.get_pitch(&self.prepared, self.config.sample_rate, 0.0, detect_clarity)
+            }
+        }
+        .map(|pitch| (pitch.frequency, pitch.clarity));
+        self.after_detector(Measurement { detection, power })
+    }
+
+    /// Everything after the pitch detector, e.g. for a recorded window. The thresholds in
+    /// effect are applied again, so a detection made at looser ones can still be turned down.
+    pub fn process_measured(&mut self, measurement: Measurement) -> WindowOutcome {
+        let (detect_clarity, detect_power) = self.detection_thresholds();
+        let detection = measurement
+            .detection
+            .filter(|&(_, clarity)| clarity >= detect_clarity && measurement.power >= detect_power);
+        let mut outcome = self.after_detector(Measurement { detection, ..measurement });
+        outcome.measurement = Some(measurement);
+        outcome
+    }
+
+    fn after_detector(&mut self, measurement: Measurement) -> WindowOutcome {
+        let Measurement { detection, power } = measurement;
+        let mut gated = power < self.detection_thresholds().1;
+        let detection = match self.level_gate.as_mut() {
+            Some(gate) => {
+                let was_open = gate.is_open();
+                let rms = window_rms(power, self.config.window_size);
+                let open = gate.update(rms);
+                if open != was_open {
+                    debug!(target: PITCH_TARGET, "Input: level gate {} at RMS {:.3}.", if open { "opens" } else { "closes" }, rms);
+                }
+                gated |= !open;
+                detection.filter(|_| open)
+            }
+            None => detection,
+        };
+        let power_threshold = self.power_threshold();
+        let (band_clarity, band_power) = self.mapping.loosest_thresholds();
+        let detection = match detection {
+            Some((frequency, clarity)) if band_clarity.is_some() || band_power.is_some() => {
+                match self.rejecting_threshold(frequency, clarity, power, power_threshold) {
+                    Some(reason) => {
+                        info!(target: PITCH_TARGET, "Input: {:.2} Hz rejected: {}.", frequency, reason);
+                        None
+                    }
+                    None => detection,
+                }
+            }
+            _ => detection,
+        };
+
+        match detection {
+            Some((frequency, clarity)) => {
+                info!(
+                    target: PITCH_TARGET,
+                    "Input: Detected pitch = {:.2} Hz (Clarity: {:.2})",
+                    frequency, clarity
+                );
+            }
+            None => {
+                if let Some(gate) = self.adaptive_gate.as_mut() {
+                    gate.observe_silence(power);
+                }
+            }
+        }
+        if let Some(gate) = self.adaptive_gate.as_mut() {
+            gate.log_periodically();
+        }
+        #[cfg(feature = "scripting")]
+        if self.script.is_some() {
+            self.window_rms = window_rms(power, self.config.window_size);
+        }
+        let mut outcome = self.process_detection(detection);
+        if let Some((key, detector)) = self.percussive.as_mut() {
+            let pitched = outcome.pitch.is_some_and(|(_, clarity)| clarity >= self.config.min_press_clarity);
+            if detector.update(power, power_threshold, pitched, self.clock.now()) {
+                info!("Action: Percussive sound; pressing key '{:?}'.", key);
+                outcome.events.extend([KeyEvent::Press(*key), KeyEvent::Release(*key)]);
+            }
+        }
+        let band = outcome.pitch.and_then(|(frequency, _)| self.mapping.band_for(frequency)).map(Band::label);
+        let length = Duration::from_secs_f64(self.config.window_size as f64 / self.config.sample_rate as f64);
+        self.stats.record_window(length, gated, outcome.pitch, band, &outcome.events);
+        outcome.measurement = Some(measurement);
+        outcome
+    }
+
+    /// What the session has done so far, window by window (see [`SessionStats`]). Only windows
+    /// of audio or a replay count, not MIDI notes.
+    pub fn stats(&self) -> &SessionStats {
+        &self.stats
+    }
+
+    // The (clarity, power) the detector works at: bands with thresholds of their own detect at
+    // the loosest, then let the band decide
+    fn detection_thresholds(&self) -> (f32, f32) {
+        let power_threshold = self.power_threshold();
+        let (band_clarity, band_power) = self.mapping.loosest_thresholds();
+        (
+            band_clarity.map_or(self.config.clarity_threshold, |c| c.min(self.config.clarity_threshold)),
+            band_power.map_or(power_threshold, |p| p.min(power_threshold)),
+        )
+    }
+
+    // What keeps a detection made at the loosest thresholds from counting: the clarity or
+    // power set by the band it falls in, or the global one outside such bands
+    fn rejecting_threshold(&self, frequency: f32, clarity: f32, power: f32, power_threshold: f32) -> Option<String> {
+        let band = self.mapping.band_for(frequency);
+        match band.and_then(|band| Some((band, band.min_clarity?))) {
+            Some((band, min)) if clarity < min => {
+                return Some(format!("clarity {:.2} is below band '{}''s min_clarity {:.2}", clarity, band.label(), min));
+            }
+            None if clarity < self.config.clarity_threshold => {
+                return Some(format!("clarity {:.2} is below --clarity {:.2}", clarity, self.config.clarity_threshold));
+            }
+            _ => {}
+        }
+        match band.and_then(|band| Some((band, band.min_power?))) {
+            Some((band, min)) if power < min => {
+                Some(format!("power {:.2} is below band '{}''s min_power {:.2}", power, band.label(), min))
+            }
+            None if power < power_threshold => {
+                Some(format!("power {:.2} is below the power threshold {:.2}", power, power_threshold))
+            }
+            _ => None,
+        }
+    }
+
+    /// Everything after detection: feed the raw `(frequency, clarity)` of one window, or `None`
+    /// when no clear pitch was found. Lets recorded or scripted pitch sequences drive the engine.
+    pub fn process_detection(&mut self, detection: Option<(f32, f32)>) -> WindowOutcome {
+        let mut outcome = self.map_detection(detection);
+        // Any pitch clear enough to press steers, ignore bands included
+        let frequency = outcome.pitch.filter(|&(_, clarity)| clarity >= self.config.min_press_clarity).map(|(frequency, _)| frequency);
+        if let Some(axis) = self.axis.as_mut() {
+            outcome.axis = axis.update(frequency, self.clock.now());
+        }
+        if let Some(curves) = self.curves.as_mut() {
+            outcome.curves = curves.update(frequency, self.clock.now());
+        }
+        outcome
+    }
+
+    fn map_detection(&mut self, detection: Option<(f32, f32)>) -> WindowOutcome {
+        let detection = match (detection, self.config.min_hz) {
+            (Some((frequency, _)), Some(min_hz)) if frequency < min_hz => {
+                debug!(target: PITCH_TARGET, "Input: {:.2} Hz is below the {:.0} Hz minimum; treating it as no pitch.", frequency, min_hz);
+                None
+            }
+            _ => detection,
+        };
+        let mut new_key_to_press: Option<Key> = None;
+        let mut trusted_frequency = None;
+        let mut pitch = None;
+        let mut ignored = false;
+        let mut low_clarity = false;
+        let mut mapped = None;
+
+        if let Some((raw_frequency, clarity)) = detection {
+            let frequency = match self.octave_corrector.as_mut() {
+                Some(corrector) => corrector.correct(raw_frequency),
+                None => raw_frequency,
+            };
+            let frequency = self.smoother.smooth(frequency);
+            if self.config.smoothing != SmoothingKind::None {
+                debug!(target: PITCH_TARGET, "Input: Smoothed pitch = {:.2} Hz", frequency);
+            }
+            let frequency = match self.vibrato.as_mut() {
+                Some(vibrato) => {
+                    let held = vibrato.update(frequency, self.clock.now());
+                    if vibrato.locked() {
+                        debug!(target: PITCH_TARGET, "Input: Vibrato around {:.2} Hz; using its center.", held);
+                    }
+                    held
+                }
+                None => frequency,
+            };
+            mapped = self.map_custom(frequency, clarity);
+            let ignores = match &mapped {
+                Some(action) => *action == Some(Action::Ignore),
+                None => self.mapping.ignores(frequency),
+            };
+            if ignores {
+                info!(target: PITCH_TARGET, "Input: {:.2} Hz is in an ignore band; treating it as no pitch.", frequency);
+                ignored = true;
+            } else {
+                // A band's own min_clarity stands in for --min-clarity-for-press
+                let band_clarity =
+                    self.mapping.band_for(frequency).and_then(|band| Some((band.label(), band.min_clarity?)));
+                let press_clarity = band_clarity.as_ref().map_or(self.config.min_press_clarity, |&(_, min)| min);
+                if clarity >= press_clarity {
+                    new_key_to_press = match &mapped {
+                        Some(Some(Action::Key(key) | Action::TapHold { hold: key, .. })) => Some(*key),
+                        Some(_) => None,
+                        None => self.key_for(frequency, clarity),
+                    };
+                    if mapped.is_none() && self.in_gap(frequency) {
+                        new_key_to_press = self.across_gap(frequency);
+                    }
+                    trusted_frequency = Some(frequency);
+                    self.last_clear = Some((frequency, new_key_to_press));
+                } else {
+                    match band_clarity {
+                        Some((label, min)) => info!(
+                            target: PITCH_TARGET,
+                            "Input: Clarity {:.2} is below band '{}''s min_clarity {:.2}; not pressing.",
+                            clarity, label, min
+                        ),
+                        None => info!(
+                            target: PITCH_TARGET,
+                            "Input: Clarity {:.2} is below --min-clarity-for-press {:.2}; not pressing.",
+                            clarity, self.config.min_press_clarity
+                        ),
+                    }
+                    low_clarity = true;
+                }
+            }
+            let tuner_mapping = match self.voices.as_ref().and_then(|voices| voices.voice_for(frequency)) {
+                Some(voice) => &voice.mapping,
+                None => &self.mapping,
+            };
+            if self.config.tuner
+                && let Some(reading) = tuner::read(tuner_mapping, frequency)
+            {
+                info!(target: PITCH_TARGET, "Tuner: {}", reading);
+            }
+            if self.config.diagnostics
+                && let Some(deviation) = tuner::nearest_center(tuner_mapping, frequency)
+            {
+                info!("Diagnostics: {}", deviation);
+            }
+            pitch = Some((frequency, clarity));
+            if ignored {
+                // Keep the noise out of the smoothing and octave history
+                self.forget_pitch();
+            }
+        } else {
+            // If no clear pitch is detected, you can log it (debug level)
+            debug!(target: PITCH_TARGET, "Input: No clear pitch detected in this audio segment.");
+            self.forget_pitch();
+        }
+
+        if !low_clarity && trusted_frequency.is_none() {
+            self.last_clear = None;
+        }
+        if low_clarity {
+            match self.config.low_clarity {
+                LowClarity::Release => {}
+                LowClarity::Hold => {
+                    if let Some((frequency, key)) = self.last_clear {
+                        trusted_frequency = Some(frequency);
+                        new_key_to_press = key;
+                    }
+                }
+                LowClarity::Ignore => return WindowOutcome { pitch, key: None, events: Vec::new(), text: None, measurement: None, axis: None, curves: Vec::new() },
+            }
+        }
+        if ignored {
+            let now = self.clock.now();
+            let since = *self.ignored_since.get_or_insert(now);
+            if now.duration_since(since) < IGNORED_PITCH_HOLD {
+                // Like a brief dropout: the active key neither repeats nor releases yet
+                return WindowOutcome { pitch, key: None, events: Vec::new(), text: None, measurement: None, axis: None, curves: Vec::new() };
+            }
+        } else {
+            self.ignored_since = None;
+        }
+
+        let events = if let Some(intervals) = self.intervals.as_mut() {
+            intervals.update(trusted_frequency, self.clock.now())
+        } else if let Some(voices) = self.voices.as_mut() {
+            let (key, events) = voices.update(trusted_frequency, self.clock.now());
+            new_key_to_press = key;
+            events
+        } else if self.latch.is_some() {
+            self.update_latch(new_key_to_press)
+        } else {
+            let previous = self.keys.active_key();
+            let band = trusted_frequency
+                .and_then(|frequency| self.mapping.band_for(frequency))
+                .filter(|band| band.key() == new_key_to_press);
+            let tap = band.and_then(Band::tap_key);
+            let timing = band.map_or(BandTiming::GLOBAL, |band| band.timing);
+            let events = self.keys.update_band(new_key_to_press, tap, timing, self.clock.now());
+            match self.config.trigger {
+                Trigger::Onset => events,
+                Trigger::Offset => defer_to_offset(previous, self.keys.active_key()),
+            }
+        };
+        let mut text = self.update_text(trusted_frequency, mapped);
+        let events = match self.patterns.as_mut() {
+            Some(matcher) => {
+                let (mut events, fired) = matcher.feed(events, self.clock.now());
+                match fired {
+                    Some(PatternAction::Key(key)) => events.extend([KeyEvent::Press(key), KeyEvent::Release(key)]),
+                    Some(PatternAction::Text(pattern_text)) => text.get_or_insert_default().push_str(&pattern_text),
+                    None => {}
+                }
+                events
+            }
+            None => events,
+        };
+        WindowOutcome { pitch, key: new_key_to_press, events, text, measurement: None, axis: None, curves: Vec::new() }
+    }
+
+    #[cfg_attr(not(feature = "scripting"), allow(unused_variables))]
+    fn key_for(&mut self, frequency: f32, clarity: f32) -> Option<Key> {
+        #[cfg(feature = "scripting")]
+        if let Some(script) = self.script.as_mut() {
+            return script.map(frequency, clarity, self.window_rms);
+        }
+        self.mapping.key_for(frequency)
+    }
+
+    // Between two bands of the mapping rather than beyond all of them. A script decides for
+    // itself.
+    fn in_gap(&self, frequency: f32) -> bool {
+        #[cfg(feature = "scripting")]
+        if self.script.is_some() {
+            return false;
+        }
+        self.mapping.in_gap(frequency)
+    }
+
+    // The key for a pitch in a gap, under --gap-behavior
+    fn across_gap(&self, frequency: f32) -> Option<Key> {
+        let active = self.keys.active_key()?;
+        match self.config.gap {
+            GapBehavior::Hold => {
+                debug!(target: PITCH_TARGET, "Input: {:.2} Hz is in a gap between bands; holding '{:?}'.", frequency, active);
+                Some(active)
+            }
+            GapBehavior::Release => {
+                debug!(target: PITCH_TARGET, "Input: {:.2} Hz is in a gap between bands; releasing '{:?}'.", frequency, active);
+                None
+            }
+        }
+    }
+
+    // The custom mapper's choice for a detection; `None` without a mapper, or with a script,
+    // which takes precedence
+    fn map_custom(&mut self, frequency: f32, clarity: f32) -> Option<Option<Action>> {
+        let mapper = self.mapper.as_ref()?;
+        #[cfg(feature = "scripting")]
+        if self.script.is_some() {
+            return None;
+        }
+        if self.recent.len() == RECENT_WINDOWS {
+            self.recent.remove(0);
+        }
+        self.recent.push(frequency);
+        let ctx = MapContext { frequency, clarity, recent: &self.recent, active_key: self.keys.active_key() };
+        Some(mapper.map(&ctx))
+    }
+
+    // `mapped` is the custom mapper's choice for the window, when there is a mapper
+    fn update_text(&mut self, frequency: Option<f32>, mapped: Option<Option<Action>>) -> Option<String> {
+        let target = match mapped {
+            Some(Some(Action::Text(text))) => frequency.map(|_| (None, text)),
+            Some(_) => None,
+            None => frequency.and_then(|freq| {
+                let index = self.mapping.band_index_for(freq)?;
+                match &self.mapping.bands()[index].action {
+                    BandAction::Text(text) => Some((Some(index), text.clone())),
+                    _ => None,
+                }
+            }),
+        };
+        let text = self.text.update(target)?;
+        info!("Action: Typing {:?}.", text);
+        Some(text)
+    }
+
+    fn update_latch(&mut self, new_key_to_press: Option<Key>) -> Vec<KeyEvent> {
+        let Some(latch) = self.latch.as_mut() else {
+            return Vec::new();
+        };
+        match latch.update(new_key_to_press, self.clock.now()) {
+            Some(LatchToggle::Down(key)) => {
+                info!("Action: Latching key '{:?}' down.", key);
+                vec![KeyEvent::Press(key)]
+            }
+            Some(LatchToggle::Up(key)) => {
+                info!("Action: Unlatching key '{:?}'.", key);
+                vec![KeyEvent::Release(key)]
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Releases every latched key (latch mode) and the key of a `hold` band's note, e.g. when
+    /// the input is lost or on shutdown. Returns the keys the caller must let go of.
+    pub fn release_latched(&mut self) -> Vec<Key> {
+        let mut keys = self.latch.as_mut().map(LatchState::release_all).unwrap_or_default();
+        keys.extend(self.keys.release_held());
+        keys
+    }
+
+    /// Starts over, e.g. after the input was reopened, on resuming from a pause or after the
+    /// idle timeout: fresh detector, no pitch history, no active key or note timing, no queued
+    /// samples. Otherwise the smoothing of the last note before the break would bend the first
+    /// one after it, and could press the wrong key. Latched keys are kept; release them first
+    /// with [`release_latched`](Self::release_latched).
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        // A fresh filter, without the stream from before
+        self.band_limit = None;
+        self.refresh_band_limit();
+        self.detector = build_detector(self.config.detector, self.config.window_size);
+        self.forget_pitch();
+        if let Some(gate) = self.level_gate.as_mut() {
+            gate.reset();
+        }
+        if let Some(latch) = self.latch.as_mut() {
+            latch.reset();
+        }
+        self.keys.reset();
+        self.text = TextTrigger::new(self.config.text_debounce);
+        self.ignored_since = None;
+        self.last_clear = None;
+        if let Some(voices) = self.voices.as_mut() {
+            voices.reset();
+        }
+        if let Some(matcher) = self.patterns.as_mut() {
+            matcher.reset();
+        }
+        if let Some((_, detector)) = self.percussive.as_mut() {
+            detector.reset();
+        }
+        if let Some(axis) = self.axis.as_mut() {
+            axis.reset();
+        }
+        if let Some(curves) = self.curves.as_mut() {
+            curves.reset();
+        }
+        if let Some(intervals) = self.intervals.as_mut() {
+            intervals.reset();
+        }
+    }
+
+    // Drops the history of the pitch so far, when it is lost and on a reset: the smoothing,
+    // vibrato and octave history and the trajectory for the mapper
+    fn forget_pitch(&mut self) {
+        self.smoother.reset();
+        if let Some(vibrato) = self.vibrato.as_mut() {
+            vibrato.reset();
+        }
+        if let Some(corrector) = self.octave_corrector.as_mut() {
+            corrector.reset();
+        }
+        self.recent.clear();
+    }
+}
+
+// Offset trigger: nothing while a note is held; one press of its key once it ends, either to
+// silence or to another note.
+fn defer_to_offset(previous: Option<Key>, current: Option<Key>) -> Vec<KeyEvent> {
+    match previous {
+        Some(key) if current != Some(key) => {
+            info!("Action: Note on '{:?}' ended. Sending deferred press!", key);
+            vec![KeyEvent::Press(key), KeyEvent::Release(key)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::key_state::SwitchConsensus;
+    use crate::mapping::Band;
+    use std::time::Duration;
+
+    const WINDOW: Duration = Duration::from_millis(43);
+
+    // The minimum note duration and switch consensus have their own tests in key_state; here
+    // keys press and switch on a note's first window
+    fn presses_at_once() -> KeyTiming {
+        KeyTiming { min_note: Duration::ZERO, switch: SwitchConsensus::IMMEDIATE, ..KeyTiming::default() }
+    }
+
+    /// Replays one detection per window, advancing the clock by a window each time.
+    fn replay(mode: KeyMode, script: &[Option<f32>]) -> Vec<KeyEvent> {
+        replay_with(EngineConfig { mode, timing: presses_at_once(), ..EngineConfig::default() }, script)
+    }
+
+    fn replay_with(config: EngineConfig, script: &[Option<f32>]) -> Vec<KeyEvent> {
+        let clock = ManualClock::new();
+        let mut engine = PitchToKeyEngine::new(config, Mapping::default(), clock.clone());
+        let mut events = Vec::new();
+        for &freq in script {
+            events.extend(engine.process_detection(freq.map(|freq| (freq, 0.9))).events);
+            clock.advance(WINDOW);
+        }
+        events
+    }
+
+    #[test]
+    fn replays_a_scripted_pitch_sequence() {
+        let a = Key::Layout('a');
+        let z = Key::Layout('z');
+        // 250 Hz held for 12 windows (~516ms), a dropout, then 210 Hz for 2 windows and silence.
+        let mut script = vec![Some(250.0); 12];
+        script.extend([None, Some(210.0), Some(210.0), None, None]);
+
+        // The 250ms hold threshold is crossed at window 6 (258ms), the next 100ms repeat
+        // interval at window 9 (387ms); the note ends before another interval passes.
+        assert_eq!(
+            replay(KeyMode::Repeat, &script),
+            vec![
+                KeyEvent::Press(a),
+                KeyEvent::Repeat(a),
+                KeyEvent::Repeat(a),
+                KeyEvent::Release(a),
+                KeyEvent::Press(z),
+                KeyEvent::Release(z),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_reset_keeps_the_last_note_out_of_the_next_one() {
+        let config = EngineConfig {
+            smoothing: SmoothingKind::Median,
+            median_window: 5,
+            timing: presses_at_once(),
+            ..EngineConfig::default()
+        };
+        let clock = ManualClock::new();
+        let mut engine = PitchToKeyEngine::new(config, Mapping::default(), clock.clone());
+        let window = |engine: &mut PitchToKeyEngine<ManualClock>, freq: f32| {
+            clock.advance(WINDOW);
+            engine.process_detection(Some((freq, 0.9))).events
+        };
+        for _ in 0..4 {
+            window(&mut engine, 250.0);
+        }
+        // E.g. paused mid-note: without a reset, the median would still say 250 Hz
+        engine.reset();
+        assert_eq!(window(&mut engine, 210.0), vec![KeyEvent::Press(Key::Layout('z'))]);
+    }
+
+    #[test]
+    fn normalized_windows_are_still_gated_on_their_raw_power() {
+        let tone = |amplitude: f32| -> Vec<f32> {
+            (0..2048).map(|i| amplitude * (std::f32::consts::TAU * 250.0 * i as f32 / 44_100.0).sin()).collect()
+        };
+        let config = EngineConfig { normalize: true, timing: presses_at_once(), ..EngineConfig::default() };
+        let mut engine = PitchToKeyEngine::new(config, Mapping::default(), ManualClock::new());
+        // Power 2048 * 0.01^2 / 2, far below the 0.7 gate, however much it would be scaled up
+        assert_eq!(engine.process(&tone(0.01)).pitch, None);
+        let (freq, _) = engine.process(&tone(0.05)).pitch.expect("a tone above the gate is detected");
+        assert!((freq - 250.0).abs() < 2.0, "detected {} Hz", freq);
+    }
+
+    #[test]
+    fn band_limiting_keeps_a_loud_tone_above_the_bands_from_masking_the_voice() {
+        let detect = |band_limit: bool| {
+            let config = EngineConfig { band_limit, timing: presses_at_once(), ..EngineConfig::default() };
+            let mapping = Mapping::new(vec![Band::new(100.0, 200.0, Key::Layout('a'))]);
+            let mut engine = PitchToKeyEngine::new(config, mapping, ManualClock::new());
+            // A 150 Hz voice under a louder 500 Hz whine, as one stream
+            let sample = |i: usize| {
+                let t = i as f32 / 44_100.0;
+                0.3 * (std::f32::consts::TAU * 150.0 * t).sin() + 0.5 * (std::f32::consts::TAU * 500.0 * t).sin()
+            };
+            let mut pitch = None;
+            for start in (0..8).map(|window| window * 2048) {
+                let window: Vec<f32> = (start..start + 2048).map(sample).collect();
+                pitch = engine.process(&window).pitch.map(|(freq, _)| freq);
+            }
+            pitch
+        };
+        let filtered = detect(true).expect("the voice is detected");
+        assert!((filtered - 150.0).abs() < 2.0, "detected {} Hz", filtered);
+        assert!(detect(false).is_none_or(|freq| (freq - 150.0).abs() > 2.0), "{:?}", detect(false));
+    }
+
+    #[test]
+    fn the_level_gate_keeps_a_fading_tail_from_pressing_again() {
+        let a = Key::Layout('a');
+        let config = EngineConfig { level_gate: Some((0.1, 0.04)), timing: presses_at_once(), ..EngineConfig::default() };
+        let mut engine = PitchToKeyEngine::new(config, Mapping::default(), ManualClock::new());
+        let mut window = |rms: f32| {
+            let power = rms * rms * 2048.0;
+            engine.process_measured(Measurement { detection: Some((250.0, 0.9)), power }).events
+        };
+        assert_eq!(window(0.06), vec![]);
+        assert_eq!(window(0.2), vec![KeyEvent::Press(a)]);
+        assert_eq!(window(0.05), vec![]);
+        assert_eq!(window(0.03), vec![KeyEvent::Release(a)]);
+        // The tail flickers back above the release level, but not the attack level
+        assert_eq!(window(0.06), vec![]);
+        assert_eq!(window(0.12), vec![KeyEvent::Press(a)]);
+    }
+
+    #[test]
+    fn intervals_press_their_keys_instead_of_the_bands() {
+        use crate::intervals::{IntervalDirection, IntervalRule};
+        let up = Key::Layout('u');
+        let config = EngineConfig { timing: presses_at_once(), ..EngineConfig::default() };
+        let mapping = Mapping::new(vec![Band::new(290.0, 310.0, Key::Layout('b')), Band::ignore(140.0, 160.0)]);
+        let clock = ManualClock::new();
+        let mut engine = PitchToKeyEngine::new(config, mapping, clock.clone());
+        let leaps = IntervalMapping {
+            rules: vec![IntervalRule { direction: IntervalDirection::Up, min_semitones: 5.0, max_semitones: None, key: up }],
+            tolerance_cents: 50.0,
+        };
+        engine.set_intervals(leaps, crate::notes::DEFAULT_A4_HZ);
+        let mut events = Vec::new();
+        // A fifth up, then a hum in the ignore band, which makes no interval of its own
+        for freq in [200.0, 200.0, 300.0, 300.0, 150.0, 150.0, 150.0, 150.0, 150.0] {
+            events.extend(engine.process_detection(Some((freq, 0.9))).events);
+            clock.advance(WINDOW);
+        }
+        assert_eq!(events, vec![KeyEvent::Press(up), KeyEvent::Release(up)]);
+    }
+
+    #[test]
+    fn the_axis_follows_the_smoothed_pitch() {
+        let config = EngineConfig { smoothing: SmoothingKind::Ema, ema_alpha: 0.5, ..EngineConfig::default() };
+        let mut engine = PitchToKeyEngine::new(config, Mapping::new(Vec::new()), ManualClock::new());
+        engine.set_axis(AxisMapping {
+            axis: crate::axis::StickAxis::LeftX,
+            low_hz: 100.0,
+            high_hz: 300.0,
+            scale: crate::axis::AxisScale::Linear,
+            center: 0.0,
+            decay: Duration::ZERO,
+        });
+        assert_eq!(engine.process_detection(Some((200.0, 0.9))).axis, Some(0.0));
+        // Smoothed to 250 Hz, three quarters of the way up the range
+        assert_eq!(engine.process_detection(Some((300.0, 0.9))).axis, Some(0.5));
+        assert_eq!(engine.process_detection(None).axis, Some(0.0));
+        assert_eq!(engine.process_detection(None).axis, None);
+    }
+
+    #[test]
+    fn letting_go_centers_every_stick_the_pitch_moves() {
+        use crate::axis::StickAxis;
+        use crate::curve::{CurveOutput, CurveShape};
+        let mut engine = PitchToKeyEngine::new(EngineConfig::default(), Mapping::new(Vec::new()), ManualClock::new());
+        assert_eq!(engine.stick_centers(), Vec::new());
+        engine.set_axis(AxisMapping {
+            axis: StickAxis::LeftX,
+            low_hz: 100.0,
+            high_hz: 300.0,
+            scale: crate::axis::AxisScale::Linear,
+            center: -1.0,
+            decay: Duration::ZERO,
+        });
+        let curve = |output| Curve { output, shape: CurveShape::Linear, freq_min: 100.0, freq_max: 300.0, out_min: -1.0, out_max: 1.0 };
+        engine.set_curves(vec![curve(CurveOutput::MouseX), curve(CurveOutput::RightY)]);
+        engine.process_detection(Some((300.0, 0.9)));
+        // The mouse only moves while there's a pitch, so it has nothing to go back to
+        assert_eq!(
+            engine.stick_centers(),
+            vec![CurveMove::Stick(StickAxis::LeftX, -1.0), CurveMove::Stick(StickAxis::RightY, 0.0)]
+        );
+    }
+
+    #[test]
+    fn a_band_clarity_override_replaces_the_global_press_clarity() {
+        let (down, a) = (Key::DownArrow, Key::Layout('a'));
+        let murky = Band { min_clarity: Some(0.5), ..Band::new(100.0, 115.0, down) };
+        let mapping = Mapping::new(vec![murky, Band::new(230.1, 270.0, a)]);
+        let config = EngineConfig { min_press_clarity: 0.8, timing: presses_at_once(), ..EngineConfig::default() };
+        let mut engine = PitchToKeyEngine::new(config, mapping, ManualClock::new());
+        assert_eq!(engine.process_detection(Some((110.0, 0.6))).events, vec![KeyEvent::Press(down)]);
+        assert_eq!(engine.process_detection(Some((110.0, 0.4))).events, vec![KeyEvent::Release(down)]);
+        assert_eq!(engine.process_detection(Some((250.0, 0.6))).events, vec![]);
+        assert_eq!(engine.process_detection(Some((250.0, 0.9))).events, vec![KeyEvent::Press(a)]);
+    }
+
+    #[test]
+    fn band_power_overrides_admit_quiet_notes_only_in_their_band() {
+        let tone = |freq: f32, amplitude: f32| -> Vec<f32> {
+            (0..2048).map(|i| amplitude * (std::f32::consts::TAU * freq * i as f32 / 44_100.0).sin()).collect()
+        };
+        let quiet = Band { min_power: Some(0.1), ..Band::new(100.0, 115.0, Key::DownArrow) };
+        let mapping = Mapping::new(vec![quiet, Band::new(230.1, 270.0, Key::Layout('a'))]);
+        let config = EngineConfig { timing: presses_at_once(), ..EngineConfig::default() };
+        let mut engine = PitchToKeyEngine::new(config, mapping, ManualClock::new());
+        // Power 2048 * 0.015^2 / 2 = 0.23: under the 0.7 gate, over the band's 0.1
+        assert_eq!(engine.process(&tone(110.0, 0.015)).key, Some(Key::DownArrow));
+        assert_eq!(engine.process(&tone(250.0, 0.015)).pitch, None);
+        assert_eq!(engine.process(&tone(250.0, 0.05)).key, Some(Key::Layout('a')));
+    }
+
+    #[test]
+    fn pitches_below_the_minimum_count_as_silence() {
+        let mapping = Mapping::default().transposed(3);
+        let config = EngineConfig { min_hz: Some(500.0), timing: presses_at_once(), ..EngineConfig::default() };
+        let mut engine = PitchToKeyEngine::new(config, mapping, ManualClock::new());
+        let outcome = engine.process_detection(Some((2000.0, 0.9)));
+        assert_eq!(outcome.events, vec![KeyEvent::Press(Key::Layout('a'))]);
+        let outcome = engine.process_detection(Some((250.0, 0.9)));
+        assert_eq!(outcome.pitch, None);
+        assert_eq!(outcome.events, vec![KeyEvent::Release(Key::Layout('a'))]);
+    }
+
+    #[test]
+    fn out_of_band_pitch_releases_the_active_key() {
+        let a = Key::Layout('a');
+        assert_eq!(
+            replay(KeyMode::Repeat, &[Some(250.0), Some(50.0), Some(50.0)]),
+            vec![KeyEvent::Press(a), KeyEvent::Release(a)]
+        );
+    }
+
+    #[test]
+    fn gap_behavior_decides_between_bands_but_not_beyond_them() {
+        let a = Key::Layout('a');
+        let mapping = Mapping::new(vec![Band::new(200.0, 300.0, a), Band::new(400.0, 500.0, Key::Layout('b'))]);
+        let run = |gap, freqs: &[f32]| {
+            let config = EngineConfig { gap, timing: presses_at_once(), ..EngineConfig::default() };
+            let mut engine = PitchToKeyEngine::new(config, mapping.clone(), ManualClock::new());
+            let mut events = Vec::new();
+            for &freq in freqs {
+                events.extend(engine.process_detection(Some((freq, 0.9))).events);
+            }
+            events
+        };
+        assert_eq!(run(GapBehavior::Hold, &[250.0, 350.0, 350.0, 250.0]), vec![KeyEvent::Press(a)]);
+        assert_eq!(run(GapBehavior::Release, &[250.0, 350.0]), vec![KeyEvent::Press(a), KeyEvent::Release(a)]);
+        assert_eq!(run(GapBehavior::Hold, &[250.0, 600.0]), vec![KeyEvent::Press(a), KeyEvent::Release(a)]);
+    }
+
+    #[test]
+    fn latch_mode_toggles_on_each_fresh_note() {
+        let a = Key::Layout('a');
+        let script = [Some(250.0), Some(250.0), Some(250.0), None, Some(250.0), Some(250.0)];
+        assert_eq!(replay(KeyMode::Latch, &script), vec![KeyEvent::Press(a), KeyEvent::Release(a)]);
+    }
+
+    #[test]
+    fn latch_mode_ignores_notes_shorter_than_the_minimum() {
+        let a = Key::Layout('a');
+        let timing = KeyTiming { min_note: Duration::from_millis(100), ..presses_at_once() };
+        let config = EngineConfig { mode: KeyMode::Latch, timing, ..EngineConfig::default() };
+        // Two windows (43ms apart) make the latch debounce but not the minimum; four make both
+        let mut script = vec![Some(250.0), Some(250.0), None];
+        script.extend([Some(250.0); 4]);
+        assert_eq!(replay_with(config, &script), vec![KeyEvent::Press(a)]);
+    }
+
+    #[test]
+    fn offset_trigger_presses_once_when_the_note_ends() {
+        let a = Key::Layout('a');
+        let z = Key::Layout('z');
+        let config = EngineConfig { trigger: Trigger::Offset, timing: presses_at_once(), ..EngineConfig::default() };
+        // Long enough on 250 Hz to repeat in onset mode, then straight to 210 Hz and silence.
+        let mut script = vec![Some(250.0); 12];
+        script.extend([Some(210.0), Some(210.0), None]);
+        assert_eq!(
+            replay_with(config, &script),
+            vec![KeyEvent::Press(a), KeyEvent::Release(a), KeyEvent::Press(z), KeyEvent::Release(z)]
+        );
+    }
+
+    #[test]
+    fn low_clarity_detections_do_not_press() {
+        let clock = ManualClock::new();
+        let config = EngineConfig { min_press_clarity: 0.8, timing: presses_at_once(), ..EngineConfig::default() };
+        let mut engine = PitchToKeyEngine::new(config, Mapping::default(), clock);
+        let a = Key::Layout('a');
+
+        let unpitched = engine.process_detection(Some((250.0, 0.5)));
+        assert_eq!(unpitched.pitch, Some((250.0, 0.5)));
+        assert_eq!((unpitched.key, unpitched.events), (None, vec![]));
+
+        assert_eq!(engine.process_detection(Some((250.0, 0.9))).events, vec![KeyEvent::Press(a)]);
+        // A clap mid-note counts as losing the pitch
+        assert_eq!(engine.process_detection(Some((250.0, 0.3))).events, vec![KeyEvent::Release(a)]);
+    }
+
+    #[test]
+    fn low_clarity_behavior_decides_what_happens_to_the_held_key() {
+        let a = Key::Layout('a');
+        // A clear note, a run of borderline windows long enough to repeat, the clear note again
+        let mut script = vec![Some((250.0, 0.9))];
+        script.extend([Some((250.0, 0.5)); 10]);
+        script.push(Some((250.0, 0.9)));
+        let run = |low_clarity| {
+            let clock = ManualClock::new();
+            let config =
+                EngineConfig { min_press_clarity: 0.8, low_clarity, timing: presses_at_once(), ..EngineConfig::default() };
+            let mut engine = PitchToKeyEngine::new(config, Mapping::default(), clock.clone());
+            let mut events = Vec::new();
+            for &detection in &script {
+                events.extend(engine.process_detection(detection).events);
+                clock.advance(WINDOW);
+            }
+            events
+        };
+        assert_eq!(run(LowClarity::Release), vec![KeyEvent::Press(a), KeyEvent::Release(a), KeyEvent::Press(a)]);
+        assert_eq!(run(LowClarity::Hold), vec![KeyEvent::Press(a), KeyEvent::Repeat(a), KeyEvent::Repeat(a)]);
+        // Nothing happens during the borderline windows; the note carries on as held
+        assert_eq!(run(LowClarity::Ignore), vec![KeyEvent::Press(a), KeyEvent::Repeat(a)]);
+    }
+
+    #[test]
+    fn text_bands_type_once_per_note() {
+        let clock = ManualClock::new();
+        let mapping = Mapping::new(vec![Band::text(300.0, 340.0, "gg"), Band::new(230.0, 270.0, Key::Layout('a'))]);
+        let mut engine = PitchToKeyEngine::new(EngineConfig::default(), mapping, clock.clone());
+        let mut typed = Vec::new();
+        // A one-window blip, a long note, then the same note again after a gap
+        let mut script = vec![Some(320.0), Some(250.0)];
+        script.extend([Some(320.0); 20]);
+        script.extend([None, Some(320.0), Some(320.0)]);
+        for freq in script {
+            let outcome = engine.process_detection(freq.map(|freq| (freq, 0.9)));
+            assert!(outcome.key.is_none() || outcome.text.is_none());
+            typed.extend(outcome.text);
+            clock.advance(WINDOW);
+        }
+        assert_eq!(typed, vec!["gg".to_string(), "gg".to_string()]);
+    }
+
+    #[test]
+    fn ignore_band_hum_does_not_press_or_release_right_away() {
+        let a = Key::Layout('a');
+        let mapping = Mapping::new(vec![Band::new(230.0, 270.0, a), Band::ignore(175.0, 185.0)]);
+        let clock = ManualClock::new();
+        let mut engine = PitchToKeyEngine::new(EngineConfig::default(), mapping, clock.clone());
+        let mut events = Vec::new();
+        // A hum window mid-note, then the hum alone long enough to count as silence
+        let mut script = vec![Some(250.0), Some(180.0), Some(250.0)];
+        script.extend([Some(180.0); 6]);
+        for freq in script {
+            events.extend(engine.process_detection(freq.map(|freq| (freq, 0.9))).events);
+            clock.advance(WINDOW);
+        }
+        assert_eq!(events, vec![KeyEvent::Press(a), KeyEvent::Release(a)]);
+    }
+
+    #[test]
+    fn a_custom_mapper_sees_the_pitch_trajectory() {
+        // Up while the pitch rises, Down while it falls, whatever the frequency
+        struct Glide;
+        impl FrequencyMapper for Glide {
+            fn map(&self, ctx: &MapContext) -> Option<Action> {
+                let [.., previous, current] = ctx.recent else {
+                    return None;
+                };
+                match current - previous {
+                    step if step > 1.0 => Some(Action::Key(Key::UpArrow)),
+                    step if step < -1.0 => Some(Action::Key(Key::DownArrow)),
+                    _ => None,
+                }
+            }
+        }
+        let config = EngineConfig { timing: presses_at_once(), ..EngineConfig::default() };
+        let clock = ManualClock::new();
+        let mut engine = PitchToKeyEngine::new(config, Mapping::default(), clock.clone());
+        engine.set_mapper(Box::new(Glide));
+        let mut keys = Vec::new();
+        for freq in [Some(200.0), Some(210.0), Some(220.0), None, Some(220.0), Some(200.0)] {
+            keys.push(engine.process_detection(freq.map(|freq| (freq, 0.9))).key);
+            clock.advance(WINDOW);
+        }
+        // The gap starts a new trajectory
+        assert_eq!(keys, [None, Some(Key::UpArrow), Some(Key::UpArrow), None, None, Some(Key::DownArrow)]);
+    }
+}
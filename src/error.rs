@@ -0,0 +1,167 @@
+use std::path::PathBuf;
+use thiserror::Error;
+
+pub type Result<T, E = PitchuError> = std::result::Result<T, E>;
+
+/// Everything that can stop pitchu. Each variant maps to its own process exit code.
+#[derive(Debug, Error)]
+pub enum PitchuError {
+    /// The requested audio host isn't compiled in or can't be used on this system.
+    #[error("audio host '{name}' is not usable: {reason}")]
+    Host { name: String, reason: String },
+
+    #[error("could not enumerate audio devices on host {host}")]
+    DeviceEnumeration {
+        host: String,
+        #[source]
+        source: cpal::DevicesError,
+    },
+
+    /// No device matched (or there's no default device at all).
+    #[error("{0}")]
+    NoDevice(String),
+
+    /// The device can't deliver audio in a form pitchu can use.
+    #[error("unsupported audio configuration on '{device}'")]
+    UnsupportedConfig {
+        device: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("could not build audio stream on '{device}'{}", quirk_note(.quirk))]
+    StreamBuild {
+        device: String,
+        quirk: Option<String>,
+        #[source]
+        source: cpal::BuildStreamError,
+    },
+
+    #[error("could not start audio stream on '{device}'{}", quirk_note(.quirk))]
+    StreamPlay {
+        device: String,
+        quirk: Option<String>,
+        #[source]
+        source: cpal::PlayStreamError,
+    },
+
+    /// The input went away and reconnecting gave up.
+    #[error("audio input device '{0}' was lost and could not be reopened")]
+    DeviceLost(String),
+
+    /// The MIDI input (`--input midi`) couldn't be opened or went away.
+    #[error("MIDI input: {0}")]
+    Midi(String),
+
+    #[error("could not read config file {}", .path.display())]
+    ConfigRead {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("could not parse config file {}", .path.display())]
+    ConfigParse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    /// The config file parsed, but a value in it is unusable.
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+
+    /// The config file's mapping script doesn't load.
+    #[error("mapping script: {0}")]
+    Script(String),
+
+    /// The session file given to `pitchu replay` can't be read.
+    #[error("could not replay {}: {reason}", .path.display())]
+    Session { path: PathBuf, reason: String },
+
+    /// The `--simulate` CSV can't be read.
+    #[error("could not simulate {}: {reason}", .path.display())]
+    Simulation { path: PathBuf, reason: String },
+
+    /// The `--play` sequence can't be read.
+    #[error("could not play {}: {reason}", .path.display())]
+    Sequence { path: PathBuf, reason: String },
+
+    /// Pressing keys (or whatever the output is) failed.
+    #[error("{backend} output failed")]
+    OutputBackend {
+        backend: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// Setting up part of the program itself (terminal UI, sockets, signal handler) failed.
+    #[error("could not {action}")]
+    Setup {
+        action: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+fn quirk_note(quirk: &Option<String>) -> String {
+    quirk.as_ref().map(|quirk| format!(" (note: {})", quirk)).unwrap_or_default()
+}
+
+impl PitchuError {
+    /// Process exit code for this error. 2 is left to argument parsing errors.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            PitchuError::Setup { .. } => 1,
+            PitchuError::Host { .. } => 10,
+            PitchuError::DeviceEnumeration { .. } => 11,
+            PitchuError::NoDevice(_) => 12,
+            PitchuError::UnsupportedConfig { .. } => 13,
+            PitchuError::StreamBuild { .. } => 14,
+            PitchuError::StreamPlay { .. } => 15,
+            PitchuError::DeviceLost(_) => 16,
+            PitchuError::Midi(_) => 17,
+            PitchuError::ConfigRead { .. } | PitchuError::ConfigParse { .. } | PitchuError::InvalidConfig(_) => 20,
+            PitchuError::Script(_) => 21,
+            PitchuError::Session { .. } | PitchuError::Simulation { .. } | PitchuError::Sequence { .. } => 22,
+            PitchuError::OutputBackend { .. } => 30,
+        }
+    }
+
+    /// What the user can do about it.
+    pub fn advice(&self) -> Option<&'static str> {
+        match self {
+            PitchuError::Host { .. } => Some("run `pitchu list-devices` to see the hosts available in this build."),
+            PitchuError::DeviceEnumeration { .. } => {
+                Some("check that the system's audio service (PulseAudio, PipeWire, JACK, ...) is running.")
+            }
+            PitchuError::NoDevice(_) => Some(
+                "no input device: check that a microphone is connected and not in exclusive use, \
+                 and run `pitchu list-devices` to see what's available.",
+            ),
+            PitchuError::UnsupportedConfig { .. } => Some("try a different device with --device."),
+            PitchuError::StreamBuild { .. } | PitchuError::StreamPlay { .. } => {
+                Some("the device may be in exclusive use by another application; close it or pick another --device.")
+            }
+            PitchuError::DeviceLost(_) => {
+                Some("reconnect the device, or allow more time with --max-reconnect-attempts / --reconnect-interval-ms.")
+            }
+            PitchuError::Midi(_) => {
+                Some("check that the MIDI keyboard is connected, and run `pitchu list-midi-ports` to see the port names.")
+            }
+            PitchuError::ConfigRead { .. } | PitchuError::ConfigParse { .. } | PitchuError::InvalidConfig(_) => {
+                Some("fix the file passed with --config; see the readme for its format.")
+            }
+            PitchuError::Script(_) => Some("fix the script named by `script` in the config file; see the readme."),
+            PitchuError::Session { .. } => Some("pass a file written by --record-session."),
+            PitchuError::Simulation { .. } => Some("each row of the CSV is time_ms,frequency,clarity; see the readme."),
+            PitchuError::Sequence { .. } => {
+                Some("each [[step]] of the sequence has a key, and optionally duration_ms and delay_ms; see the readme.")
+            }
+            PitchuError::OutputBackend { .. } => {
+                Some("check that pitchu is allowed to simulate input (on macOS: Privacy & Security > Accessibility).")
+            }
+            PitchuError::Setup { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use pitchu::engine::Measurement;
+use pitchu::keys::{key_name, KeyEvent};
+use pitchu::session::{SessionRecord, SESSION_VERSION};
+use serde_json::json;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+static SESSION_START: OnceLock<Instant> = OnceLock::new();
+
+/// Monotonic time since the session started, shared by the log file and the event log so
+/// their timestamps line up.
+pub fn session_elapsed() -> Duration {
+    SESSION_START.get_or_init(Instant::now).elapsed()
+}
+
+/// When a file is rotated: once it grows past `max_bytes` it becomes `<name>.1` (the previous
+/// `.1` becomes `.2`, and so on) and at most `keep` old files are kept.
+#[derive(Debug, Clone, Copy)]
+pub struct Rotation {
+    pub max_bytes: u64,
+    pub keep: usize,
+}
+
+enum Message {
+    Line(String),
+    Flush(mpsc::SyncSender<()>),
+}
+
+/// Appends lines to a file from a dedicated thread, so a slow disk never holds up the caller.
+pub struct FileSink {
+    sender: Sender<Message>,
+}
+
+impl FileSink {
+    /// Opens (or creates) `path` for appending. The file is opened here so problems with it are
+    /// reported to the caller; everything after that happens on the writer thread.
+    pub fn open(path: &Path, rotation: Option<Rotation>) -> io::Result<Self> {
+        FileSink::start(path, open_append(path)?, rotation)
+    }
+
+    /// Like [`open`](Self::open), but replaces whatever `path` held.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        FileSink::start(path, File::create(path)?, None)
+    }
+
+    fn start(path: &Path, file: File, rotation: Option<Rotation>) -> io::Result<Self> {
+        let written = file.metadata()?.len();
+        let (sender, receiver) = mpsc::channel();
+        let writer = Writer { path: path.to_path_buf(), file: BufWriter::new(file), written, rotation };
+        thread::Builder::new().name("pitchu-file-log".to_string()).spawn(move || writer.run(receiver))?;
+        // Starts the session clock if nothing has yet
+        session_elapsed();
+        Ok(FileSink { sender })
+    }
+
+    /// Queues `line` (without its newline).
+    pub fn write_line(&self, line: String) {
+        let _ = self.sender.send(Message::Line(line));
+    }
+
+    /// Waits until everything queued so far is on disk.
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::sync_channel(1);
+        if self.sender.send(Message::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+struct Writer {
+    path: PathBuf,
+    file: BufWriter<File>,
+    written: u64,
+    rotation: Option<Rotation>,
+}
+
+impl Writer {
+    fn run(mut self, receiver: Receiver<Message>) {
+        while let Ok(message) = receiver.recv() {
+            self.handle(message);
+            // Write out whatever else is already queued, then flush once
+            while let Ok(message) = receiver.try_recv() {
+                self.handle(message);
+            }
+            let _ = self.file.flush();
+        }
+        let _ = self.file.flush();
+    }
+
+    fn handle(&mut self, message: Message) {
+        match message {
+            Message::Line(line) => {
+                if let Err(e) = writeln!(self.file, "{}", line) {
+                    eprintln!("Could not write to {}: {}", self.path.display(), e);
+                    return;
+                }
+                self.written += line.len() as u64 + 1;
+                if let Some(rotation) = self.rotation
+                    && self.written >= rotation.max_bytes
+                    && let Err(e) = self.rotate(rotation.keep)
+                {
+                    // Not through `log`: this thread writes the log file itself
+                    eprintln!("Could not rotate {}: {}", self.path.display(), e);
+                }
+            }
+            Message::Flush(done) => {
+                let _ = self.file.flush();
+                let _ = done.send(());
+            }
+        }
+    }
+
+    fn rotate(&mut self, keep: usize) -> io::Result<()> {
+        self.file.flush()?;
+        let numbered = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        if keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(numbered(keep));
+            for n in (1..keep).rev() {
+                let from = numbered(n);
+                if from.exists() {
+                    std::fs::rename(&from, numbered(n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, numbered(1))?;
+        }
+        self.file = BufWriter::new(open_append(&self.path)?);
+        self.written = 0;
+        Ok(())
+    }
+}
+
+/// The `--event-log` file: one JSON object per line for every detected pitch, key event and
+/// typed text, timestamped with [`session_elapsed`] in milliseconds.
+pub struct EventLog {
+    sink: FileSink,
+}
+
+impl EventLog {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(EventLog { sink: FileSink::open(path, None)? })
+    }
+
+    pub fn pitch(&self, channel: usize, freq: f32, clarity: f32) {
+        self.write(json!({
+            "type": "pitch",
+            "channel": channel,
+            "freq": rounded(freq, 100.0),
+            "clarity": rounded(clarity, 1000.0),
+        }));
+    }
+
+    pub fn key(&self, channel: usize, event: KeyEvent) {
+        let (action, key) = match event {
+            KeyEvent::Press(key) => ("press", key),
+            KeyEvent::Repeat(key) => ("repeat", key),
+            KeyEvent::Release(key) => ("release", key),
+            KeyEvent::Down(key) => ("down", key),
+            KeyEvent::Up(key) => ("up", key),
+        };
+        self.write(json!({ "type": "key", "channel": channel, "action": action, "key": key_name(key) }));
+    }
+
+    pub fn text(&self, channel: usize, text: &str) {
+        self.write(json!({ "type": "text", "channel": channel, "text": text }));
+    }
+
+    fn write(&self, mut entry: serde_json::Value) {
+        // Tenths of a millisecond are plenty and keep the lines short
+        entry["t_ms"] = json!((session_elapsed().as_secs_f64() * 10_000.0).round() / 10.0);
+        self.sink.write_line(entry.to_string());
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        self.sink.flush();
+    }
+}
+
+/// The `--record-session` file (see [`pitchu::session`]), replayable with `pitchu replay`.
+pub struct SessionRecorder {
+    sink: FileSink,
+}
+
+impl SessionRecorder {
+    pub fn create(path: &Path, sample_rate: usize, window_size: usize, channels: usize) -> io::Result<Self> {
+        let recorder = SessionRecorder { sink: FileSink::create(path)? };
+        recorder.write(&SessionRecord::Session { version: SESSION_VERSION, sample_rate, window_size, channels });
+        Ok(recorder)
+    }
+
+    pub fn window(&self, channel: usize, measurement: Measurement) {
+        self.write(&SessionRecord::window(session_elapsed(), channel, measurement));
+    }
+
+    pub fn key(&self, channel: usize, event: KeyEvent) {
+        self.write(&SessionRecord::key(session_elapsed(), channel, event));
+    }
+
+    pub fn text(&self, channel: usize, text: &str) {
+        self.write(&SessionRecord::text(session_elapsed(), channel, text.to_string()));
+    }
+
+    fn write(&self, record: &SessionRecord) {
+        if let Ok(line) = serde_json::to_string(record) {
+            self.sink.write_line(line);
+        }
+    }
+}
+
+impl Drop for SessionRecorder {
+    fn drop(&mut self) {
+        self.sink.flush();
+    }
+}
+
+// `value` rounded to 1/`steps`, as an f64 so it serializes without float noise (247.3, not
+// 247.300003)
+fn rounded(value: f32, steps: f64) -> f64 {
+    (f64::from(value) * steps).round() / steps
+}
@@ -0,0 +1,117 @@
+use enigo::Key;
+use std::collections::HashMap;
+
+/// A button (or D-pad direction) of the virtual controller used by `--output gamepad`, named
+/// after the Xbox layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    A,
+    B,
+    X,
+    Y,
+    /// Left shoulder (LB).
+    L,
+    /// Right shoulder (RB).
+    R,
+    /// Left trigger (LT), pulled all the way.
+    L2,
+    /// Right trigger (RT), pulled all the way.
+    R2,
+    /// Left stick click.
+    L3,
+    /// Right stick click.
+    R3,
+    Start,
+    Select,
+    Guide,
+    DpadUp,
+    DpadDown,
+    DpadLeft,
+    DpadRight,
+}
+
+const BUTTON_NAMES: &[(&str, Button)] = &[
+    ("A", Button::A),
+    ("B", Button::B),
+    ("X", Button::X),
+    ("Y", Button::Y),
+    ("L", Button::L),
+    ("R", Button::R),
+    ("L2", Button::L2),
+    ("R2", Button::R2),
+    ("L3", Button::L3),
+    ("R3", Button::R3),
+    ("Start", Button::Start),
+    ("Select", Button::Select),
+    ("Guide", Button::Guide),
+    ("DpadUp", Button::DpadUp),
+    ("DpadDown", Button::DpadDown),
+    ("DpadLeft", Button::DpadLeft),
+    ("DpadRight", Button::DpadRight),
+];
+
+impl Button {
+    /// The name config files use, e.g. `"DpadUp"`.
+    pub fn name(self) -> &'static str {
+        BUTTON_NAMES.iter().find(|(_, button)| *button == self).map_or("?", |(name, _)| name)
+    }
+}
+
+/// The button for `name` (case-insensitive), e.g. `A`, `Start` or `DpadLeft`.
+pub fn parse_button(name: &str) -> Option<Button> {
+    BUTTON_NAMES.iter().find(|(known, _)| known.eq_ignore_ascii_case(name)).map(|&(_, button)| button)
+}
+
+/// Which controller button each mapped key presses with `--output gamepad`.
+///
+/// The mapping itself still produces keys; this table translates them at the output, so every
+/// mapping (bands, presets, scripts) works with a controller too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ButtonMap {
+    buttons: HashMap<Key, Button>,
+}
+
+impl ButtonMap {
+    pub fn button_for(&self, key: Key) -> Option<Button> {
+        self.buttons.get(&key).copied()
+    }
+
+    /// Makes `key` press `button`, replacing what it pressed before.
+    pub fn set(&mut self, key: Key, button: Button) {
+        self.buttons.insert(key, button);
+    }
+}
+
+impl Default for ButtonMap {
+    /// The controller equivalents of the `mgba-default` preset's keys.
+    fn default() -> Self {
+        let buttons = [
+            (Key::UpArrow, Button::DpadUp),
+            (Key::DownArrow, Button::DpadDown),
+            (Key::LeftArrow, Button::DpadLeft),
+            (Key::RightArrow, Button::DpadRight),
+            (Key::Layout('x'), Button::A),
+            (Key::Layout('z'), Button::B),
+            (Key::Layout('a'), Button::L),
+            (Key::Layout('s'), Button::R),
+            (Key::Return, Button::Start),
+            (Key::Backspace, Button::Select),
+        ];
+        ButtonMap { buttons: buttons.into_iter().collect() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn button_names_round_trip() {
+        for &(name, button) in BUTTON_NAMES {
+            assert_eq!(parse_button(name), Some(button));
+            assert_eq!(button.name(), name);
+        }
+        assert_eq!(parse_button("dpadleft"), Some(Button::DpadLeft));
+        assert_eq!(parse_button("Turbo"), None);
+    }
+}
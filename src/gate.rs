@@ -0,0 +1,171 @@
+use log::info;
+use std::time::{Duration, Instant};
+
+// How quickly the noise floor estimate follows new silent windows (0.0 - 1.0)
+const FLOOR_SMOOTHING: f32 = 0.05;
+// The adaptive threshold never drops below this, so digital silence doesn't open the gate wide
+const MIN_ADAPTIVE_POWER: f32 = 0.05;
+// How often the current adaptive threshold is logged
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Sum of squared samples, the same "power" measure the McLeod detector gates on.
+pub fn window_power(window: &[f32]) -> f32 {
+    window.iter().map(|s| s * s).sum()
+}
+
+/// RMS level `--normalize` scales every window that passes the gate to.
+pub const NORMALIZED_RMS: f32 = 0.25;
+
+/// Scales `window` in place to an RMS of `target_rms`, so detection sees the same level
+/// however loud the input was. The gain is capped so no sample exceeds 1.0, and an all-zero
+/// window is left alone.
+pub fn normalize(window: &mut [f32], target_rms: f32) {
+    let rms = (window_power(window) / window.len().max(1) as f32).sqrt();
+    let peak = window.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    if rms <= 0.0 {
+        return;
+    }
+    let gain = (target_rms / rms).min(1.0 / peak);
+    for sample in window.iter_mut() {
+        *sample *= gain;
+    }
+}
+
+/// RMS of a window, from its [`window_power`].
+pub fn window_rms(power: f32, window_size: usize) -> f32 {
+    (power / window_size.max(1) as f32).sqrt()
+}
+
+/// A level gate with two thresholds (`--gate-attack` and `--gate-release`): it opens once
+/// the RMS reaches `attack` and stays open until it falls below `release`. A note fading out
+/// closes it for good, instead of flickering open and shut around a single threshold.
+pub struct HysteresisGate {
+    attack: f32,
+    release: f32,
+    open: bool,
+}
+
+impl HysteresisGate {
+    /// A `release` above `attack` is lowered to it, which makes a single threshold.
+    pub fn new(attack: f32, release: f32) -> Self {
+        HysteresisGate { attack, release: release.min(attack), open: false }
+    }
+
+    /// Feed a window's RMS; returns whether the gate is open for it.
+    pub fn update(&mut self, rms: f32) -> bool {
+        self.open = if self.open { rms >= self.release } else { rms >= self.attack };
+        self.open
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Closes the gate, so the next note has to reach `attack` again.
+    pub fn reset(&mut self) {
+        self.open = false;
+    }
+}
+
+/// A power threshold that tracks the background noise floor.
+///
+/// Windows without a detected pitch are treated as background and feed a running
+/// average of their power; the effective threshold sits `ratio` times above it.
+pub struct AdaptiveGate {
+    initial_threshold: f32,
+    ratio: f32,
+    noise_floor: Option<f32>,
+    last_log: Instant,
+}
+
+impl AdaptiveGate {
+    pub fn new(initial_threshold: f32, ratio: f32) -> Self {
+        AdaptiveGate {
+            initial_threshold,
+            ratio,
+            noise_floor: None,
+            last_log: Instant::now(),
+        }
+    }
+
+    /// The power threshold to hand to the detector for the next window.
+    pub fn threshold(&self) -> f32 {
+        match self.noise_floor {
+            Some(floor) => (floor * self.ratio).max(MIN_ADAPTIVE_POWER),
+            None => self.initial_threshold,
+        }
+    }
+
+    /// Feed the power of a window in which no pitch was detected.
+    pub fn observe_silence(&mut self, power: f32) {
+        self.noise_floor = Some(match self.noise_floor {
+            Some(floor) => floor + FLOOR_SMOOTHING * (power - floor),
+            None => power,
+        });
+    }
+
+    /// Log the current threshold every few seconds so it can be watched tracking the room.
+    pub fn log_periodically(&mut self) {
+        if self.last_log.elapsed() >= LOG_INTERVAL {
+            info!(
+                "Gate: adaptive power threshold = {:.3} (noise floor {:.3})",
+                self.threshold(),
+                self.noise_floor.unwrap_or(0.0)
+            );
+            self.last_log = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    fn rms(window: &[f32]) -> f32 {
+        (window_power(window) / window.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn normalizing_brings_quiet_and_loud_windows_to_the_same_level() {
+        let tone = |amplitude: f32| -> Vec<f32> { (0..1024).map(|i| amplitude * (TAU * i as f32 / 64.0).sin()).collect() };
+        let (mut quiet, mut loud) = (tone(0.01), tone(0.9));
+        normalize(&mut quiet, NORMALIZED_RMS);
+        normalize(&mut loud, NORMALIZED_RMS);
+        assert!((rms(&quiet) - NORMALIZED_RMS).abs() < 1e-4);
+        assert!((rms(&loud) - NORMALIZED_RMS).abs() < 1e-4);
+    }
+
+    #[test]
+    fn the_gate_opens_at_attack_and_closes_below_release() {
+        let mut gate = HysteresisGate::new(0.1, 0.04);
+        // Too quiet to open
+        assert!(!gate.update(0.08));
+        assert!(gate.update(0.1));
+        // A fading note stays open down to the release level
+        assert!(gate.update(0.05));
+        assert!(!gate.update(0.039));
+        // and its tail, flickering above the release level again, stays shut
+        assert!(!gate.update(0.06));
+        assert!(!gate.is_open());
+        assert!(gate.update(0.2));
+        gate.reset();
+        assert!(!gate.update(0.05));
+        // A release above the attack is a single threshold
+        let mut single = HysteresisGate::new(0.1, 0.5);
+        assert!(single.update(0.1));
+        assert!(!single.update(0.09));
+    }
+
+    #[test]
+    fn normalizing_never_clips_and_leaves_silence_alone() {
+        // One spike in near silence: reaching the target RMS would push it far past 1.0
+        let mut spike = vec![0.0; 1024];
+        spike[10] = 0.5;
+        normalize(&mut spike, NORMALIZED_RMS);
+        assert_eq!(spike[10], 1.0);
+        let mut silence = vec![0.0; 1024];
+        normalize(&mut silence, NORMALIZED_RMS);
+        assert!(silence.iter().all(|&s| s == 0.0));
+    }
+}
@@ -0,0 +1,151 @@
+use enigo::Key;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// How long the output lets a key stay down by default before it decides the release was lost.
+pub const DEFAULT_MAX_HOLD: Duration = Duration::from_secs(10);
+
+/// The keys (and buttons) an output has put down and not yet let go, and since when. With a
+/// maximum hold, a key down for longer counts as stuck, e.g. because its release was lost,
+/// and [`overdue`](Self::overdue) hands it back to be let go. With a maximum number of keys,
+/// the keys already down come first: one more is refused until another comes up, so a noisy
+/// detection can't put down a handful at once.
+pub struct HeldKeys {
+    max_hold: Option<Duration>,
+    max_keys: Option<usize>,
+    held: HashMap<Key, Instant>,
+    // Keys refused at the cap, whose release is dropped too
+    refused: HashSet<Key>,
+}
+
+impl HeldKeys {
+    /// Keys are never overdue when `max_hold` is `None`, and any number may be down when
+    /// `max_keys` is `None`.
+    pub fn new(max_hold: Option<Duration>, max_keys: Option<usize>) -> Self {
+        HeldKeys { max_hold, max_keys, held: HashMap::new(), refused: HashSet::new() }
+    }
+
+    /// Notes `key` going down at `now`; false, and the key isn't noted, when the maximum number
+    /// of keys is already down. Pressing a key that is already down (a latched key repeating)
+    /// keeps the time it first went down.
+    pub fn down(&mut self, key: Key, now: Instant) -> bool {
+        if !self.held.contains_key(&key) && self.max_keys.is_some_and(|max| self.held.len() >= max) {
+            self.refused.insert(key);
+            return false;
+        }
+        self.held.entry(key).or_insert(now);
+        true
+    }
+
+    /// True, once, for a key refused by [`down`](Self::down) that is now coming up:
+    /// there's nothing to let go.
+    pub fn take_refused(&mut self, key: Key) -> bool {
+        self.refused.remove(&key)
+    }
+
+    /// Notes `key` coming up; false if it wasn't down.
+    pub fn up(&mut self, key: Key) -> bool {
+        self.held.remove(&key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.held.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    /// True for a key refused by [`down`](Self::down) that hasn't come up yet. It stays refused
+    /// even once there's room, or a latched key would go down partway through its note.
+    pub fn is_refused(&self, key: Key) -> bool {
+        self.refused.contains(&key)
+    }
+
+    /// Forgets every key, returning them to be let go.
+    pub fn release_all(&mut self) -> Vec<Key> {
+        self.refused.clear();
+        self.held.drain().map(|(key, _)| key).collect()
+    }
+
+    /// Forgets the keys held for longer than the maximum at `now`, returning each with how long
+    /// it was down.
+    pub fn overdue(&mut self, now: Instant) -> Vec<(Key, Duration)> {
+        let Some(max_hold) = self.max_hold else {
+            return Vec::new();
+        };
+        let overdue: Vec<(Key, Duration)> = self
+            .held
+            .iter()
+            .map(|(&key, &since)| (key, now.saturating_duration_since(since)))
+            .filter(|&(_, held_for)| held_for > max_hold)
+            .collect();
+        for (key, _) in &overdue {
+            self.held.remove(key);
+        }
+        overdue
+    }
+
+    /// How long from `now` until the next key becomes overdue, to wait no longer than that.
+    pub fn next_due(&self, now: Instant) -> Option<Duration> {
+        let max_hold = self.max_hold?;
+        self.held.values().map(|&since| (since + max_hold).saturating_duration_since(now)).min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_lost_release_is_caught_after_the_maximum_hold() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut held = HeldKeys::new(Some(Duration::from_millis(1000)), None);
+        held.down(Key::Layout('a'), at(0));
+        assert!(held.down(Key::Layout('b'), at(200)));
+        // 'b' comes up as it should; the release of 'a' never arrives
+        assert!(held.up(Key::Layout('b')));
+        assert_eq!(held.next_due(at(300)), Some(Duration::from_millis(700)));
+        // Repeats of a latched key don't restart its count
+        held.down(Key::Layout('a'), at(900));
+        assert!(held.overdue(at(1000)).is_empty());
+        assert_eq!(held.overdue(at(1001)), vec![(Key::Layout('a'), Duration::from_millis(1001))]);
+        assert!(held.is_empty());
+        // Its late release then finds nothing to let go
+        assert!(!held.up(Key::Layout('a')));
+        assert_eq!(held.next_due(at(2000)), None);
+    }
+
+    #[test]
+    fn without_a_maximum_keys_stay_until_released() {
+        let start = Instant::now();
+        let mut held = HeldKeys::new(None, None);
+        held.down(Key::Space, start);
+        held.down(Key::Layout('x'), start);
+        assert!(held.overdue(start + Duration::from_secs(3600)).is_empty());
+        assert_eq!(held.next_due(start), None);
+        let mut released = held.release_all();
+        released.sort_by_key(|key| format!("{:?}", key));
+        assert_eq!(released, vec![Key::Layout('x'), Key::Space]);
+        assert!(held.is_empty());
+    }
+
+    #[test]
+    fn keys_beyond_the_maximum_are_refused_with_their_release() {
+        let start = Instant::now();
+        let mut held = HeldKeys::new(None, Some(2));
+        assert!(held.down(Key::Layout('a'), start));
+        assert!(held.down(Key::Layout('b'), start));
+        // A repeat of a key already down still counts as down
+        assert!(held.down(Key::Layout('a'), start));
+        assert!(!held.down(Key::Layout('c'), start));
+        assert!(held.is_refused(Key::Layout('c')));
+        assert!(held.take_refused(Key::Layout('c')));
+        assert!(!held.take_refused(Key::Layout('c')));
+        // Once one comes up, there's room again
+        assert!(held.up(Key::Layout('b')));
+        assert!(!held.take_refused(Key::Layout('b')));
+        assert!(held.down(Key::Layout('c'), start));
+    }
+}
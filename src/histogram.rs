@@ -0,0 +1,82 @@
+use crate::mapping::{Band, Mapping};
+use std::fmt::Write;
+
+// Width of one histogram bucket
+const BUCKET_HZ: f32 = 5.0;
+// Detections above this land in the last bucket; well above any singing voice
+const MAX_HZ: f32 = 2000.0;
+const BAR_WIDTH: usize = 40;
+
+/// Counts detected frequencies in fixed-width buckets. Recording a detection is one array
+/// increment, so it stays on for the whole session.
+pub struct FrequencyHistogram {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl FrequencyHistogram {
+    pub fn new() -> Self {
+        FrequencyHistogram {
+            counts: vec![0; (MAX_HZ / BUCKET_HZ) as usize],
+            total: 0,
+        }
+    }
+
+    pub fn record(&mut self, freq: f32) {
+        let bucket = ((freq.max(0.0) / BUCKET_HZ) as usize).min(self.counts.len() - 1);
+        self.counts[bucket] += 1;
+        self.total += 1;
+    }
+
+    /// A text report of every bucket between the lowest and highest detection, each labelled
+    /// with the band its midpoint falls into, or `gap` when no band covers it.
+    pub fn report(&self, mapping: &Mapping) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Frequency histogram ({} detections, {} Hz buckets):", self.total, BUCKET_HZ);
+        let first = self.counts.iter().position(|&count| count > 0);
+        let last = self.counts.iter().rposition(|&count| count > 0);
+        let (Some(first), Some(last)) = (first, last) else {
+            let _ = writeln!(out, "  (no pitch detected)");
+            return out;
+        };
+        let max_count = self.counts[first..=last].iter().copied().max().unwrap_or(1);
+
+        let mut previous_band = None;
+        for (bucket, &count) in self.counts.iter().enumerate().take(last + 1).skip(first) {
+            let low = bucket as f32 * BUCKET_HZ;
+            let band = mapping.band_for(low + BUCKET_HZ / 2.0);
+            if let Some(band) = band
+                && previous_band != Some(band)
+            {
+                let _ = writeln!(
+                    out,
+                    "  ---- '{}' {:.1}-{:.1} Hz ----",
+                    band.label(),
+                    band.low_hz,
+                    band.high_hz
+                );
+            }
+            previous_band = band;
+
+            let bar_len = (count as usize * BAR_WIDTH).div_ceil(max_count as usize);
+            let label = band.map_or_else(|| "gap".to_string(), Band::label);
+            let _ = writeln!(
+                out,
+                "  {:6.0}-{:<6.0} Hz {:>8} {:<width$} {}",
+                low,
+                low + BUCKET_HZ,
+                count,
+                "#".repeat(bar_len),
+                label,
+                width = BAR_WIDTH
+            );
+        }
+        out
+    }
+}
+
+impl Default for FrequencyHistogram {
+    fn default() -> Self {
+        FrequencyHistogram::new()
+    }
+}
@@ -0,0 +1,68 @@
+use device_query::{DeviceQuery, DeviceState, Keycode};
+use std::fmt;
+use std::str::FromStr;
+
+/// A key combination such as `LControl+LShift+P`, or `none` for no hotkey.
+/// Key names are those of `device_query`'s `Keycode` (e.g. `F9`, `LAlt`, `Space`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hotkey(Vec<Keycode>);
+
+impl Hotkey {
+    pub fn is_disabled(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        if spec.eq_ignore_ascii_case("none") {
+            return Ok(Hotkey(Vec::new()));
+        }
+        spec.split('+')
+            .map(|name| Keycode::from_str(name.trim()).map_err(|_| format!("unknown key '{}' in hotkey '{}'", name.trim(), spec)))
+            .collect::<Result<_, _>>()
+            .map(Hotkey)
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self.0.iter().map(|key| key.to_string()).collect();
+        write!(f, "{}", names.join("+"))
+    }
+}
+
+/// Watches the keyboard system-wide (whichever window has focus) for a hotkey press.
+pub struct HotkeyWatcher {
+    device: DeviceState,
+    hotkey: Hotkey,
+    was_down: bool,
+}
+
+impl HotkeyWatcher {
+    /// `None` if the hotkey is `none` or the keyboard can't be queried (e.g. no X display).
+    pub fn new(hotkey: Hotkey) -> Option<Self> {
+        if hotkey.is_disabled() {
+            return None;
+        }
+        let device = DeviceState::checked_new()?;
+        Some(HotkeyWatcher { device, hotkey, was_down: false })
+    }
+
+    /// True once per press of the full combination.
+    pub fn pressed(&mut self) -> bool {
+        let keys = self.device.get_keys();
+        let down = self.hotkey.0.iter().all(|key| keys.contains(key));
+        let pressed = down && !self.was_down;
+        self.was_down = down;
+        pressed
+    }
+
+    /// True for as long as the full combination is held down.
+    pub fn held(&mut self) -> bool {
+        self.pressed();
+        self.was_down
+    }
+}
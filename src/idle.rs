@@ -0,0 +1,56 @@
+use std::time::{Duration, Instant};
+
+/// Notices when nothing has been heard for a while, so keys left active by a session walked
+/// away from can be let go (`--idle-timeout`).
+///
+/// It fires once per stretch of silence and re-arms as soon as something is heard again.
+pub struct IdleWatchdog {
+    timeout: Duration,
+    last_heard: Instant,
+    idle: bool,
+}
+
+impl IdleWatchdog {
+    /// Starts counting from `now`, as if something had just been heard.
+    pub fn new(timeout: Duration, now: Instant) -> Self {
+        IdleWatchdog { timeout, last_heard: now, idle: false }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Feed whether anything was heard at `now`. Returns `true` once, at the first call
+    /// `timeout` or more after the last time something was.
+    pub fn observe(&mut self, heard: bool, now: Instant) -> bool {
+        if heard {
+            self.last_heard = now;
+            self.idle = false;
+            return false;
+        }
+        if self.idle || now.duration_since(self.last_heard) < self.timeout {
+            return false;
+        }
+        self.idle = true;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fires_once_per_stretch_of_silence() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut watchdog = IdleWatchdog::new(Duration::from_millis(1000), start);
+        assert!(!watchdog.observe(false, at(999)));
+        assert!(watchdog.observe(false, at(1000)));
+        assert!(!watchdog.observe(false, at(5000)));
+        // Hearing anything re-arms it and restarts the count
+        assert!(!watchdog.observe(true, at(5100)));
+        assert!(!watchdog.observe(false, at(6000)));
+        assert!(watchdog.observe(false, at(6100)));
+    }
+}
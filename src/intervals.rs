@@ -0,0 +1,239 @@
+//! `[intervals]`: keys for the jump from one note to the next instead of for the note itself,
+//! e.g. a leap up of a fourth or more pressing one key and any step down another, so the same
+//! commands work in whatever key and register they are sung.
+
+use crate::key_state::{KeyStateMachine, KeyTiming};
+use crate::keys::{key_name, KeyEvent};
+use crate::notes::{cents, frequency_to_midi};
+use enigo::Key;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// Which way an interval goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntervalDirection {
+    Up,
+    Down,
+    /// Roughly the note before again.
+    Same,
+}
+
+/// One kind of jump and the key it presses. `Up` and `Down` rules cover jumps from
+/// `min_semitones` to `max_semitones` (no limit when `None`) that way; a `Same` rule ignores
+/// both.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalRule {
+    pub direction: IntervalDirection,
+    pub min_semitones: f32,
+    pub max_semitones: Option<f32>,
+    pub key: Key,
+}
+
+/// The rules of an `[intervals]` section, in priority order like [`Mapping`](crate::mapping::Mapping)
+/// bands.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalMapping {
+    pub rules: Vec<IntervalRule>,
+    /// How far (in cents) a jump may be off and still count: within this of no jump at all is
+    /// the same note, and a rule's range is widened by it at both ends.
+    pub tolerance_cents: f32,
+}
+
+impl IntervalMapping {
+    /// The key for a jump of `semitones`, negative for down, if a rule covers it.
+    pub fn key_for(&self, semitones: f32) -> Option<Key> {
+        let tolerance = self.tolerance_cents / 100.0;
+        let direction = if semitones.abs() <= tolerance {
+            IntervalDirection::Same
+        } else if semitones > 0.0 {
+            IntervalDirection::Up
+        } else {
+            IntervalDirection::Down
+        };
+        let size = semitones.abs();
+        self.rules
+            .iter()
+            .filter(|rule| rule.direction == direction)
+            .find(|rule| {
+                direction == IntervalDirection::Same
+                    || (size >= rule.min_semitones - tolerance
+                        && rule.max_semitones.is_none_or(|max| size <= max + tolerance))
+            })
+            .map(|rule| rule.key)
+    }
+}
+
+/// Turns the pitch of each window into the keys of an [`IntervalMapping`]. Which note is being
+/// sung is decided by a [`KeyStateMachine`] over the nearest semitone, so a note only counts
+/// once it has lasted the minimum note duration and, after another note, won the switch
+/// consensus: vibrato and the notes a slide passes through don't make intervals of their own.
+/// Each new note taps the key of its jump from the note before, which is kept across silence.
+pub struct IntervalTracker {
+    mapping: IntervalMapping,
+    notes: KeyStateMachine,
+    // The pitch of the last note that counted
+    previous: Option<f32>,
+    // Where the semitones lie, for telling notes apart
+    a4_hz: f32,
+}
+
+impl IntervalTracker {
+    /// Notes are told apart by the nearest semitone with A4 at `a4_hz`.
+    pub fn new(mapping: IntervalMapping, timing: KeyTiming, a4_hz: f32) -> Self {
+        IntervalTracker { mapping, notes: KeyStateMachine::new(timing), previous: None, a4_hz }
+    }
+
+    pub fn mapping(&self) -> &IntervalMapping {
+        &self.mapping
+    }
+
+    /// Feed one window's trusted pitch, if any, at `now`. Returns the key events of an interval
+    /// completed by this window.
+    pub fn update(&mut self, frequency: Option<f32>, now: Instant) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        let note_started = self
+            .notes
+            .update(frequency.map(|frequency| semitone_of(frequency, self.a4_hz)), now)
+            .iter()
+            .any(|event| matches!(event, KeyEvent::Press(_)));
+        let Some(frequency) = frequency.filter(|_| note_started) else {
+            return events;
+        };
+        match self.previous {
+            Some(previous) => {
+                let semitones = cents(frequency, previous) / 100.0;
+                match self.mapping.key_for(semitones) {
+                    Some(key) => {
+                        info!("Action: Interval of {:+.1} semitones; pressing '{}'.", semitones, key_name(key));
+                        events.extend([KeyEvent::Press(key), KeyEvent::Release(key)]);
+                    }
+                    None => info!("Info: Interval of {:+.1} semitones matches no rule.", semitones),
+                }
+            }
+            None => info!("Info: First note at {:.1} Hz; intervals count from here.", frequency),
+        }
+        self.previous = Some(frequency);
+        events
+    }
+
+    /// Forgets the note being sung and the note before it.
+    pub fn reset(&mut self) {
+        self.notes.reset();
+        self.previous = None;
+    }
+}
+
+// Stands in for the note being sung in the key state machine, which only compares keys; it is
+// never pressed
+fn semitone_of(frequency: f32, a4_hz: f32) -> Key {
+    Key::Raw(frequency_to_midi(frequency, a4_hz).round().clamp(0.0, 127.0) as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_state::SwitchConsensus;
+    use crate::notes::{transpose, DEFAULT_A4_HZ};
+    use std::time::Duration;
+
+    const A: Key = Key::Layout('a');
+    const X: Key = Key::Layout('x');
+    const Z: Key = Key::Layout('z');
+
+    fn jumps() -> IntervalMapping {
+        IntervalMapping {
+            rules: vec![
+                IntervalRule { direction: IntervalDirection::Up, min_semitones: 5.0, max_semitones: None, key: A },
+                IntervalRule { direction: IntervalDirection::Down, min_semitones: 1.0, max_semitones: None, key: Z },
+                IntervalRule { direction: IntervalDirection::Same, min_semitones: 0.0, max_semitones: None, key: X },
+            ],
+            tolerance_cents: 50.0,
+        }
+    }
+
+    #[test]
+    fn jumps_are_classed_by_direction_and_size() {
+        let mapping = jumps();
+        assert_eq!(mapping.key_for(5.0), Some(A));
+        assert_eq!(mapping.key_for(12.0), Some(A));
+        // Slightly flat still counts as a fourth
+        assert_eq!(mapping.key_for(4.6), Some(A));
+        // A third up has no rule
+        assert_eq!(mapping.key_for(4.0), None);
+        assert_eq!(mapping.key_for(-1.0), Some(Z));
+        assert_eq!(mapping.key_for(-7.0), Some(Z));
+        assert_eq!(mapping.key_for(0.3), Some(X));
+        assert_eq!(mapping.key_for(-0.4), Some(X));
+    }
+
+    #[test]
+    fn only_notes_that_settle_make_intervals() {
+        let timing = KeyTiming {
+            min_note: Duration::from_millis(60),
+            switch: SwitchConsensus { needed: 2, windows: 3 },
+            ..KeyTiming::default()
+        };
+        let mut tracker = IntervalTracker::new(jumps(), timing, DEFAULT_A4_HZ);
+        let start = Instant::now();
+        let mut pressed = Vec::new();
+        let mut sing = |from_ms: u64, to_ms: u64, frequency: Option<f32>, pressed: &mut Vec<(u64, Key)>| {
+            for ms in (from_ms..to_ms).step_by(20) {
+                for event in tracker.update(frequency, start + Duration::from_millis(ms)) {
+                    if let KeyEvent::Press(key) = event {
+                        pressed.push((ms, key));
+                    }
+                }
+            }
+        };
+        let c4 = 261.6;
+        // The first note only sets where intervals count from
+        sing(0, 200, Some(c4), &mut pressed);
+        // A slide up to G passes through D and E too quickly to count
+        sing(200, 220, Some(transpose(c4, 2.0)), &mut pressed);
+        sing(220, 240, Some(transpose(c4, 4.0)), &mut pressed);
+        // Vibrato on G stays within its semitone
+        for (ms, cents) in [(240, 0.0), (260, 30.0), (280, -30.0), (300, 20.0), (320, -20.0)] {
+            sing(ms, ms + 20, Some(transpose(c4, 7.0 + cents / 100.0)), &mut pressed);
+        }
+        sing(340, 400, None, &mut pressed);
+        // G again after a breath, then down to C
+        sing(400, 500, Some(transpose(c4, 7.0)), &mut pressed);
+        sing(500, 600, Some(c4), &mut pressed);
+        // Each counts once it has lasted 60ms, from the first window that backed it
+        assert_eq!(pressed, vec![(300, A), (460, X), (560, Z)]);
+    }
+
+    #[test]
+    fn notes_are_told_apart_in_the_configured_tuning() {
+        let timing = KeyTiming {
+            min_note: Duration::from_millis(60),
+            switch: SwitchConsensus { needed: 2, windows: 3 },
+            ..KeyTiming::default()
+        };
+        // Half a semitone below A440, so its notes sit on the edges of A440's semitones
+        let a4 = transpose(DEFAULT_A4_HZ, -0.5);
+        let c4 = transpose(a4, -9.0);
+        let g4 = transpose(c4, 7.0);
+        let pressed = |a4_hz| {
+            let mut tracker = IntervalTracker::new(jumps(), timing, a4_hz);
+            let start = Instant::now();
+            let mut pressed = Vec::new();
+            let mut sing = |from_ms: u64, frequency: f32| {
+                for ms in (from_ms..from_ms + 100).step_by(20) {
+                    pressed.extend(tracker.update(Some(frequency), start + Duration::from_millis(ms)));
+                }
+            };
+            sing(0, c4);
+            // A slow vibrato of 30 cents either way on G
+            for (i, cents) in [30.0, -30.0, 30.0, -30.0].into_iter().enumerate() {
+                sing(100 + 100 * i as u64, transpose(g4, cents / 100.0));
+            }
+            pressed.into_iter().filter(|event| matches!(event, KeyEvent::Press(_))).collect::<Vec<_>>()
+        };
+        assert_eq!(pressed(a4), vec![KeyEvent::Press(A)]);
+        // Against A440 the vibrato crosses from one semitone to the next and back
+        assert!(pressed(DEFAULT_A4_HZ).len() > 1);
+    }
+}
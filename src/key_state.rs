@@ -0,0 +1,756 @@
+use crate::keys::KeyEvent;
+use clap::ValueEnum;
+use enigo::Key;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// Longest window history a switch consensus may look at
+const MAX_CONSENSUS_WINDOWS: usize = 16;
+
+/// Tempos `--bpm` accepts.
+pub const BPM_RANGE: RangeInclusive<f32> = 20.0..=400.0;
+/// Repeats per beat a [`RepeatGrid`] accepts.
+pub const DIVISION_RANGE: RangeInclusive<u32> = 1..=16;
+
+/// Timing of repeat mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyTiming {
+    /// How long a note must be held before continuous presses start.
+    pub hold_threshold: Duration,
+    /// How often a held key is pressed again once continuous presses have started.
+    pub repeat_interval: Duration,
+    /// Makes the repeat interval shrink the longer the note is held.
+    pub acceleration: Option<RepeatAcceleration>,
+    /// How long a note must be heard before its initial press, so blips don't press keys.
+    /// The hold threshold still counts from the note's first window. Zero presses at once.
+    pub min_note: Duration,
+    /// How many recent windows a different band must win before the active key switches.
+    pub switch: SwitchConsensus,
+    /// For notes with a tap key: released sooner than this (but after the minimum note
+    /// duration) they press the tap key once; held this long they press their own key.
+    pub tap_hold: Duration,
+    /// Puts repeats on a tempo grid instead of every repeat interval (and its acceleration).
+    pub grid: Option<RepeatGrid>,
+}
+
+/// A tempo grid for repeats, e.g. eighth notes at 120 BPM: once a note is past the hold
+/// threshold, it repeats on every grid point. The points are counted from the grid's origin,
+/// so a window that comes in late delays one repeat, not all the ones after it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RepeatGrid {
+    /// One beat, e.g. 500ms at 120 BPM.
+    pub beat: Duration,
+    /// Repeats per beat: 2 for eighth notes, 4 for sixteenths.
+    pub division: u32,
+    pub from: GridFrom,
+}
+
+/// Where a [`RepeatGrid`] counts from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GridFrom {
+    /// The start of the session, so the repeats of every note share one beat, e.g. a
+    /// metronome's.
+    #[default]
+    Session,
+    /// Each note's first press.
+    Note,
+}
+
+impl RepeatGrid {
+    /// The grid for `bpm` beats per minute.
+    pub fn new(bpm: f32, division: u32, from: GridFrom) -> Self {
+        RepeatGrid { beat: Duration::from_secs_f32(60.0 / bpm), division, from }
+    }
+
+    /// Time between two grid points.
+    pub fn step(&self) -> Duration {
+        self.beat / self.division.max(1)
+    }
+
+    /// The first grid point counted from `origin` at or after `at`.
+    pub fn next(&self, origin: Instant, at: Instant) -> Instant {
+        let step = self.step().as_nanos().max(1);
+        let since = at.saturating_duration_since(origin).as_nanos();
+        origin + Duration::from_nanos((since.div_ceil(step) * step) as u64)
+    }
+}
+
+/// Switching from an active key to another needs the new key in `needed` of the last
+/// `windows` windows, written `needed/windows` (e.g. `2/3`). `1/1` switches at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SwitchConsensus {
+    pub needed: usize,
+    pub windows: usize,
+}
+
+impl SwitchConsensus {
+    pub const IMMEDIATE: SwitchConsensus = SwitchConsensus { needed: 1, windows: 1 };
+}
+
+impl FromStr for SwitchConsensus {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not a consensus like 2/3 (windows needed / windows looked at)", spec);
+        let (needed, windows) = spec.split_once('/').ok_or_else(invalid)?;
+        let needed: usize = needed.trim().parse().map_err(|_| invalid())?;
+        let windows: usize = windows.trim().parse().map_err(|_| invalid())?;
+        if needed == 0 || needed > windows || windows > MAX_CONSENSUS_WINDOWS {
+            return Err(format!(
+                "consensus {}/{} needs 1 <= needed <= windows <= {}",
+                needed, windows, MAX_CONSENSUS_WINDOWS
+            ));
+        }
+        Ok(SwitchConsensus { needed, windows })
+    }
+}
+
+impl TryFrom<String> for SwitchConsensus {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        spec.parse()
+    }
+}
+
+impl From<SwitchConsensus> for String {
+    fn from(consensus: SwitchConsensus) -> Self {
+        consensus.to_string()
+    }
+}
+
+impl fmt::Display for SwitchConsensus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.needed, self.windows)
+    }
+}
+
+impl Default for KeyTiming {
+    fn default() -> Self {
+        KeyTiming {
+            hold_threshold: Duration::from_millis(250),
+            repeat_interval: Duration::from_millis(100),
+            acceleration: None,
+            min_note: Duration::from_millis(70),
+            switch: SwitchConsensus { needed: 2, windows: 3 },
+            tap_hold: Duration::from_millis(200),
+            grid: None,
+        }
+    }
+}
+
+/// A band's own hold and repeat timing, standing in for [`KeyTiming`]'s where set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BandTiming {
+    pub hold_threshold: Option<Duration>,
+    pub repeat_interval: Option<Duration>,
+    /// With a [`RepeatGrid`]: repeats per beat, instead of the grid's.
+    pub repeat_division: Option<u32>,
+    pub behavior: KeyBehavior,
+}
+
+impl BandTiming {
+    /// No overrides: the global timing applies.
+    pub const GLOBAL: BandTiming = BandTiming {
+        hold_threshold: None,
+        repeat_interval: None,
+        repeat_division: None,
+        behavior: KeyBehavior::ClickRepeat,
+    };
+}
+
+/// What a band's key does over a note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyBehavior {
+    /// A click when the note starts, then another every repeat interval past the hold
+    /// threshold.
+    #[default]
+    ClickRepeat,
+    /// Down when the note starts and up when it ends, lost or switched to another band.
+    Hold,
+    /// One click per note, however long it is held.
+    OneShot,
+}
+
+/// Repeat acceleration, like OS key repeat: the interval starts at the repeat interval and
+/// drops by `ramp` for every second of repeating, down to `floor`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RepeatAcceleration {
+    pub floor: Duration,
+    pub ramp: Duration,
+}
+
+impl KeyTiming {
+    /// The interval between repeats once a key has been repeating for `repeating_for`.
+    pub fn repeat_interval_after(&self, repeating_for: Duration) -> Duration {
+        let Some(acceleration) = self.acceleration else {
+            return self.repeat_interval;
+        };
+        let reduction = acceleration.ramp.mul_f32(repeating_for.as_secs_f32());
+        let floor = acceleration.floor.min(self.repeat_interval);
+        self.repeat_interval.saturating_sub(reduction).max(floor)
+    }
+
+    // A note that ended before its key was pressed: a tap if it had a tap key and outlasted
+    // the minimum note duration, otherwise nothing
+    fn unpressed_note_ended(&self, note: &HeldKey, now: Instant) -> Vec<KeyEvent> {
+        let lasted = now.duration_since(note.since);
+        match note.tap {
+            Some(tap) if lasted >= self.min_note => {
+                info!("Action: Note for '{:?}' ended after {}ms. Tapping '{:?}'!", note.key, lasted.as_millis(), tap);
+                vec![KeyEvent::Press(tap), KeyEvent::Release(tap)]
+            }
+            _ => {
+                debug!("Info: Key '{:?}' was too short to press.", note.key);
+                Vec::new()
+            }
+        }
+    }
+
+    /// This timing with `band`'s overrides applied.
+    pub fn with_band(&self, band: BandTiming) -> KeyTiming {
+        KeyTiming {
+            hold_threshold: band.hold_threshold.unwrap_or(self.hold_threshold),
+            repeat_interval: band.repeat_interval.unwrap_or(self.repeat_interval),
+            grid: self.grid.map(|grid| RepeatGrid { division: band.repeat_division.unwrap_or(grid.division), ..grid }),
+            ..*self
+        }
+    }
+
+    // Whether `held`, last pressed at `last_press` and past the hold threshold, repeats at
+    // `now`; `session` is when the session started
+    fn repeat_due(&self, held: &HeldKey, last_press: Instant, now: Instant, session: Instant) -> bool {
+        let held_for = now.duration_since(held.since);
+        let Some(grid) = self.grid else {
+            return now.duration_since(last_press) >= self.repeat_interval_after(held_for - self.hold_threshold);
+        };
+        let origin = match grid.from {
+            GridFrom::Session => session,
+            GridFrom::Note => held.first_press.unwrap_or(held.since),
+        };
+        // The first grid point past both the threshold and the last press
+        let after = (held.since + self.hold_threshold).max(last_press + Duration::from_nanos(1));
+        now >= grid.next(origin, after)
+    }
+
+    /// How long a note must last before its key is pressed: the minimum note duration, or for
+    /// a note with a tap key the tap/hold threshold if that is longer.
+    fn press_after(&self, tap: Option<Key>) -> Duration {
+        match tap {
+            Some(_) => self.min_note.max(self.tap_hold),
+            None => self.min_note,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HeldKey {
+    key: Key,
+    since: Instant,
+    // None until the note has lasted the minimum note duration and its key was pressed
+    last_press: Option<Instant>,
+    // The initial press, where a note's own repeat grid counts from
+    first_press: Option<Instant>,
+    // Pressed instead of `key` if the note ends before the tap/hold threshold
+    tap: Option<Key>,
+    // The band's own hold/repeat timing
+    band: BandTiming,
+}
+
+/// The hold/repeat logic of repeat mode: a fresh note presses its key once it has lasted the
+/// minimum note duration, holding it past the hold threshold repeats the press every repeat
+/// interval, and losing the pitch releases it. A note lost before it was pressed leaves no
+/// trace. Once a key is pressed, a different key only takes over when it wins the switch
+/// consensus; until then the held key stays.
+///
+/// A note can also carry its band's tap key and timing (see
+/// [`update_band`](Self::update_band)). With a tap key the note's own key waits for the
+/// tap/hold threshold, and a note that ends sooner presses the tap key once instead.
+#[derive(Debug, Default)]
+pub struct KeyStateMachine {
+    timing: KeyTiming,
+    held: Option<HeldKey>,
+    // Keys of the latest windows since the pitch was last lost, newest last, with their times
+    recent: VecDeque<(Key, Instant)>,
+    // The first window, where a session-wide repeat grid counts from
+    started: Option<Instant>,
+}
+
+impl KeyStateMachine {
+    pub fn new(timing: KeyTiming) -> Self {
+        KeyStateMachine { timing, held: None, recent: VecDeque::new(), started: None }
+    }
+
+    /// The key of the note being followed, whether it was pressed yet or is still waiting out
+    /// the minimum note duration.
+    pub fn note_key(&self) -> Option<Key> {
+        self.held.map(|held| held.key)
+    }
+
+    /// The key currently held, if any. A note still waiting out the minimum note duration
+    /// doesn't count.
+    pub fn active_key(&self) -> Option<Key> {
+        self.held.filter(|held| held.last_press.is_some()).map(|held| held.key)
+    }
+
+    /// Feed the key mapped from the current window (if any) and the time of that window.
+    pub fn update(&mut self, detected: Option<Key>, now: Instant) -> Vec<KeyEvent> {
+        self.update_band(detected, None, BandTiming::GLOBAL, now)
+    }
+
+    /// Like [`update`](Self::update), with the options of the window's band: `tap` is the key a
+    /// note released before the tap/hold threshold presses (and releases) once on release,
+    /// while a longer one presses and repeats `detected`. `band` overrides the hold and repeat
+    /// timing. Both are taken from the window that starts a note.
+    pub fn update_band(&mut self, detected: Option<Key>, tap: Option<Key>, band: BandTiming, now: Instant) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        let session = *self.started.get_or_insert(now);
+        match detected {
+            Some(key) => {
+                if self.recent.len() == self.timing.switch.windows {
+                    self.recent.pop_front();
+                }
+                self.recent.push_back((key, now));
+            }
+            None => self.recent.clear(),
+        }
+        let consensus = detected.is_some_and(|key| self.won_consensus(key));
+        // A switch away from a pressed key counts from the first window that backed it
+        let first_backing = detected.and_then(|key| self.first_recent(key)).unwrap_or(now);
+        match (detected, self.held.as_mut()) {
+            // Same note/key is still being held
+            (Some(key), Some(held)) if key == held.key => {
+                let held_for = now.duration_since(held.since);
+                let Some(last_press) = held.last_press else {
+                    if held_for >= self.timing.press_after(held.tap) {
+                        info!("Action: Key '{:?}' held for {}ms. Sending initial press!", key, held_for.as_millis());
+                        events.push(first_press(key, held.band));
+                        held.last_press = Some(now);
+                        held.first_press = Some(now);
+                    }
+                    return events;
+                };
+                let timing = self.timing.with_band(held.band);
+                if held.band.behavior != KeyBehavior::ClickRepeat {
+                    debug!("Info: Key '{:?}' held; its band never repeats.", key);
+                } else if held_for < timing.hold_threshold {
+                    debug!(
+                        "Info: Key '{:?}' held, but still within hold threshold ({}ms remaining).",
+                        key,
+                        (timing.hold_threshold - held_for).as_millis()
+                    );
+                } else if timing.repeat_due(held, last_press, now, session) {
+                    info!("Action: Repeating key '{:?}' (held).", key);
+                    events.push(KeyEvent::Repeat(key));
+                    held.last_press = Some(now);
+                }
+            }
+            // A different band, but not often enough yet to take over from the pressed key
+            (Some(key), Some(held)) if held.last_press.is_some() && !consensus => {
+                debug!(
+                    "Info: Key '{:?}' detected, but not in {} of the last {} windows; keeping '{:?}'.",
+                    key, self.timing.switch.needed, self.timing.switch.windows, held.key
+                );
+            }
+            // A new key is detected (either different from the held one, or none was held)
+            (Some(key), previous) => {
+                let switching = previous.as_ref().is_some_and(|previous| previous.last_press.is_some());
+                if let Some(previous) = previous {
+                    if previous.last_press.is_none() {
+                        events.extend(self.timing.unpressed_note_ended(previous, now));
+                    } else if previous.band.behavior == KeyBehavior::Hold {
+                        // Let go of it before anything else is pressed
+                        info!("Action: Switching away from '{:?}'. Letting go of it!", previous.key);
+                        events.push(KeyEvent::Up(previous.key));
+                    }
+                }
+                let since = if switching { first_backing } else { now };
+                let press_after = self.timing.press_after(tap);
+                let last_press = if now.duration_since(since) >= press_after {
+                    info!("Action: New key '{:?}' detected. Sending initial press!", key);
+                    events.push(first_press(key, band));
+                    Some(now)
+                } else {
+                    debug!("Info: New key '{:?}' detected; waiting {}ms before pressing.", key, press_after.as_millis());
+                    None
+                };
+                self.held = Some(HeldKey { key, since, last_press, first_press: last_press, tap, band });
+            }
+            // No valid pitch, but a key was held (note released/lost)
+            (None, Some(held)) => {
+                if held.last_press.is_some() {
+                    info!("Info: Pitch lost. Releasing key '{:?}' state.", held.key);
+                    events.push(if held.band.behavior == KeyBehavior::Hold {
+                        KeyEvent::Up(held.key)
+                    } else {
+                        KeyEvent::Release(held.key)
+                    });
+                } else {
+                    events.extend(self.timing.unpressed_note_ended(held, now));
+                }
+                self.held = None;
+            }
+            (None, None) => {}
+        }
+        events
+    }
+
+    /// Forgets the held key without reporting a release.
+    pub fn reset(&mut self) {
+        self.held = None;
+        self.recent.clear();
+    }
+
+    /// Forgets the note of a `hold` band whose key is down, returning the key to let go of.
+    pub fn release_held(&mut self) -> Option<Key> {
+        let held = self.held.take_if(|held| held.last_press.is_some() && held.band.behavior == KeyBehavior::Hold)?;
+        Some(held.key)
+    }
+
+    fn won_consensus(&self, key: Key) -> bool {
+        self.recent.iter().filter(|&&(recent, _)| recent == key).count() >= self.timing.switch.needed
+    }
+
+    fn first_recent(&self, key: Key) -> Option<Instant> {
+        self.recent.iter().find(|&&(recent, _)| recent == key).map(|&(_, at)| at)
+    }
+}
+
+// The press that starts a note of `band`
+fn first_press(key: Key, band: BandTiming) -> KeyEvent {
+    match band.behavior {
+        KeyBehavior::Hold => KeyEvent::Down(key),
+        KeyBehavior::ClickRepeat | KeyBehavior::OneShot => KeyEvent::Press(key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: Key = Key::Layout('a');
+    const Z: Key = Key::Layout('z');
+
+    /// Feeds `(ms since start, detected key)` steps and collects every event with its time.
+    /// Presses and switches at once, without a minimum note duration or switch consensus.
+    fn run(steps: &[(u64, Option<Key>)]) -> Vec<(u64, KeyEvent)> {
+        run_with(immediate(), steps)
+    }
+
+    fn immediate() -> KeyTiming {
+        KeyTiming { min_note: Duration::ZERO, switch: SwitchConsensus::IMMEDIATE, ..KeyTiming::default() }
+    }
+
+    fn run_with(timing: KeyTiming, steps: &[(u64, Option<Key>)]) -> Vec<(u64, KeyEvent)> {
+        let start = Instant::now();
+        let mut machine = KeyStateMachine::new(timing);
+        steps
+            .iter()
+            .flat_map(|&(ms, key)| {
+                machine
+                    .update(key, start + Duration::from_millis(ms))
+                    .into_iter()
+                    .map(move |event| (ms, event))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sustained_note_repeats_after_the_hold_threshold() {
+        let steps: Vec<_> = (0..=8).map(|i| (i * 50, Some(A))).collect();
+        assert_eq!(
+            run(&steps),
+            vec![
+                (0, KeyEvent::Press(A)),
+                (250, KeyEvent::Repeat(A)),
+                (350, KeyEvent::Repeat(A)),
+            ]
+        );
+    }
+
+    #[test]
+    fn first_repeat_fires_as_soon_as_the_threshold_is_crossed() {
+        // The first repeat is measured from the initial press, which is always more than
+        // one repeat interval before the threshold, so it lands on the first window past it.
+        let steps = [(0, Some(A)), (240, Some(A)), (260, Some(A)), (300, Some(A)), (361, Some(A))];
+        assert_eq!(
+            run(&steps),
+            vec![(0, KeyEvent::Press(A)), (260, KeyEvent::Repeat(A)), (361, KeyEvent::Repeat(A))]
+        );
+    }
+
+    #[test]
+    fn note_change_mid_hold_presses_the_new_key_and_restarts_the_hold() {
+        let steps = [(0, Some(A)), (150, Some(A)), (200, Some(Z)), (400, Some(Z)), (450, Some(Z))];
+        assert_eq!(
+            run(&steps),
+            vec![(0, KeyEvent::Press(A)), (200, KeyEvent::Press(Z)), (450, KeyEvent::Repeat(Z))]
+        );
+    }
+
+    #[test]
+    fn brief_dropout_releases_and_presses_again() {
+        let steps = [(0, Some(A)), (200, None), (250, Some(A)), (400, Some(A))];
+        assert_eq!(
+            run(&steps),
+            vec![(0, KeyEvent::Press(A)), (200, KeyEvent::Release(A)), (250, KeyEvent::Press(A))]
+        );
+    }
+
+    #[test]
+    fn silence_without_a_held_key_does_nothing() {
+        assert_eq!(run(&[(0, None), (50, None)]), vec![]);
+    }
+
+    #[test]
+    fn repeats_land_on_the_tempo_grid_without_drifting() {
+        // Sixteenths at 120 BPM, every 125ms, heard in 30ms windows from a note starting at 30ms
+        let mut steps = vec![(0, None)];
+        steps.extend((1..=26).map(|i| (i * 30, Some(A))));
+        let repeats = |from: GridFrom| -> Vec<u64> {
+            let timing = KeyTiming { grid: Some(RepeatGrid::new(120.0, 4, from)), ..immediate() };
+            run_with(timing, &steps)
+                .into_iter()
+                .filter(|(_, event)| *event == KeyEvent::Repeat(A))
+                .map(|(ms, _)| ms)
+                .collect()
+        };
+        // From the session start: the grid points past the 280ms threshold are 375, 500, 625
+        // and 750ms, each repeated on the first window after it
+        assert_eq!(repeats(GridFrom::Session), [390, 510, 630, 750]);
+        // From the note's press at 30ms: 280, 405, 530, 655 and 780ms
+        assert_eq!(repeats(GridFrom::Note), [300, 420, 540, 660, 780]);
+    }
+
+    #[test]
+    fn acceleration_shrinks_the_repeat_interval_down_to_the_floor() {
+        let timing = KeyTiming {
+            acceleration: Some(RepeatAcceleration {
+                floor: Duration::from_millis(40),
+                ramp: Duration::from_millis(100),
+            }),
+            ..immediate()
+        };
+        let steps: Vec<_> = (0..=200).map(|i| (i * 10, Some(A))).collect();
+        let repeats: Vec<u64> = run_with(timing, &steps)
+            .into_iter()
+            .filter(|(_, event)| *event == KeyEvent::Repeat(A))
+            .map(|(ms, _)| ms)
+            .collect();
+        let gaps: Vec<u64> = repeats.windows(2).map(|pair| pair[1] - pair[0]).collect();
+
+        // Repeats start at the hold threshold and come faster the longer the note is held
+        assert_eq!(repeats[0], 250);
+        assert_eq!(gaps[0], 100);
+        assert!(gaps.windows(2).all(|pair| pair[1] <= pair[0]), "{:?}", gaps);
+        // After 0.6s of repeating the interval has reached the floor
+        assert!(gaps.ends_with(&[40, 40, 40]), "{:?}", gaps);
+    }
+
+    fn with_min_note(ms: u64) -> KeyTiming {
+        KeyTiming { min_note: Duration::from_millis(ms), ..immediate() }
+    }
+
+    fn with_consensus(spec: &str) -> KeyTiming {
+        KeyTiming { switch: spec.parse().unwrap(), ..immediate() }
+    }
+
+    #[test]
+    fn one_window_blip_is_ignored() {
+        let steps = [(0, None), (46, Some(A)), (93, None), (139, None)];
+        assert_eq!(run_with(with_min_note(70), &steps), vec![]);
+    }
+
+    #[test]
+    fn three_window_note_is_pressed_once_it_lasts_the_minimum() {
+        let steps = [(0, Some(A)), (46, Some(A)), (93, Some(A)), (139, None)];
+        assert_eq!(
+            run_with(with_min_note(70), &steps),
+            vec![(93, KeyEvent::Press(A)), (139, KeyEvent::Release(A))]
+        );
+    }
+
+    #[test]
+    fn hold_threshold_counts_from_the_first_window() {
+        let steps: Vec<_> = (0..=6).map(|i| (i * 50, Some(A))).collect();
+        assert_eq!(
+            run_with(with_min_note(70), &steps),
+            vec![(100, KeyEvent::Press(A)), (250, KeyEvent::Repeat(A))]
+        );
+    }
+
+    #[test]
+    fn switching_notes_restarts_the_minimum() {
+        let steps = [(0, Some(A)), (50, Some(Z)), (100, Some(Z)), (150, Some(Z))];
+        assert_eq!(run_with(with_min_note(70), &steps), vec![(150, KeyEvent::Press(Z))]);
+    }
+
+    #[test]
+    fn stray_windows_at_a_band_edge_do_not_switch_keys() {
+        // Hovering on the A/Z boundary: lone Z windows never make 2 of the last 3
+        let steps = [(0, Some(A)), (50, Some(Z)), (100, Some(A)), (150, Some(A)), (200, Some(Z)), (250, Some(A))];
+        assert_eq!(run_with(with_consensus("2/3"), &steps), vec![(0, KeyEvent::Press(A)), (250, KeyEvent::Repeat(A))]);
+    }
+
+    #[test]
+    fn crossing_into_the_next_band_switches_once_it_wins_the_consensus() {
+        let steps = [(0, Some(A)), (50, Some(A)), (100, Some(Z)), (150, Some(A)), (200, Some(Z)), (250, Some(Z))];
+        assert_eq!(
+            run_with(with_consensus("2/3"), &steps),
+            vec![(0, KeyEvent::Press(A)), (200, KeyEvent::Press(Z))]
+        );
+    }
+
+    #[test]
+    fn consensus_leaves_presses_from_silence_and_releases_alone() {
+        let steps = [(0, Some(A)), (50, None), (100, Some(Z))];
+        assert_eq!(
+            run_with(with_consensus("3/3"), &steps),
+            vec![(0, KeyEvent::Press(A)), (50, KeyEvent::Release(A)), (100, KeyEvent::Press(Z))]
+        );
+    }
+
+    #[test]
+    fn a_switch_counts_its_minimum_note_from_the_first_backing_window() {
+        let timing = KeyTiming { min_note: Duration::from_millis(70), ..with_consensus("2/3") };
+        let steps = [(0, Some(A)), (100, Some(A)), (150, Some(Z)), (200, Some(Z)), (250, Some(Z))];
+        assert_eq!(
+            run_with(timing, &steps),
+            vec![(100, KeyEvent::Press(A)), (250, KeyEvent::Press(Z))]
+        );
+    }
+
+    #[test]
+    fn consensus_specs_parse_and_validate() {
+        assert_eq!("2/3".parse(), Ok(SwitchConsensus { needed: 2, windows: 3 }));
+        assert_eq!(SwitchConsensus { needed: 2, windows: 3 }.to_string(), "2/3");
+        for bad in ["0/3", "4/3", "2", "a/b", "2/17"] {
+            assert!(bad.parse::<SwitchConsensus>().is_err(), "{}", bad);
+        }
+    }
+
+    // Feeds `(ms, key)` steps to a band with tap key Z and hold key X, at the default 70ms
+    // minimum note and 200ms tap/hold threshold
+    fn run_tap_hold(steps: &[(u64, Option<Key>)]) -> Vec<(u64, KeyEvent)> {
+        let start = Instant::now();
+        let timing = KeyTiming { min_note: Duration::from_millis(70), ..immediate() };
+        let mut machine = KeyStateMachine::new(timing);
+        steps
+            .iter()
+            .flat_map(|&(ms, key)| {
+                let tap = key.map(|_| Z);
+                machine.update_band(key, tap, BandTiming::GLOBAL, start + Duration::from_millis(ms)).into_iter().map(move |event| (ms, event))
+            })
+            .collect()
+    }
+
+    const X: Key = Key::Layout('x');
+
+    #[test]
+    fn a_short_note_taps_once_when_it_ends() {
+        let steps = [(0, Some(X)), (50, Some(X)), (100, Some(X)), (150, None), (200, None)];
+        assert_eq!(run_tap_hold(&steps), vec![(150, KeyEvent::Press(Z)), (150, KeyEvent::Release(Z))]);
+    }
+
+    #[test]
+    fn a_sustained_note_presses_and_repeats_the_hold_key() {
+        // Pressed at the 200ms tap/hold threshold; repeats still count from the first window
+        let steps: Vec<_> = (0..=7).map(|i| (i * 50, Some(X))).chain([(400, None)]).collect();
+        assert_eq!(
+            run_tap_hold(&steps),
+            vec![(200, KeyEvent::Press(X)), (300, KeyEvent::Repeat(X)), (400, KeyEvent::Release(X))]
+        );
+    }
+
+    #[test]
+    fn a_blip_is_neither_a_tap_nor_a_hold() {
+        assert_eq!(run_tap_hold(&[(0, Some(X)), (50, None)]), vec![]);
+    }
+
+    #[test]
+    fn band_timing_overrides_the_global_repeat() {
+        let start = Instant::now();
+        let run_band = |band: BandTiming| -> Vec<(u64, KeyEvent)> {
+            let mut machine = KeyStateMachine::new(immediate());
+            (0..=10)
+                .map(|i| (i * 50, Some(A)))
+                .chain([(550, None)])
+                .flat_map(|(ms, key)| {
+                    machine.update_band(key, None, band, start + Duration::from_millis(ms)).into_iter().map(move |event| (ms, event))
+                })
+                .collect()
+        };
+        let fast = BandTiming { hold_threshold: Some(Duration::from_millis(100)), repeat_interval: Some(Duration::from_millis(50)), ..BandTiming::GLOBAL };
+        assert_eq!(
+            run_band(fast),
+            [(0, KeyEvent::Press(A))]
+                .into_iter()
+                .chain((2..=10).map(|i| (i * 50, KeyEvent::Repeat(A))))
+                .chain([(550, KeyEvent::Release(A))])
+                .collect::<Vec<_>>()
+        );
+        let single = BandTiming { behavior: KeyBehavior::OneShot, ..BandTiming::GLOBAL };
+        assert_eq!(run_band(single), vec![(0, KeyEvent::Press(A)), (550, KeyEvent::Release(A))]);
+        let hold = BandTiming { behavior: KeyBehavior::Hold, ..BandTiming::GLOBAL };
+        assert_eq!(run_band(hold), vec![(0, KeyEvent::Down(A)), (550, KeyEvent::Up(A))]);
+    }
+
+    // Each step with the behavior of its band
+    fn run_behaviors(steps: &[(u64, Option<(Key, KeyBehavior)>)]) -> Vec<(u64, KeyEvent)> {
+        let start = Instant::now();
+        let mut machine = KeyStateMachine::new(immediate());
+        steps
+            .iter()
+            .flat_map(|&(ms, note)| {
+                let band = BandTiming { behavior: note.map_or(KeyBehavior::ClickRepeat, |(_, behavior)| behavior), ..BandTiming::GLOBAL };
+                machine
+                    .update_band(note.map(|(key, _)| key), None, band, start + Duration::from_millis(ms))
+                    .into_iter()
+                    .map(move |event| (ms, event))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_held_key_comes_up_on_a_dropout_and_goes_down_again() {
+        let hold = Some((A, KeyBehavior::Hold));
+        assert_eq!(
+            run_behaviors(&[(0, hold), (300, hold), (400, None), (450, hold), (800, hold)]),
+            vec![(0, KeyEvent::Down(A)), (400, KeyEvent::Up(A)), (450, KeyEvent::Down(A))]
+        );
+        // A one-shot key clicks once per note, so once more after a dropout
+        let once = Some((Z, KeyBehavior::OneShot));
+        assert_eq!(
+            run_behaviors(&[(0, once), (300, once), (400, None), (450, once), (800, once)]),
+            vec![(0, KeyEvent::Press(Z)), (400, KeyEvent::Release(Z)), (450, KeyEvent::Press(Z))]
+        );
+    }
+
+    #[test]
+    fn switching_from_a_hold_band_lets_go_before_the_next_press() {
+        let hold = Some((A, KeyBehavior::Hold));
+        let once = Some((Z, KeyBehavior::OneShot));
+        assert_eq!(
+            run_behaviors(&[(0, hold), (300, hold), (350, once), (700, once), (750, hold)]),
+            vec![
+                (0, KeyEvent::Down(A)),
+                (350, KeyEvent::Up(A)),
+                (350, KeyEvent::Press(Z)),
+                (750, KeyEvent::Down(A)),
+            ]
+        );
+        let mut machine = KeyStateMachine::new(immediate());
+        let start = Instant::now();
+        machine.update_band(Some(A), None, BandTiming { behavior: KeyBehavior::Hold, ..BandTiming::GLOBAL }, start);
+        // Asked to let go, e.g. on pause, it hands back the key that is down
+        assert_eq!(machine.release_held(), Some(A));
+        assert_eq!(machine.release_held(), None);
+    }
+}
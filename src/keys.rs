@@ -0,0 +1,143 @@
+use crate::layout::{key_scancode, parse_physical, physical_name, scancode_key};
+use clap::ValueEnum;
+use enigo::Key;
+use serde::{Deserialize, Serialize};
+
+/// Short human-readable name for a key, used in logs and external event streams.
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Layout(c) => c.to_string(),
+        Key::Raw(_) => match (physical_name(key), key_scancode(key)) {
+            (Some(code), _) => code.to_string(),
+            (None, Some(scancode)) => format!("{}{}", SCANCODE_PREFIX, scancode),
+            (None, None) => format!("{:?}", key),
+        },
+        other => format!("{:?}", other),
+    }
+}
+
+// How key names spell a raw scancode, e.g. `scancode:44`
+const SCANCODE_PREFIX: &str = "scancode:";
+
+/// How a key was written: `character`, `named key`, `physical key` or `scancode`, for
+/// messages that should say what a key name was taken to mean.
+pub fn key_kind(key: Key) -> &'static str {
+    match key {
+        Key::Layout(_) => "character",
+        Key::Raw(_) if physical_name(key).is_some() => "physical key",
+        Key::Raw(_) => "scancode",
+        _ => "named key",
+    }
+}
+
+/// Named keys accepted by [`parse_key`], spelled as [`key_name`] prints them.
+const NAMED_KEYS: &[(&str, Key)] = &[
+    ("Alt", Key::Alt),
+    ("Backspace", Key::Backspace),
+    ("CapsLock", Key::CapsLock),
+    ("Control", Key::Control),
+    ("Delete", Key::Delete),
+    ("DownArrow", Key::DownArrow),
+    ("End", Key::End),
+    ("Escape", Key::Escape),
+    ("F1", Key::F1),
+    ("F2", Key::F2),
+    ("F3", Key::F3),
+    ("F4", Key::F4),
+    ("F5", Key::F5),
+    ("F6", Key::F6),
+    ("F7", Key::F7),
+    ("F8", Key::F8),
+    ("F9", Key::F9),
+    ("F10", Key::F10),
+    ("F11", Key::F11),
+    ("F12", Key::F12),
+    ("Home", Key::Home),
+    ("LeftArrow", Key::LeftArrow),
+    ("Meta", Key::Meta),
+    ("PageDown", Key::PageDown),
+    ("PageUp", Key::PageUp),
+    ("Return", Key::Return),
+    ("RightArrow", Key::RightArrow),
+    ("Shift", Key::Shift),
+    ("Space", Key::Space),
+    ("Tab", Key::Tab),
+    ("UpArrow", Key::UpArrow),
+];
+
+/// The key for `name`: a single character types that character, anything else must be one of
+/// the named keys (case-insensitive), e.g. `DownArrow` or `Return`, a physical key by its W3C
+/// code, e.g. `KeyZ` (see [`parse_physical`]), or a raw scancode, e.g. `scancode:44`.
+pub fn parse_key(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(Key::Layout(c));
+    }
+    if let Some(scancode) = name.strip_prefix(SCANCODE_PREFIX) {
+        return scancode.parse().ok().and_then(scancode_key);
+    }
+    NAMED_KEYS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        .map(|&(_, key)| key)
+        .or_else(|| parse_physical(name))
+}
+
+/// What happened to a key as a result of the detected pitch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyEvent {
+    /// Initial press when a new note is detected.
+    Press(Key),
+    /// Re-press while the note is held past the hold threshold.
+    Repeat(Key),
+    /// The note ended; the key is no longer active.
+    Release(Key),
+    /// A `hold` band's note started: its key goes down and stays down until the matching
+    /// [`Up`](Self::Up).
+    Down(Key),
+    /// A `hold` band's note ended or gave way to another band: let go of its key.
+    Up(Key),
+}
+
+/// How a detected note drives its key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyMode {
+    /// Press on a new note, then repeat while it is held past the hold threshold.
+    Repeat,
+    /// Each fresh note toggles its key between held down and released.
+    Latch,
+}
+
+/// When a note fires its key in repeat mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    /// Press as soon as the note starts.
+    Onset,
+    /// Press once when the note ends, for games that judge timing on release.
+    Offset,
+}
+
+/// What a detection below the press clarity (`--min-clarity-for-press`) does to the active key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LowClarity {
+    /// Keep the key of the last clear window, as if that pitch were still heard.
+    Hold,
+    /// Treat the window as no pitch, releasing the key.
+    Release,
+    /// Leave the key state alone: no repeat, no release.
+    Ignore,
+}
+
+/// What a pitch between two bands, out of every band but within the mapping's range, does to
+/// the active key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GapBehavior {
+    /// Keep the active key, as if the pitch were still in its band.
+    Hold,
+    /// Let go of the key, like a pitch outside every band.
+    Release,
+}
@@ -0,0 +1,80 @@
+use enigo::Key;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// A key toggled by a debounced note in latch mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LatchToggle {
+    Down(Key),
+    Up(Key),
+}
+
+/// Latch mode: each fresh detection of a note flips its key between held down and released,
+/// so a direction can stay held without sustaining the note.
+///
+/// A note counts as fresh once it has been detected for `debounce_windows` consecutive windows
+/// after a different note or silence, and for at least `min_note`; it then toggles exactly once
+/// until the pitch changes again.
+pub struct LatchState {
+    debounce_windows: u32,
+    min_note: Duration,
+    latched: HashSet<Key>,
+    candidate: Option<Key>,
+    candidate_windows: u32,
+    // First window of the candidate
+    candidate_since: Option<Instant>,
+    toggled: bool,
+}
+
+impl LatchState {
+    pub fn new(debounce_windows: u32, min_note: Duration) -> Self {
+        LatchState {
+            debounce_windows: debounce_windows.max(1),
+            min_note,
+            latched: HashSet::new(),
+            candidate: None,
+            candidate_windows: 0,
+            candidate_since: None,
+            toggled: false,
+        }
+    }
+
+    /// Feed the key mapped from the current window (if any) and the time of that window.
+    pub fn update(&mut self, detected: Option<Key>, now: Instant) -> Option<LatchToggle> {
+        if detected == self.candidate {
+            self.candidate_windows = self.candidate_windows.saturating_add(1);
+        } else {
+            self.candidate = detected;
+            self.candidate_windows = 1;
+            self.candidate_since = Some(now);
+            self.toggled = false;
+        }
+
+        let key = self.candidate?;
+        let lasted = self.candidate_since.map_or(Duration::ZERO, |since| now.duration_since(since));
+        if self.toggled || self.candidate_windows < self.debounce_windows || lasted < self.min_note {
+            return None;
+        }
+        self.toggled = true;
+        if self.latched.remove(&key) {
+            Some(LatchToggle::Up(key))
+        } else {
+            self.latched.insert(key);
+            Some(LatchToggle::Down(key))
+        }
+    }
+
+    /// Forgets a note that was still being debounced, keeping the latched keys.
+    pub fn reset(&mut self) {
+        self.candidate = None;
+        self.candidate_windows = 0;
+        self.candidate_since = None;
+        self.toggled = false;
+    }
+
+    /// Releases every latched key, e.g. on shutdown. Returns the keys that were held.
+    pub fn release_all(&mut self) -> Vec<Key> {
+        self.reset();
+        self.latched.drain().collect()
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+use std::time::Duration;
+
+// Consecutive over-budget events before a warning
+const OVER_BUDGET_EVENTS_PER_WARNING: u32 = 5;
+
+/// Collects capture-to-key-press latencies and notices when they keep exceeding a budget.
+pub struct LatencyTracker {
+    budget: Duration,
+    samples: Vec<Duration>,
+    over_budget: u32,
+}
+
+impl LatencyTracker {
+    pub fn new(budget: Duration) -> Self {
+        LatencyTracker { budget, samples: Vec::new(), over_budget: 0 }
+    }
+
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    /// Adds one event's latency. Returns `true` once several events in a row have gone over
+    /// the budget, then again after each further run of that length.
+    pub fn record(&mut self, latency: Duration) -> bool {
+        self.samples.push(latency);
+        if latency <= self.budget {
+            self.over_budget = 0;
+            return false;
+        }
+        self.over_budget += 1;
+        self.over_budget.is_multiple_of(OVER_BUDGET_EVENTS_PER_WARNING)
+    }
+
+    /// Percentiles over every recorded event, or `None` before the first one.
+    pub fn summary(&self) -> Option<LatencySummary> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100];
+        Some(LatencySummary {
+            events: sorted.len(),
+            p50: percentile(50),
+            p95: percentile(95),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySummary {
+    pub events: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f32() * 1000.0;
+        write!(
+            f,
+            "p50 {:.0}ms, p95 {:.0}ms, max {:.0}ms over {} key press(es)",
+            ms(self.p50),
+            ms(self.p95),
+            ms(self.max),
+            self.events
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_over_recorded_latencies() {
+        let mut tracker = LatencyTracker::new(Duration::from_millis(150));
+        assert_eq!(tracker.summary(), None);
+        for ms in (1..=100).rev() {
+            tracker.record(Duration::from_millis(ms));
+        }
+        let summary = tracker.summary().unwrap();
+        assert_eq!(summary.events, 100);
+        assert_eq!(summary.p50, Duration::from_millis(50));
+        assert_eq!(summary.p95, Duration::from_millis(95));
+        assert_eq!(summary.max, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn warns_only_after_consecutive_events_over_budget() {
+        let mut tracker = LatencyTracker::new(Duration::from_millis(150));
+        let slow = Duration::from_millis(200);
+        let fast = Duration::from_millis(50);
+        for _ in 0..4 {
+            assert!(!tracker.record(slow));
+        }
+        // An event within budget starts the count over
+        assert!(!tracker.record(fast));
+        for _ in 0..4 {
+            assert!(!tracker.record(slow));
+        }
+        assert!(tracker.record(slow));
+    }
+}
@@ -0,0 +1,207 @@
+//! Keyboard layouts and physical keys. A character key presses whichever key types that
+//! character on the current layout, so a mapping written with QWERTY in mind presses the wrong
+//! keys on AZERTY: a game listening for the key QWERTY calls Z wants the one AZERTY calls W.
+//! Either `--layout` moves the characters to the same positions on another layout
+//! ([`Layout::remap`]), or the mapping names physical keys, by their W3C code (`"KeyZ"`) or by
+//! scancode (`{ scancode = 44 }`), which press the same key whatever the layout.
+
+use clap::ValueEnum;
+use enigo::Key;
+use serde::{Deserialize, Serialize};
+
+/// The layout a QWERTY-minded mapping is pressed on, for `--layout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// US and UK QWERTY; characters are pressed as written.
+    Qwerty,
+    /// French AZERTY.
+    Azerty,
+    /// German QWERTZ.
+    Qwertz,
+    /// US Dvorak.
+    Dvorak,
+    Colemak,
+}
+
+// The three letter rows, left to right; each layout's rows line up with QWERTY's
+const QWERTY_ROWS: [&str; 3] = ["qwertyuiop[]", "asdfghjkl;'", "zxcvbnm,./"];
+const AZERTY_ROWS: [&str; 3] = ["azertyuiop^$", "qsdfghjklmù", "wxcvbn,;:!"];
+const QWERTZ_ROWS: [&str; 3] = ["qwertzuiopü+", "asdfghjklöä", "yxcvbnm,.-"];
+const DVORAK_ROWS: [&str; 3] = ["',.pyfgcrl/=", "aoeuidhtns-", ";qjkxbmwvz"];
+const COLEMAK_ROWS: [&str; 3] = ["qwfpgjluy;[]", "arstdhneio'", "zxcvbkm,./"];
+
+impl Layout {
+    fn rows(self) -> [&'static str; 3] {
+        match self {
+            Layout::Qwerty => QWERTY_ROWS,
+            Layout::Azerty => AZERTY_ROWS,
+            Layout::Qwertz => QWERTZ_ROWS,
+            Layout::Dvorak => DVORAK_ROWS,
+            Layout::Colemak => COLEMAK_ROWS,
+        }
+    }
+
+    /// The character this layout has where QWERTY has `c`, so pressing it presses the key a
+    /// QWERTY user would. Only the letter rows move; anything else is returned unchanged.
+    pub fn remap(self, c: char) -> char {
+        let lower = c.to_ascii_lowercase();
+        for (qwerty, row) in QWERTY_ROWS.iter().zip(self.rows()) {
+            if let Some(index) = qwerty.chars().position(|key| key == lower)
+                && let Some(moved) = row.chars().nth(index)
+            {
+                return if c.is_ascii_uppercase() { moved.to_uppercase().next().unwrap_or(moved) } else { moved };
+            }
+        }
+        c
+    }
+
+    /// [`remap`](Self::remap) for character keys; other keys are left alone.
+    pub fn remap_key(self, key: Key) -> Key {
+        match key {
+            Key::Layout(c) => Key::Layout(self.remap(c)),
+            other => other,
+        }
+    }
+}
+
+// Physical keys by W3C code, with their Linux key code (as `evtest` shows it), their macOS
+// virtual key code and the character US QWERTY types there
+const PHYSICAL_KEYS: &[(&str, u16, u16, char)] = &[
+    ("KeyA", 30, 0x00, 'a'),
+    ("KeyB", 48, 0x0B, 'b'),
+    ("KeyC", 46, 0x08, 'c'),
+    ("KeyD", 32, 0x02, 'd'),
+    ("KeyE", 18, 0x0E, 'e'),
+    ("KeyF", 33, 0x03, 'f'),
+    ("KeyG", 34, 0x05, 'g'),
+    ("KeyH", 35, 0x04, 'h'),
+    ("KeyI", 23, 0x22, 'i'),
+    ("KeyJ", 36, 0x26, 'j'),
+    ("KeyK", 37, 0x28, 'k'),
+    ("KeyL", 38, 0x25, 'l'),
+    ("KeyM", 50, 0x2E, 'm'),
+    ("KeyN", 49, 0x2D, 'n'),
+    ("KeyO", 24, 0x1F, 'o'),
+    ("KeyP", 25, 0x23, 'p'),
+    ("KeyQ", 16, 0x0C, 'q'),
+    ("KeyR", 19, 0x0F, 'r'),
+    ("KeyS", 31, 0x01, 's'),
+    ("KeyT", 20, 0x11, 't'),
+    ("KeyU", 22, 0x20, 'u'),
+    ("KeyV", 47, 0x09, 'v'),
+    ("KeyW", 17, 0x0D, 'w'),
+    ("KeyX", 45, 0x07, 'x'),
+    ("KeyY", 21, 0x10, 'y'),
+    ("KeyZ", 44, 0x06, 'z'),
+    ("Digit1", 2, 0x12, '1'),
+    ("Digit2", 3, 0x13, '2'),
+    ("Digit3", 4, 0x14, '3'),
+    ("Digit4", 5, 0x15, '4'),
+    ("Digit5", 6, 0x17, '5'),
+    ("Digit6", 7, 0x16, '6'),
+    ("Digit7", 8, 0x1A, '7'),
+    ("Digit8", 9, 0x1C, '8'),
+    ("Digit9", 10, 0x19, '9'),
+    ("Digit0", 11, 0x1D, '0'),
+    ("Minus", 12, 0x1B, '-'),
+    ("Equal", 13, 0x18, '='),
+    ("BracketLeft", 26, 0x21, '['),
+    ("BracketRight", 27, 0x1E, ']'),
+    ("Semicolon", 39, 0x29, ';'),
+    ("Quote", 40, 0x27, '\''),
+    ("Backquote", 41, 0x32, '`'),
+    ("Backslash", 43, 0x2A, '\\'),
+    ("Comma", 51, 0x2B, ','),
+    ("Period", 52, 0x2F, '.'),
+    ("Slash", 53, 0x2C, '/'),
+];
+
+// X11 numbers keys from 8 up: the Linux key code plus 8
+const X11_KEYCODE_OFFSET: u16 = 8;
+const X11_MAX_KEYCODE: u16 = 255;
+
+/// The key for a raw `scancode`: a Linux key code (44 is the key QWERTY calls Z), a macOS
+/// virtual key code, or a Windows virtual-key code. `None` if it can't be sent here.
+pub fn scancode_key(scancode: u16) -> Option<Key> {
+    if cfg!(target_os = "linux") {
+        let keycode = scancode.checked_add(X11_KEYCODE_OFFSET).filter(|&keycode| keycode <= X11_MAX_KEYCODE)?;
+        Some(Key::Raw(keycode))
+    } else {
+        Some(Key::Raw(scancode))
+    }
+}
+
+/// The scancode [`scancode_key`] made `key` from, if it is a raw key.
+pub fn key_scancode(key: Key) -> Option<u16> {
+    match key {
+        Key::Raw(keycode) if cfg!(target_os = "linux") => keycode.checked_sub(X11_KEYCODE_OFFSET),
+        Key::Raw(scancode) => Some(scancode),
+        _ => None,
+    }
+}
+
+/// The key for a W3C code such as `"KeyZ"` or `"Digit1"`, ignoring case. Windows places sent
+/// keys by character, so there it is the character US QWERTY has at that position, which
+/// `--layout` then moves.
+pub fn parse_physical(code: &str) -> Option<Key> {
+    let &(_, linux, macos, qwerty) = PHYSICAL_KEYS.iter().find(|(name, ..)| name.eq_ignore_ascii_case(code))?;
+    if cfg!(target_os = "linux") {
+        scancode_key(linux)
+    } else if cfg!(target_os = "macos") {
+        Some(Key::Raw(macos))
+    } else {
+        Some(Key::Layout(qwerty))
+    }
+}
+
+/// The W3C code of a raw key that [`parse_physical`] could have made.
+pub fn physical_name(key: Key) -> Option<&'static str> {
+    let scancode = key_scancode(key)?;
+    PHYSICAL_KEYS
+        .iter()
+        .find(|&&(_, linux, macos, _)| {
+            if cfg!(target_os = "linux") {
+                linux == scancode
+            } else {
+                cfg!(target_os = "macos") && macos == scancode
+            }
+        })
+        .map(|&(name, ..)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letters_move_to_the_same_position() {
+        assert_eq!(Layout::Azerty.remap('z'), 'w');
+        assert_eq!(Layout::Azerty.remap('a'), 'q');
+        assert_eq!(Layout::Azerty.remap('X'), 'X');
+        assert_eq!(Layout::Azerty.remap('Q'), 'A');
+        assert_eq!(Layout::Qwertz.remap('y'), 'z');
+        assert_eq!(Layout::Dvorak.remap('s'), 'o');
+        // Off the letter rows, nothing moves
+        assert_eq!(Layout::Azerty.remap('1'), '1');
+        assert_eq!(Layout::Qwerty.remap_key(Key::Layout('z')), Key::Layout('z'));
+        assert_eq!(Layout::Azerty.remap_key(Key::Space), Key::Space);
+        for layout in Layout::value_variants() {
+            assert!(layout.rows().iter().zip(QWERTY_ROWS).all(|(row, qwerty)| row.chars().count() == qwerty.len()));
+        }
+    }
+
+    #[test]
+    fn physical_keys_round_trip() {
+        let z = parse_physical("keyz").unwrap();
+        if cfg!(target_os = "linux") {
+            assert_eq!(z, Key::Raw(52));
+            assert_eq!(scancode_key(44), Some(z));
+            assert_eq!(key_scancode(z), Some(44));
+            assert_eq!(physical_name(z), Some("KeyZ"));
+            assert_eq!(scancode_key(300), None);
+        }
+        assert_eq!(parse_physical("KeyZZ"), None);
+        assert_eq!(physical_name(Key::Layout('z')), None);
+    }
+}
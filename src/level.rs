@@ -0,0 +1,98 @@
+use log::{info, warn};
+
+// Samples at or beyond this magnitude count as clipped
+const CLIP_LEVEL: f32 = 0.999;
+// Clipped samples within one second that trigger a warning
+const CLIPPED_SAMPLES_PER_WARNING: usize = 8;
+// Minimum seconds between two clipping warnings
+const CLIP_WARNING_COOLDOWN_SECS: u32 = 5;
+// Span (seconds) over which the loudest window is compared against the power threshold
+const QUIET_SPAN_SECS: u32 = 10;
+// The too-quiet warning only fires when the loudest window was at least this close to the
+// threshold, so plain room silence doesn't trigger it
+const QUIET_MIN_FRACTION: f32 = 0.1;
+
+/// Convert a linear amplitude to dBFS.
+pub fn to_dbfs(amplitude: f32) -> f32 {
+    20.0 * amplitude.max(1e-6).log10()
+}
+
+/// Per-second peak/RMS tracking with warnings for clipping and for input too quiet for the gate.
+pub struct LevelMeter {
+    readout: bool,
+    seconds: u32,
+    samples: usize,
+    peak: f32,
+    sum_squares: f32,
+    clipped: usize,
+    last_clip_warning: Option<u32>,
+    loudest_window_power: f32,
+}
+
+impl LevelMeter {
+    /// With `readout` set, logs a one-line peak/RMS summary every second.
+    pub fn new(readout: bool) -> Self {
+        LevelMeter {
+            readout,
+            seconds: 0,
+            samples: 0,
+            peak: 0.0,
+            sum_squares: 0.0,
+            clipped: 0,
+            last_clip_warning: None,
+            loudest_window_power: 0.0,
+        }
+    }
+
+    /// Feed one analysis window. `power_threshold` is the gate currently in effect.
+    pub fn observe(&mut self, window: &[f32], sample_rate: usize, power_threshold: f32) {
+        let mut window_power = 0.0;
+        for &sample in window {
+            let magnitude = sample.abs();
+            self.peak = self.peak.max(magnitude);
+            window_power += sample * sample;
+            if magnitude >= CLIP_LEVEL {
+                self.clipped += 1;
+            }
+        }
+        self.sum_squares += window_power;
+        self.samples += window.len();
+        self.loudest_window_power = self.loudest_window_power.max(window_power);
+
+        if self.samples >= sample_rate {
+            self.finish_second(power_threshold);
+        }
+    }
+
+    fn finish_second(&mut self, power_threshold: f32) {
+        self.seconds += 1;
+        let rms = (self.sum_squares / self.samples as f32).sqrt();
+        if self.readout {
+            info!("Meter: peak {:6.1} dBFS, RMS {:6.1} dBFS", to_dbfs(self.peak), to_dbfs(rms));
+        }
+
+        let cooled_down = self
+            .last_clip_warning
+            .is_none_or(|at| self.seconds - at >= CLIP_WARNING_COOLDOWN_SECS);
+        if self.clipped >= CLIPPED_SAMPLES_PER_WARNING && cooled_down {
+            warn!("Input clipping ({} samples at full scale in the last second) — lower your mic gain.", self.clipped);
+            self.last_clip_warning = Some(self.seconds);
+        }
+
+        if self.seconds.is_multiple_of(QUIET_SPAN_SECS) {
+            let loudest = self.loudest_window_power;
+            if loudest < power_threshold && loudest >= power_threshold * QUIET_MIN_FRACTION {
+                warn!(
+                    "Input too quiet for current --power setting: the loudest window in the last {}s had power {:.3}, below the threshold of {:.3}. Raise your mic gain or lower --power.",
+                    QUIET_SPAN_SECS, loudest, power_threshold
+                );
+            }
+            self.loudest_window_power = 0.0;
+        }
+
+        self.samples = 0;
+        self.peak = 0.0;
+        self.sum_squares = 0.0;
+        self.clipped = 0;
+    }
+}
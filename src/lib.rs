@@ -0,0 +1,58 @@
+//! Pitch detection and pitch-to-key logic, independent of the audio backend and of how keys
+//! are actually pressed. The `pitchu` binary wires cpal and enigo to [`engine::PitchToKeyEngine`].
+//! Mapping logic of your own plugs into the engine as a [`mapper::FrequencyMapper`].
+
+pub mod audio_queue;
+pub mod axis;
+pub mod backend;
+pub mod band_meter;
+pub mod bandlimit;
+pub mod clock;
+pub mod config;
+pub mod curve;
+pub mod decimate;
+pub mod detector;
+pub mod engine;
+pub mod error;
+pub mod gamepad;
+pub mod gate;
+pub mod held_keys;
+pub mod histogram;
+pub mod idle;
+pub mod intervals;
+pub mod key_state;
+pub mod keys;
+pub mod latch;
+pub mod latency;
+pub mod layout;
+pub mod level;
+pub mod mapper;
+pub mod mapping;
+pub mod mapping_table;
+pub mod notes;
+pub mod octave;
+pub mod osc;
+pub mod patterns;
+pub mod percussive;
+pub mod pitch_stats;
+pub mod practice;
+pub mod presets;
+pub mod register;
+pub mod relative;
+pub mod remote;
+pub mod scope;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod sequence;
+pub mod session;
+pub mod simulate;
+pub mod smoothing;
+pub mod stats;
+pub mod sweep;
+pub mod tuner;
+pub mod vibrato;
+pub mod voices;
+pub mod whistle;
+
+/// Log target of the per-window pitch lines, which are only shown with `--log-pitch`.
+pub const PITCH_TARGET: &str = "pitchu::pitch";
@@ -0,0 +1,128 @@
+use crate::cli::Args;
+use crate::file_log::{session_elapsed, FileSink, Rotation};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use pitchu::PITCH_TARGET;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+// Messages from before `init`, logged once it has installed the logger
+static EARLY: Mutex<Vec<(Level, String)>> = Mutex::new(Vec::new());
+// Whether a line without a newline (the `--meter` bar) is being redrawn on stderr
+static STATUS_LINE: AtomicBool = AtomicBool::new(false);
+
+/// Console log level chosen by `-v`/`--quiet`.
+pub fn level_from_args(args: &Args) -> LevelFilter {
+    if args.quiet {
+        return LevelFilter::Warn;
+    }
+    match args.verbose {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Sets up console logging, plus the `--log-file` if one was given. `RUST_LOG`, when set,
+/// replaces the console filter derived from the flags; the file has its own `--log-file-level`.
+/// `pipe` redirects console output away from stderr (e.g. into the TUI).
+pub fn init(args: &Args, pipe: Option<Box<dyn Write + Send>>) -> io::Result<()> {
+    let mut builder = env_logger::Builder::new();
+    match std::env::var("RUST_LOG") {
+        Ok(filters) => {
+            builder.parse_filters(&filters);
+        }
+        Err(_) => {
+            builder.filter_level(level_from_args(args));
+            if !args.log_pitch {
+                builder.filter_module(PITCH_TARGET, LevelFilter::Off);
+            }
+        }
+    }
+    if let Some(pipe) = pipe {
+        builder.target(env_logger::Target::Pipe(pipe));
+    }
+    let console = builder.build();
+    let file = match &args.log_file {
+        Some(path) => {
+            let rotation = Rotation { max_bytes: args.log_file_max_mb * 1024 * 1024, keep: args.log_file_keep };
+            Some(FileLog { sink: FileSink::open(path, Some(rotation))?, level: args.log_file_level, pitch: args.log_pitch })
+        }
+        None => None,
+    };
+    let max_level = console.filter().max(file.as_ref().map_or(LevelFilter::Off, |file| file.level));
+    log::set_max_level(max_level);
+    // Only fails if a logger is already installed, which would be a bug
+    let _ = log::set_boxed_logger(Box::new(SessionLogger { console, file }));
+    for (level, message) in std::mem::take(&mut *EARLY.lock().unwrap_or_else(PoisonError::into_inner)) {
+        log::log!(target: env!("CARGO_CRATE_NAME"), level, "{}", message);
+    }
+    Ok(())
+}
+
+/// Keeps `message` for the logger [`init`] installs. Setup that runs before it (config,
+/// mapping) uses this, since the `log` macros drop everything until then.
+pub fn early(level: Level, message: String) {
+    EARLY.lock().unwrap_or_else(PoisonError::into_inner).push((level, message));
+}
+
+/// Whether a status line is being redrawn in place on stderr, as the `--meter` bar is. While
+/// one is, each console record clears it first, so the record gets a line of its own and the
+/// status line is redrawn below it.
+pub fn set_status_line(active: bool) {
+    STATUS_LINE.store(active, Ordering::Relaxed);
+}
+
+/// Waits for the log file to catch up; call before exiting.
+pub fn flush() {
+    log::logger().flush();
+}
+
+struct FileLog {
+    sink: FileSink,
+    level: LevelFilter,
+    // Whether the per-window pitch lines go to the file too
+    pitch: bool,
+}
+
+impl FileLog {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level && (self.pitch || !metadata.target().starts_with(PITCH_TARGET))
+    }
+}
+
+// The console logger and the log file, each with its own filter
+struct SessionLogger {
+    console: env_logger::Logger,
+    file: Option<FileLog>,
+}
+
+impl Log for SessionLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.console.enabled(metadata) || self.file.as_ref().is_some_and(|file| file.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if STATUS_LINE.load(Ordering::Relaxed) && self.console.enabled(record.metadata()) {
+            // Held across the record, so the status line can't be redrawn in between
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[K");
+            self.console.log(record);
+        } else {
+            self.console.log(record);
+        }
+        if let Some(file) = &self.file
+            && file.enabled(record.metadata())
+        {
+            let elapsed = session_elapsed().as_secs_f64();
+            file.sink.write_line(format!("[{:10.3}s {:5} {}] {}", elapsed, record.level(), record.target(), record.args()));
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+        if let Some(file) = &self.file {
+            file.sink.flush();
+        }
+    }
+}
@@ -1,158 +1,1686 @@
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use enigo::{Enigo, Key, KeyboardControllable};
-use pitch_detection::detector::mcleod::McLeodDetector;
-use pitch_detection::detector::PitchDetector;
-use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
-use std::thread;
+mod audio;
+mod bench;
+mod cli;
+mod dashboard;
+mod file_log;
+mod hotkey;
+mod logging;
+mod metronome;
+mod midi;
+mod monitor;
+mod output;
+mod play;
+mod receive;
+mod replay;
+mod test_mapping;
+mod tui;
+mod tune;
+mod virtual_keyboard;
+mod virtual_pad;
+mod ws;
+
+use audio::{
+    device_name, list_devices, reconnect, select_host, select_input_device, select_loopback_device, AudioInput,
+    CaptureSettings, ReconnectPolicy,
+};
+use clap::{CommandFactory, FromArgMatches};
+use enigo::Key;
+use cli::{Args, Command, Cue, DumpFormat, InputSource};
+use dashboard::{Dashboard, DashboardEvent};
+use file_log::{EventLog, SessionRecorder};
+use hotkey::HotkeyWatcher;
+use log::{debug, info, warn, Level};
+use midi::{HeldNotes, MidiNotes};
+use metronome::Metronome;
+use monitor::Monitor;
+use output::{KeyCommand, KeyOutput, OutputKind, OutputSettings};
+use pitchu::band_meter;
+use pitchu::clock::{AudioClock, Clock, ManualClock, SystemClock};
+use pitchu::config::{band_configs, default_config_path, Config, DEFAULT_CONFIG};
+use pitchu::curve::{Curve, CurveMove};
+use pitchu::decimate::PASSBAND;
+use pitchu::engine::{EngineConfig, PitchToKeyEngine};
+use pitchu::error::{PitchuError, Result};
+use pitchu::gamepad::ButtonMap;
+use pitchu::gate::window_power;
+use pitchu::histogram::FrequencyHistogram;
+use pitchu::idle::IdleWatchdog;
+use pitchu::key_state::{GridFrom, KeyBehavior, KeyTiming, RepeatAcceleration, RepeatGrid};
+use pitchu::keys::{key_name, KeyEvent, KeyMode, Trigger};
+use pitchu::latency::LatencySummary;
+use pitchu::level::LevelMeter;
+use pitchu::mapping::Mapping;
+use pitchu::mapping_table::mapping_table;
+use pitchu::notes::{frequency_to_midi, midi_to_frequency, nearest_note, parse_note_name};
+use pitchu::osc::OscSender;
+use pitchu::patterns::Pattern;
+use pitchu::practice::Practice;
+use pitchu::presets::VocalRange;
+use pitchu::register::{transposition, RegisterEstimator, REGISTER_LISTEN};
+use pitchu::relative::TonicCalibrator;
+#[cfg(feature = "scripting")]
+use pitchu::script::ScriptMapper;
+use pitchu::scope::Scope;
+use pitchu::sequence::{parse_sequence, sequence_length};
+use pitchu::session::{Replay, Session};
+use pitchu::simulate::{parse_pitch_csv, simulate};
+use pitchu::voices::combined_mapping;
+use pitchu::smoothing::SmoothingKind;
+use pitchu::whistle::{WHISTLE_MIN_HZ, WHISTLE_WINDOW_SIZE};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, IsTerminal, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use log::{info, error, debug};
+use tui::{Tui, UiEvent};
+use ws::{PitchBroadcaster, PitchEvent};
 
 const BUFFER_SIZE: usize = 2048;
+// How long the tonic of a relative mapping must be held to calibrate it
+const TONIC_HOLD: Duration = Duration::from_secs(1);
+// Tonic of a relative mapping played from MIDI (C4)
+const MIDI_TONIC: u8 = 60;
+// Longest the loop waits for audio before checking the hotkey, Ctrl-C and the device again
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+// How often the mapping script file is checked for edits
+const SCRIPT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// Cells in the --meter band bar
+const BAND_METER_WIDTH: usize = 48;
+// Clarity from which a --cue color line is green, and from which it is yellow rather than red
+const CUE_CONFIDENT_CLARITY: f32 = 0.8;
+const CUE_BORDERLINE_CLARITY: f32 = 0.5;
+
+/// One detection pipeline: the downmixed input, or one input channel with `--dual-channel`.
+struct Channel {
+    engine: PitchToKeyEngine<AudioClock>,
+    /// The engine's clock, moved on by every sample the channel takes from the input.
+    clock: AudioClock,
+    level_meter: LevelMeter,
+    histogram: FrequencyHistogram,
+    warmup_samples: usize,
+    /// Waiting for the tonic of a relative mapping; no keys are mapped meanwhile.
+    calibrator: Option<TonicCalibrator>,
+    /// `--auto-transpose`: listening for the singer's register, and the mapping to move to it.
+    /// No keys are mapped meanwhile either.
+    register: Option<(RegisterEstimator, Mapping)>,
+}
+
+fn main() -> ExitCode {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let result = run(args, &matches);
+    logging::flush();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            let mut source = e.source();
+            while let Some(cause) = source {
+                eprintln!("  caused by: {}", cause);
+                source = cause.source();
+            }
+            if let Some(advice) = e.advice() {
+                eprintln!("Hint: {}", advice);
+            }
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    env_logger::init();
+fn run(mut args: Args, matches: &clap::ArgMatches) -> Result<()> {
+    if args.config.is_empty()
+        && let Some(path) = default_config_path().filter(|path| path.is_file())
+    {
+        args.config.push(path);
+    }
+    match &args.command {
+        Some(Command::ListDevices) => {
+            logging::init(&args, None).map_err(|e| setup_error("open the log file", e))?;
+            list_devices();
+            return Ok(());
+        }
+        Some(Command::ListMidiPorts) => {
+            logging::init(&args, None).map_err(|e| setup_error("open the log file", e))?;
+            return midi::list_ports();
+        }
+        Some(Command::Init { force }) => return init_config(*force),
+        Some(Command::ConfigPath) => {
+            match default_config_path() {
+                Some(path) => println!("{}", path.display()),
+                None => println!("No config directory: the system has no home directory."),
+            }
+            return Ok(());
+        }
+        Some(Command::Bench { window_sizes, iterations }) => {
+            bench::run(window_sizes, *iterations);
+            return Ok(());
+        }
+        Some(Command::Receive { listen }) => {
+            let listen = listen.clone();
+            logging::init(&args, None).map_err(|e| setup_error("open the log file", e))?;
+            log_config_files(&args);
+            let config = Config::load_all(&args.config)?;
+            args.apply_config(matches, &config);
+            let buttons = config.button_map()?;
+            let listener = TcpListener::bind(&listen).map_err(|e| setup_error("listen for remote key presses", e))?;
+            warn!("Receive: anyone who can reach {} can press keys on this machine.", listen);
+            if !args.output.iter().any(OutputKind::presses_keys) {
+                warn!("Receive: --output osc presses no keys, so nothing sent here will happen.");
+            }
+            let running = Arc::new(AtomicBool::new(true));
+            let running_handler = Arc::clone(&running);
+            ctrlc::set_handler(move || running_handler.store(false, Ordering::SeqCst))
+                .map_err(|e| setup_error("install the Ctrl-C handler", e))?;
+            let settings = OutputSettings { remote: None, ..output_settings(&args, buttons, &[], None) };
+            let output = KeyOutput::start(Duration::from_millis(args.latency_budget_ms), settings);
+            return receive::run(listener, output, &running).map_err(|e| setup_error("receive remote key presses", e));
+        }
+        Some(Command::Tune) => {
+            logging::init(&args, None).map_err(|e| setup_error("open the log file", e))?;
+            log_config_files(&args);
+            let config = Config::load_all(&args.config)?;
+            args.apply_config(matches, &config);
+            return run_tune(&args);
+        }
+        // Replayed once the mapping is set up, below
+        Some(Command::Replay { .. }) | Some(Command::TestMapping { .. }) | Some(Command::ShowMapping) | None => {}
+    }
+
+    if let Some(preset) = args.print_preset {
+        let config = Config { bands: band_configs(&preset.mapping(args.range)), ..Config::default() };
+        let text = toml::to_string(&config).map_err(|e| setup_error("serialize the preset", e))?;
+        println!("# pitchu preset '{}', {} range\n{}", preset.name(), args.range.name(), text);
+        return Ok(());
+    }
+
+    let config = Config::load_all(&args.config)?;
+    args.apply_config(matches, &config);
+    let pipelines = if args.dual_channel { 2 } else { 1 };
+    let relative = config.relative_mapping()?;
+    let intervals = config.interval_mapping()?;
+    let voices = config.voices()?;
+    let buttons = config.button_map()?;
+    let percussive = config.percussive()?;
+    let axis = config.axis_mapping(args.a4)?;
+    let curves = config.curves()?;
+    let mappings = if !voices.is_empty() {
+        vec![combined_mapping(&voices); pipelines]
+    } else if relative.is_some() {
+        // Filled in once each channel's tonic has been sung
+        vec![Mapping::new(Vec::new()); pipelines]
+    } else if intervals.is_some() {
+        // The intervals replace the bands' keys; their ignore bands still apply
+        let ignores = config.mapping(args.a4)?.bands().iter().filter(|band| band.is_ignore()).cloned().collect();
+        vec![Mapping::new(ignores); pipelines]
+    } else if let Some(preset) = args.preset {
+        if config.has_bands() || config.has_channel_bands(0) || config.has_channel_bands(1) {
+            logging::early(Level::Warn, format!("--preset {} replaces the config file's bands.", preset.name()));
+        }
+        vec![preset.mapping(args.range); pipelines]
+    } else if args.dual_channel {
+        vec![config.channel_mapping(0, args.a4)?, config.channel_mapping(1, args.a4)?]
+    } else {
+        vec![config.mapping(args.a4)?]
+    };
+
+    let patterns = mappings.iter().map(|mapping| config.patterns(mapping)).collect::<Result<Vec<_>>>()?;
+
+    if args.gate_attack == 0.0 && args.gate_release > 0.0 {
+        logging::early(Level::Warn, "--gate-release only applies with --gate-attack; ignoring it.".to_string());
+    } else if args.gate_attack > 0.0 && args.gate_release > args.gate_attack {
+        logging::early(
+            Level::Warn,
+            format!("--gate-release {} is above --gate-attack {}; using a single threshold.", args.gate_release, args.gate_attack),
+        );
+    }
+    if args.preset.is_none() && args.range != VocalRange::Low {
+        logging::early(Level::Warn, "--range only applies to --preset; ignoring it.".to_string());
+    }
+
+    if let Some(format) = args.dump_config {
+        let mut effective = args.to_config(&mappings);
+        effective.relative = config.relative.clone();
+        effective.intervals = config.intervals.clone();
+        effective.script = config.script.clone();
+        effective.gamepad = config.gamepad.clone();
+        effective.patterns = config.patterns.clone();
+        effective.curves = config.curves.clone();
+        effective.percussive_key = config.percussive_key.clone();
+        effective.percussive_refractory_ms = config.percussive_refractory_ms;
+        if !voices.is_empty() {
+            effective.voices = config.voices.clone();
+            effective.bands.clear();
+            effective.channel0 = None;
+            effective.channel1 = None;
+        }
+        let text = match format {
+            DumpFormat::Toml => toml::to_string(&effective).map_err(|e| setup_error("serialize the config", e))?,
+            DumpFormat::Json => {
+                serde_json::to_string_pretty(&effective).map_err(|e| setup_error("serialize the config", e))?
+            }
+        };
+        println!("{}", text);
+        return Ok(());
+    }
+
+    // After the dump, which keeps the bands as written. MIDI notes are already in their range.
+    let (mappings, voices) = if args.whistle && args.input == InputSource::Audio {
+        args.apply_whistle(matches, &config);
+        let octaves = args.whistle_octaves;
+        (
+            mappings.iter().map(|mapping| mapping.transposed(octaves)).collect(),
+            voices.iter().map(|voice| voice.transposed(octaves)).collect(),
+        )
+    } else {
+        (mappings, voices)
+    };
+    let mappings = match args.transpose {
+        Some(_) if relative.is_some() || intervals.is_some() || !voices.is_empty() => {
+            logging::early(
+                Level::Warn,
+                "--transpose has no effect with [relative], [intervals] or [[voices]] mappings.".to_string(),
+            );
+            mappings
+        }
+        Some(semitones) => {
+            logging::early(Level::Info, format!("Transpose: moving every band {:+} semitone(s).", semitones));
+            mappings.iter().map(|mapping| mapping.transposed_semitones(semitones)).collect()
+        }
+        None => mappings,
+    };
+    if args.auto_transpose && (relative.is_some() || intervals.is_some() || !voices.is_empty()) {
+        logging::early(
+            Level::Warn,
+            "--auto-transpose has no effect with [relative], [intervals] or [[voices]] mappings.".to_string(),
+        );
+        args.auto_transpose = false;
+    }
+
+    if let Some(Command::ShowMapping) = &args.command {
+        logging::init(&args, None).map_err(|e| setup_error("open the log file", e))?;
+        log_config_files(&args);
+        if relative.is_some() {
+            warn!("The [relative] bands are placed once the tonic is sung; shown here at C4.");
+        }
+        for (mapping, index) in mappings.iter().zip(0..) {
+            let mapping = match &relative {
+                Some(relative) => relative.at_tonic(midi_to_frequency(MIDI_TONIC as f32, args.a4)),
+                None => mapping.clone(),
+            };
+            if mappings.len() > 1 {
+                println!("Channel {}:", index);
+            }
+            print!("{}", mapping_table(&mapping, args.mode, args.a4));
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::TestMapping { step_hz, interactive }) = &args.command {
+        logging::init(&args, None).map_err(|e| setup_error("open the log file", e))?;
+        log_config_files(&args);
+        if intervals.is_some() {
+            warn!("[intervals] decide the keys; only their ignore bands are shown.");
+        }
+        if config.script.is_some() {
+            warn!("The mapping script decides the keys at runtime; these are the bands it replaces.");
+        }
+        let mappings = match &relative {
+            Some(relative) => {
+                info!("Relative mapping: using C4 as the tonic.");
+                vec![relative.at_tonic(midi_to_frequency(MIDI_TONIC as f32, args.a4)); mappings.len()]
+            }
+            None => mappings,
+        };
+        test_mapping::run(&mappings, *step_hz, *interactive, args.a4);
+        return Ok(());
+    }
+
+    // Replays and simulations run the mapping on a virtual clock, without audio
+    let offline_engines = |channels: usize, engine_config: EngineConfig, clock: &ManualClock| -> Result<Vec<_>> {
+        let mut engines = Vec::new();
+        for (mapping, patterns) in mappings.iter().zip(&patterns).take(channels) {
+            let mapping = match &relative {
+                Some(relative) => {
+                    info!("Relative mapping: using C4 as the tonic.");
+                    relative.at_tonic(midi_to_frequency(MIDI_TONIC as f32, args.a4))
+                }
+                None => mapping.clone(),
+            };
+            let mut engine = PitchToKeyEngine::new(engine_config.clone(), mapping, clock.clone());
+            attach_script(&mut engine, config.script.as_deref(), args.a4)?;
+            engine.set_patterns(patterns.clone());
+            if let Some((key, refractory)) = percussive {
+                engine.set_percussive(key, refractory);
+            }
+            if !voices.is_empty() {
+                engine.set_voices(voices.clone());
+            }
+            if let Some(intervals) = &intervals {
+                engine.set_intervals(intervals.clone(), args.a4);
+            }
+            engines.push(engine);
+        }
+        Ok(engines)
+    };
+    if let Some(Command::Replay { session: path, instant }) = &args.command {
+        logging::init(&args, None).map_err(|e| setup_error("open the log file", e))?;
+        log_config_files(&args);
+        let session = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Session::parse(&text))
+            .map_err(|reason| PitchuError::Session { path: path.clone(), reason })?;
+        info!(
+            "Replay: {} ({} Hz, {}-sample windows, {} channel(s)).",
+            path.display(),
+            session.sample_rate,
+            session.window_size,
+            session.channels
+        );
+        if session.channels > mappings.len() {
+            warn!("Replay: the session has {} channels; pass --dual-channel to replay both.", session.channels);
+        }
+        let clock = ManualClock::new();
+        let engine_config = EngineConfig { window_size: session.window_size, ..engine_config(&args, session.sample_rate) };
+        let engines = offline_engines(session.channels, engine_config, &clock)?;
+        replay::run(&session, Replay::new(engines, clock), *instant);
+        return Ok(());
+    }
+    if let Some(path) = &args.simulate {
+        logging::init(&args, None).map_err(|e| setup_error("open the log file", e))?;
+        let windows = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| parse_pitch_csv(&text))
+            .map_err(|reason| PitchuError::Simulation { path: path.clone(), reason })?;
+        info!("Simulation: {} window(s) from {}.", windows.len(), path.display());
+        let clock = ManualClock::new();
+        let mut engines = offline_engines(1, engine_config(&args, 0), &clock)?;
+        let events = engines.first_mut().map(|engine| simulate(&windows, engine, &clock)).unwrap_or_default();
+        let csv: String = events.iter().map(|event| format!("{}\n", event)).collect();
+        print!("{}", csv);
+        if let Some(output) = &args.simulate_output {
+            std::fs::write(output, &csv)
+                .map_err(|e| setup_error(&format!("write the simulation output {}", output.display()), e))?;
+            info!("Simulation: {} event(s) written to {}.", events.len(), output.display());
+        }
+        return Ok(());
+    }
+
+    if let Some(path) = &args.play {
+        logging::init(&args, None).map_err(|e| setup_error("open the log file", e))?;
+        let steps = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| parse_sequence(&text))
+            .map_err(|reason| PitchuError::Sequence { path: path.clone(), reason })?;
+        info!(
+            "Play: {} step(s) from {}, {:.1}s in all.",
+            steps.len(),
+            path.display(),
+            sequence_length(&steps).as_secs_f32()
+        );
+        let running = Arc::new(AtomicBool::new(true));
+        let running_handler = Arc::clone(&running);
+        ctrlc::set_handler(move || running_handler.store(false, Ordering::SeqCst))
+            .map_err(|e| setup_error("install the Ctrl-C handler", e))?;
+        let mappings: Vec<&Mapping> = mappings.iter().collect();
+        let settings = output_settings(&args, buttons, &mappings, None);
+        play::run(&steps, KeyOutput::start(Duration::from_millis(args.latency_budget_ms), settings), &running);
+        return Ok(());
+    }
+
+    let running = Arc::new(AtomicBool::new(true));
+    let tui_available = args.tui && std::io::stdout().is_terminal();
+    let tui = if tui_available { Some(Tui::start(Arc::clone(&running), mappings[0].clone(), args.a4, args.scope).map_err(|e| setup_error("start the terminal UI", e))?) } else { None };
+    logging::init(&args, tui.as_ref().map(|tui| Box::new(tui.log_writer()) as Box<dyn std::io::Write + Send>))
+        .map_err(|e| setup_error("open the log file", e))?;
+    log_config_files(&args);
+    if args.tui && tui.is_none() {
+        warn!("--tui needs stdout to be a terminal; falling back to plain logging.");
+    }
+    if args.cue == Cue::Color && tui.is_some() {
+        warn!("--cue color would draw over the terminal UI, which already shows key presses; ignoring it.");
+        args.cue = Cue::None;
+    }
+    if let Some(axis) = &axis {
+        if args.remote.is_some() {
+            warn!("[gamepad.axis] isn't sent with --remote; only key presses are.");
+        } else if !args.output.contains(&OutputKind::Gamepad) {
+            warn!("[gamepad.axis] needs --output gamepad; the stick won't move.");
+        } else {
+            info!(
+                "Gamepad: {:?} follows {:.1}-{:.1} Hz{}.",
+                axis.axis,
+                axis.low_hz,
+                axis.high_hz,
+                if args.dual_channel { " on channel 0" } else { "" }
+            );
+        }
+    }
+    log_curves(&args, &curves);
+    if args.remote.is_some() {
+        for kind in args.output.iter().filter(|kind| kind.presses_keys() && **kind != OutputKind::Keyboard) {
+            warn!("--output {} has no effect with --remote; the receiving side picks its own output.", kind);
+        }
+    }
+    if args.dual_channel && !(config.has_channel_bands(0) && config.has_channel_bands(1)) {
+        warn!("--dual-channel: a channel without its own [channelN] bands uses the shared mapping, so both channels may press the same keys.");
+    }
+
+    for (mapping, index) in mappings.iter().zip(0..) {
+        warn_ignore_overlaps(mapping, &channel_label(args.dual_channel, index));
+    }
+    let tap_hold_bands = mappings[0].bands().iter().filter(|band| band.tap_key().is_some()).count();
+    if tap_hold_bands > 0 {
+        info!(
+            "Tap/hold: {} band(s) wait {}ms to tell a tap from a hold, so their hold keys press that much later.",
+            tap_hold_bands, args.tap_hold_ms
+        );
+        if args.mode == KeyMode::Latch || args.trigger == Trigger::Offset {
+            warn!("Tap keys only work in repeat mode with --trigger onset; those bands use their hold key.");
+        }
+    }
+
+    let held_bands = mappings[0].bands().iter().filter(|band| band.timing.behavior == KeyBehavior::Hold).count();
+    if held_bands > 0 && (args.mode == KeyMode::Latch || args.trigger == Trigger::Offset) {
+        warn!("action = \"hold\" only works in repeat mode with --trigger onset; {} band(s) click instead.", held_bands);
+    }
+
+    log_patterns(&patterns[0]);
 
     info!("Starting up pitch-to-key program...");
+    info!("Tuning: A4 = {} Hz.", args.a4);
+    // A relative mapping has no bands until the tonic is sung; it's shown then
+    if relative.is_none() {
+        for (mapping, index) in mappings.iter().zip(0..).filter(|(mapping, _)| !mapping.bands().is_empty()) {
+            info!("Mapping{}:", channel_label(args.dual_channel, index));
+            log_mapping_table(mapping, &args);
+        }
+    }
+    if args.whistle && args.input == InputSource::Audio {
+        log_whistle_ranges(&args, &mappings, relative.is_some());
+    }
 
-    let host = cpal::default_host();
-    let device = host
-        .default_input_device()
-        .ok_or_else(|| {
-            error!("No input device available. Please ensure a microphone is connected and recognized by your system.");
-            Box::<dyn std::error::Error>::from("No input device available")
-        })?;
-    info!("Found default input device: {}", device.name()?);
-
-    let config = device.default_input_config()?;
-    info!("Using default input stream config: {:?}", config);
-
-    let sample_rate = config.sample_rate().0 as usize;
-    let mut detector = McLeodDetector::new(BUFFER_SIZE, BUFFER_SIZE / 2);
-
-    // --- State variables for continuous key presses ---
-    let mut current_active_key: Option<Key> = None;
-    let mut current_key_start_time: Option<Instant> = None;
-    let mut last_continuous_send_time: Option<Instant> = None;
-
-    // Constants for timing
-    const HOLD_THRESHOLD_MILLIS: u64 = 250; // How long to hold a note before continuous presses start
-    const REPEAT_INTERVAL_MILLIS: u64 = 100; // How often to send a key press once continuous is active
-
-    let shared_audio_queue = Arc::new(Mutex::new(VecDeque::new()));
-    let audio_queue_clone = Arc::clone(&shared_audio_queue);
-
-    info!("Building audio input stream...");
-    let stream = device.build_input_stream(
-        &config.into(),
-        move |data: &[f32], _: &cpal::InputCallbackInfo| {
-            let mut audio_queue = audio_queue_clone.lock().unwrap();
-            audio_queue.extend(data.iter().cloned());
-        },
-        move |err| error!("Stream error: {:?}", err),
-        None,
-    )?;
+    if let InputSource::Midi(port) = &args.input {
+        if args.dual_channel {
+            warn!("--dual-channel has no effect with MIDI input; using channel 0's mapping.");
+        }
+        if args.whistle {
+            warn!("--whistle has no effect with MIDI input.");
+            args.whistle = false;
+        }
+        if percussive.is_some() {
+            warn!("percussive_key has no effect with MIDI input.");
+        }
+        if args.decimate > 1 {
+            warn!("--decimate has no effect with MIDI input.");
+        }
+        if args.practice.is_some() {
+            warn!("--practice has no effect with MIDI input.");
+        }
+        if args.output.iter().any(|kind| !kind.presses_keys()) {
+            warn!("--output osc sends nothing with MIDI input.");
+        }
+        if args.http.is_some() {
+            warn!("--http shows nothing with MIDI input.");
+        }
+        if args.scope == Some(Scope::Spectrum) {
+            warn!("--scope spectrum has no audio to show with MIDI input.");
+        }
+        let mapping = match &relative {
+            Some(relative) => {
+                info!("MIDI input: relative mapping uses C4 as its tonic.");
+                relative.at_tonic(midi_to_frequency(MIDI_TONIC as f32, args.a4))
+            }
+            None => mappings.into_iter().next().unwrap_or_default(),
+        };
+        let mut engine = PitchToKeyEngine::new(engine_config(&args, 0), mapping, SystemClock);
+        if let Some(axis) = &axis {
+            engine.set_axis(axis.clone());
+        }
+        if !curves.is_empty() {
+            engine.set_curves(curves.clone());
+        }
+        if let Some(intervals) = &intervals {
+            engine.set_intervals(intervals.clone(), args.a4);
+        }
+        attach_script(&mut engine, config.script.as_deref(), args.a4)?;
+        engine.set_patterns(patterns.into_iter().next().unwrap_or_default());
+        return run_midi(&args, port.as_deref(), engine, buttons, &running, tui);
+    }
+
+    let host = select_host(args.host.as_deref())?;
+    info!("Using audio host: {}", host.id().name());
+    let (device, loopback) = if args.loopback {
+        select_loopback_device(&host, args.device.as_deref())?
+    } else {
+        (select_input_device(&host, args.device.as_deref())?, false)
+    };
+    info!("Found {} device: {}", if args.loopback { "loopback" } else { "input" }, device_name(&device)?);
+
+    let window_size = window_size(&args);
+    let capture = CaptureSettings { pipelines, max_latency_ms: args.max_latency_ms, window_size, decimate: args.decimate };
+    let mut input = AudioInput::open(&host, &device, loopback, &capture)?;
+    if args.decimate > 1 {
+        warn_above_passband(&mappings, input.sample_rate);
+    }
+    let reconnect_policy = ReconnectPolicy {
+        interval: Duration::from_millis(args.reconnect_interval_ms),
+        max_attempts: args.max_reconnect_attempts,
+    };
+
+    let mut channels: Vec<Channel> = mappings
+        .into_iter()
+        .map(|mapping| {
+            let (mapping, register) = if args.auto_transpose {
+                // Filled in once the register is known
+                (Mapping::new(Vec::new()), Some((register_estimator(&input, window_size), mapping)))
+            } else {
+                (mapping, None)
+            };
+            let clock = AudioClock::new();
+            Channel {
+                engine: PitchToKeyEngine::new(engine_config(&args, input.sample_rate), mapping, clock.clone()),
+                clock,
+                level_meter: LevelMeter::new(args.meter),
+                histogram: FrequencyHistogram::new(),
+                warmup_samples: warmup_samples_for(&input, args.warmup_ms),
+                calibrator: relative.as_ref().map(|_| tonic_calibrator(&input, window_size)),
+                register,
+            }
+        })
+        .collect();
+    for (channel, patterns) in channels.iter_mut().zip(patterns) {
+        attach_script(&mut channel.engine, config.script.as_deref(), args.a4)?;
+        channel.engine.set_patterns(patterns);
+        if let Some((key, refractory)) = percussive {
+            channel.engine.set_percussive(key, refractory);
+        }
+        if let Some(intervals) = &intervals {
+            channel.engine.set_intervals(intervals.clone(), args.a4);
+        }
+    }
+    // Both channels steering one stick (or the mouse) would fight over it
+    if let Some(channel) = channels.first_mut() {
+        if let Some(axis) = &axis {
+            channel.engine.set_axis(axis.clone());
+        }
+        if !curves.is_empty() {
+            channel.engine.set_curves(curves.clone());
+        }
+    }
+    if let Some((low, high)) = channels.first().and_then(|channel| channel.engine.band_limit()) {
+        info!("Band-limiting detection to {:.0}-{:.0} Hz and half an octave beyond (--no-bandlimit turns it off).", low, high);
+    }
+    if let Some((key, refractory)) = percussive {
+        info!(
+            "Percussive sounds press '{}', at most once per {}ms; each waits 100ms to make sure no note follows.",
+            key_name(key),
+            refractory.as_millis()
+        );
+    }
+    if config.script.is_some() && (!voices.is_empty() || relative.is_some() || intervals.is_some()) {
+        warn!("The mapping script is not used by [[voices]], [relative] or [intervals] mappings.");
+    }
+    if !voices.is_empty() {
+        for channel in &mut channels {
+            channel.engine.set_voices(voices.clone());
+        }
+        for voice in &voices {
+            info!("Voice '{}': {:.1}-{:.1} Hz, {} band(s).", voice.name, voice.low_hz, voice.high_hz, voice.mapping.bands().len());
+        }
+        if config.has_bands() {
+            warn!("The config file's [[voices]] replace its bands.");
+        }
+        if args.mode == KeyMode::Latch {
+            warn!("--mode latch is not supported with [[voices]]; using repeat mode.");
+            args.mode = KeyMode::Repeat;
+        }
+    }
+    if relative.is_some() {
+        if config.has_bands() {
+            warn!("The config file's [relative] section replaces its bands.");
+        }
+        info!("Relative mapping: sing and hold your tonic (\"do\") to calibrate.");
+    }
+    if let Some(intervals) = &intervals {
+        if config.has_bands() {
+            warn!("The config file's [intervals] section replaces its bands; only ignore bands still apply.");
+        }
+        if args.mode == KeyMode::Latch {
+            warn!("--mode latch has no effect with [intervals]; each interval taps its key.");
+        }
+        info!(
+            "Intervals: {} rule(s), {:.0} cents of tolerance; the first note sung is where they count from.",
+            intervals.rules.len(),
+            intervals.tolerance_cents
+        );
+    }
+    if args.auto_transpose {
+        info!(
+            "Auto-transpose: sing for {}s to find your register; the bands move in steps of {} semitone(s).",
+            REGISTER_LISTEN.as_secs(),
+            args.transpose_step
+        );
+    }
+    for channel in &channels {
+        channel.engine.check_frequency_range();
+    }
+    if args.adaptive_gate {
+        info!("Adaptive gate enabled (ratio {:.1} above the noise floor).", args.adaptive_ratio);
+    }
+    if args.normalize {
+        info!("Normalization enabled: windows past the gate are scaled to a fixed level.");
+    }
+    if args.smoothing != SmoothingKind::None {
+        info!("Frequency smoothing: {:?}", args.smoothing);
+    }
+    if args.mode == KeyMode::Latch {
+        info!("Latch mode: each fresh note toggles its key (debounce {} window(s)).", args.latch_debounce);
+        if args.trigger == Trigger::Offset {
+            warn!("--trigger offset has no effect in latch mode.");
+        }
+    } else if args.trigger == Trigger::Offset {
+        info!("Offset trigger: keys are pressed when a note ends, not when it starts.");
+    }
+    if args.bpm > 0.0 {
+        let from = match args.grid_from {
+            GridFrom::Session => "the session start",
+            GridFrom::Note => "each note's first press",
+        };
+        info!("Rhythm mode: held keys repeat {} time(s) per beat at {} BPM, counted from {}.", args.repeat_division, args.bpm, from);
+        if args.mode == KeyMode::Latch {
+            warn!("--bpm has no effect in latch mode, which doesn't repeat.");
+        }
+        if args.repeat_accel {
+            warn!("--repeat-accel has no effect with --bpm; repeats stay on the grid.");
+        }
+    } else if args.metronome {
+        warn!("--metronome needs --bpm; no clicks.");
+    }
+
+    let mut practice = start_practice(&args);
+
+    let mut discarded_ms: f32 = 0.0;
+
+    let _metronome = if args.metronome && args.bpm > 0.0 {
+        Some(Metronome::start(&host, args.bpm)?)
+    } else {
+        None
+    };
+
+    let mut monitor = if args.monitor {
+        if args.loopback {
+            warn!("Monitor: --monitor with --loopback plays system audio back into itself; expect an echo loop.");
+        }
+        Some(Monitor::start(&host, args.monitor_device.as_deref(), input.sample_rate)?)
+    } else {
+        None
+    };
+
+    let broadcaster = args
+        .ws_port
+        .map(PitchBroadcaster::start)
+        .transpose()
+        .map_err(|e| setup_error("open the WebSocket server", e))?;
+    let dashboard = args
+        .http
+        .map(|address| Dashboard::start(address, channels[0].engine.mapping()))
+        .transpose()
+        .map_err(|e| setup_error("start the dashboard", e))?;
+    let mut osc = Vec::new();
+    for kind in &args.output {
+        if let OutputKind::Osc(target) = kind {
+            let target = target.as_deref().unwrap_or(&args.osc_target);
+            let sender =
+                OscSender::connect(target, args.osc_address.clone()).map_err(|e| setup_error("open the OSC socket", e))?;
+            info!("OSC: sending {} messages to {}.", args.osc_address, target);
+            osc.push(sender);
+        }
+    }
+
+    let running_handler = Arc::clone(&running);
+    ctrlc::set_handler(move || running_handler.store(false, Ordering::SeqCst))
+        .map_err(|e| setup_error("install the Ctrl-C handler", e))?;
 
-    stream.play()?;
     info!("Successfully started audio stream!");
     info!("Listening for pitch... (sing into your mic)");
     info!("Ensure mGBA (or the target application) is the active window.");
     info!("---");
 
-    let mut enigo = Enigo::new();
+    let mut pause_hotkey = HotkeyWatcher::new(args.pause_hotkey.clone());
+    match &pause_hotkey {
+        Some(_) => info!("Press {} to pause or resume key presses.", args.pause_hotkey),
+        None if args.pause_hotkey.is_disabled() => {}
+        None => warn!("Pause hotkey: the keyboard can't be watched here, so {} won't work.", args.pause_hotkey),
+    }
+    let mut paused = false;
+    let mut enable_key = enable_key_watcher(&args);
+    let mut enabled = enable_key.is_none();
+    let mut calibrate_hotkey = relative.as_ref().and_then(|_| HotkeyWatcher::new(args.calibrate_hotkey.clone()));
+    if calibrate_hotkey.is_some() {
+        info!("Press {} to sing a new tonic.", args.calibrate_hotkey);
+    }
+    let mut latency_hotkey = HotkeyWatcher::new(args.latency_hotkey.clone());
+    if latency_hotkey.is_some() {
+        info!("Press {} to show the key press latency so far.", args.latency_hotkey);
+    }
+    let mut panic_hotkey = HotkeyWatcher::new(args.panic_hotkey.clone());
+    if panic_hotkey.is_some() {
+        info!("Press {} to let go of every key held down.", args.panic_hotkey);
+    }
+
+    let mappings: Vec<&Mapping> = channels
+        .iter()
+        .map(|channel| channel.register.as_ref().map_or(channel.engine.mapping(), |(_, mapping)| mapping))
+        .collect();
+    let settings = output_settings(&args, buttons, &mappings, broadcaster.clone());
+    let mut output = KeyOutput::start(Duration::from_millis(args.latency_budget_ms), settings);
+    let event_log = open_event_log(&args)?;
+    let recorder = open_session_recorder(&args, input.sample_rate, window_size, pipelines)?;
+    let mut audio_window = vec![0.0f32; window_size];
+    let mut idle = idle_watchdog(&args);
+    let mut last_window_at: Option<Instant> = None;
+    let mut script_checked_at = Instant::now();
+    // Only on a terminal: the bar redraws itself with a carriage return
+    let band_meter = args.meter && tui.is_none() && std::io::stdout().is_terminal();
+    logging::set_status_line(band_meter);
+
+    while running.load(Ordering::SeqCst) {
+        if script_checked_at.elapsed() >= SCRIPT_CHECK_INTERVAL {
+            for channel in &mut channels {
+                reload_script(&mut channel.engine);
+            }
+            script_checked_at = Instant::now();
+        }
+        if input.has_failed() {
+            warn!("Audio: input device '{}' is no longer available. Waiting for it to come back...", input.device_name);
+            for channel in &mut channels {
+                for key in channel.engine.release_latched() {
+                    info!("Action: Releasing latched key '{:?}' (input lost).", key);
+                    output.send(KeyCommand::Up(key), None);
+                }
+            }
+            let device_name = input.device_name.clone();
+            let loopback = input.loopback;
+            drop(input);
+            input = match reconnect(&host, &device_name, loopback, &reconnect_policy, &capture, &running) {
+                Some(input) => input,
+                None if !running.load(Ordering::SeqCst) => break,
+                None => return Err(PitchuError::DeviceLost(device_name)),
+            };
+            for channel in &mut channels {
+                channel.engine.set_sample_rate(input.sample_rate);
+                channel.engine.check_frequency_range();
+                restart_channel(channel, &input, args.warmup_ms);
+            }
+        }
 
-    loop {
-        let mut audio_queue = shared_audio_queue.lock().unwrap();
+        if pause_hotkey.as_mut().is_some_and(HotkeyWatcher::pressed) {
+            paused = !paused;
+            if paused {
+                info!("Paused: listening continues, but no keys will be pressed.");
+                for channel in &mut channels {
+                    for key in channel.engine.release_latched() {
+                        info!("Action: Releasing latched key '{:?}' (paused).", key);
+                        output.send(KeyCommand::Up(key), None);
+                    }
+                    // Forget the held note, so it counts as new after resuming
+                    channel.engine.reset();
+                    send_curve_moves(&mut output, &channel.engine.stick_centers());
+                }
+            } else {
+                info!("Resumed: key presses are back on.");
+                for channel in &mut channels {
+                    restart_channel(channel, &input, args.warmup_ms);
+                }
+            }
+        }
+        if let Some(watcher) = enable_key.as_mut()
+            && watcher.held() != enabled
+        {
+            enabled = !enabled;
+            log_enable_key(enabled);
+            for channel in &mut channels {
+                if enabled {
+                    restart_channel(channel, &input, args.warmup_ms);
+                } else {
+                    release_everything(&mut channel.engine, &mut output, "enable key up");
+                }
+            }
+        }
+        if calibrate_hotkey.as_mut().is_some_and(HotkeyWatcher::pressed) {
+            info!("Relative mapping: sing and hold your new tonic (\"do\").");
+            for channel in &mut channels {
+                for key in channel.engine.release_latched() {
+                    output.send(KeyCommand::Up(key), None);
+                }
+                channel.engine.set_mapping(Mapping::new(Vec::new()));
+                channel.engine.reset();
+                channel.calibrator = Some(tonic_calibrator(&input, window_size));
+            }
+        }
+        if latency_hotkey.as_mut().is_some_and(HotkeyWatcher::pressed) {
+            log_latency(output.latency_summary());
+        }
+        if panic_hotkey.as_mut().is_some_and(HotkeyWatcher::pressed) {
+            warn!("Panic hotkey: letting go of every key held down.");
+            for channel in &mut channels {
+                channel.engine.release_latched();
+                channel.engine.reset();
+                send_curve_moves(&mut output, &channel.engine.stick_centers());
+            }
+            output.send(KeyCommand::ReleaseAll, None);
+        }
 
-        // Process audio in chunks of BUFFER_SIZE
-        while audio_queue.len() >= BUFFER_SIZE {
-            let audio_window: Vec<f32> = audio_queue.drain(0..BUFFER_SIZE).collect();
+        let sample_rate = input.sample_rate;
+        let samples_per_ms = input.samples_per_ms();
+        // A paused session isn't idle: nothing would be pressed anyway
+        let muted = paused || !enabled;
+        let mut heard = muted;
+        for (index, (channel, audio_consumer)) in channels.iter_mut().zip(&mut input.consumers).enumerate() {
+            let dropped = audio_consumer.take_overruns();
+            let discarded = audio_consumer.discard_backlog(input.latency_cap, window_size);
+            // Lost audio still took its time
+            channel.clock.advance_samples(dropped + discarded, sample_rate);
+            // All channels are captured in lockstep and lose the same audio; report it once
+            if index == 0 {
+                if dropped > 0 {
+                    warn!("Audio queue full: dropped {} incoming samples. Processing is falling behind.", dropped);
+                    discarded_ms += dropped as f32 / samples_per_ms;
+                }
+                if discarded > 0 {
+                    let ms = discarded as f32 / samples_per_ms;
+                    warn!(
+                        "Processing fell behind: discarded {} oldest samples (~{:.0}ms) to stay within --max-latency-ms.",
+                        discarded, ms
+                    );
+                    discarded_ms += ms;
+                }
+            }
 
-            let mut new_key_to_press: Option<Key> = None;
+            // Process audio in chunks of window_size
+            while audio_consumer.pop_window(&mut audio_window) {
+                // The engine times the window by its end
+                channel.clock.advance_samples(window_size, sample_rate);
+                if index == 0 {
+                    let now = Instant::now();
+                    if let Some(last) = last_window_at {
+                        debug!("Audio: window completed {:.1}ms after the previous one.", (now - last).as_secs_f32() * 1000.0);
+                    }
+                    last_window_at = Some(now);
+                }
+                // Let the device and buffers settle before anything can press a key
+                if channel.warmup_samples > 0 {
+                    channel.warmup_samples = channel.warmup_samples.saturating_sub(window_size);
+                    if channel.warmup_samples == 0 && index == 0 {
+                        info!("Warm-up complete. Listening for pitch.");
+                    }
+                    continue;
+                }
+                if index == 0
+                    && let Some(monitor) = monitor.as_mut()
+                {
+                    monitor.push(&audio_window);
+                }
+                channel.level_meter.observe(&audio_window, sample_rate, channel.engine.power_threshold());
+                if muted {
+                    continue;
+                }
+                let outcome = channel.engine.process(&audio_window);
+                if let Some(recorder) = &recorder
+                    && let Some(measurement) = outcome.measurement
+                {
+                    recorder.window(index, measurement);
+                }
+                heard |= outcome.pitch.is_some();
+                if let Some(practice) = practice.as_mut() {
+                    // Scored on the first channel; nothing is pressed
+                    if index == 0 && practice.observe(outcome.pitch.map(|(freq, _)| freq), Instant::now()) {
+                        running.store(false, Ordering::SeqCst);
+                    }
+                    continue;
+                }
+                let captured_at = audio_consumer.captured_at();
+                if let Some(calibrator) = channel.calibrator.as_mut()
+                    && let Some(tonic) = calibrator.observe(outcome.pitch.map(|(freq, _)| freq))
+                    && let Some(relative) = &relative
+                {
+                    let note = nearest_note(tonic, args.a4);
+                    info!("Tonic{}: {:.1} Hz ({} {:+.0}c).", channel_label(args.dual_channel, index), tonic, note.name(), note.cents);
+                    channel.engine.set_mapping(relative.at_tonic(tonic));
+                    if index == 0
+                        && let Some(dashboard) = &dashboard
+                    {
+                        dashboard.publish(DashboardEvent::bands(channel.engine.mapping()));
+                    }
+                    log_mapping_table(channel.engine.mapping(), &args);
+                    channel.engine.check_frequency_range();
+                    channel.calibrator = None;
+                }
+                if let Some((estimator, mapping)) = channel.register.as_mut()
+                    && let Some(register) = estimator.observe(outcome.pitch.map(|(freq, _)| freq))
+                {
+                    let semitones = transposition(mapping, register, args.transpose_step);
+                    let note = nearest_note(register, args.a4);
+                    info!(
+                        "Auto-transpose{}: register {:.1} Hz ({}); moving every band {:+} semitone(s).",
+                        channel_label(args.dual_channel, index),
+                        register,
+                        note.name(),
+                        semitones
+                    );
+                    channel.engine.set_mapping(mapping.transposed_semitones(semitones));
+                    log_mapping_table(channel.engine.mapping(), &args);
+                    channel.engine.check_frequency_range();
+                    if index == 0
+                        && let Some(dashboard) = &dashboard
+                    {
+                        dashboard.publish(DashboardEvent::bands(channel.engine.mapping()));
+                    }
+                    channel.register = None;
+                }
 
-            if let Some(pitch) = detector.get_pitch(&audio_window, sample_rate, 0.7, 0.2) {
-                info!(
-                    "Input: Detected pitch = {:.2} Hz (Clarity: {:.2})",
-                    pitch.frequency, pitch.clarity
-                );
-                new_key_to_press = map_frequency_to_key(pitch.frequency);
+                if let Some((frequency, clarity)) = outcome.pitch {
+                    channel.histogram.record(frequency);
+                    if let Some(event_log) = &event_log {
+                        event_log.pitch(index, frequency, clarity);
+                    }
+                    if index == 0
+                        && let Some(dashboard) = &dashboard
+                    {
+                        dashboard.publish(DashboardEvent::Pitch { freq: frequency, clarity, key: outcome.key.map(key_name) });
+                    }
+                    for osc in &osc {
+                        osc.send_pitch(frequency, clarity, args.dual_channel.then_some(index));
+                    }
+                    if let Some(broadcaster) = &broadcaster {
+                        broadcaster.publish(PitchEvent {
+                            freq: frequency,
+                            clarity,
+                            key: outcome.key.map(key_name),
+                            channel: args.dual_channel.then_some(index),
+                        });
+                    }
+                }
+                if index == 0 && band_meter {
+                    draw_band_meter(channel.engine.mapping(), outcome.pitch.map(|(freq, _)| freq));
+                }
+                if index == 0
+                    && let Some(tui) = &tui
+                {
+                    let rms = (window_power(&audio_window) / window_size as f32).sqrt();
+                    let peak = audio_window.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+                    tui.send(UiEvent::Window { pitch: outcome.pitch, rms, peak });
+                    tui.send_audio(&audio_window, sample_rate);
+                }
+
+                if let (Some(value), Some(axis)) = (outcome.axis, &axis) {
+                    output.send(KeyCommand::Axis(axis.axis, value), None);
+                }
+                send_curve_moves(&mut output, &outcome.curves);
+                for event in outcome.events {
+                    if let Some(command) = KeyCommand::for_event(args.mode, event) {
+                        output.send(command, captured_at);
+                    }
+                    if let KeyEvent::Press(key) | KeyEvent::Down(key) = event {
+                        cue_press(args.cue, key, outcome.pitch.map(|(_, clarity)| clarity));
+                    }
+                    if let Some(event_log) = &event_log {
+                        event_log.key(index, event);
+                    }
+                    if let Some(recorder) = &recorder {
+                        recorder.key(index, event);
+                    }
+                    if let Some(tui) = &tui {
+                        tui.send(UiEvent::Key(event));
+                    }
+                    if index == 0
+                        && let Some(dashboard) = &dashboard
+                    {
+                        dashboard.publish(DashboardEvent::key(event));
+                    }
+                }
+                if let Some(text) = outcome.text {
+                    if let Some(event_log) = &event_log {
+                        event_log.text(index, &text);
+                    }
+                    if let Some(recorder) = &recorder {
+                        recorder.text(index, &text);
+                    }
+                    output.send(KeyCommand::Type(text), captured_at);
+                }
+            }
+        }
+
+        if let Some(watchdog) = idle.as_mut()
+            && watchdog.observe(heard, Instant::now())
+        {
+            log_idle(watchdog, "pitch");
+            for channel in &mut channels {
+                release_everything(&mut channel.engine, &mut output, "idle");
+            }
+        }
+
+        // Wake as soon as the next window is queued
+        input.wait_for_window(IDLE_POLL_INTERVAL);
+    }
+
+    for channel in &mut channels {
+        for key in channel.engine.release_latched() {
+            info!("Action: Releasing latched key '{:?}' on shutdown.", key);
+            output.send(KeyCommand::Up(key), None);
+        }
+    }
+
+    if band_meter {
+        logging::set_status_line(false);
+        eprintln!();
+    }
+
+    // Finish queued key presses and let go of anything still held
+    let latency = output.finish();
+
+    // Hand the terminal back before the summary so it stays on screen
+    drop(tui);
+
+    info!("---");
+    info!("Shutting down. Session summary:");
+    info!("  Audio dropped to cap latency: ~{:.0}ms", discarded_ms);
+    log_latency(latency);
+
+    if let Some(practice) = &practice {
+        log_practice_score(&args, practice);
+    }
+
+    for (index, channel) in channels.iter().enumerate() {
+        if args.dual_channel {
+            println!("Channel {}:", index);
+        }
+        print!("{}", channel.engine.stats().report());
+    }
+    let mut report = String::new();
+    for (index, channel) in channels.iter().enumerate() {
+        if args.dual_channel {
+            report.push_str(&format!("Channel {}:\n", index));
+        }
+        report.push_str(&channel.histogram.report(channel.engine.mapping()));
+    }
+    print!("{}", report);
+    if let Some(path) = &args.histogram_file {
+        match std::fs::write(path, &report) {
+            Ok(()) => info!("Histogram written to {}", path.display()),
+            Err(e) => warn!("Could not write histogram to {}: {}", path.display(), e),
+        }
+    }
+    Ok(())
+}
+
+// What the outputs need; `mappings` give the notes a `midi` output plays
+fn output_settings(args: &Args, buttons: ButtonMap, mappings: &[&Mapping], ws: Option<PitchBroadcaster>) -> OutputSettings {
+    let mut midi_notes = HashMap::new();
+    for band in mappings.iter().flat_map(|mapping| mapping.bands()) {
+        let note = frequency_to_midi(band.center_hz(), args.a4).round().clamp(0.0, 127.0) as u8;
+        for key in band.key().into_iter().chain(band.tap_key()) {
+            midi_notes.entry(key).or_insert(note);
+        }
+    }
+    OutputSettings {
+        kinds: args.output.clone(),
+        layout: args.layout,
+        buttons,
+        remote: args.remote.clone(),
+        midi_notes,
+        ws,
+        max_hold: (args.max_hold_ms > 0).then(|| Duration::from_millis(args.max_hold_ms)),
+        max_keys: (args.max_simultaneous_keys > 0).then_some(args.max_simultaneous_keys),
+    }
+}
+
+/// Plays `engine`'s mapping from a MIDI keyboard: the sounding note acts as a detected pitch with
+/// clarity 1.0, and releasing it as pitch loss. No audio is captured.
+fn run_midi(
+    args: &Args,
+    port: Option<&str>,
+    mut engine: PitchToKeyEngine,
+    buttons: ButtonMap,
+    running: &Arc<AtomicBool>,
+    tui: Option<Tui>,
+) -> Result<()> {
+    let notes = MidiNotes::open(port)?;
+    let mut script_checked_at = Instant::now();
+
+    let running_handler = Arc::clone(running);
+    ctrlc::set_handler(move || running_handler.store(false, Ordering::SeqCst))
+        .map_err(|e| setup_error("install the Ctrl-C handler", e))?;
+    let mut pause_hotkey = HotkeyWatcher::new(args.pause_hotkey.clone());
+    let mut panic_hotkey = HotkeyWatcher::new(args.panic_hotkey.clone());
+    let mut paused = false;
+    let mut enable_key = enable_key_watcher(args);
+    let mut enabled = enable_key.is_none();
+    let broadcaster = args
+        .ws_port
+        .map(PitchBroadcaster::start)
+        .transpose()
+        .map_err(|e| setup_error("open the WebSocket server", e))?;
+    let settings = output_settings(args, buttons, &[engine.mapping()], broadcaster);
+    let mut output = KeyOutput::start(Duration::from_millis(args.latency_budget_ms), settings);
+    let event_log = open_event_log(args)?;
+    info!("Play notes on '{}' to press keys.", notes.port_name);
+
+    let mut held = HeldNotes::default();
+    let mut idle = idle_watchdog(args);
+    while running.load(Ordering::SeqCst) {
+        if pause_hotkey.as_mut().is_some_and(HotkeyWatcher::pressed) {
+            paused = !paused;
+            info!("{}", if paused { "Paused: no keys will be pressed." } else { "Resumed: key presses are back on." });
+            for key in engine.release_latched() {
+                output.send(KeyCommand::Up(key), None);
+            }
+            engine.reset();
+            if paused {
+                send_curve_moves(&mut output, &engine.stick_centers());
+            }
+        }
+        if let Some(watcher) = enable_key.as_mut()
+            && watcher.held() != enabled
+        {
+            enabled = !enabled;
+            log_enable_key(enabled);
+            if !enabled {
+                release_everything(&mut engine, &mut output, "enable key up");
+            }
+        }
+        if panic_hotkey.as_mut().is_some_and(HotkeyWatcher::pressed) {
+            warn!("Panic hotkey: letting go of every key held down.");
+            engine.release_latched();
+            engine.reset();
+            send_curve_moves(&mut output, &engine.stick_centers());
+            output.send(KeyCommand::ReleaseAll, None);
+        }
+
+        if script_checked_at.elapsed() >= SCRIPT_CHECK_INTERVAL {
+            reload_script(&mut engine);
+            script_checked_at = Instant::now();
+        }
+
+        // Re-evaluate on every message, and on a regular tick so held notes repeat
+        let received = notes.recv_timeout(IDLE_POLL_INTERVAL)?;
+        if let Some((message, _)) = received {
+            held.apply(message);
+        }
+        // Counted from the last message rather than the last sounding note, so a note whose
+        // note-off got lost is let go too
+        if let Some(watchdog) = idle.as_mut()
+            && watchdog.observe(received.is_some() || paused || !enabled, Instant::now())
+        {
+            log_idle(watchdog, "MIDI message");
+            held = HeldNotes::default();
+            release_everything(&mut engine, &mut output, "idle");
+        }
+        if paused || !enabled {
+            continue;
+        }
+        let detection = held.sounding().map(|note| (midi_to_frequency(note as f32, args.a4), 1.0));
+        let outcome = engine.process_detection(detection);
+        if let Some(event_log) = &event_log
+            && let Some((frequency, clarity)) = outcome.pitch
+        {
+            event_log.pitch(0, frequency, clarity);
+        }
+        if let Some(tui) = &tui {
+            tui.send(UiEvent::Window { pitch: outcome.pitch, rms: 0.0, peak: 0.0 });
+        }
+        if let (Some(value), Some(axis)) = (outcome.axis, engine.axis()) {
+            output.send(KeyCommand::Axis(axis.axis, value), None);
+        }
+        send_curve_moves(&mut output, &outcome.curves);
+        for event in outcome.events {
+            if let Some(command) = KeyCommand::for_event(args.mode, event) {
+                output.send(command, received.map(|(_, at)| at));
+            }
+            if let KeyEvent::Press(key) | KeyEvent::Down(key) = event {
+                cue_press(args.cue, key, outcome.pitch.map(|(_, clarity)| clarity));
+            }
+            if let Some(event_log) = &event_log {
+                event_log.key(0, event);
+            }
+            if let Some(tui) = &tui {
+                tui.send(UiEvent::Key(event));
+            }
+        }
+        if let Some(text) = outcome.text {
+            if let Some(event_log) = &event_log {
+                event_log.text(0, &text);
+            }
+            output.send(KeyCommand::Type(text), received.map(|(_, at)| at));
+        }
+    }
+
+    for key in engine.release_latched() {
+        output.send(KeyCommand::Up(key), None);
+    }
+    let latency = output.finish();
+    drop(tui);
+    info!("---");
+    info!("Shutting down. Session summary:");
+    log_latency(latency);
+    Ok(())
+}
+
+fn log_latency(summary: Option<LatencySummary>) {
+    match summary {
+        Some(summary) => info!("  Key press latency: {}", summary),
+        None => info!("  Key press latency: no key presses yet"),
+    }
+}
+
+fn open_event_log(args: &Args) -> Result<Option<EventLog>> {
+    let Some(path) = &args.event_log else {
+        return Ok(None);
+    };
+    let event_log = EventLog::open(path).map_err(|e| setup_error(&format!("open the event log {}", path.display()), e))?;
+    info!("Event log: writing to {}.", path.display());
+    Ok(Some(event_log))
+}
+
+/// The `--practice` run, if one was asked for.
+fn start_practice(args: &Args) -> Option<Practice> {
+    let note = args.practice.as_deref()?;
+    let target_hz = midi_to_frequency(parse_note_name(note)? as f32, args.a4);
+    info!(
+        "Practice: sing and hold {} ({:.1} Hz); scoring starts with your first note and lasts {}s. No keys will be pressed.",
+        note, target_hz, args.practice_secs
+    );
+    if args.dual_channel {
+        info!("Practice: only channel 0 is scored.");
+    }
+    Some(Practice::new(target_hz, Duration::from_secs(args.practice_secs)))
+}
+
+fn log_practice_score(args: &Args, practice: &Practice) {
+    let note = args.practice.as_deref().unwrap_or_default();
+    match practice.score() {
+        Some(score) => println!("Practice {} ({:.1} Hz): {}", note, practice.target_hz(), score),
+        None => println!("Practice {}: no pitch was heard.", note),
+    }
+}
+
+fn open_session_recorder(
+    args: &Args,
+    sample_rate: usize,
+    window_size: usize,
+    channels: usize,
+) -> Result<Option<SessionRecorder>> {
+    let Some(path) = &args.record_session else {
+        return Ok(None);
+    };
+    let recorder = SessionRecorder::create(path, sample_rate, window_size, channels)
+        .map_err(|e| setup_error(&format!("create the session file {}", path.display()), e))?;
+    info!("Recording the session to {}; replay it with `pitchu replay {}`.", path.display(), path.display());
+    Ok(Some(recorder))
+}
+
+/// `pitchu init`: writes [`DEFAULT_CONFIG`] to the default config path, creating its directory.
+fn init_config(force: bool) -> Result<()> {
+    let path = default_config_path().ok_or_else(|| {
+        setup_error("find a config directory", io::Error::new(io::ErrorKind::NotFound, "the system has no home directory"))
+    })?;
+    if path.exists() && !force {
+        let exists = io::Error::new(io::ErrorKind::AlreadyExists, "it already exists; pass --force to replace it");
+        return Err(setup_error(&format!("write the config file {}", path.display()), exists));
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| setup_error(&format!("create the config directory {}", dir.display()), e))?;
+    }
+    std::fs::write(&path, DEFAULT_CONFIG)
+        .map_err(|e| setup_error(&format!("write the config file {}", path.display()), e))?;
+    println!("Wrote {}.", path.display());
+    Ok(())
+}
+
+/// Logs `mapping` as a table, one line per band.
+fn log_mapping_table(mapping: &Mapping, args: &Args) {
+    for line in mapping_table(mapping, args.mode, args.a4).lines() {
+        info!("  {}", line);
+    }
+}
+
+/// Logs which config files were read, or that there were none.
+fn log_config_files(args: &Args) {
+    if !args.config.is_empty() {
+        let files: Vec<String> = args.config.iter().map(|path| path.display().to_string()).collect();
+        info!("Config: loaded {}.", files.join(", "));
+        return;
+    }
+    match default_config_path() {
+        Some(path) => info!("Config: no --config and no {}; using the built-in defaults.", path.display()),
+        None => info!("Config: no --config; using the built-in defaults."),
+    }
+}
+
+fn setup_error(action: &str, source: impl Error + Send + Sync + 'static) -> PitchuError {
+    PitchuError::Setup { action: action.to_string(), source: Box::new(source) }
+}
+
+fn engine_config(args: &Args, sample_rate: usize) -> EngineConfig {
+    EngineConfig {
+        window_size: window_size(args),
+        sample_rate,
+        power_threshold: args.power,
+        clarity_threshold: args.clarity,
+        min_hz: args.whistle.then_some(WHISTLE_MIN_HZ),
+        min_press_clarity: args.min_clarity_for_press,
+        low_clarity: args.low_clarity_behavior,
+        gap: args.gap_behavior,
+        adaptive_ratio: args.adaptive_gate.then_some(args.adaptive_ratio),
+        level_gate: (args.gate_attack > 0.0).then(|| {
+            let release = if args.gate_release > 0.0 { args.gate_release } else { args.gate_attack / 2.0 };
+            (args.gate_attack, release)
+        }),
+        normalize: args.normalize,
+        // A practice note needn't be in any band
+        band_limit: !args.no_bandlimit && args.practice.is_none(),
+        detector: args.detector,
+        octave_tolerance_cents: args.octave_correct.then_some(args.octave_tolerance_cents),
+        smoothing: args.smoothing,
+        ema_alpha: args.ema_alpha,
+        median_window: args.median_window,
+        vibrato_tolerance_cents: (args.vibrato_tolerance > 0.0).then_some(args.vibrato_tolerance),
+        mode: args.mode,
+        trigger: args.trigger,
+        timing: KeyTiming {
+            hold_threshold: Duration::from_millis(args.hold_ms),
+            repeat_interval: Duration::from_millis(args.repeat_ms),
+            acceleration: args.repeat_accel.then(|| RepeatAcceleration {
+                floor: Duration::from_millis(args.repeat_floor_ms),
+                ramp: Duration::from_millis(args.repeat_ramp_ms),
+            }),
+            min_note: Duration::from_millis(args.min_note_ms),
+            switch: args.switch_consensus,
+            tap_hold: Duration::from_millis(args.tap_hold_ms),
+            grid: (args.bpm > 0.0).then(|| RepeatGrid::new(args.bpm, args.repeat_division, args.grid_from)),
+        },
+        latch_debounce: args.latch_debounce,
+        text_debounce: args.latch_debounce,
+        voice_hold: Duration::from_millis(args.voice_hold_ms),
+        tuner: args.tuner,
+        diagnostics: args.diagnostics,
+    }
+}
+
+/// Hands key selection to the config file's mapping script, if it names one.
+#[cfg(feature = "scripting")]
+fn attach_script<C: Clock>(engine: &mut PitchToKeyEngine<C>, script: Option<&Path>, a4_hz: f32) -> Result<()> {
+    if let Some(path) = script {
+        engine.set_script(ScriptMapper::load(path, a4_hz)?);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "scripting"))]
+fn attach_script<C: Clock>(_engine: &mut PitchToKeyEngine<C>, script: Option<&Path>, _a4_hz: f32) -> Result<()> {
+    match script {
+        Some(path) => Err(PitchuError::Script(format!(
+            "{} can't be used: this build has no scripting support (feature `scripting`)",
+            path.display()
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(feature = "scripting")]
+fn reload_script<C: Clock>(engine: &mut PitchToKeyEngine<C>) {
+    engine.reload_script_if_changed();
+}
+
+#[cfg(not(feature = "scripting"))]
+fn reload_script<C: Clock>(_engine: &mut PitchToKeyEngine<C>) {}
+
+/// Warns about every key or text band partly covered by an ignore band.
+fn warn_ignore_overlaps(mapping: &Mapping, label: &str) {
+    let bands = mapping.bands();
+    for (i, j) in mapping.overlapping_bands() {
+        let (ignore, other) = match (bands[i].is_ignore(), bands[j].is_ignore()) {
+            (true, false) => (&bands[i], &bands[j]),
+            (false, true) => (&bands[j], &bands[i]),
+            _ => continue,
+        };
+        warn!(
+            "Ignore band {:.1}-{:.1} Hz overlaps the band for '{}'{} ({:.1}-{:.1} Hz); the overlap is ignored.",
+            ignore.low_hz, ignore.high_hz, other.label(), label, other.low_hz, other.high_hz
+        );
+    }
+}
+
+/// Rings the bell or prints a clarity-colored line for an initial press, per `--cue`.
+fn cue_press(cue: Cue, key: Key, clarity: Option<f32>) {
+    let mut stderr = std::io::stderr().lock();
+    match cue {
+        Cue::None => return,
+        Cue::Bell => {
+            let _ = write!(stderr, "\x07");
+        }
+        Cue::Color => {
+            let clarity_text = clarity.map_or(String::new(), |clarity| format!(" (clarity {:.2})", clarity));
+            if stderr.is_terminal() {
+                // Green when confident, yellow when borderline, red when barely clear enough
+                let color = match clarity {
+                    Some(clarity) if clarity >= CUE_CONFIDENT_CLARITY => "32",
+                    Some(clarity) if clarity >= CUE_BORDERLINE_CLARITY => "33",
+                    Some(_) => "31",
+                    None => "36",
+                };
+                // Clears a band meter line first; the meter redraws on the next window
+                let _ = writeln!(stderr, "\r\x1b[K\x1b[1;{}m>> {}{}\x1b[0m", color, key_name(key), clarity_text);
             } else {
-                // If no clear pitch is detected, you can log it (debug level)
-                debug!("Input: No clear pitch detected in this audio segment.");
-            }
-
-            // --- Logic for handling key presses (single or continuous) ---
-            match (new_key_to_press, current_active_key) {
-                // Case 1: Same note/key is still being held
-                (Some(new_key), Some(active_key)) if new_key == active_key => {
-                    if let Some(start_time) = current_key_start_time {
-                        // Check if the hold threshold has been met
-                        if start_time.elapsed() >= Duration::from_millis(HOLD_THRESHOLD_MILLIS) {
-                            // If it has, check if enough time has passed since the last continuous send
-                            if let Some(last_send_time) = last_continuous_send_time {
-                                if last_send_time.elapsed() >= Duration::from_millis(REPEAT_INTERVAL_MILLIS) {
-                                    info!("Action: Repeating key '{:?}' (held).", active_key);
-                                    enigo.key_click(active_key);
-                                    last_continuous_send_time = Some(Instant::now());
-                                }
-                            } else {
-                                // This path should be hit if last_continuous_send_time got reset somehow,
-                                // but we're past the hold threshold. Send an initial repeat.
-                                info!("Action: Repeating key '{:?}' (first repeat after hold threshold).", active_key);
-                                enigo.key_click(active_key);
-                                last_continuous_send_time = Some(Instant::now());
-                            }
-                        } else {
-                            debug!(
-                                "Info: Key '{:?}' held, but still within hold threshold ({}ms remaining).",
-                                active_key,
-                                (Duration::from_millis(HOLD_THRESHOLD_MILLIS) - start_time.elapsed()).as_millis()
-                            );
-                        }
-                    }
-                },
-                // Case 2: A new key is detected (either different from active, or no active key was present)
-                (Some(new_key), _) => {
-                    info!("Action: New key '{:?}' detected. Sending initial press!", new_key);
-                    enigo.key_click(new_key);
-                    current_active_key = Some(new_key);
-                    current_key_start_time = Some(Instant::now());
-                    last_continuous_send_time = Some(Instant::now()); // Record time of this first press
-                },
-                // Case 3: No valid pitch detected, but a key was previously active (note released/lost)
-                (None, Some(active_key)) => {
-                    info!("Info: Pitch lost. Releasing key '{:?}' state.", active_key);
-                    current_active_key = None;
-                    current_key_start_time = None;
-                    last_continuous_send_time = None;
-                },
-                // Case 4: No valid pitch detected, and no key was active. Do nothing.
-                (None, None) => {
-                    // This can be very verbose, only uncomment for specific debugging:
-                    // debug!("Input: No clear pitch and no active key.");
-                }
-            }
-        }
-        // IMPORTANT: Explicitly drop the mutex lock before sleeping.
-        drop(audio_queue);
-
-        thread::sleep(Duration::from_millis(50)); // Main loop polling rate
-    }
-}
-
-// Your existing map_frequency_to_key function
-fn map_frequency_to_key(freq: f32) -> Option<Key> {
-    match freq {
-        100.0..=115.0 => Some(Key::DownArrow),
-        115.1..=130.0 => Some(Key::LeftArrow),
-        130.1..=145.0 => Some(Key::RightArrow),
-        145.1..=160.0 => Some(Key::UpArrow),
-        160.1..=175.0 => Some(Key::Backspace),
-        175.1..=200.0 => Some(Key::Layout('x')),
-        200.1..=230.0 => Some(Key::Layout('z')),
-        230.1..=270.0 => Some(Key::Layout('a')),
-        270.1..=305.0 => Some(Key::Layout('s')),
-        305.1..=338.0 => Some(Key::Return),
-        _ => None,
+                let _ = writeln!(stderr, ">> {}{}", key_name(key), clarity_text);
+            }
+        }
+    }
+    let _ = stderr.flush();
+}
+
+/// Redraws the `--meter` band bar in place on stderr.
+fn draw_band_meter(mapping: &Mapping, freq: Option<f32>) {
+    let mut stderr = std::io::stderr().lock();
+    let _ = write!(stderr, "\r{}\x1b[K", band_meter::render(mapping, freq, BAND_METER_WIDTH));
+    let _ = stderr.flush();
+}
+
+fn idle_watchdog(args: &Args) -> Option<IdleWatchdog> {
+    args.idle_timeout.map(|secs| IdleWatchdog::new(Duration::from_secs(secs), Instant::now()))
+}
+
+fn log_idle(watchdog: &IdleWatchdog, what: &str) {
+    info!("Idle: no {} for {}s; releasing every key.", what, watchdog.timeout().as_secs());
+}
+
+/// The `--enable-key` watcher; `None` (always pressing) when there is none or the keyboard
+/// can't be watched.
+fn enable_key_watcher(args: &Args) -> Option<HotkeyWatcher> {
+    let watcher = HotkeyWatcher::new(args.enable_key.clone());
+    match &watcher {
+        Some(_) => info!("Enable key: keys are only pressed while {} is held down.", args.enable_key),
+        None if args.enable_key.is_disabled() => {}
+        None => warn!("Enable key: the keyboard can't be watched here, so {} won't work; keys are always pressed.", args.enable_key),
+    }
+    watcher
+}
+
+/// Lists the `[[curves]]`, warning about the ones nothing will carry out.
+fn log_curves(args: &Args, curves: &[Curve]) {
+    if curves.is_empty() {
+        return;
+    }
+    if args.remote.is_some() {
+        warn!("[[curves]] aren't sent with --remote; only key presses are.");
+        return;
+    }
+    for curve in curves {
+        let (needs, unit) = match curve.output.stick() {
+            Some(_) => (OutputKind::Gamepad, ""),
+            None => (OutputKind::Keyboard, " px/s"),
+        };
+        if !args.output.contains(&needs) {
+            warn!("[[curves]] {:?} needs --output {}; it won't move.", curve.output, needs);
+            continue;
+        }
+        info!(
+            "Curve: {:?} follows {:.1}-{:.1} Hz ({:?}), from {}{} to {}{}{}.",
+            curve.output,
+            curve.freq_min,
+            curve.freq_max,
+            curve.shape,
+            curve.out_min,
+            unit,
+            curve.out_max,
+            unit,
+            if args.dual_channel { " on channel 0" } else { "" }
+        );
+    }
+}
+
+fn log_enable_key(enabled: bool) {
+    if enabled {
+        info!("Enable key down: key presses are on.");
+    } else {
+        info!("Enable key up: listening continues, but no keys will be pressed.");
+    }
+}
+
+/// Moves the mouse and sticks: the way the `[[curves]]` say, or back to center.
+fn send_curve_moves(output: &mut KeyOutput, moves: &[CurveMove]) {
+    for &curve_move in moves {
+        output.send(
+            match curve_move {
+                CurveMove::Mouse { dx, dy } => KeyCommand::MouseMove(dx, dy),
+                CurveMove::Stick(axis, value) => KeyCommand::Axis(axis, value),
+            },
+            None,
+        );
+    }
+}
+
+/// Lets go of everything, e.g. after `--idle-timeout`: latched keys, the engine's active key,
+/// the sticks and anything else the output still holds down. `why` ends the log line of each
+/// latched key.
+fn release_everything<C: Clock>(engine: &mut PitchToKeyEngine<C>, output: &mut KeyOutput, why: &str) {
+    for key in engine.release_latched() {
+        info!("Action: Releasing latched key '{:?}' ({}).", key, why);
+        output.send(KeyCommand::Up(key), None);
+    }
+    engine.reset();
+    send_curve_moves(output, &engine.stick_centers());
+    output.send(KeyCommand::ReleaseAll, None);
+}
+
+/// Samples per analysis window: shorter for `--whistle`.
+fn window_size(args: &Args) -> usize {
+    if args.whistle { WHISTLE_WINDOW_SIZE } else { BUFFER_SIZE }
+}
+
+/// Warns about bands that `--decimate`'s anti-aliasing filter cuts into.
+fn warn_above_passband(mappings: &[Mapping], sample_rate: usize) {
+    let passband_hz = PASSBAND * sample_rate as f32;
+    for band in mappings.iter().flat_map(Mapping::bands).filter(|band| band.high_hz > passband_hz) {
+        warn!(
+            "Band {:.1}-{:.1} Hz ('{}') reaches above {:.0} Hz, where --decimate filters the input out; lower the factor.",
+            band.low_hz, band.high_hz, band.label(), passband_hz
+        );
     }
 }
+
+fn log_patterns(patterns: &[Pattern]) {
+    for pattern in patterns {
+        let steps: Vec<String> = pattern.steps.iter().map(|&key| key_name(key)).collect();
+        info!(
+            "Pattern '{}': {} within {}ms{}.",
+            pattern.name,
+            steps.join(" > "),
+            pattern.within.as_millis(),
+            if pattern.suppress { ", holding its steps' keys back until it completes or fails" } else { "" }
+        );
+    }
+}
+
+/// Logs where each key sits once `--whistle` has moved the bands up.
+fn log_whistle_ranges(args: &Args, mappings: &[Mapping], relative: bool) {
+    info!(
+        "Whistle: bands moved up {} octave(s); pitches below {:.0} Hz are ignored.",
+        args.whistle_octaves, WHISTLE_MIN_HZ
+    );
+    if relative {
+        info!("Whistle: the [relative] steps follow the whistled tonic.");
+        return;
+    }
+    for (mapping, index) in mappings.iter().zip(0..) {
+        for band in mapping.bands() {
+            info!(
+                "Whistle{}: '{}' at {:.1}-{:.1} Hz.",
+                channel_label(args.dual_channel, index),
+                band.label(),
+                band.low_hz,
+                band.high_hz
+            );
+        }
+    }
+}
+
+/// " (channel N)" in dual-channel mode, nothing otherwise.
+fn channel_label(dual_channel: bool, index: usize) -> String {
+    if dual_channel { format!(" (channel {})", index) } else { String::new() }
+}
+
+fn register_estimator(input: &AudioInput, window_size: usize) -> RegisterEstimator {
+    let windows = input.sample_rate as f32 * REGISTER_LISTEN.as_secs_f32() / window_size as f32;
+    RegisterEstimator::new(windows.ceil() as usize)
+}
+
+fn tonic_calibrator(input: &AudioInput, window_size: usize) -> TonicCalibrator {
+    let windows = input.sample_rate as f32 * TONIC_HOLD.as_secs_f32() / window_size as f32;
+    TonicCalibrator::new(windows.ceil() as usize)
+}
+
+/// `pitchu tune`: the audio input and detection of a normal run, with no bands behind them.
+fn run_tune(args: &Args) -> Result<()> {
+    if args.input != InputSource::Audio {
+        warn!("--input has no effect with tune, which listens to audio.");
+    }
+    let host = select_host(args.host.as_deref())?;
+    let (device, loopback) = if args.loopback {
+        select_loopback_device(&host, args.device.as_deref())?
+    } else {
+        (select_input_device(&host, args.device.as_deref())?, false)
+    };
+    info!("Found {} device: {}", if args.loopback { "loopback" } else { "input" }, device_name(&device)?);
+    let window_size = window_size(args);
+    let capture = CaptureSettings { pipelines: 1, max_latency_ms: args.max_latency_ms, window_size, decimate: args.decimate };
+    let mut input = AudioInput::open(&host, &device, loopback, &capture)?;
+    let config = EngineConfig { tuner: false, diagnostics: false, ..engine_config(args, input.sample_rate) };
+    let mut engine = PitchToKeyEngine::new(config, Mapping::new(Vec::new()), SystemClock);
+
+    let running = Arc::new(AtomicBool::new(true));
+    let running_handler = Arc::clone(&running);
+    ctrlc::set_handler(move || running_handler.store(false, Ordering::SeqCst))
+        .map_err(|e| setup_error("install the Ctrl-C handler", e))?;
+    let warmup_samples = warmup_samples_for(&input, args.warmup_ms);
+    tune::run(&mut input, &mut engine, window_size, warmup_samples, args.a4, &running);
+    Ok(())
+}
+
+/// Starts `channel` over after a break in listening (a reconnect, a pause, the enable key
+/// coming back down): a reset engine and a fresh warm-up, so nothing from before the break
+/// decides the first key after it.
+fn restart_channel(channel: &mut Channel, input: &AudioInput, warmup_ms: u64) {
+    channel.engine.reset();
+    channel.warmup_samples = warmup_samples_for(input, warmup_ms);
+}
+
+/// Samples to discard after `input` starts, covering `warmup_ms`.
+fn warmup_samples_for(input: &AudioInput, warmup_ms: u64) -> usize {
+    (input.samples_per_ms() * warmup_ms as f32) as usize
+}
@@ -0,0 +1,53 @@
+use crate::mapping::{BandAction, Mapping};
+use enigo::Key;
+
+/// What a [`FrequencyMapper`] can choose for a detection: the same actions a band has.
+pub type Action = BandAction;
+
+/// Everything a [`FrequencyMapper`] gets to decide on one window.
+#[derive(Debug, Clone, Copy)]
+pub struct MapContext<'a> {
+    /// The detected frequency, after octave correction and smoothing.
+    pub frequency: f32,
+    pub clarity: f32,
+    /// The frequencies of the windows since the pitch was last lost, oldest first and ending
+    /// with `frequency`; at most [`RECENT_WINDOWS`] of them.
+    pub recent: &'a [f32],
+    /// The key currently held (repeat mode), if any.
+    pub active_key: Option<Key>,
+}
+
+/// How many recent frequencies a [`MapContext`] carries.
+pub const RECENT_WINDOWS: usize = 32;
+
+/// Mapping logic of your own, e.g. one that follows the pitch's trajectory rather than where
+/// it is right now. Give it to the engine with
+/// [`PitchToKeyEngine::set_mapper`](crate::engine::PitchToKeyEngine::set_mapper).
+///
+/// Returning [`Action::Ignore`] treats the window as no pitch; `None` maps it to no key, like
+/// a pitch outside every band.
+pub trait FrequencyMapper: Send {
+    fn map(&self, ctx: &MapContext) -> Option<Action>;
+}
+
+/// The band table: the action of the band containing the frequency.
+impl FrequencyMapper for Mapping {
+    fn map(&self, ctx: &MapContext) -> Option<Action> {
+        self.band_for(ctx.frequency).map(|band| band.action.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::Band;
+
+    #[test]
+    fn the_band_table_maps_by_the_current_frequency() {
+        let mapping = Mapping::new(vec![Band::new(100.0, 150.0, Key::Layout('a')), Band::ignore(120.0, 130.0)]);
+        let at = |frequency| MapContext { frequency, clarity: 0.9, recent: &[], active_key: None };
+        assert_eq!(mapping.map(&at(110.0)), Some(Action::Key(Key::Layout('a'))));
+        assert_eq!(mapping.map(&at(125.0)), Some(Action::Ignore));
+        assert_eq!(mapping.map(&at(200.0)), None);
+    }
+}
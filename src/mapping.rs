@@ -0,0 +1,247 @@
+use crate::key_state::BandTiming;
+use crate::keys::key_name;
+use crate::notes::transpose;
+use crate::presets::{Preset, VocalRange};
+use enigo::Key;
+
+/// What singing into a band does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BandAction {
+    /// Press (and repeat, or latch) a key.
+    Key(Key),
+    /// Type a string once per note, e.g. a chat macro. Never repeats.
+    Text(String),
+    /// A short note presses `tap` once when it ends; a note held past the tap/hold threshold
+    /// presses and repeats `hold` like a key band.
+    TapHold { tap: Key, hold: Key },
+    /// A dead zone for a steady noise such as a fan or hum: pitches here count as no pitch.
+    Ignore,
+}
+
+/// A frequency range (inclusive at both ends) and what it does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Band {
+    pub low_hz: f32,
+    pub high_hz: f32,
+    pub action: BandAction,
+    /// What `[[patterns]]` steps may call the band, instead of its key's name.
+    pub name: Option<String>,
+    /// Clarity a detection in this band needs, in place of both `--clarity` and
+    /// `--min-clarity-for-press`.
+    pub min_clarity: Option<f32>,
+    /// Window power a detection in this band needs, in place of the power gate's threshold.
+    pub min_power: Option<f32>,
+    /// Hold and repeat timing of this band's key, where it differs from the global timing.
+    pub timing: BandTiming,
+}
+
+impl Band {
+    pub const fn new(low_hz: f32, high_hz: f32, key: Key) -> Self {
+        Band::with_action(low_hz, high_hz, BandAction::Key(key))
+    }
+
+    pub fn text(low_hz: f32, high_hz: f32, text: impl Into<String>) -> Self {
+        Band::with_action(low_hz, high_hz, BandAction::Text(text.into()))
+    }
+
+    pub const fn ignore(low_hz: f32, high_hz: f32) -> Self {
+        Band::with_action(low_hz, high_hz, BandAction::Ignore)
+    }
+
+    const fn with_action(low_hz: f32, high_hz: f32, action: BandAction) -> Self {
+        Band { low_hz, high_hz, action, name: None, min_clarity: None, min_power: None, timing: BandTiming::GLOBAL }
+    }
+
+    /// The key this band presses (for a tap/hold band, its hold key); `None` for a text or
+    /// ignore band.
+    pub fn key(&self) -> Option<Key> {
+        match self.action {
+            BandAction::Key(key) | BandAction::TapHold { hold: key, .. } => Some(key),
+            BandAction::Text(_) | BandAction::Ignore => None,
+        }
+    }
+
+    /// The key a short note presses in a tap/hold band.
+    pub fn tap_key(&self) -> Option<Key> {
+        match self.action {
+            BandAction::TapHold { tap, .. } => Some(tap),
+            _ => None,
+        }
+    }
+
+    pub fn is_ignore(&self) -> bool {
+        self.action == BandAction::Ignore
+    }
+
+    /// How the band is shown in logs and reports: the key name, the text in quotes, or
+    /// `tap/hold` for a tap/hold band.
+    pub fn label(&self) -> String {
+        match &self.action {
+            BandAction::Key(key) => key_name(*key),
+            BandAction::Text(text) => format!("{:?}", text),
+            BandAction::TapHold { tap, hold } => format!("{}/{}", key_name(*tap), key_name(*hold)),
+            BandAction::Ignore => "ignore".to_string(),
+        }
+    }
+
+    pub fn contains(&self, freq: f32) -> bool {
+        (self.low_hz..=self.high_hz).contains(&freq)
+    }
+
+    /// The band moved by whole `octaves` (down for negative values), keeping its action.
+    pub fn transposed(&self, octaves: i32) -> Band {
+        let factor = 2f32.powi(octaves);
+        Band { low_hz: self.low_hz * factor, high_hz: self.high_hz * factor, ..self.clone() }
+    }
+
+    /// The band moved by `semitones` (down for negative values), keeping its action.
+    pub fn transposed_semitones(&self, semitones: i32) -> Band {
+        Band { low_hz: transpose(self.low_hz, semitones as f32), high_hz: transpose(self.high_hz, semitones as f32), ..self.clone() }
+    }
+
+    /// Center of the band on a musical (logarithmic) scale.
+    pub fn center_hz(&self) -> f32 {
+        (self.low_hz * self.high_hz).sqrt()
+    }
+}
+
+/// The pitch-to-key table.
+///
+/// Bands may overlap: they are tried in the order they were declared and the first one
+/// containing the frequency wins, so declaration order is priority. A narrow band listed
+/// before a wider one carves its range out of the wider band, which then acts as the fallback
+/// around it. A table of disjoint bands is the special case where order doesn't matter.
+///
+/// Ignore bands are the exception: they take precedence over every other band wherever they
+/// overlap, whatever the order.
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    bands: Vec<Band>,
+}
+
+impl Mapping {
+    pub fn new(bands: Vec<Band>) -> Self {
+        Mapping { bands }
+    }
+
+    pub fn bands(&self) -> &[Band] {
+        &self.bands
+    }
+
+    /// The ignore band containing `freq`, else the first declared band containing it.
+    pub fn band_for(&self, freq: f32) -> Option<&Band> {
+        self.band_index_for(freq).map(|index| &self.bands[index])
+    }
+
+    /// The index in [`bands`](Self::bands) of [`band_for`](Self::band_for)'s band.
+    pub fn band_index_for(&self, freq: f32) -> Option<usize> {
+        let containing = |band: &Band| band.contains(freq);
+        self.bands
+            .iter()
+            .position(|band| band.is_ignore() && containing(band))
+            .or_else(|| self.bands.iter().position(containing))
+    }
+
+    /// True if `freq` is between two bands rather than in one or beyond all of them.
+    pub fn in_gap(&self, freq: f32) -> bool {
+        self.band_for(freq).is_none() && self.range_hz().is_some_and(|(low, high)| (low..=high).contains(&freq))
+    }
+
+    /// True if `freq` falls in an ignore band.
+    pub fn ignores(&self, freq: f32) -> bool {
+        self.band_for(freq).is_some_and(Band::is_ignore)
+    }
+
+    /// Pairs of bands (by index, earlier first) whose ranges share some frequencies. Overlap is
+    /// allowed, but in a table meant to be disjoint it is a mistake.
+    pub fn overlapping_bands(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for (i, a) in self.bands.iter().enumerate() {
+            for (j, b) in self.bands.iter().enumerate().skip(i + 1) {
+                if a.low_hz <= b.high_hz && b.low_hz <= a.high_hz {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// The lowest `min_clarity` and `min_power` any band sets, if any does.
+    pub fn loosest_thresholds(&self) -> (Option<f32>, Option<f32>) {
+        let loosest = |threshold: fn(&Band) -> Option<f32>| self.bands.iter().filter_map(threshold).reduce(f32::min);
+        (loosest(|band| band.min_clarity), loosest(|band| band.min_power))
+    }
+
+    /// Every band moved by whole `octaves`, e.g. to play a table laid out for singing by
+    /// whistling. Order and overlaps stay as they were.
+    pub fn transposed(&self, octaves: i32) -> Mapping {
+        Mapping::new(self.bands.iter().map(|band| band.transposed(octaves)).collect())
+    }
+
+    /// Every band moved by `semitones`, for `--transpose`.
+    pub fn transposed_semitones(&self, semitones: i32) -> Mapping {
+        Mapping::new(self.bands.iter().map(|band| band.transposed_semitones(semitones)).collect())
+    }
+
+    /// The lowest and highest band edges; `None` without bands.
+    pub fn range_hz(&self) -> Option<(f32, f32)> {
+        let low = self.bands.iter().map(|band| band.low_hz).reduce(f32::min)?;
+        let high = self.bands.iter().map(|band| band.high_hz).reduce(f32::max)?;
+        Some((low, high))
+    }
+
+    /// Geometric middle of [`range_hz`](Self::range_hz).
+    pub fn center_hz(&self) -> Option<f32> {
+        self.range_hz().map(|(low, high)| (low * high).sqrt())
+    }
+
+    /// The key of the band containing `freq`. `None` outside every band and in a text band.
+    pub fn key_for(&self, freq: f32) -> Option<Key> {
+        self.band_for(freq).and_then(Band::key)
+    }
+}
+
+impl Default for Mapping {
+    /// The [`mgba-default`](Preset::MgbaDefault) preset for a low-to-mid vocal range.
+    fn default() -> Self {
+        Preset::MgbaDefault.mapping(VocalRange::Low)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn earlier_band_wins_where_bands_overlap() {
+        let mapping = Mapping::new(vec![
+            Band::new(215.0, 225.0, Key::Layout('a')),
+            Band::new(200.0, 240.0, Key::Layout('b')),
+        ]);
+        assert_eq!(mapping.key_for(220.0), Some(Key::Layout('a')));
+        assert_eq!(mapping.key_for(205.0), Some(Key::Layout('b')));
+        assert_eq!(mapping.key_for(230.0), Some(Key::Layout('b')));
+        assert_eq!(mapping.key_for(250.0), None);
+        assert_eq!(mapping.overlapping_bands(), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn ignore_bands_win_wherever_they_overlap() {
+        let mapping = Mapping::new(vec![Band::new(170.0, 200.0, Key::Layout('x')), Band::ignore(175.0, 185.0)]);
+        assert!(mapping.ignores(180.0));
+        assert_eq!(mapping.key_for(180.0), None);
+        assert_eq!(mapping.key_for(190.0), Some(Key::Layout('x')));
+        assert!(!mapping.ignores(190.0));
+    }
+
+    #[test]
+    fn transposing_moves_every_band_by_whole_octaves() {
+        let mapping = Mapping::new(vec![Band::new(230.1, 270.0, Key::Layout('a')), Band::ignore(175.0, 185.0)]);
+        let whistled = mapping.transposed(3);
+        assert_eq!(whistled.bands()[0], Band::new(1840.8, 2160.0, Key::Layout('a')));
+        assert_eq!(whistled.bands()[1], Band::ignore(1400.0, 1480.0));
+        assert_eq!(whistled.key_for(2000.0), Some(Key::Layout('a')));
+        assert_eq!(whistled.key_for(250.0), None);
+        assert_eq!(whistled.transposed(-3).bands(), mapping.bands());
+    }
+}
@@ -0,0 +1,110 @@
+//! The mapping in effect as a table, one row per band, printed at startup, whenever the mapping
+//! changes while running, and by `pitchu show-mapping`: which note does what.
+
+use crate::key_state::KeyBehavior;
+use crate::keys::KeyMode;
+use crate::mapping::{Band, BandAction, Mapping};
+use crate::notes::nearest_note;
+
+const HEADER: [&str; 6] = ["Band", "Range", "Notes", "Key", "Mode", "Overrides"];
+
+/// `mapping` as an aligned text table: each band's name, frequency range, the notes nearest
+/// its edges, what it presses, how (under `mode`) and the settings it overrides. Bands are
+/// listed in declaration order, which is also their priority where they overlap.
+pub fn mapping_table(mapping: &Mapping, mode: KeyMode, a4_hz: f32) -> String {
+    if mapping.bands().is_empty() {
+        return "No bands.\n".to_string();
+    }
+    let mut rows = vec![HEADER.map(str::to_string)];
+    rows.extend(mapping.bands().iter().map(|band| row(band, mode, a4_hz)));
+    let mut widths = [0; HEADER.len()];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut table = String::new();
+    for row in &rows {
+        let cells: Vec<String> = row.iter().zip(widths).map(|(cell, width)| format!("{:<width$}", cell)).collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+fn row(band: &Band, mode: KeyMode, a4_hz: f32) -> [String; 6] {
+    let note = |freq: f32| nearest_note(freq, a4_hz).name();
+    [
+        band.name.clone().unwrap_or_else(|| "-".to_string()),
+        format!("{:.1}-{:.1} Hz", band.low_hz, band.high_hz),
+        format!("{}-{}", note(band.low_hz), note(band.high_hz)),
+        if band.is_ignore() { "-".to_string() } else { band.label() },
+        mode_name(band, mode).to_string(),
+        overrides(band),
+    ]
+}
+
+// Band behaviors only apply in repeat mode; latch toggles every key band the same way
+fn mode_name(band: &Band, mode: KeyMode) -> &'static str {
+    match (&band.action, mode) {
+        (BandAction::Ignore, _) => "ignore",
+        (BandAction::Text(_), _) => "text",
+        (_, KeyMode::Latch) => "latch",
+        (BandAction::TapHold { .. }, KeyMode::Repeat) => "tap/hold",
+        (BandAction::Key(_), KeyMode::Repeat) => match band.timing.behavior {
+            KeyBehavior::ClickRepeat => "repeat",
+            KeyBehavior::Hold => "hold",
+            KeyBehavior::OneShot => "one shot",
+        },
+    }
+}
+
+// As they are written in the config file
+fn overrides(band: &Band) -> String {
+    let mut overrides = Vec::new();
+    if let Some(clarity) = band.min_clarity {
+        overrides.push(format!("min_clarity={}", clarity));
+    }
+    if let Some(power) = band.min_power {
+        overrides.push(format!("min_power={}", power));
+    }
+    if let Some(hold) = band.timing.hold_threshold {
+        overrides.push(format!("hold_ms={}", hold.as_millis()));
+    }
+    if let Some(repeat) = band.timing.repeat_interval {
+        overrides.push(format!("repeat_ms={}", repeat.as_millis()));
+    }
+    if let Some(division) = band.timing.repeat_division {
+        overrides.push(format!("repeat_division={}", division));
+    }
+    if overrides.is_empty() { "-".to_string() } else { overrides.join(" ") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_state::BandTiming;
+    use enigo::Key;
+    use std::time::Duration;
+
+    #[test]
+    fn every_band_is_a_row_in_aligned_columns() {
+        let held = Band {
+            name: Some("jump".to_string()),
+            min_clarity: Some(0.5),
+            timing: BandTiming { hold_threshold: Some(Duration::from_millis(300)), behavior: KeyBehavior::Hold, ..BandTiming::GLOBAL },
+            ..Band::new(110.0, 130.0, Key::Space)
+        };
+        let mapping = Mapping::new(vec![held, Band::text(220.0, 247.0, "gg"), Band::ignore(60.0, 62.0)]);
+        let table = mapping_table(&mapping, KeyMode::Repeat, 440.0);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("Band  Range"), "{}", table);
+        assert_eq!(lines[1], "jump  110.0-130.0 Hz  A2-C3  Space  hold    min_clarity=0.5 hold_ms=300");
+        assert_eq!(lines[2], "-     220.0-247.0 Hz  A3-B3  \"gg\"   text    -");
+        assert_eq!(lines[3], "-     60.0-62.0 Hz    B1-B1  -      ignore  -");
+        // Latch toggles the key whatever the band's own behavior
+        assert!(mapping_table(&mapping, KeyMode::Latch, 440.0).lines().nth(1).unwrap().contains(" latch "));
+        assert_eq!(mapping_table(&Mapping::new(Vec::new()), KeyMode::Repeat, 440.0), "No bands.\n");
+    }
+}
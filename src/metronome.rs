@@ -0,0 +1,93 @@
+use crate::audio::{device_name, quirk_of};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use pitchu::error::{PitchuError, Result};
+use log::{error, info, warn};
+
+// Pitch and length of a click, and how loud it starts
+const CLICK_HZ: f32 = 1500.0;
+const CLICK_SECS: f32 = 0.03;
+const CLICK_LEVEL: f32 = 0.3;
+
+/// `--metronome`: clicks every beat of `--bpm` on the default output device, to sing along to
+/// rhythm mode's repeat grid.
+pub struct Metronome {
+    _stream: cpal::Stream,
+}
+
+impl Metronome {
+    pub fn start(host: &cpal::Host, bpm: f32) -> Result<Self> {
+        let device = host
+            .default_output_device()
+            .ok_or_else(|| PitchuError::NoDevice("no output device available for --metronome".to_string()))?;
+        let device_name = device_name(&device)?;
+        let config = device
+            .default_output_config()
+            .map_err(|source| PitchuError::UnsupportedConfig { device: device_name.clone(), source: Box::new(source) })?;
+        let output_rate = config.sample_rate().0 as f32;
+        let click = Click { beat: (output_rate * 60.0 / bpm).round().max(1.0) as u64, rate: output_rate, sample: 0 };
+
+        let stream_config: cpal::StreamConfig = config.clone().into();
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => build_click_stream::<f32>(&device, &stream_config, click),
+            cpal::SampleFormat::I16 => build_click_stream::<i16>(&device, &stream_config, click),
+            cpal::SampleFormat::U16 => build_click_stream::<u16>(&device, &stream_config, click),
+            other => {
+                return Err(PitchuError::UnsupportedConfig {
+                    device: device_name,
+                    source: format!("metronome output sample format {:?}", other).into(),
+                });
+            }
+        }
+        .map_err(|source| PitchuError::StreamBuild { device: device_name.clone(), quirk: quirk_of(host), source })?;
+        stream
+            .play()
+            .map_err(|source| PitchuError::StreamPlay { device: device_name.clone(), quirk: quirk_of(host), source })?;
+
+        info!("Metronome: clicking at {} BPM on '{}'.", bpm, device_name);
+        warn!("Metronome: use headphones! Clicks through speakers reach the microphone.");
+        Ok(Metronome { _stream: stream })
+    }
+}
+
+/// State owned by the output callback: where in the beat the stream is.
+struct Click {
+    // Samples per beat at the output rate
+    beat: u64,
+    rate: f32,
+    sample: u64,
+}
+
+impl Click {
+    fn next_sample(&mut self) -> f32 {
+        let t = (self.sample % self.beat) as f32 / self.rate;
+        self.sample += 1;
+        if t >= CLICK_SECS {
+            return 0.0;
+        }
+        // A short sine burst, dying away over the click
+        let envelope = CLICK_LEVEL * (1.0 - t / CLICK_SECS).powi(2);
+        envelope * (std::f32::consts::TAU * CLICK_HZ * t).sin()
+    }
+}
+
+fn build_click_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut click: Click,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>,
+{
+    let channels = config.channels.max(1) as usize;
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            for frame in data.chunks_mut(channels) {
+                let sample = T::from_sample(click.next_sample());
+                frame.fill(sample);
+            }
+        },
+        move |err| error!("Metronome stream error: {:?}", err),
+        None,
+    )
+}
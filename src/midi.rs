@@ -0,0 +1,159 @@
+use log::info;
+use midir::{Ignore, MidiIO, MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+use pitchu::error::{PitchuError, Result};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+const CLIENT_NAME: &str = "pitchu";
+
+/// A note starting or ending on the MIDI input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteMessage {
+    On(u8),
+    Off(u8),
+}
+
+/// Decodes a note-on or note-off on any channel. A note-on with velocity 0 is a note-off.
+fn parse_message(bytes: &[u8]) -> Option<NoteMessage> {
+    match *bytes {
+        [status, note, velocity] if status & 0xF0 == 0x90 && velocity > 0 => Some(NoteMessage::On(note)),
+        [status, note, _] if matches!(status & 0xF0, 0x80 | 0x90) => Some(NoteMessage::Off(note)),
+        _ => None,
+    }
+}
+
+fn midi_input() -> Result<MidiInput> {
+    MidiInput::new(CLIENT_NAME).map_err(|e| PitchuError::Midi(format!("could not initialize: {}", e)))
+}
+
+/// Names of the MIDI input ports currently available.
+pub fn port_names() -> Result<Vec<String>> {
+    let input = midi_input()?;
+    Ok(input.ports().iter().filter_map(|port| input.port_name(port).ok()).collect())
+}
+
+/// Prints the MIDI input ports, one per line.
+pub fn list_ports() -> Result<()> {
+    let names = port_names()?;
+    if names.is_empty() {
+        println!("(no MIDI input ports)");
+    }
+    for name in names {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+/// Note-on/note-off messages from one MIDI input port. Closing happens on drop.
+pub struct MidiNotes {
+    pub port_name: String,
+    receiver: Receiver<(NoteMessage, Instant)>,
+    _connection: MidiInputConnection<()>,
+}
+
+impl MidiNotes {
+    /// Connects to the first port whose name contains `port` (case-insensitive), or to the
+    /// first port when `None`.
+    pub fn open(port: Option<&str>) -> Result<Self> {
+        let mut input = midi_input()?;
+        input.ignore(Ignore::All);
+        let Some((port, port_name)) = find_port(&input, port) else {
+            return Err(PitchuError::Midi(match port {
+                Some(wanted) => format!("no input port matching '{}'", wanted),
+                None => "no input ports available".to_string(),
+            }));
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let connection = input
+            .connect(
+                &port,
+                "pitchu-input",
+                move |_, bytes, _| {
+                    if let Some(message) = parse_message(bytes) {
+                        let _ = sender.send((message, Instant::now()));
+                    }
+                },
+                (),
+            )
+            .map_err(|e| PitchuError::Midi(format!("could not connect to '{}': {}", port_name, e)))?;
+        info!("MIDI: listening on '{}'.", port_name);
+        Ok(MidiNotes { port_name, receiver, _connection: connection })
+    }
+
+    /// The next message and when it arrived, or `Ok(None)` if none arrives within `timeout`.
+    /// Fails if the port has gone away.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<(NoteMessage, Instant)>> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(message) => Ok(Some(message)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(PitchuError::Midi(format!("'{}' disconnected", self.port_name))),
+        }
+    }
+}
+
+// The first port whose name contains `wanted` (case-insensitive), or the first port
+fn find_port<T: MidiIO>(io: &T, wanted: Option<&str>) -> Option<(T::Port, String)> {
+    let named = |candidate: &T::Port| io.port_name(candidate).ok().map(|name| (candidate.clone(), name));
+    let ports = io.ports();
+    match wanted {
+        Some(wanted) => {
+            ports.iter().filter_map(named).find(|(_, name)| name.to_lowercase().contains(&wanted.to_lowercase()))
+        }
+        None => ports.iter().find_map(named),
+    }
+}
+
+// Velocity of the notes `--output midi` plays
+const OUTPUT_VELOCITY: u8 = 100;
+
+/// Note-on/note-off messages to one MIDI output port, on channel 1, for `--output midi`.
+pub struct MidiOut {
+    pub port_name: String,
+    connection: MidiOutputConnection,
+}
+
+impl MidiOut {
+    /// Connects to the first output port whose name contains `port` (case-insensitive), or to
+    /// the first one when `None`.
+    pub fn open(port: Option<&str>) -> Result<Self> {
+        let output =
+            MidiOutput::new(CLIENT_NAME).map_err(|e| PitchuError::Midi(format!("could not initialize: {}", e)))?;
+        let Some((found, port_name)) = find_port(&output, port) else {
+            return Err(PitchuError::Midi(match port {
+                Some(wanted) => format!("no output port matching '{}'", wanted),
+                None => "no output ports available".to_string(),
+            }));
+        };
+        let connection = output
+            .connect(&found, "pitchu-output")
+            .map_err(|e| PitchuError::Midi(format!("could not connect to '{}': {}", port_name, e)))?;
+        Ok(MidiOut { port_name, connection })
+    }
+
+    /// Starts or ends `note`. Fails once the port has gone away.
+    pub fn note(&mut self, note: u8, on: bool) -> std::result::Result<(), String> {
+        let message = if on { [0x90, note, OUTPUT_VELOCITY] } else { [0x80, note, 0] };
+        self.connection.send(&message).map_err(|e| format!("'{}': {}", self.port_name, e))
+    }
+}
+
+/// The notes currently held down; the most recently pressed one is the one that sounds.
+#[derive(Default)]
+pub struct HeldNotes(Vec<u8>);
+
+impl HeldNotes {
+    pub fn apply(&mut self, message: NoteMessage) {
+        match message {
+            NoteMessage::On(note) => {
+                self.0.retain(|&held| held != note);
+                self.0.push(note);
+            }
+            NoteMessage::Off(note) => self.0.retain(|&held| held != note),
+        }
+    }
+
+    pub fn sounding(&self) -> Option<u8> {
+        self.0.last().copied()
+    }
+}
@@ -0,0 +1,121 @@
+use pitchu::audio_queue::{audio_queue, capacity_for_latency, AudioConsumer, AudioProducer};
+use crate::audio::{device_name, quirk_of};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use pitchu::error::{PitchuError, Result};
+use log::{error, info, warn};
+
+// Playback never lags the analysed audio by more than this
+const MONITOR_MAX_LATENCY_MS: u64 = 100;
+// Granularity in which stale monitor audio is skipped
+const MONITOR_DISCARD_STEP: usize = 256;
+
+/// Plays the analysed audio back through an output device, so you can hear what the detector hears.
+pub struct Monitor {
+    producer: AudioProducer,
+    _stream: cpal::Stream,
+}
+
+impl Monitor {
+    /// Opens `device_name` (or the default output device) for playback of mono audio at `sample_rate`.
+    pub fn start(host: &cpal::Host, name: Option<&str>, sample_rate: usize) -> Result<Self> {
+        let device = match name {
+            Some(name) => host
+                .output_devices()
+                .map_err(|source| PitchuError::DeviceEnumeration { host: host.id().name().to_string(), source })?
+                .find(|d| d.name().is_ok_and(|n| n == name))
+                .ok_or_else(|| PitchuError::NoDevice(format!("no output device named '{}' for --monitor", name)))?,
+            None => host
+                .default_output_device()
+                .ok_or_else(|| PitchuError::NoDevice("no output device available for --monitor".to_string()))?,
+        };
+        let device_name = device_name(&device)?;
+        let config = device
+            .default_output_config()
+            .map_err(|source| PitchuError::UnsupportedConfig { device: device_name.clone(), source: Box::new(source) })?;
+        let output_rate = config.sample_rate().0 as usize;
+
+        let cap = capacity_for_latency(sample_rate, 1, MONITOR_MAX_LATENCY_MS, MONITOR_DISCARD_STEP);
+        let (producer, consumer) = audio_queue(2 * cap);
+        let playback = Playback {
+            consumer,
+            cap,
+            step: sample_rate as f32 / output_rate as f32,
+            position: 0.0,
+            previous: 0.0,
+            next: 0.0,
+        };
+
+        let stream_config: cpal::StreamConfig = config.clone().into();
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => build_playback_stream::<f32>(&device, &stream_config, playback),
+            cpal::SampleFormat::I16 => build_playback_stream::<i16>(&device, &stream_config, playback),
+            cpal::SampleFormat::U16 => build_playback_stream::<u16>(&device, &stream_config, playback),
+            other => {
+                return Err(PitchuError::UnsupportedConfig {
+                    device: device_name,
+                    source: format!("monitor output sample format {:?}", other).into(),
+                });
+            }
+        }
+        .map_err(|source| PitchuError::StreamBuild { device: device_name.clone(), quirk: quirk_of(host), source })?;
+        stream
+            .play()
+            .map_err(|source| PitchuError::StreamPlay { device: device_name.clone(), quirk: quirk_of(host), source })?;
+
+        info!("Monitor: playing analysed audio on '{}' ({} Hz).", device_name, output_rate);
+        warn!("Monitor: use headphones! Playing through speakers feeds back into the microphone.");
+        Ok(Monitor { producer, _stream: stream })
+    }
+
+    /// Queues one analysis window for playback.
+    pub fn push(&mut self, window: &[f32]) {
+        self.producer.push(window);
+    }
+}
+
+/// State owned by the output callback: a linear resampler from the capture rate to the output rate.
+struct Playback {
+    consumer: AudioConsumer,
+    cap: usize,
+    step: f32,
+    position: f32,
+    previous: f32,
+    next: f32,
+}
+
+impl Playback {
+    fn next_sample(&mut self) -> f32 {
+        while self.position >= 1.0 {
+            self.previous = self.next;
+            // Underruns simply hold the last sample, which is inaudible at these levels
+            self.next = self.consumer.pop_sample().unwrap_or(self.next);
+            self.position -= 1.0;
+        }
+        let sample = self.previous + (self.next - self.previous) * self.position;
+        self.position += self.step;
+        sample
+    }
+}
+
+fn build_playback_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut playback: Playback,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>,
+{
+    let channels = config.channels.max(1) as usize;
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            playback.consumer.discard_backlog(playback.cap, MONITOR_DISCARD_STEP);
+            for frame in data.chunks_mut(channels) {
+                let sample = T::from_sample(playback.next_sample());
+                frame.fill(sample);
+            }
+        },
+        move |err| error!("Monitor stream error: {:?}", err),
+        None,
+    )
+}
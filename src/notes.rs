@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// Standard concert pitch for A4.
+pub const DEFAULT_A4_HZ: f32 = 440.0;
+/// A4 references accepted by `--a4`: from below baroque pitch (415 Hz) to a semitone above
+/// concert pitch.
+pub const A4_RANGE_HZ: RangeInclusive<f32> = 400.0..=466.0;
+
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+const A4_MIDI: i32 = 69;
+
+/// The nearest equal-tempered note to a frequency.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NearestNote {
+    pub midi: i32,
+    /// Signed distance from the note's exact frequency, in cents (-50 to +50).
+    pub cents: f32,
+}
+
+impl NearestNote {
+    /// For example `"A4"` or `"C#3"`.
+    pub fn name(&self) -> String {
+        note_name(self.midi)
+    }
+}
+
+/// Signed distance from `reference` to `freq` in cents.
+pub fn cents(freq: f32, reference: f32) -> f32 {
+    1200.0 * (freq / reference).log2()
+}
+
+/// Fractional MIDI note number of a frequency.
+pub fn frequency_to_midi(freq: f32, a4_hz: f32) -> f32 {
+    A4_MIDI as f32 + 12.0 * (freq / a4_hz).log2()
+}
+
+/// Frequency of a (possibly fractional) MIDI note number.
+pub fn midi_to_frequency(midi: f32, a4_hz: f32) -> f32 {
+    transpose(a4_hz, midi - A4_MIDI as f32)
+}
+
+/// `freq` moved by `semitones` (fractional and negative values allowed).
+pub fn transpose(freq: f32, semitones: f32) -> f32 {
+    freq * 2f32.powf(semitones / 12.0)
+}
+
+/// The frequency range within `tolerance_cents` of `center_hz`, as `(low_hz, high_hz)`.
+/// This is how a note, named or relative, becomes a mapping band.
+pub fn note_window(center_hz: f32, tolerance_cents: f32) -> (f32, f32) {
+    (transpose(center_hz, -tolerance_cents / 100.0), transpose(center_hz, tolerance_cents / 100.0))
+}
+
+pub fn nearest_note(freq: f32, a4_hz: f32) -> NearestNote {
+    let midi = frequency_to_midi(freq, a4_hz);
+    let rounded = midi.round();
+    NearestNote {
+        midi: rounded as i32,
+        cents: (midi - rounded) * 100.0,
+    }
+}
+
+/// The MIDI note number of a note in scientific pitch notation, e.g. `"C4"`, `"F#3"` or
+/// `"Bb2"`.
+pub fn parse_note_name(name: &str) -> Option<i32> {
+    let mut chars = name.chars();
+    let letter = chars.next()?.to_ascii_uppercase();
+    let mut semitone = NOTE_NAMES.iter().position(|&note| note == letter.to_string())? as i32;
+    let rest = chars.as_str();
+    let octave = if let Some(octave) = rest.strip_prefix('#') {
+        semitone += 1;
+        octave
+    } else if let Some(octave) = rest.strip_prefix('b') {
+        semitone -= 1;
+        octave
+    } else {
+        rest
+    };
+    let octave: i32 = octave.parse().ok()?;
+    Some((octave + 1) * 12 + semitone)
+}
+
+/// A scale, as the semitones above its first note, for generating bands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scale {
+    Chromatic,
+    Major,
+    /// Natural minor.
+    Minor,
+    /// Major pentatonic.
+    Pentatonic,
+}
+
+impl Scale {
+    fn degrees(self) -> &'static [i32] {
+        match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Pentatonic => &[0, 2, 4, 7, 9],
+        }
+    }
+
+    /// The first `count` notes of the scale, in semitones above its first note, continuing
+    /// into the octaves above.
+    pub fn semitones(self, count: usize) -> Vec<i32> {
+        let degrees = self.degrees();
+        (0..count).map(|n| 12 * (n / degrees.len()) as i32 + degrees[n % degrees.len()]).collect()
+    }
+}
+
+/// Scientific pitch notation for a MIDI note number, using sharps.
+pub fn note_name(midi: i32) -> String {
+    let octave = midi.div_euclid(12) - 1;
+    format!("{}{}", NOTE_NAMES[midi.rem_euclid(12) as usize], octave)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn note_names_parse_to_midi_numbers() {
+        assert_eq!(parse_note_name("A4"), Some(69));
+        assert_eq!(parse_note_name("c4"), Some(60));
+        assert_eq!(parse_note_name("F#3"), Some(54));
+        assert_eq!(parse_note_name("Bb2"), Some(46));
+        assert_eq!(parse_note_name("C-1"), Some(0));
+        assert_eq!(parse_note_name("H4"), None);
+        assert_eq!(parse_note_name("C"), None);
+        for midi in 0..128 {
+            assert_eq!(parse_note_name(&note_name(midi)), Some(midi));
+        }
+    }
+
+    #[test]
+    fn scales_continue_into_the_next_octave() {
+        assert_eq!(Scale::Major.semitones(9), [0, 2, 4, 5, 7, 9, 11, 12, 14]);
+        assert_eq!(Scale::Pentatonic.semitones(3), [0, 2, 4]);
+    }
+}
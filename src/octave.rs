@@ -0,0 +1,60 @@
+use crate::PITCH_TARGET;
+use crate::notes::cents;
+use log::debug;
+
+// After this many corrections in a row the new octave is taken to be intentional
+const MAX_CONSECUTIVE_CORRECTIONS: u32 = 3;
+
+/// Snaps readings that land at half or double the previous stable frequency back to its octave.
+///
+/// The detector regularly locks onto a harmonic or a subharmonic for a window or two;
+/// those readings sit almost exactly one octave away from the note actually being sung.
+pub struct OctaveCorrector {
+    tolerance_cents: f32,
+    last_stable: Option<f32>,
+    consecutive_corrections: u32,
+}
+
+impl OctaveCorrector {
+    pub fn new(tolerance_cents: f32) -> Self {
+        OctaveCorrector {
+            tolerance_cents,
+            last_stable: None,
+            consecutive_corrections: 0,
+        }
+    }
+
+    pub fn correct(&mut self, freq: f32) -> f32 {
+        let corrected = match self.last_stable {
+            Some(previous) if self.consecutive_corrections < MAX_CONSECUTIVE_CORRECTIONS => {
+                if cents(freq, previous / 2.0).abs() <= self.tolerance_cents {
+                    Some(freq * 2.0)
+                } else if cents(freq, previous * 2.0).abs() <= self.tolerance_cents {
+                    Some(freq / 2.0)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        match corrected {
+            Some(corrected) => {
+                debug!(target: PITCH_TARGET, "Input: Octave error corrected {:.2} Hz -> {:.2} Hz", freq, corrected);
+                self.consecutive_corrections += 1;
+                corrected
+            }
+            None => {
+                self.consecutive_corrections = 0;
+                self.last_stable = Some(freq);
+                freq
+            }
+        }
+    }
+
+    /// Forget the reference pitch; called when the pitch is lost.
+    pub fn reset(&mut self) {
+        self.last_stable = None;
+        self.consecutive_corrections = 0;
+    }
+}
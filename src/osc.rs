@@ -0,0 +1,119 @@
+//! `--output osc`: every detected pitch as an Open Sound Control message over UDP, for Max/MSP,
+//! Pure Data, TouchDesigner and the like. The messages are simple enough to encode by hand.
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+
+/// Default for `--osc-target`.
+pub const DEFAULT_OSC_TARGET: &str = "127.0.0.1:9000";
+/// Default for `--osc-address`.
+pub const DEFAULT_OSC_ADDRESS: &str = "/pitchu/pitch";
+
+/// One argument of an OSC message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+}
+
+/// Checks that `address` can be sent: it starts with `/` and has none of the characters OSC
+/// reserves for patterns.
+pub fn parse_address(address: &str) -> Result<String, String> {
+    if !address.starts_with('/') {
+        return Err(format!("'{}' must start with '/'", address));
+    }
+    if let Some(c) = address.chars().find(|c| c.is_whitespace() || "#*,?[]{}".contains(*c)) {
+        return Err(format!("'{}' can't contain '{}'", address, c));
+    }
+    Ok(address.to_string())
+}
+
+/// Encodes one message: the address, the type tags, then the arguments big-endian, each
+/// string null-terminated and padded to four bytes.
+pub fn encode(address: &str, args: &[OscArg]) -> Vec<u8> {
+    let mut packet = Vec::new();
+    push_string(&mut packet, address);
+    let tags: String = std::iter::once(',')
+        .chain(args.iter().map(|arg| match arg {
+            OscArg::Int(_) => 'i',
+            OscArg::Float(_) => 'f',
+        }))
+        .collect();
+    push_string(&mut packet, &tags);
+    for arg in args {
+        match arg {
+            OscArg::Int(value) => packet.extend(value.to_be_bytes()),
+            OscArg::Float(value) => packet.extend(value.to_be_bytes()),
+        }
+    }
+    packet
+}
+
+fn push_string(packet: &mut Vec<u8>, value: &str) {
+    packet.extend(value.as_bytes());
+    // At least one null
+    packet.extend(std::iter::repeat_n(0, 4 - value.len() % 4));
+}
+
+/// Sends pitches to one host and port. UDP never waits for the other side, so this is called
+/// straight from the processing loop.
+pub struct OscSender {
+    socket: UdpSocket,
+    address: String,
+}
+
+impl OscSender {
+    /// `target` is `host:port`; `address` should have passed [`parse_address`].
+    pub fn connect(target: &str, address: String) -> io::Result<Self> {
+        let target = target
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no address for {}", target)))?;
+        let local: SocketAddr =
+            if target.is_ipv4() { (Ipv4Addr::UNSPECIFIED, 0).into() } else { (Ipv6Addr::UNSPECIFIED, 0).into() };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(target)?;
+        socket.set_nonblocking(true)?;
+        Ok(OscSender { socket, address })
+    }
+
+    /// `<address> ,ff frequency clarity`, or `,ffi` with the input channel when there is one.
+    /// A message that can't be sent is dropped, like any lost datagram.
+    pub fn send_pitch(&self, freq: f32, clarity: f32, channel: Option<usize>) {
+        let mut args = vec![OscArg::Float(freq), OscArg::Float(clarity)];
+        args.extend(channel.map(|channel| OscArg::Int(channel as i32)));
+        let _ = self.socket.send(&encode(&self.address, &args));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_padded_to_four_bytes() {
+        let packet = encode("/pitchu/pitch", &[OscArg::Float(440.0), OscArg::Int(1)]);
+        let mut expected = b"/pitchu/pitch\0\0\0,fi\0".to_vec();
+        expected.extend(440.0f32.to_be_bytes());
+        expected.extend(1i32.to_be_bytes());
+        assert_eq!(packet, expected);
+        // A string that fills its four bytes still gets a null
+        assert_eq!(encode("/abc", &[]), b"/abc\0\0\0\0,\0\0\0");
+
+        assert!(parse_address("/pitch").is_ok());
+        assert!(parse_address("pitch").is_err());
+        assert!(parse_address("/pitch/*").is_err());
+    }
+
+    #[test]
+    fn pitches_arrive_over_udp() {
+        let receiver = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        receiver.set_read_timeout(Some(std::time::Duration::from_secs(2))).unwrap();
+        let target = receiver.local_addr().unwrap().to_string();
+        let sender = OscSender::connect(&target, "/p".to_string()).unwrap();
+        sender.send_pitch(220.0, 0.5, None);
+        let mut buffer = [0; 64];
+        let length = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..length], encode("/p", &[OscArg::Float(220.0), OscArg::Float(0.5)]));
+    }
+}
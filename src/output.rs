@@ -0,0 +1,765 @@
+use crate::file_log::session_elapsed;
+use crate::virtual_keyboard::VirtualKeyboard;
+use crate::virtual_pad::VirtualPad;
+use crate::midi::MidiOut;
+use crate::ws::{KeyMessage, PitchBroadcaster};
+use enigo::{Enigo, Key, KeyboardControllable, MouseControllable};
+use log::{debug, info, warn};
+use pitchu::axis::StickAxis;
+use pitchu::backend::OutputBackend;
+use pitchu::gamepad::{Button, ButtonMap};
+use pitchu::held_keys::HeldKeys;
+use pitchu::keys::{key_name, KeyEvent, KeyMode};
+use pitchu::layout::Layout;
+use pitchu::latency::{LatencySummary, LatencyTracker};
+use pitchu::remote::{write_message, RemoteCommand, RemoteMessage};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Commands buffered for the output thread before new ones are dropped
+const COMMAND_QUEUE_DEPTH: usize = 64;
+// Minimum time between two "output stalled" warnings
+const STALL_WARNING_INTERVAL: Duration = Duration::from_secs(2);
+// How long a clicked controller button stays down; games poll pads once per frame
+const GAMEPAD_CLICK_HOLD: Duration = Duration::from_millis(40);
+/// How long `--remote` goes without writing anything before it sends a keepalive.
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+// How often `--remote` tries to reconnect, and how long one attempt may take
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+// A receiver that can't take a message within this time counts as gone
+const REMOTE_WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// One place key presses go; `--output` takes several.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputKind {
+    /// Simulated keyboard input.
+    Keyboard,
+    /// A virtual game controller; keys become buttons through the config's `[gamepad]` table.
+    Gamepad,
+    /// A keyboard made with uinput (Linux), for headless machines and Wayland.
+    Uinput,
+    /// No key presses; every detected pitch is sent as an OSC message, to this `host:port` or
+    /// `--osc-target`.
+    Osc(Option<String>),
+    /// Each key plays its band's note on a MIDI output port: the first whose name contains
+    /// this, or the first port.
+    Midi(Option<String>),
+    /// Key presses as JSON on the `--ws-port` WebSocket, next to the pitches.
+    Ws,
+}
+
+impl OutputKind {
+    /// Whether this output presses keys somewhere, as opposed to only reporting pitches.
+    pub fn presses_keys(&self) -> bool {
+        !matches!(self, OutputKind::Osc(_))
+    }
+}
+
+impl FromStr for OutputKind {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (kind, detail) = match spec.split_once(':') {
+            Some((kind, detail)) if !detail.is_empty() => (kind, Some(detail.to_string())),
+            _ => (spec, None),
+        };
+        match (kind.to_ascii_lowercase().as_str(), detail) {
+            ("keyboard" | "enigo", None) => Ok(OutputKind::Keyboard),
+            ("gamepad", None) => Ok(OutputKind::Gamepad),
+            ("uinput", None) => Ok(OutputKind::Uinput),
+            ("ws", None) => Ok(OutputKind::Ws),
+            ("osc", target) => Ok(OutputKind::Osc(target)),
+            ("midi", port) => Ok(OutputKind::Midi(port)),
+            _ => Err(format!(
+                "unknown output '{}' (expected `keyboard`, `gamepad`, `uinput`, `ws`, `osc[:<host:port>]` or `midi[:<port name>]`)",
+                spec
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputKind::Keyboard => write!(f, "keyboard"),
+            OutputKind::Gamepad => write!(f, "gamepad"),
+            OutputKind::Uinput => write!(f, "uinput"),
+            OutputKind::Ws => write!(f, "ws"),
+            OutputKind::Osc(None) => write!(f, "osc"),
+            OutputKind::Osc(Some(target)) => write!(f, "osc:{}", target),
+            OutputKind::Midi(None) => write!(f, "midi"),
+            OutputKind::Midi(Some(port)) => write!(f, "midi:{}", port),
+        }
+    }
+}
+
+/// What the output thread opens its backends from.
+pub struct OutputSettings {
+    pub kinds: Vec<OutputKind>,
+    /// Where the keyboard presses character keys (see [`Layout::remap`]).
+    pub layout: Layout,
+    /// Controller buttons for a `gamepad` output.
+    pub buttons: ButtonMap,
+    /// `host:port` of a `pitchu receive` that gets every command instead of the local outputs.
+    pub remote: Option<String>,
+    /// The note each key plays on a `midi` output.
+    pub midi_notes: HashMap<Key, u8>,
+    /// The `--ws-port` server, for a `ws` output.
+    pub ws: Option<PitchBroadcaster>,
+    /// Keys held down for longer than this are let go as stuck; never when `None`.
+    pub max_hold: Option<Duration>,
+    /// Most keys (and buttons) down at once; any number when `None`.
+    pub max_keys: Option<usize>,
+}
+
+/// One thing to do with the keyboard.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyCommand {
+    Click(Key),
+    Down(Key),
+    Up(Key),
+    /// Type a string, e.g. a text band's macro.
+    Type(String),
+    /// Let go of every key (or button) still held down.
+    ReleaseAll,
+    /// Move a controller stick axis, from -1.0 to 1.0.
+    Axis(StickAxis, f32),
+    /// Move the mouse pointer by this many pixels.
+    MouseMove(i32, i32),
+}
+
+impl KeyCommand {
+    /// What `event` means for the keyboard in `mode`: a click per press or repeat in repeat
+    /// mode, holding down and letting go in latch mode. A `hold` band's key goes down and up
+    /// in either.
+    pub fn for_event(mode: KeyMode, event: KeyEvent) -> Option<Self> {
+        match (mode, event) {
+            (_, KeyEvent::Down(key)) => Some(KeyCommand::Down(key)),
+            (_, KeyEvent::Up(key)) => Some(KeyCommand::Up(key)),
+            (KeyMode::Repeat, KeyEvent::Press(key) | KeyEvent::Repeat(key)) => Some(KeyCommand::Click(key)),
+            (KeyMode::Repeat, KeyEvent::Release(_)) => None,
+            (KeyMode::Latch, KeyEvent::Press(key) | KeyEvent::Repeat(key)) => Some(KeyCommand::Down(key)),
+            (KeyMode::Latch, KeyEvent::Release(key)) => Some(KeyCommand::Up(key)),
+        }
+    }
+}
+
+// A command and when the audio that caused it was captured
+struct Queued {
+    command: KeyCommand,
+    captured_at: Option<Instant>,
+}
+
+/// Presses keys on a dedicated thread, so a slow injection (enigo can block for milliseconds,
+/// e.g. on a loaded X11 server) never delays analysis of the next window.
+///
+/// Commands run in the order they were sent. Dropping the handle drains the queue, releases any
+/// key still held down and joins the thread.
+///
+/// Commands sent with a capture time are timed from capture to injection; see
+/// [`latency_summary`](Self::latency_summary).
+pub struct KeyOutput {
+    sender: Option<SyncSender<Queued>>,
+    handle: Option<JoinHandle<()>>,
+    latency: Arc<Mutex<LatencyTracker>>,
+    dropped: usize,
+    last_warning: Option<Instant>,
+}
+
+impl KeyOutput {
+    /// Warns when key presses keep landing more than `latency_budget` after their audio arrived.
+    /// Every output in `settings` gets every command; see [`OutputSettings`]. A `gamepad` or
+    /// `uinput` output that can't be created falls back to the keyboard, unless the keyboard
+    /// is an output already.
+    pub fn start(latency_budget: Duration, settings: OutputSettings) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(COMMAND_QUEUE_DEPTH);
+        let latency = Arc::new(Mutex::new(LatencyTracker::new(latency_budget)));
+        let latency_thread = Arc::clone(&latency);
+        let held = HeldKeys::new(settings.max_hold, settings.max_keys);
+        let handle = thread::spawn(move || output_loop(receiver, open_backends(settings), held, &latency_thread));
+        KeyOutput { sender: Some(sender), handle: Some(handle), latency, dropped: 0, last_warning: None }
+    }
+
+    /// Never blocks. If the output thread has stalled and the queue is full, the command is
+    /// dropped with a (rate-limited) warning. `captured_at` is when the audio that caused the
+    /// command arrived, if this is a response to audio.
+    pub fn send(&mut self, command: KeyCommand, captured_at: Option<Instant>) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        match sender.try_send(Queued { command, captured_at }) {
+            Ok(()) => {}
+            Err(TrySendError::Full(Queued { command, .. })) => {
+                self.dropped += 1;
+                if self.last_warning.is_none_or(|at| at.elapsed() >= STALL_WARNING_INTERVAL) {
+                    warn!(
+                        "Output: key injection is stalled; dropped {:?} ({} command(s) dropped so far).",
+                        command, self.dropped
+                    );
+                    self.last_warning = Some(Instant::now());
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => warn!("Output: the key output thread has stopped."),
+        }
+    }
+
+    /// Capture-to-key-press latency percentiles so far.
+    pub fn latency_summary(&self) -> Option<LatencySummary> {
+        self.latency.lock().ok()?.summary()
+    }
+
+    /// Finishes the queued commands like dropping does, then returns the final latency summary.
+    pub fn finish(mut self) -> Option<LatencySummary> {
+        self.stop();
+        self.latency_summary()
+    }
+
+    fn stop(&mut self) {
+        // Closing the channel lets the thread finish what's queued and exit
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for KeyOutput {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// Created on the output thread, since enigo and the platform controller handles needn't be Send
+fn open_backends(settings: OutputSettings) -> Vec<Box<dyn OutputBackend>> {
+    let OutputSettings { kinds, layout, buttons, remote, midi_notes, ws, max_hold: _, max_keys: _ } = settings;
+    if let Some(address) = remote {
+        return vec![Box::new(RemoteSender::connect(address))];
+    }
+    let has_keyboard = kinds.contains(&OutputKind::Keyboard);
+    let fallback = |reason: String, what: &str| -> Option<Box<dyn OutputBackend>> {
+        if has_keyboard {
+            warn!("Output: no {} ({}); the keyboard output carries on.", what, reason);
+            None
+        } else {
+            warn!("Output: no {} ({}); falling back to the keyboard.", what, reason);
+            Some(Box::new(KeyboardBackend { enigo: Enigo::new(), layout }))
+        }
+    };
+    let mut backends: Vec<Box<dyn OutputBackend>> = Vec::new();
+    let mut opened: Vec<&OutputKind> = Vec::new();
+    for kind in &kinds {
+        if opened.contains(&kind) {
+            continue;
+        }
+        opened.push(kind);
+        let backend: Option<Box<dyn OutputBackend>> = match kind {
+            OutputKind::Keyboard => Some(Box::new(KeyboardBackend { enigo: Enigo::new(), layout })),
+            OutputKind::Gamepad => match VirtualPad::open() {
+                Ok(pad) => {
+                    info!("Output: pressing buttons on a virtual gamepad.");
+                    Some(Box::new(GamepadBackend { pad, buttons: buttons.clone(), unmapped: HashSet::new() }))
+                }
+                Err(reason) => fallback(reason, "virtual gamepad"),
+            },
+            OutputKind::Uinput => match VirtualKeyboard::open() {
+                Ok(keyboard) => {
+                    info!("Output: pressing keys on a uinput keyboard.");
+                    Some(Box::new(UinputBackend { keyboard, unsupported: HashSet::new() }))
+                }
+                Err(reason) => fallback(reason, "uinput keyboard"),
+            },
+            OutputKind::Midi(port) => match MidiOut::open(port.as_deref()) {
+                Ok(out) => {
+                    info!("Output: playing notes on MIDI port '{}'.", out.port_name);
+                    Some(Box::new(MidiBackend { out, notes: midi_notes.clone(), unmapped: HashSet::new() }))
+                }
+                Err(e) => {
+                    warn!("Output: no MIDI output ({}); the other outputs carry on.", e);
+                    None
+                }
+            },
+            OutputKind::Ws => match &ws {
+                Some(broadcaster) => Some(Box::new(WsBackend(broadcaster.clone()))),
+                None => {
+                    warn!("Output: --output ws needs --ws-port; nothing is sent.");
+                    None
+                }
+            },
+            // Pitches are sent from the processing loop
+            OutputKind::Osc(_) => None,
+        };
+        backends.extend(backend);
+    }
+    if backends.is_empty() {
+        info!("Output: no keys are pressed.");
+    }
+    backends
+}
+
+fn run(backend: &mut dyn OutputBackend, command: &KeyCommand) -> Result<(), String> {
+    match command {
+        KeyCommand::Click(key) => backend.tap(*key),
+        KeyCommand::Down(key) => backend.press(*key),
+        KeyCommand::Up(key) => backend.release(*key),
+        KeyCommand::Type(text) => backend.type_text(text),
+        KeyCommand::Axis(axis, value) => backend.axis(*axis, *value),
+        KeyCommand::MouseMove(dx, dy) => backend.mouse_move(*dx, *dy),
+        // Handled by output_loop, which knows what is held
+        KeyCommand::ReleaseAll => Ok(()),
+    }
+}
+
+// The simplest backend, and the one to copy for a new kind of output
+struct KeyboardBackend {
+    enigo: Enigo,
+    // Character keys are pressed where the layout has them
+    layout: Layout,
+}
+
+impl OutputBackend for KeyboardBackend {
+    fn name(&self) -> String {
+        "keyboard".to_string()
+    }
+
+    fn tap(&mut self, key: Key) -> Result<(), String> {
+        self.enigo.key_click(self.layout.remap_key(key));
+        Ok(())
+    }
+
+    fn press(&mut self, key: Key) -> Result<(), String> {
+        self.enigo.key_down(self.layout.remap_key(key));
+        Ok(())
+    }
+
+    fn release(&mut self, key: Key) -> Result<(), String> {
+        self.enigo.key_up(self.layout.remap_key(key));
+        Ok(())
+    }
+
+    fn type_text(&mut self, text: &str) -> Result<(), String> {
+        self.enigo.key_sequence(text);
+        Ok(())
+    }
+
+    fn mouse_move(&mut self, dx: i32, dy: i32) -> Result<(), String> {
+        self.enigo.mouse_move_relative(dx, dy);
+        Ok(())
+    }
+}
+
+struct GamepadBackend {
+    pad: VirtualPad,
+    buttons: ButtonMap,
+    // Keys without a button, reported once each
+    unmapped: HashSet<Key>,
+}
+
+impl GamepadBackend {
+    fn button(&mut self, key: Key) -> Option<Button> {
+        let button = self.buttons.button_for(key);
+        if button.is_none() && self.unmapped.insert(key) {
+            warn!("Output: key '{}' has no gamepad button; add it to [gamepad.buttons].", key_name(key));
+        }
+        button
+    }
+}
+
+impl OutputBackend for GamepadBackend {
+    fn name(&self) -> String {
+        "gamepad".to_string()
+    }
+
+    fn tap(&mut self, key: Key) -> Result<(), String> {
+        let Some(button) = self.button(key) else {
+            return Ok(());
+        };
+        self.pad.set(button, true)?;
+        thread::sleep(GAMEPAD_CLICK_HOLD);
+        self.pad.set(button, false)
+    }
+
+    fn press(&mut self, key: Key) -> Result<(), String> {
+        self.button(key).map_or(Ok(()), |button| self.pad.set(button, true))
+    }
+
+    fn release(&mut self, key: Key) -> Result<(), String> {
+        self.button(key).map_or(Ok(()), |button| self.pad.set(button, false))
+    }
+
+    fn type_text(&mut self, text: &str) -> Result<(), String> {
+        warn!("Output: a gamepad can't type {:?}; skipped.", text);
+        Ok(())
+    }
+
+    fn axis(&mut self, axis: StickAxis, value: f32) -> Result<(), String> {
+        self.pad.set_axis(axis, value)
+    }
+}
+
+struct UinputBackend {
+    keyboard: VirtualKeyboard,
+    // Keys it has no code for, reported once each
+    unsupported: HashSet<Key>,
+}
+
+impl UinputBackend {
+    fn supports(&mut self, key: Key) -> bool {
+        if VirtualKeyboard::supports(key) {
+            return true;
+        }
+        if self.unsupported.insert(key) {
+            warn!("Output: the uinput keyboard has no key for '{}'; skipped.", key_name(key));
+        }
+        false
+    }
+}
+
+impl OutputBackend for UinputBackend {
+    fn name(&self) -> String {
+        "uinput".to_string()
+    }
+
+    fn tap(&mut self, key: Key) -> Result<(), String> {
+        if self.supports(key) { self.keyboard.click(key) } else { Ok(()) }
+    }
+
+    fn press(&mut self, key: Key) -> Result<(), String> {
+        if self.supports(key) { self.keyboard.set(key, true) } else { Ok(()) }
+    }
+
+    fn release(&mut self, key: Key) -> Result<(), String> {
+        if self.supports(key) { self.keyboard.set(key, false) } else { Ok(()) }
+    }
+
+    fn type_text(&mut self, text: &str) -> Result<(), String> {
+        let skipped = self.keyboard.type_text(text)?;
+        if !skipped.is_empty() {
+            warn!("Output: the uinput keyboard can't type {:?}; skipped.", String::from_iter(skipped));
+        }
+        Ok(())
+    }
+}
+
+// Each key plays its band's note: a short one per tap, or held while the key is
+struct MidiBackend {
+    out: MidiOut,
+    notes: HashMap<Key, u8>,
+    // Keys without a note, reported once each
+    unmapped: HashSet<Key>,
+}
+
+impl MidiBackend {
+    fn note(&mut self, key: Key) -> Option<u8> {
+        let note = self.notes.get(&key).copied();
+        if note.is_none() && self.unmapped.insert(key) {
+            warn!("Output: key '{}' belongs to no band, so it has no MIDI note; skipped.", key_name(key));
+        }
+        note
+    }
+}
+
+impl OutputBackend for MidiBackend {
+    fn name(&self) -> String {
+        format!("MIDI port '{}'", self.out.port_name)
+    }
+
+    fn tap(&mut self, key: Key) -> Result<(), String> {
+        let Some(note) = self.note(key) else {
+            return Ok(());
+        };
+        self.out.note(note, true)?;
+        self.out.note(note, false)
+    }
+
+    fn press(&mut self, key: Key) -> Result<(), String> {
+        self.note(key).map_or(Ok(()), |note| self.out.note(note, true))
+    }
+
+    fn release(&mut self, key: Key) -> Result<(), String> {
+        self.note(key).map_or(Ok(()), |note| self.out.note(note, false))
+    }
+}
+
+struct WsBackend(PitchBroadcaster);
+
+impl WsBackend {
+    fn publish(&self, action: &'static str, key: Option<Key>, text: Option<&str>) -> Result<(), String> {
+        self.0.publish_key(KeyMessage { action, key: key.map(key_name), text: text.map(str::to_string) });
+        Ok(())
+    }
+}
+
+impl OutputBackend for WsBackend {
+    fn name(&self) -> String {
+        "ws".to_string()
+    }
+
+    fn tap(&mut self, key: Key) -> Result<(), String> {
+        self.publish("tap", Some(key), None)
+    }
+
+    fn press(&mut self, key: Key) -> Result<(), String> {
+        self.publish("press", Some(key), None)
+    }
+
+    fn release(&mut self, key: Key) -> Result<(), String> {
+        self.publish("release", Some(key), None)
+    }
+
+    fn type_text(&mut self, text: &str) -> Result<(), String> {
+        self.publish("text", None, Some(text))
+    }
+}
+
+/// The `--remote` connection. Reconnects after the link drops; commands issued while it is
+/// down are dropped, and the receiver lets go of its keys when it notices.
+struct RemoteSender {
+    address: String,
+    stream: Option<TcpStream>,
+    last_attempt: Option<Instant>,
+    // Commands dropped since the link was lost
+    dropped: usize,
+    // The last message that made it onto the link; only writes keep the receiver listening
+    last_write: Instant,
+}
+
+impl RemoteSender {
+    fn connect(address: String) -> Self {
+        let mut remote = RemoteSender { address, stream: None, last_attempt: None, dropped: 0, last_write: Instant::now() };
+        remote.reconnect();
+        remote
+    }
+
+    fn send(&mut self, command: RemoteCommand) {
+        if self.stream.is_none() {
+            self.reconnect();
+        }
+        let Some(stream) = self.stream.as_mut() else {
+            if command != RemoteCommand::Keepalive {
+                self.dropped += 1;
+            }
+            return;
+        };
+        let message = RemoteMessage { t_ms: session_elapsed().as_millis() as u64, command };
+        match write_message(stream, &message) {
+            Ok(()) => self.last_write = Instant::now(),
+            Err(e) => {
+                warn!("Output: lost the connection to {} ({}); reconnecting.", self.address, e);
+                self.stream = None;
+                self.dropped += 1;
+            }
+        }
+    }
+
+    fn reconnect(&mut self) {
+        if self.last_attempt.is_some_and(|at| at.elapsed() < RECONNECT_INTERVAL) {
+            return;
+        }
+        let first = self.last_attempt.is_none();
+        self.last_attempt = Some(Instant::now());
+        match open_stream(&self.address) {
+            Ok(stream) => {
+                info!("Output: sending key presses to {}.", self.address);
+                if self.dropped > 0 {
+                    warn!("Output: {} command(s) were dropped while disconnected.", self.dropped);
+                    self.dropped = 0;
+                }
+                self.stream = Some(stream);
+                self.last_write = Instant::now();
+            }
+            Err(e) if first => warn!(
+                "Output: could not connect to {} ({}); retrying every {}s.",
+                self.address,
+                e,
+                RECONNECT_INTERVAL.as_secs()
+            ),
+            Err(e) => debug!("Output: could not connect to {}: {}", self.address, e),
+        }
+    }
+}
+
+// Commands for the other machine; it never fails, it reconnects
+impl OutputBackend for RemoteSender {
+    fn name(&self) -> String {
+        format!("remote {}", self.address)
+    }
+
+    fn tap(&mut self, key: Key) -> Result<(), String> {
+        self.send(RemoteCommand::Click { key: key_name(key) });
+        Ok(())
+    }
+
+    fn press(&mut self, key: Key) -> Result<(), String> {
+        self.send(RemoteCommand::Down { key: key_name(key) });
+        Ok(())
+    }
+
+    fn release(&mut self, key: Key) -> Result<(), String> {
+        self.send(RemoteCommand::Up { key: key_name(key) });
+        Ok(())
+    }
+
+    fn type_text(&mut self, text: &str) -> Result<(), String> {
+        self.send(RemoteCommand::Type { text: text.to_string() });
+        Ok(())
+    }
+
+    // Stick and mouse moves aren't sent, so they don't count: only time since the last write
+    fn idle(&mut self) -> Result<(), String> {
+        if self.last_write.elapsed() >= KEEPALIVE_INTERVAL {
+            self.send(RemoteCommand::Keepalive);
+        }
+        Ok(())
+    }
+}
+
+fn open_stream(address: &str) -> std::io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_nodelay(true)?;
+                stream.set_write_timeout(Some(REMOTE_WRITE_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address to connect to")))
+}
+
+fn output_loop(
+    receiver: Receiver<Queued>,
+    mut backends: Vec<Box<dyn OutputBackend>>,
+    mut held: HeldKeys,
+    latency: &Mutex<LatencyTracker>,
+) {
+    loop {
+        // On every pass, however busy the queue is; each backend knows when it's due
+        backends.retain_mut(|backend| keep(backend.as_mut(), |backend| backend.idle()));
+        let wait = held.next_due(Instant::now()).map_or(KEEPALIVE_INTERVAL, |due| due.min(KEEPALIVE_INTERVAL));
+        let received = receiver.recv_timeout(wait);
+        for (key, held_for) in held.overdue(Instant::now()) {
+            warn!(
+                "Output: '{}' has been held down for {:.1}s, longer than --max-hold-ms; letting go of it. \
+                 Its release was probably lost; please report this if it keeps happening.",
+                key_name(key),
+                held_for.as_secs_f32()
+            );
+            backends.retain_mut(|backend| keep(backend.as_mut(), |backend| backend.release(key)));
+        }
+        let Queued { command, captured_at } = match received {
+            Ok(queued) => queued,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if command == KeyCommand::ReleaseAll {
+            for key in held.release_all() {
+                debug!("Output: releasing '{:?}'.", key);
+                backends.retain_mut(|backend| keep(backend.as_mut(), |backend| backend.release(key)));
+            }
+            continue;
+        }
+        let started = Instant::now();
+        match command {
+            // Refused at its first press, and warned about then
+            KeyCommand::Down(key) if held.is_refused(key) => continue,
+            KeyCommand::Down(key) if !held.down(key, started) => {
+                warn!(
+                    "Output: not pressing '{}'; {} keys are already down (--max-simultaneous-keys).",
+                    key_name(key),
+                    held.len()
+                );
+                continue;
+            }
+            KeyCommand::Up(key) if held.take_refused(key) => continue,
+            KeyCommand::Up(key) => {
+                held.up(key);
+            }
+            _ => {}
+        }
+        backends.retain_mut(|backend| keep(backend.as_mut(), |backend| run(backend, &command)));
+        debug!("Output: {:?} took {:.1}ms", command, started.elapsed().as_secs_f32() * 1000.0);
+        if let Some(captured_at) = captured_at {
+            record_latency(latency, &command, captured_at.elapsed());
+        }
+    }
+    for key in held.release_all() {
+        debug!("Output: releasing '{:?}' on shutdown.", key);
+        for backend in &mut backends {
+            let _ = backend.release(key);
+        }
+    }
+    for backend in &mut backends {
+        backend.shutdown();
+    }
+}
+
+// Runs `action` on `backend`; false if it failed and the backend should be closed
+fn keep(backend: &mut dyn OutputBackend, action: impl FnOnce(&mut dyn OutputBackend) -> Result<(), String>) -> bool {
+    match action(backend) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Output: {} failed ({}); closing it, the other outputs carry on.", backend.name(), e);
+            false
+        }
+    }
+}
+
+fn record_latency(latency: &Mutex<LatencyTracker>, command: &KeyCommand, elapsed: Duration) {
+    debug!("Latency: {:?} {:.1}ms after its audio was captured", command, elapsed.as_secs_f32() * 1000.0);
+    let Ok(mut tracker) = latency.lock() else {
+        return;
+    };
+    if tracker.record(elapsed) {
+        warn!(
+            "Latency: several key presses in a row landed more than {}ms after the audio (last {:.0}ms). \
+             Try a smaller --max-latency-ms and watch for \"Processing fell behind\" warnings.",
+            tracker.budget().as_millis(),
+            elapsed.as_secs_f32() * 1000.0
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pitchu::remote::read_message;
+    use std::net::TcpListener;
+
+    #[test]
+    fn a_steady_stream_of_stick_moves_still_sends_keepalives() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (sender, receiver) = mpsc::sync_channel(COMMAND_QUEUE_DEPTH);
+        let output = thread::spawn(move || {
+            let latency = Mutex::new(LatencyTracker::new(Duration::from_secs(1)));
+            let backends: Vec<Box<dyn OutputBackend>> = vec![Box::new(RemoteSender::connect(address))];
+            output_loop(receiver, backends, HeldKeys::new(None, None), &latency);
+        });
+        let (mut stream, _) = listener.accept().unwrap();
+        let queue = |command| sender.send(Queued { command, captured_at: None }).unwrap();
+        // A latched key, then only stick moves (which aren't sent) for well over the interval
+        queue(KeyCommand::Down(Key::Layout('a')));
+        let start = Instant::now();
+        while start.elapsed() < KEEPALIVE_INTERVAL * 3 / 2 {
+            queue(KeyCommand::Axis(StickAxis::LeftX, 0.5));
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(sender);
+        output.join().unwrap();
+        let mut commands = Vec::new();
+        while let Ok(message) = read_message(&mut stream) {
+            commands.push(message.command);
+        }
+        assert_eq!(commands.first(), Some(&RemoteCommand::Down { key: "a".to_string() }));
+        assert!(commands.contains(&RemoteCommand::Keepalive), "{:?}", commands);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::keys::KeyEvent;
+use enigo::Key;
+use log::info;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// What a completed pattern does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatternAction {
+    /// Press and release this key.
+    Key(Key),
+    /// Type this text.
+    Text(String),
+}
+
+/// A short melody, given as the keys its bands press, that triggers an action of its own when
+/// all of its steps are pressed in order within `within` (a combo).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub name: String,
+    pub steps: Vec<Key>,
+    /// From the first step's press to the last one's.
+    pub within: Duration,
+    pub action: PatternAction,
+    /// Whether the keys pressed along the way are dropped when the pattern completes. They are
+    /// held back while it is under way, so they arrive late when it is not completed.
+    pub suppress: bool,
+}
+
+/// Watches the key presses coming out of the key state machine for [`Pattern`]s.
+///
+/// When several patterns complete on the same press the longest wins, and a pattern that is the
+/// start of a longer one waits until the longer one either completes or can no longer do so
+/// (a press that breaks it, or its time running out) before firing.
+pub struct PatternMatcher {
+    patterns: Vec<Pattern>,
+    // Recent presses, oldest first; no more than the longest pattern has steps
+    history: VecDeque<(Key, Instant)>,
+    longest: usize,
+    // Events waiting for the pattern under way to be decided
+    held_back: Vec<KeyEvent>,
+    // A completed pattern waiting on a longer one that starts the same way
+    pending: Option<usize>,
+}
+
+impl PatternMatcher {
+    pub fn new(patterns: Vec<Pattern>) -> Self {
+        let longest = patterns.iter().map(|pattern| pattern.steps.len()).max().unwrap_or(0);
+        PatternMatcher {
+            patterns,
+            history: VecDeque::with_capacity(longest + 1),
+            longest,
+            held_back: Vec::new(),
+            pending: None,
+        }
+    }
+
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    /// Feed one window's key events at `now` (also with none, so time-outs are noticed).
+    /// Returns the events to carry out, which may include ones held back from earlier windows,
+    /// and the action of the pattern completed, if any.
+    pub fn feed(&mut self, events: Vec<KeyEvent>, now: Instant) -> (Vec<KeyEvent>, Option<PatternAction>) {
+        let mut passed = Vec::new();
+        let mut fired = None;
+        for event in events {
+            match event {
+                KeyEvent::Press(key) => {
+                    if let Some(action) = self.press(key, now, &mut passed) {
+                        fired = Some(action);
+                    }
+                }
+                // A key going up waits only behind its own press, so it's dropped with it and
+                // never stranded by a pattern it has no part in
+                KeyEvent::Up(key) | KeyEvent::Release(key) if !self.holds(key) => passed.push(event),
+                _ => self.held_back.push(event),
+            }
+        }
+        if let Some(pending) = self.pending
+            && !self.extends(pending, now)
+        {
+            fired = Some(self.fire(pending, &mut passed));
+        }
+        if !self.holding(now) {
+            passed.append(&mut self.held_back);
+        }
+        (passed, fired)
+    }
+
+    /// Forgets the presses so far and drops anything held back.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.held_back.clear();
+        self.pending = None;
+    }
+
+    fn press(&mut self, key: Key, now: Instant, passed: &mut Vec<KeyEvent>) -> Option<PatternAction> {
+        let mut fired = None;
+        self.remember(key, now);
+        if let Some(pending) = self.pending
+            && !self.extends(pending, now)
+            && self.complete(now).is_none_or(|index| self.steps(index) <= self.steps(pending))
+        {
+            // This press ends every longer pattern, so the shorter one stands; the press itself
+            // starts afresh after it
+            self.history.pop_back();
+            fired = Some(self.fire(pending, passed));
+            self.remember(key, now);
+        }
+        self.held_back.push(KeyEvent::Press(key));
+        match self.complete(now) {
+            Some(index) if self.extends(index, now) => self.pending = Some(index),
+            Some(index) => fired = Some(self.fire(index, passed)),
+            None => {}
+        }
+        fired
+    }
+
+    fn remember(&mut self, key: Key, now: Instant) {
+        self.history.push_back((key, now));
+        if self.history.len() > self.longest {
+            self.history.pop_front();
+        }
+    }
+
+    fn fire(&mut self, index: usize, passed: &mut Vec<KeyEvent>) -> PatternAction {
+        let pattern = &self.patterns[index];
+        info!("Action: Pattern '{}' completed.", pattern.name);
+        if pattern.suppress {
+            // Only the steps' own keys are dropped; anything else that waited goes out
+            passed.extend(self.held_back.drain(..).filter(|&event| !pattern.steps.contains(&key_of(event))));
+        } else {
+            passed.append(&mut self.held_back);
+        }
+        self.history.clear();
+        self.pending = None;
+        pattern.action.clone()
+    }
+
+    fn holds(&self, key: Key) -> bool {
+        self.held_back.iter().any(|&event| key_of(event) == key)
+    }
+
+    fn steps(&self, index: usize) -> usize {
+        self.patterns[index].steps.len()
+    }
+
+    // The longest pattern the latest presses complete; the first declared among equals
+    fn complete(&self, now: Instant) -> Option<usize> {
+        (0..self.patterns.len())
+            .rev()
+            .filter(|&index| self.progress(index, now) == self.steps(index))
+            .max_by_key(|&index| self.steps(index))
+    }
+
+    // How many of the pattern's steps the latest presses match, in time
+    fn progress(&self, index: usize, now: Instant) -> usize {
+        let pattern = &self.patterns[index];
+        (1..=pattern.steps.len().min(self.history.len()))
+            .rev()
+            .find(|&count| {
+                let start = self.history.len() - count;
+                let (_, started) = self.history[start];
+                now.duration_since(started) <= pattern.within
+                    && self.history.range(start..).map(|&(key, _)| key).eq(pattern.steps[..count].iter().copied())
+            })
+            .unwrap_or(0)
+    }
+
+    // Whether a longer pattern than `index` is under way, having matched at least as many presses
+    fn extends(&self, index: usize, now: Instant) -> bool {
+        let len = self.steps(index);
+        (0..self.patterns.len()).any(|other| {
+            let steps = self.steps(other);
+            let progress = self.progress(other, now);
+            steps > len && progress >= len && progress < steps
+        })
+    }
+
+    // Whether events must wait: a suppressing pattern is under way or waiting to fire
+    fn holding(&self, now: Instant) -> bool {
+        self.pending.is_some_and(|pending| self.patterns[pending].suppress)
+            || (0..self.patterns.len()).any(|index| {
+                let pattern = &self.patterns[index];
+                let progress = self.progress(index, now);
+                pattern.suppress && progress > 0 && progress < pattern.steps.len()
+            })
+    }
+}
+
+fn key_of(event: KeyEvent) -> Key {
+    match event {
+        KeyEvent::Press(key) | KeyEvent::Repeat(key) | KeyEvent::Release(key) | KeyEvent::Down(key) | KeyEvent::Up(key) => key,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: Key = Key::Layout('a');
+    const S: Key = Key::Layout('s');
+    const D: Key = Key::Layout('d');
+    const B: Key = Key::Layout('b');
+
+    fn pattern(name: &str, steps: &[Key], action: Key, suppress: bool) -> Pattern {
+        Pattern {
+            name: name.to_string(),
+            steps: steps.to_vec(),
+            within: Duration::from_millis(1500),
+            action: PatternAction::Key(action),
+            suppress,
+        }
+    }
+
+    #[test]
+    fn presses_in_order_and_in_time_fire_the_pattern() {
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let mut matcher = PatternMatcher::new(vec![pattern("rise", &[A, S, D], Key::Return, false)]);
+        assert_eq!(matcher.feed(vec![KeyEvent::Press(A)], at(0)), (vec![KeyEvent::Press(A)], None));
+        assert_eq!(matcher.feed(vec![KeyEvent::Press(S)], at(500)).1, None);
+        assert_eq!(matcher.feed(vec![KeyEvent::Press(D)], at(1000)).1, Some(PatternAction::Key(Key::Return)));
+        // Too slow the second time
+        matcher.feed(vec![KeyEvent::Press(A)], at(2000));
+        matcher.feed(vec![KeyEvent::Press(S)], at(3000));
+        assert_eq!(matcher.feed(vec![KeyEvent::Press(D)], at(4000)).1, None);
+    }
+
+    #[test]
+    fn the_longest_match_wins() {
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let patterns = vec![pattern("short", &[A, S], Key::Return, false), pattern("long", &[A, S, D], Key::Tab, false)];
+
+        let mut matcher = PatternMatcher::new(patterns.clone());
+        matcher.feed(vec![KeyEvent::Press(A)], at(0));
+        // The short pattern is complete but waits on the long one
+        assert_eq!(matcher.feed(vec![KeyEvent::Press(S)], at(100)).1, None);
+        assert_eq!(matcher.feed(vec![KeyEvent::Press(D)], at(200)).1, Some(PatternAction::Key(Key::Tab)));
+
+        // A press that breaks the long one lets the short one fire
+        matcher.feed(vec![KeyEvent::Press(A)], at(1000));
+        matcher.feed(vec![KeyEvent::Press(S)], at(1100));
+        assert_eq!(matcher.feed(vec![KeyEvent::Press(A)], at(1200)).1, Some(PatternAction::Key(Key::Return)));
+
+        // So does running out of time for it
+        let mut matcher = PatternMatcher::new(patterns);
+        matcher.feed(vec![KeyEvent::Press(A)], at(0));
+        matcher.feed(vec![KeyEvent::Press(S)], at(100));
+        assert_eq!(matcher.feed(Vec::new(), at(1000)).1, None);
+        assert_eq!(matcher.feed(Vec::new(), at(1600)).1, Some(PatternAction::Key(Key::Return)));
+    }
+
+    #[test]
+    fn suppressed_steps_are_held_back_and_dropped_on_a_match() {
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let mut matcher = PatternMatcher::new(vec![pattern("rise", &[A, S, D], Key::Return, true)]);
+        assert_eq!(matcher.feed(vec![KeyEvent::Press(A)], at(0)), (Vec::new(), None));
+        assert_eq!(matcher.feed(vec![KeyEvent::Repeat(A), KeyEvent::Press(S)], at(300)), (Vec::new(), None));
+        assert_eq!(matcher.feed(vec![KeyEvent::Press(D)], at(600)), (Vec::new(), Some(PatternAction::Key(Key::Return))));
+
+        // Not completed: the held-back presses come out once the pattern is broken
+        matcher.feed(vec![KeyEvent::Press(A)], at(1000));
+        let (events, fired) = matcher.feed(vec![KeyEvent::Press(D)], at(1100));
+        assert_eq!(fired, None);
+        assert_eq!(events, vec![KeyEvent::Press(A), KeyEvent::Press(D)]);
+    }
+
+    #[test]
+    fn keys_outside_a_suppressed_pattern_still_come_up() {
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let mut matcher = PatternMatcher::new(vec![pattern("rise", &[A, S, D], Key::Return, true)]);
+        // A hold band's key goes down, then gives way to the pattern's first step
+        assert_eq!(matcher.feed(vec![KeyEvent::Down(B)], at(0)).0, vec![KeyEvent::Down(B)]);
+        assert_eq!(matcher.feed(vec![KeyEvent::Up(B), KeyEvent::Press(A)], at(100)).0, vec![KeyEvent::Up(B)]);
+        matcher.feed(vec![KeyEvent::Press(S)], at(200));
+        assert_eq!(matcher.feed(vec![KeyEvent::Press(D)], at(300)), (Vec::new(), Some(PatternAction::Key(Key::Return))));
+
+        // Held down during the pattern: it waits, then goes out with its release
+        matcher.feed(vec![KeyEvent::Press(A)], at(1000));
+        matcher.feed(vec![KeyEvent::Down(B), KeyEvent::Press(S)], at(1100));
+        let (events, fired) = matcher.feed(vec![KeyEvent::Up(B), KeyEvent::Press(D)], at(1200));
+        assert_eq!(fired, Some(PatternAction::Key(Key::Return)));
+        assert_eq!(events, vec![KeyEvent::Down(B), KeyEvent::Up(B)]);
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::{Duration, Instant};
+
+/// Default for `percussive_refractory_ms`.
+pub const DEFAULT_REFRACTORY_MS: u64 = 250;
+// How many times the previous window's power a window needs to count as a sharp onset
+const ONSET_RATIO: f32 = 4.0;
+// How long a sound waits for a pitch before it counts as percussive: the consonant a sung note
+// starts on is followed by its pitch well within this
+const CONFIRM_DELAY: Duration = Duration::from_millis(100);
+
+/// Spots unpitched transients, such as a beatboxed "p", "t" or "k": a window well above the
+/// power threshold, much louder than the one before it, and not followed by a pitch within
+/// 100 ms.
+///
+/// A sound fires at most once: onsets within `refractory` of the last one are ignored.
+pub struct PercussiveDetector {
+    refractory: Duration,
+    previous_power: f32,
+    // When the onset waiting out CONFIRM_DELAY was heard
+    candidate: Option<Instant>,
+    last_onset: Option<Instant>,
+}
+
+impl PercussiveDetector {
+    pub fn new(refractory: Duration) -> Self {
+        PercussiveDetector { refractory, previous_power: 0.0, candidate: None, last_onset: None }
+    }
+
+    /// Feed one window's power, the threshold in effect, and whether the window had a pitch
+    /// that counts. Returns `true` once the window where a percussive sound is confirmed.
+    pub fn update(&mut self, power: f32, power_threshold: f32, pitched: bool, now: Instant) -> bool {
+        let onset = power >= power_threshold && power >= self.previous_power * ONSET_RATIO;
+        self.previous_power = power;
+        if pitched {
+            // The onset was the start of a note
+            self.candidate = None;
+            return false;
+        }
+        if onset
+            && self.candidate.is_none()
+            && self.last_onset.is_none_or(|last| now.duration_since(last) >= self.refractory)
+        {
+            self.candidate = Some(now);
+            self.last_onset = Some(now);
+        }
+        match self.candidate {
+            Some(heard) if now.duration_since(heard) >= CONFIRM_DELAY => {
+                self.candidate = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forgets the previous window and any sound waiting to be confirmed.
+    pub fn reset(&mut self) {
+        self.previous_power = 0.0;
+        self.candidate = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(46);
+    const THRESHOLD: f32 = 0.7;
+
+    // One (power, pitched) per window; the windows at which the detector fired
+    fn run(windows: &[(f32, bool)]) -> Vec<usize> {
+        let mut detector = PercussiveDetector::new(Duration::from_millis(DEFAULT_REFRACTORY_MS));
+        let t0 = Instant::now();
+        (0..windows.len())
+            .filter(|&index| {
+                let (power, pitched) = windows[index];
+                detector.update(power, THRESHOLD, pitched, t0 + WINDOW * index as u32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_loud_unpitched_burst_fires_once() {
+        // Quiet, a plosive decaying over a few windows, quiet again
+        let windows = [(0.1, false), (5.0, false), (3.0, false), (1.0, false), (0.1, false), (0.1, false)];
+        assert_eq!(run(&windows), [4]);
+    }
+
+    #[test]
+    fn the_consonant_starting_a_sung_note_does_not_fire() {
+        let windows = [(0.1, false), (5.0, false), (4.0, true), (4.0, true), (4.0, true)];
+        assert!(run(&windows).is_empty());
+    }
+
+    #[test]
+    fn onsets_within_the_refractory_period_are_ignored() {
+        // Bursts at 46 ms and 230 ms, then one well after
+        let quiet = (0.1, false);
+        let burst = (5.0, false);
+        let windows = [quiet, burst, quiet, quiet, quiet, burst, quiet, quiet, quiet, burst, quiet, quiet, quiet];
+        assert_eq!(run(&windows), [4, 12]);
+    }
+}
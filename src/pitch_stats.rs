@@ -0,0 +1,108 @@
+//! `pitchu tune`: what the detector heard over a stretch of listening, summed up when it ends,
+//! to pick `--power` and `--clarity` and see the range a voice actually covers.
+
+use std::fmt;
+
+/// The lowest, highest and median of a set of values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spread {
+    pub min: f32,
+    pub max: f32,
+    pub median: f32,
+}
+
+impl Spread {
+    /// `None` for no values. The median of an even count is halfway between the middle two.
+    pub fn of(values: &[f32]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f32::total_cmp);
+        let middle = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) { (sorted[middle - 1] + sorted[middle]) / 2.0 } else { sorted[middle] };
+        Some(Spread { min: sorted[0], max: sorted[sorted.len() - 1], median })
+    }
+}
+
+impl fmt::Display for Spread {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "min {:.3}, median {:.3}, max {:.3}", self.min, self.median, self.max)
+    }
+}
+
+/// Collects every window's pitch and level.
+#[derive(Debug, Default)]
+pub struct PitchStats {
+    frequencies: Vec<f32>,
+    clarities: Vec<f32>,
+    // RMS of every window, pitched or not
+    levels: Vec<f32>,
+}
+
+impl PitchStats {
+    pub fn new() -> Self {
+        PitchStats::default()
+    }
+
+    /// Feed one window's `(frequency, clarity)`, if it had a pitch, and its RMS level.
+    pub fn observe(&mut self, pitch: Option<(f32, f32)>, rms: f32) {
+        if let Some((frequency, clarity)) = pitch {
+            self.frequencies.push(frequency);
+            self.clarities.push(clarity);
+        }
+        self.levels.push(rms);
+    }
+
+    pub fn windows(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Share of the windows that had a pitch.
+    pub fn voiced(&self) -> f32 {
+        if self.levels.is_empty() {
+            return 0.0;
+        }
+        self.frequencies.len() as f32 / self.levels.len() as f32
+    }
+
+    pub fn frequency(&self) -> Option<Spread> {
+        Spread::of(&self.frequencies)
+    }
+
+    pub fn clarity(&self) -> Option<Spread> {
+        Spread::of(&self.clarities)
+    }
+
+    pub fn level(&self) -> Option<Spread> {
+        Spread::of(&self.levels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pitches_and_levels_are_summed_up_apart() {
+        let mut stats = PitchStats::new();
+        assert_eq!(stats.frequency(), None);
+        assert_eq!(stats.voiced(), 0.0);
+        for (pitch, rms) in [
+            (Some((220.0, 0.9)), 0.05),
+            (None, 0.001),
+            (Some((110.0, 0.7)), 0.04),
+            (Some((440.0, 0.95)), 0.08),
+            (Some((230.0, 0.8)), 0.06),
+        ] {
+            stats.observe(pitch, rms);
+        }
+        assert_eq!(stats.windows(), 5);
+        assert_eq!(stats.voiced(), 0.8);
+        // Four pitches: the median is halfway between the middle two
+        assert_eq!(stats.frequency(), Some(Spread { min: 110.0, max: 440.0, median: 225.0 }));
+        assert_eq!(stats.clarity().map(|clarity| clarity.max), Some(0.95));
+        // The silent window still counts for the level
+        assert_eq!(stats.level(), Some(Spread { min: 0.001, max: 0.08, median: 0.05 }));
+    }
+}
@@ -0,0 +1,51 @@
+use crate::output::{KeyCommand, KeyOutput};
+use log::info;
+use pitchu::keys::key_name;
+use pitchu::sequence::Step;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often a wait checks for Ctrl-C
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// `--play`: presses each step's key on `output` on time, counting from the end of the step
+/// before. Ctrl-C stops early, letting go of the key being held.
+pub fn run(steps: &[Step], mut output: KeyOutput, running: &AtomicBool) {
+    let mut due = Instant::now();
+    for (index, step) in steps.iter().enumerate() {
+        due += step.delay;
+        if !wait_until(due, running) {
+            break;
+        }
+        if step.duration.is_zero() {
+            info!("Play: step {}/{}, tapping {}.", index + 1, steps.len(), key_name(step.key));
+            output.send(KeyCommand::Click(step.key), None);
+            continue;
+        }
+        info!("Play: step {}/{}, {} for {}ms.", index + 1, steps.len(), key_name(step.key), step.duration.as_millis());
+        output.send(KeyCommand::Down(step.key), None);
+        due += step.duration;
+        let finished = wait_until(due, running);
+        output.send(KeyCommand::Up(step.key), None);
+        if !finished {
+            break;
+        }
+    }
+    if !running.load(Ordering::SeqCst) {
+        info!("Play: stopped.");
+    }
+    output.finish();
+}
+
+// Sleeps until `due`; false if Ctrl-C came first
+fn wait_until(due: Instant, running: &AtomicBool) -> bool {
+    while running.load(Ordering::SeqCst) {
+        let left = due.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(STOP_POLL_INTERVAL));
+    }
+    false
+}
@@ -0,0 +1,135 @@
+use crate::notes::cents;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Default for `--practice-secs`.
+pub const DEFAULT_PRACTICE_SECS: u64 = 5;
+
+/// `--practice`: times how long a target note is held, from the first pitch heard, and collects
+/// how far each window lands from it.
+pub struct Practice {
+    target_hz: f32,
+    duration: Duration,
+    started: Option<Instant>,
+    // Cents from the target of every window with a pitch
+    errors: Vec<f32>,
+    windows: usize,
+}
+
+impl Practice {
+    pub fn new(target_hz: f32, duration: Duration) -> Self {
+        Practice { target_hz, duration, started: None, errors: Vec::new(), windows: 0 }
+    }
+
+    pub fn target_hz(&self) -> f32 {
+        self.target_hz
+    }
+
+    /// Feed one window's frequency, if it had a pitch. Returns `true` once the time is up.
+    pub fn observe(&mut self, frequency: Option<f32>, now: Instant) -> bool {
+        let started = match (self.started, frequency) {
+            (Some(started), _) => started,
+            (None, Some(_)) => *self.started.insert(now),
+            // Waiting for the first note
+            (None, None) => return false,
+        };
+        if now.duration_since(started) >= self.duration {
+            return true;
+        }
+        self.windows += 1;
+        if let Some(frequency) = frequency {
+            self.errors.push(cents(frequency, self.target_hz));
+        }
+        false
+    }
+
+    pub fn has_started(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// The score so far; `None` until a pitch has been heard.
+    pub fn score(&self) -> Option<PracticeScore> {
+        if self.errors.is_empty() {
+            return None;
+        }
+        let count = self.errors.len() as f32;
+        let mean_cents = self.errors.iter().sum::<f32>() / count;
+        let variance = self.errors.iter().map(|error| (error - mean_cents).powi(2)).sum::<f32>() / count;
+        let mean_abs_cents = self.errors.iter().map(|error| error.abs()).sum::<f32>() / count;
+        let voiced = count / self.windows as f32;
+        let points = (100.0 - mean_abs_cents - variance.sqrt()).clamp(0.0, 100.0) * voiced;
+        Some(PracticeScore { mean_cents, variance, voiced, points: points.round() as u32 })
+    }
+}
+
+/// How a practice run went.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PracticeScore {
+    /// Average distance from the target, in cents: positive is sharp, negative flat.
+    pub mean_cents: f32,
+    /// Spread around that average, in cents squared; its square root is how much the pitch
+    /// wavered.
+    pub variance: f32,
+    /// Share of the run in which a pitch was heard.
+    pub voiced: f32,
+    /// 0-100: 100 minus the average distance and the wavering in cents, scaled by `voiced`.
+    pub points: u32,
+}
+
+impl fmt::Display for PracticeScore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/100 (mean {:+.1} cents, wavering ±{:.1} cents, variance {:.1}, pitch heard {:.0}% of the time)",
+            self.points,
+            self.mean_cents,
+            self.variance.sqrt(),
+            self.variance,
+            self.voiced * 100.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::transpose;
+
+    const WINDOW: Duration = Duration::from_millis(50);
+
+    fn run(pitches: &[Option<f32>]) -> Option<PracticeScore> {
+        let t0 = Instant::now();
+        let mut practice = Practice::new(220.0, Duration::from_secs(1));
+        for (index, &pitch) in pitches.iter().enumerate() {
+            if practice.observe(pitch, t0 + WINDOW * index as u32) {
+                break;
+            }
+        }
+        practice.score()
+    }
+
+    #[test]
+    fn a_steady_note_slightly_sharp_scores_high() {
+        let sharp = transpose(220.0, 0.1);
+        let score = run(&[Some(sharp); 40]).unwrap();
+        assert!((score.mean_cents - 10.0).abs() < 0.01);
+        assert!(score.variance < 0.01);
+        assert_eq!(score.voiced, 1.0);
+        assert_eq!(score.points, 90);
+    }
+
+    #[test]
+    fn wavering_and_gaps_cost_points() {
+        // Silence before the first note doesn't count; gaps after it do
+        let flat = transpose(220.0, -0.2);
+        let sharp = transpose(220.0, 0.2);
+        let mut pitches = vec![None; 10];
+        pitches.extend([Some(flat), Some(sharp), None, None].repeat(10));
+        let score = run(&pitches).unwrap();
+        assert!(score.mean_cents.abs() < 0.01);
+        assert!((score.variance - 400.0).abs() < 0.5);
+        assert_eq!(score.voiced, 0.5);
+        assert_eq!(score.points, 30);
+        assert_eq!(run(&[None; 40]), None);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::mapping::{Band, Mapping};
+use clap::ValueEnum;
+use enigo::Key;
+
+/// Built-in mappings for common targets, selected with `--preset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// mGBA's default keyboard bindings: arrows, X/Z for A/B, A/S for L/R, Backspace and Return
+    /// for select and start. The table used when nothing else is configured.
+    MgbaDefault,
+    /// RetroArch's default keyboard bindings: arrows, X/Z for A/B, S/A for X/Y, Shift and
+    /// Return for select and start.
+    RetroarchDefault,
+    /// Arrows, Return and Escape, for moving through menus.
+    MenuNavigation,
+}
+
+/// Which vocal range a preset's bands are laid out for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum VocalRange {
+    /// A typical male voice, roughly 100-340 Hz.
+    #[default]
+    Low,
+    /// A typical female voice: the low bands an octave up.
+    High,
+}
+
+impl VocalRange {
+    pub fn name(self) -> &'static str {
+        match self {
+            VocalRange::Low => "low",
+            VocalRange::High => "high",
+        }
+    }
+}
+
+const MGBA_DEFAULT: &[Band] = &[
+    Band::new(100.0, 115.0, Key::DownArrow),
+    Band::new(115.1, 130.0, Key::LeftArrow),
+    Band::new(130.1, 145.0, Key::RightArrow),
+    Band::new(145.1, 160.0, Key::UpArrow),
+    Band::new(160.1, 175.0, Key::Backspace),
+    Band::new(175.1, 200.0, Key::Layout('x')),
+    Band::new(200.1, 230.0, Key::Layout('z')),
+    Band::new(230.1, 270.0, Key::Layout('a')),
+    Band::new(270.1, 305.0, Key::Layout('s')),
+    Band::new(305.1, 338.0, Key::Return),
+];
+
+const RETROARCH_DEFAULT: &[Band] = &[
+    Band::new(100.0, 115.0, Key::DownArrow),
+    Band::new(115.1, 130.0, Key::LeftArrow),
+    Band::new(130.1, 145.0, Key::RightArrow),
+    Band::new(145.1, 160.0, Key::UpArrow),
+    Band::new(160.1, 175.0, Key::Layout('x')),
+    Band::new(175.1, 200.0, Key::Layout('z')),
+    Band::new(200.1, 230.0, Key::Layout('s')),
+    Band::new(230.1, 270.0, Key::Layout('a')),
+    Band::new(270.1, 305.0, Key::Shift),
+    Band::new(305.1, 338.0, Key::Return),
+];
+
+// Fewer, wider bands: menus need accuracy more than speed
+const MENU_NAVIGATION: &[Band] = &[
+    Band::new(100.0, 120.0, Key::DownArrow),
+    Band::new(120.1, 142.0, Key::UpArrow),
+    Band::new(142.1, 168.0, Key::LeftArrow),
+    Band::new(168.1, 200.0, Key::RightArrow),
+    Band::new(200.1, 260.0, Key::Return),
+    Band::new(260.1, 338.0, Key::Escape),
+];
+
+impl Preset {
+    pub const ALL: [Preset; 3] = [Preset::MgbaDefault, Preset::RetroarchDefault, Preset::MenuNavigation];
+
+    /// The name accepted by `--preset`, e.g. `mgba-default`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::MgbaDefault => "mgba-default",
+            Preset::RetroarchDefault => "retroarch-default",
+            Preset::MenuNavigation => "menu-navigation",
+        }
+    }
+
+    /// The preset's bands for `range`.
+    pub fn mapping(self, range: VocalRange) -> Mapping {
+        let bands = match self {
+            Preset::MgbaDefault => MGBA_DEFAULT,
+            Preset::RetroarchDefault => RETROARCH_DEFAULT,
+            Preset::MenuNavigation => MENU_NAVIGATION,
+        };
+        let factor = match range {
+            VocalRange::Low => 1.0,
+            VocalRange::High => 2.0,
+        };
+        Mapping::new(
+            bands
+                .iter()
+                .map(|band| Band { low_hz: band.low_hz * factor, high_hz: band.high_hz * factor, ..band.clone() })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_have_no_overlapping_bands() {
+        for preset in Preset::ALL {
+            for range in [VocalRange::Low, VocalRange::High] {
+                let mapping = preset.mapping(range);
+                assert!(!mapping.bands().is_empty());
+                assert_eq!(mapping.overlapping_bands(), vec![], "{} ({:?})", preset.name(), range);
+            }
+        }
+    }
+
+    #[test]
+    fn high_range_is_an_octave_up() {
+        let low = Preset::MenuNavigation.mapping(VocalRange::Low);
+        let high = Preset::MenuNavigation.mapping(VocalRange::High);
+        assert_eq!(high.key_for(220.0), low.key_for(110.0));
+        assert_eq!(high.key_for(600.0), low.key_for(300.0));
+        assert_eq!(high.key_for(110.0), None);
+    }
+
+    #[test]
+    fn names_match_the_command_line() {
+        for preset in Preset::ALL {
+            assert_eq!(Preset::from_str(preset.name(), false), Ok(preset));
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use crate::output::{KeyCommand, KeyOutput, KEEPALIVE_INTERVAL};
+use log::{debug, info, warn};
+use pitchu::keys::parse_key;
+use pitchu::remote::{read_message, RemoteCommand};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// How often the listener checks for a sender (and Ctrl-C) while nobody is connected
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// A sender that stays silent through this many keepalive intervals counts as gone
+const MISSED_KEEPALIVES: u32 = 3;
+
+/// `pitchu receive`: presses the keys a `--remote` sender sends, one sender at a time. When
+/// the link closes, breaks or goes quiet, everything the sender was holding is let go.
+pub fn run(listener: TcpListener, mut output: KeyOutput, running: &AtomicBool) -> std::io::Result<()> {
+    listener.set_nonblocking(true)?;
+    info!("Receive: waiting for a sender on {}.", listener.local_addr()?);
+    while running.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                info!("Receive: {} connected.", peer);
+                let reason = serve(stream, peer, &mut output, running);
+                output.send(KeyCommand::ReleaseAll, None);
+                info!("Receive: {} {}; released its keys.", peer, reason);
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL_INTERVAL),
+            Err(e) => {
+                warn!("Receive: could not accept a connection: {}", e);
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Carries out one sender's commands until the link ends, and says how it ended
+fn serve(mut stream: TcpStream, peer: SocketAddr, output: &mut KeyOutput, running: &AtomicBool) -> String {
+    let timeout = KEEPALIVE_INTERVAL * MISSED_KEEPALIVES;
+    if let Err(e) = stream.set_nonblocking(false).and_then(|()| stream.set_read_timeout(Some(timeout))) {
+        return format!("could not be read from ({})", e);
+    }
+    // Each message, keepalives included, brings the loop back round to check for Ctrl-C
+    while running.load(Ordering::SeqCst) {
+        let message = match read_message(&mut stream) {
+            Ok(message) => message,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return format!("sent nothing for {}s", timeout.as_secs());
+            }
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return "disconnected".to_string(),
+            Err(e) => return format!("dropped ({})", e),
+        };
+        debug!("Receive: {:?} from {}, sent at {}ms.", message.command, peer, message.t_ms);
+        let command = match message.command {
+            RemoteCommand::Click { key } => parse_key(&key).map(KeyCommand::Click).ok_or(key),
+            RemoteCommand::Down { key } => parse_key(&key).map(KeyCommand::Down).ok_or(key),
+            RemoteCommand::Up { key } => parse_key(&key).map(KeyCommand::Up).ok_or(key),
+            RemoteCommand::Type { text } => Ok(KeyCommand::Type(text)),
+            RemoteCommand::ReleaseAll => Ok(KeyCommand::ReleaseAll),
+            RemoteCommand::Keepalive => continue,
+        };
+        match command {
+            Ok(command) => output.send(command, None),
+            Err(key) => warn!("Receive: {} sent unknown key '{}'; skipped.", peer, key),
+        }
+    }
+    "was let go on shutdown".to_string()
+}
@@ -0,0 +1,88 @@
+//! `--auto-transpose`: moves the mapping to the singer's register, found from the first few
+//! seconds of singing.
+
+use crate::mapping::Mapping;
+use crate::notes::cents;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+/// How much singing `--auto-transpose` listens to before it settles on a transposition.
+pub const REGISTER_LISTEN: Duration = Duration::from_secs(3);
+/// Default for `--transpose-step`: whole octaves.
+pub const DEFAULT_TRANSPOSE_STEP: i32 = 12;
+/// Values `--transpose-step` accepts.
+pub const TRANSPOSE_STEP_RANGE: RangeInclusive<i32> = 1..=12;
+/// Values `--transpose` accepts, in semitones.
+pub const TRANSPOSE_RANGE: RangeInclusive<i32> = -48..=48;
+
+/// Collects the pitch of every window with one, gaps and all, until it has enough to name the
+/// singer's register.
+pub struct RegisterEstimator {
+    windows_needed: usize,
+    readings: Vec<f32>,
+}
+
+impl RegisterEstimator {
+    /// Decides after `windows_needed` windows with a pitch.
+    pub fn new(windows_needed: usize) -> Self {
+        RegisterEstimator { windows_needed: windows_needed.max(1), readings: Vec::new() }
+    }
+
+    /// Feed the pitch of one window. Returns the register, the median of the readings, once
+    /// there are enough, and `None` before that.
+    pub fn observe(&mut self, pitch: Option<f32>) -> Option<f32> {
+        self.readings.extend(pitch);
+        if self.readings.len() < self.windows_needed {
+            return None;
+        }
+        let mut sorted = std::mem::take(&mut self.readings);
+        sorted.sort_by(f32::total_cmp);
+        Some(sorted[sorted.len() / 2])
+    }
+}
+
+/// Semitones, a multiple of `step`, that move the middle of `mapping` closest to
+/// `register_hz`. Zero for a mapping without bands.
+pub fn transposition(mapping: &Mapping, register_hz: f32, step: i32) -> i32 {
+    let Some(center) = mapping.center_hz() else {
+        return 0;
+    };
+    let step = step.max(1);
+    let steps = (cents(register_hz, center) / 100.0 / step as f32).round() as i32;
+    steps * step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::Band;
+    use crate::notes::transpose;
+    use enigo::Key;
+
+    #[test]
+    fn the_median_of_the_voiced_windows_is_the_register() {
+        let mut estimator = RegisterEstimator::new(5);
+        // Silence doesn't count, and one cracked note doesn't move the median
+        for pitch in [Some(200.0), None, Some(210.0), Some(800.0), None] {
+            assert_eq!(estimator.observe(pitch), None);
+        }
+        assert_eq!(estimator.observe(Some(190.0)), None);
+        assert_eq!(estimator.observe(Some(205.0)), Some(205.0));
+    }
+
+    #[test]
+    fn the_mapping_moves_by_whole_steps_towards_the_register() {
+        // Centred on 200 Hz
+        let mapping = Mapping::new(vec![Band::new(100.0, 150.0, Key::Layout('a')), Band::new(300.0, 400.0, Key::Layout('z'))]);
+        // A bass an octave and a bit below
+        assert_eq!(transposition(&mapping, transpose(200.0, -14.0), 12), -12);
+        assert_eq!(transposition(&mapping, transpose(200.0, -14.0), 1), -14);
+        assert_eq!(transposition(&mapping, transpose(200.0, 5.0), 12), 0);
+        assert_eq!(transposition(&mapping, transpose(200.0, 7.0), 12), 12);
+        assert_eq!(transposition(&Mapping::new(Vec::new()), 440.0, 12), 0);
+
+        let moved = mapping.transposed_semitones(-12);
+        assert!((moved.bands()[0].low_hz - 50.0).abs() < 0.01);
+        assert!((moved.center_hz().unwrap() - 100.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::mapping::{Band, Mapping};
+use crate::notes::{cents, note_window, transpose};
+use enigo::Key;
+
+/// A mapping written as semitone offsets from a tonic ("do") that is sung at runtime, so the
+/// bands follow the singer's voice instead of fixed frequencies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelativeMapping {
+    /// `(semitones above the tonic, key)`, in priority order like [`Mapping`] bands.
+    pub steps: Vec<(f32, Key)>,
+    /// How far (in cents) from each step's exact pitch still counts as that step.
+    pub tolerance_cents: f32,
+}
+
+impl RelativeMapping {
+    /// Concrete bands for a tonic at `tonic_hz`.
+    pub fn at_tonic(&self, tonic_hz: f32) -> Mapping {
+        Mapping::new(
+            self.steps
+                .iter()
+                .map(|&(semitones, key)| {
+                    let (low_hz, high_hz) = note_window(transpose(tonic_hz, semitones), self.tolerance_cents);
+                    Band::new(low_hz, high_hz, key)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// How far (in cents) readings may wander while still counting as one sustained note.
+const STEADY_CENTS: f32 = 50.0;
+
+/// Waits for a steadily held note and reports its pitch, to calibrate a [`RelativeMapping`].
+pub struct TonicCalibrator {
+    windows_needed: usize,
+    readings: Vec<f32>,
+}
+
+impl TonicCalibrator {
+    /// The note must be held for `windows_needed` consecutive windows.
+    pub fn new(windows_needed: usize) -> Self {
+        TonicCalibrator { windows_needed: windows_needed.max(1), readings: Vec::new() }
+    }
+
+    /// Feed the pitch of one window. Returns the tonic (the median reading) once a note has
+    /// been held steadily for long enough. Silence or a jump to another note starts over.
+    pub fn observe(&mut self, pitch: Option<f32>) -> Option<f32> {
+        let Some(freq) = pitch else {
+            self.readings.clear();
+            return None;
+        };
+        if self.readings.first().is_some_and(|&first| cents(freq, first).abs() > STEADY_CENTS) {
+            self.readings.clear();
+        }
+        self.readings.push(freq);
+        if self.readings.len() < self.windows_needed {
+            return None;
+        }
+        let mut sorted = std::mem::take(&mut self.readings);
+        sorted.sort_by(f32::total_cmp);
+        Some(sorted[sorted.len() / 2])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_become_bands_around_each_offset_from_the_tonic() {
+        let relative = RelativeMapping {
+            steps: vec![(0.0, Key::Layout('z')), (12.0, Key::Layout('x'))],
+            tolerance_cents: 50.0,
+        };
+        let mapping = relative.at_tonic(220.0);
+        assert_eq!(mapping.key_for(220.0), Some(Key::Layout('z')));
+        assert_eq!(mapping.key_for(440.0), Some(Key::Layout('x')));
+        // A quarter tone off is the edge of the band
+        assert_eq!(mapping.key_for(transpose(220.0, 0.49)), Some(Key::Layout('z')));
+        assert_eq!(mapping.key_for(transpose(220.0, 0.51)), None);
+    }
+
+    #[test]
+    fn calibrator_waits_for_a_steady_note() {
+        let mut calibrator = TonicCalibrator::new(3);
+        assert_eq!(calibrator.observe(Some(200.0)), None);
+        // A jump of more than half a semitone starts over
+        assert_eq!(calibrator.observe(Some(300.0)), None);
+        assert_eq!(calibrator.observe(Some(301.0)), None);
+        assert_eq!(calibrator.observe(None), None);
+        assert_eq!(calibrator.observe(Some(249.0)), None);
+        assert_eq!(calibrator.observe(Some(251.0)), None);
+        assert_eq!(calibrator.observe(Some(250.0)), Some(250.0));
+    }
+}
@@ -0,0 +1,86 @@
+//! The wire format of `--remote` and `pitchu receive`: each message is a big-endian `u32`
+//! length followed by that many bytes of JSON, e.g.
+//! `{"t_ms":1520,"type":"down","key":"DownArrow"}`.
+
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+
+/// Largest message accepted; anything longer means the stream is not speaking this protocol.
+pub const MAX_MESSAGE_BYTES: u32 = 64 * 1024;
+
+/// What the receiving side should do. Keys are spelled as in config files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RemoteCommand {
+    Click { key: String },
+    Down { key: String },
+    Up { key: String },
+    Type { text: String },
+    ReleaseAll,
+    /// Sent when there is nothing else to send, so either side notices a dead link.
+    Keepalive,
+}
+
+/// A [`RemoteCommand`] and when the sender issued it, in milliseconds since its session
+/// started.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoteMessage {
+    pub t_ms: u64,
+    #[serde(flatten)]
+    pub command: RemoteCommand,
+}
+
+pub fn write_message(writer: &mut impl Write, message: &RemoteMessage) -> io::Result<()> {
+    let payload = serde_json::to_vec(message)?;
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+    // One write, so a message is never split across two Nagle-delayed segments
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+pub fn read_message(reader: &mut impl Read) -> io::Result<RemoteMessage> {
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length);
+    if length > MAX_MESSAGE_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("a {} byte message is over the {} byte limit", length, MAX_MESSAGE_BYTES),
+        ));
+    }
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload)?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            RemoteMessage { t_ms: 5, command: RemoteCommand::Down { key: "DownArrow".to_string() } },
+            RemoteMessage { t_ms: 9, command: RemoteCommand::Type { text: "gg".to_string() } },
+            RemoteMessage { t_ms: 1000, command: RemoteCommand::Keepalive },
+        ];
+        let mut stream = Vec::new();
+        for message in &messages {
+            write_message(&mut stream, message).unwrap();
+        }
+        assert_eq!(&stream[4..stream[3] as usize + 4], br#"{"t_ms":5,"type":"down","key":"DownArrow"}"#);
+        let mut reader = stream.as_slice();
+        for message in &messages {
+            assert_eq!(&read_message(&mut reader).unwrap(), message);
+        }
+        assert_eq!(read_message(&mut reader).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn oversized_messages_are_rejected() {
+        let mut reader: &[u8] = &[0xff, 0xff, 0xff, 0xff];
+        assert_eq!(read_message(&mut reader).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,76 @@
+use log::{info, warn};
+use pitchu::session::{KeyAction, Replay, Session, SessionRecord};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A replayed press this close to a recorded one of the same key counts as the same press
+const SAME_PRESS_MS: f64 = 100.0;
+
+/// `pitchu replay`: feeds the session's windows to `replay` and prints every key event and text
+/// it decides on, then the presses that differ from the recording. Paced like the recording
+/// unless `instant`.
+pub fn run(session: &Session, mut replay: Replay, instant: bool) {
+    let first_ms = session.records.first().map_or(0.0, SessionRecord::t_ms);
+    let started = Instant::now();
+    let mut replayed = Vec::new();
+    for record in &session.records {
+        if !instant {
+            let due = Duration::from_secs_f64((record.t_ms() - first_ms).max(0.0) / 1000.0);
+            thread::sleep(due.saturating_sub(started.elapsed()));
+        }
+        for decision in replay.feed(record) {
+            println!("{}", describe(&decision));
+            replayed.push(decision);
+        }
+    }
+
+    let recorded: Vec<&SessionRecord> = session.decisions().collect();
+    let recorded_presses = presses(recorded.iter().copied());
+    let replayed_presses = presses(replayed.iter());
+    info!("---");
+    info!(
+        "Replay: {} window(s); {} press(es) recorded, {} replayed.",
+        session.records.iter().filter(|record| matches!(record, SessionRecord::Window { .. })).count(),
+        recorded_presses.len(),
+        replayed_presses.len()
+    );
+    for press in unmatched(&recorded_presses, &replayed_presses) {
+        warn!("Replay: the recording pressed {}; the replay doesn't.", press_label(press));
+    }
+    for press in unmatched(&replayed_presses, &recorded_presses) {
+        warn!("Replay: the replay presses {}; the recording didn't.", press_label(press));
+    }
+}
+
+fn describe(record: &SessionRecord) -> String {
+    match record {
+        SessionRecord::Key { t_ms, channel, action, key } => {
+            format!("{:>9.1}ms  channel {}  {:?} '{}'", t_ms, channel, action, key)
+        }
+        SessionRecord::Text { t_ms, channel, text } => format!("{:>9.1}ms  channel {}  Type {:?}", t_ms, channel, text),
+        other => format!("{:?}", other),
+    }
+}
+
+fn press_label(record: &SessionRecord) -> String {
+    match record {
+        SessionRecord::Key { t_ms, channel, key, .. } => format!("'{}' at {:.1}ms (channel {})", key, t_ms, channel),
+        other => format!("{:?}", other),
+    }
+}
+
+fn presses<'a>(records: impl Iterator<Item = &'a SessionRecord>) -> Vec<&'a SessionRecord> {
+    records.filter(|record| matches!(record, SessionRecord::Key { action: KeyAction::Press | KeyAction::Down, .. })).collect()
+}
+
+// The presses in `ours` with no press of the same key and channel nearby in `theirs`
+fn unmatched<'a>(ours: &[&'a SessionRecord], theirs: &[&SessionRecord]) -> Vec<&'a SessionRecord> {
+    let same = |a: &SessionRecord, b: &SessionRecord| match (a, b) {
+        (
+            SessionRecord::Key { t_ms: t_a, channel: channel_a, key: key_a, .. },
+            SessionRecord::Key { t_ms: t_b, channel: channel_b, key: key_b, .. },
+        ) => channel_a == channel_b && key_a == key_b && (t_a - t_b).abs() <= SAME_PRESS_MS,
+        _ => false,
+    };
+    ours.iter().copied().filter(|&press| !theirs.iter().any(|&other| same(press, other))).collect()
+}
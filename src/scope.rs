@@ -0,0 +1,213 @@
+//! `--scope`: a panel in the terminal UI showing either the detected pitch over the last few
+//! seconds against the band edges, or a coarse spectrogram of the input. Both render to plain
+//! lines of text so the UI can draw them at whatever size the terminal allows.
+
+use crate::level::to_dbfs;
+use crate::mapping::Mapping;
+use clap::ValueEnum;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::time::Duration;
+
+/// How much pitch history the trace shows.
+pub const TRACE_SPAN: Duration = Duration::from_secs(10);
+/// Frequency bands of a spectrum, log-spaced between [`SPECTRUM_LOW_HZ`] and [`SPECTRUM_HIGH_HZ`].
+pub const SPECTRUM_BINS: usize = 48;
+pub const SPECTRUM_LOW_HZ: f32 = 50.0;
+pub const SPECTRUM_HIGH_HZ: f32 = 4000.0;
+
+// Spectrogram shading, quietest first, and the levels it spans
+const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+const SHADE_FLOOR_DB: f32 = -70.0;
+const SHADE_CEILING_DB: f32 = -10.0;
+// Room above and below the outermost band edges in the trace, in semitones
+const TRACE_MARGIN: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Scope {
+    /// Detected frequency over the last ten seconds, with band edges as horizontal lines.
+    Trace,
+    /// Scrolling spectrogram of the input.
+    Spectrum,
+}
+
+/// Level in dBFS of each of the [`SPECTRUM_BINS`] bands of `samples`, lowest first. Each bin is
+/// one DFT coefficient at the band's centre, computed with the Goertzel algorithm over a Hann
+/// window; the bands are coarse enough that one coefficient each is plenty.
+pub fn spectrum(samples: &[f32], sample_rate: usize) -> Vec<f32> {
+    let n = samples.len();
+    if n == 0 || sample_rate == 0 {
+        return vec![SHADE_FLOOR_DB; SPECTRUM_BINS];
+    }
+    let window: Vec<f32> = (0..n).map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos()).collect();
+    let window_sum: f32 = window.iter().sum();
+    let span = (SPECTRUM_HIGH_HZ / SPECTRUM_LOW_HZ).ln();
+    (0..SPECTRUM_BINS)
+        .map(|bin| {
+            let hz = SPECTRUM_LOW_HZ * (span * (bin as f32 + 0.5) / SPECTRUM_BINS as f32).exp();
+            let coefficient = 2.0 * (2.0 * PI * hz / sample_rate as f32).cos();
+            let (mut s1, mut s2) = (0.0f32, 0.0f32);
+            for (sample, weight) in samples.iter().zip(&window) {
+                let s0 = sample * weight + coefficient * s1 - s2;
+                s2 = s1;
+                s1 = s0;
+            }
+            let power = (s1 * s1 + s2 * s2 - coefficient * s1 * s2).max(0.0);
+            // Amplitude of a sine at this frequency
+            to_dbfs(2.0 * power.sqrt() / window_sum)
+        })
+        .collect()
+}
+
+/// Detected pitches of the last [`TRACE_SPAN`].
+#[derive(Default)]
+pub struct PitchTrace {
+    // When each window was analysed, and its frequency if it had a pitch
+    points: VecDeque<(Duration, Option<f32>)>,
+}
+
+impl PitchTrace {
+    pub fn push(&mut self, at: Duration, freq: Option<f32>) {
+        self.points.push_back((at, freq));
+        while self.points.front().is_some_and(|&(first, _)| at.saturating_sub(first) > TRACE_SPAN) {
+            self.points.pop_front();
+        }
+    }
+
+    /// `height` lines of `width` cells, the newest pitch at the right edge when it is `now`. The
+    /// vertical axis is logarithmic and spans the band edges of `mapping` with a little room.
+    /// `•` marks a pitch, `─` a band edge, and `^` or `v` a pitch above or below the view.
+    pub fn render(&self, mapping: &Mapping, now: Duration, width: usize, height: usize) -> Vec<String> {
+        let bands = mapping.bands();
+        let low = bands.iter().map(|band| band.low_hz).fold(f32::INFINITY, f32::min);
+        let high = bands.iter().map(|band| band.high_hz).fold(0.0, f32::max);
+        if width == 0 || height == 0 {
+            return Vec::new();
+        }
+        let (low, high) = if low < high { (low, high) } else { (SPECTRUM_LOW_HZ, SPECTRUM_HIGH_HZ) };
+        let margin = 2f32.powf(TRACE_MARGIN / 12.0);
+        let (bottom, top) = (low / margin, high * margin);
+        let span = (top / bottom).ln();
+        // Row 0 is the top
+        let row_of = |freq: f32| ((top / freq).ln() / span * height as f32) as usize;
+
+        let mut cells = vec![vec![' '; width]; height];
+        for band in bands {
+            for edge in [band.low_hz, band.high_hz] {
+                if edge > bottom && edge < top {
+                    cells[row_of(edge).min(height - 1)].fill('─');
+                }
+            }
+        }
+        let column_span = TRACE_SPAN.as_secs_f32() / width as f32;
+        for &(at, freq) in &self.points {
+            let Some(freq) = freq else {
+                continue;
+            };
+            let age = now.saturating_sub(at).as_secs_f32();
+            let Some(column) = (width - 1).checked_sub((age / column_span) as usize) else {
+                continue;
+            };
+            let (row, mark) = if freq >= top {
+                (0, '^')
+            } else if freq <= bottom {
+                (height - 1, 'v')
+            } else {
+                (row_of(freq).min(height - 1), '•')
+            };
+            cells[row][column] = mark;
+        }
+        cells.into_iter().map(String::from_iter).collect()
+    }
+}
+
+/// Recent spectra, newest last, as many as there are columns to show them in.
+#[derive(Default)]
+pub struct Spectrogram {
+    columns: VecDeque<Vec<f32>>,
+}
+
+impl Spectrogram {
+    /// Adds one [`spectrum`], keeping at most `limit`.
+    pub fn push(&mut self, spectrum: Vec<f32>, limit: usize) {
+        while self.columns.len() >= limit.max(1) {
+            self.columns.pop_front();
+        }
+        self.columns.push_back(spectrum);
+    }
+
+    /// `height` lines of `width` cells, highest frequencies on top and the newest spectrum in
+    /// the right-hand column, each cell shaded by the loudest bin it covers.
+    pub fn render(&self, width: usize, height: usize) -> Vec<String> {
+        if width == 0 || height == 0 {
+            return Vec::new();
+        }
+        let shown = self.columns.len().min(width);
+        let columns: Vec<&Vec<f32>> = self.columns.iter().skip(self.columns.len() - shown).collect();
+        (0..height)
+            .map(|row| {
+                // Bins covered by this row, counted from the top
+                let first = (height - 1 - row) * SPECTRUM_BINS / height;
+                let last = ((height - row) * SPECTRUM_BINS / height).max(first + 1);
+                let mut line: String = " ".repeat(width - shown);
+                line.extend(columns.iter().map(|spectrum| {
+                    let db = spectrum[first..last.min(spectrum.len())].iter().copied().fold(SHADE_FLOOR_DB, f32::max);
+                    shade(db)
+                }));
+                line
+            })
+            .collect()
+    }
+}
+
+fn shade(db: f32) -> char {
+    let level = ((db - SHADE_FLOOR_DB) / (SHADE_CEILING_DB - SHADE_FLOOR_DB)).clamp(0.0, 1.0);
+    SHADES[(level * (SHADES.len() - 1) as f32).round() as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::Band;
+    use enigo::Key;
+
+    #[test]
+    fn a_sine_shows_up_in_its_own_bin() {
+        let sample_rate = 48_000;
+        let span = (SPECTRUM_HIGH_HZ / SPECTRUM_LOW_HZ).ln();
+        let bin = 20;
+        let hz = SPECTRUM_LOW_HZ * (span * (bin as f32 + 0.5) / SPECTRUM_BINS as f32).exp();
+        let samples: Vec<f32> = (0..4096).map(|n| 0.5 * (2.0 * PI * hz * n as f32 / sample_rate as f32).sin()).collect();
+        let levels = spectrum(&samples, sample_rate);
+        assert_eq!(levels.len(), SPECTRUM_BINS);
+        // 0.5 full scale is about -6 dBFS
+        assert!((levels[bin] + 6.0).abs() < 0.5, "{}", levels[bin]);
+        assert!(levels[bin - 5] < -40.0 && levels[bin + 5] < -40.0);
+
+        let mut spectrogram = Spectrogram::default();
+        for _ in 0..3 {
+            spectrogram.push(levels.clone(), 2);
+        }
+        let lines = spectrogram.render(4, SPECTRUM_BINS);
+        assert_eq!(lines[SPECTRUM_BINS - 1 - bin], "  ██");
+        assert_eq!(lines[0], "    ");
+    }
+
+    #[test]
+    fn the_trace_scrolls_and_draws_band_edges() {
+        let mapping = Mapping::new(vec![Band::new(100.0, 200.0, Key::Layout('a'))]);
+        let mut trace = PitchTrace::default();
+        trace.push(Duration::from_secs(0), Some(150.0));
+        trace.push(Duration::from_secs(5), None);
+        trace.push(Duration::from_secs(11), Some(1000.0));
+        trace.push(Duration::from_secs(12), Some(141.0));
+        // The first pitch has scrolled away
+        assert_eq!(trace.points.len(), 3);
+
+        let lines = trace.render(&mapping, Duration::from_secs(12), 10, 9);
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], "        ^ ");
+        assert!(lines.iter().filter(|line| line.starts_with("──")).count() == 2);
+        assert!(lines.iter().any(|line| line.ends_with('•')));
+    }
+}
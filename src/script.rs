@@ -0,0 +1,188 @@
+use crate::error::{PitchuError, Result};
+use crate::keys::parse_key;
+use crate::notes::{cents, frequency_to_midi, midi_to_frequency, nearest_note};
+use enigo::Key;
+use log::{info, warn};
+use rhai::{Dynamic, Engine, Map, Scope, AST, FLOAT, INT};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Operations one call of `map` may run before it is cut off, so a runaway loop can't stall
+// the audio loop (a simple script needs a few hundred)
+const MAX_OPERATIONS: u64 = 50_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_STRING_SIZE: usize = 1024;
+const MAX_ARRAY_SIZE: usize = 1024;
+const MAX_MAP_SIZE: usize = 1024;
+
+/// Mapping logic written in Rhai, for rules a band table can't express.
+///
+/// The script defines `fn map(freq, clarity, rms, state)` and returns a key name (`"a"`,
+/// `"Return"`, ...) or `()` for no key. `state` is a map that persists between calls, so the
+/// script can remember earlier notes. Helpers in scope: `note_name(freq)` (e.g. `"A3"`),
+/// `midi(freq)`, `hz(midi)` and `cents(freq, reference)`; the note helpers use the `a4_hz`
+/// reference the script was loaded with.
+///
+/// Each call runs with an operation budget; a call that exceeds it, or fails, maps to no key.
+pub struct ScriptMapper {
+    path: PathBuf,
+    engine: Engine,
+    ast: AST,
+    state: Dynamic,
+    modified: Option<SystemTime>,
+    // The last runtime problem reported, so a failing script warns once rather than per window
+    last_problem: Option<String>,
+}
+
+impl ScriptMapper {
+    pub fn load(path: &Path, a4_hz: f32) -> Result<Self> {
+        let engine = sandboxed_engine(a4_hz);
+        let ast = compile(&engine, path)?;
+        info!("Script: mapping with {}.", path.display());
+        Ok(ScriptMapper {
+            path: path.to_path_buf(),
+            engine,
+            ast,
+            state: Dynamic::from_map(Map::new()).into_shared(),
+            modified: modified_time(path),
+            last_problem: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Recompiles the script if the file changed since it was loaded. A script that no longer
+    /// compiles is reported and the previous version stays in use. `state` is kept either way.
+    pub fn reload_if_changed(&mut self) {
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+        match compile(&self.engine, &self.path) {
+            Ok(ast) => {
+                info!("Script: reloaded {}.", self.path.display());
+                self.ast = ast;
+                self.last_problem = None;
+            }
+            Err(e) => warn!("Script: {}; keeping the previous version.", e),
+        }
+    }
+
+    /// Runs the script's `map` for one detection.
+    pub fn map(&mut self, freq: f32, clarity: f32, rms: f32) -> Option<Key> {
+        let args = (freq as FLOAT, clarity as FLOAT, rms as FLOAT, self.state.clone());
+        let result = self.engine.call_fn::<Dynamic>(&mut Scope::new(), &self.ast, "map", args);
+        let value = match result {
+            Ok(value) => value,
+            Err(e) => {
+                self.report(format!("map({:.1}, ...) failed: {}", freq, e));
+                return None;
+            }
+        };
+        if value.is_unit() {
+            return None;
+        }
+        let Some(name) = value.clone().into_immutable_string().ok() else {
+            self.report(format!("map returned {} ({}); expected a key name or ()", value, value.type_name()));
+            return None;
+        };
+        let key = parse_key(&name);
+        if key.is_none() {
+            self.report(format!("map returned unknown key '{}'", name));
+        }
+        key
+    }
+
+    fn report(&mut self, problem: String) {
+        if self.last_problem.as_ref() != Some(&problem) {
+            warn!("Script: {}", problem);
+            self.last_problem = Some(problem);
+        }
+    }
+}
+
+fn sandboxed_engine(a4_hz: f32) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_ARRAY_SIZE);
+    engine.set_max_map_size(MAX_MAP_SIZE);
+    engine.register_fn("note_name", move |freq: FLOAT| nearest_note(freq as f32, a4_hz).name());
+    engine.register_fn("midi", move |freq: FLOAT| frequency_to_midi(freq as f32, a4_hz) as FLOAT);
+    engine.register_fn("hz", move |midi: INT| midi_to_frequency(midi as f32, a4_hz) as FLOAT);
+    engine.register_fn("hz", move |midi: FLOAT| midi_to_frequency(midi as f32, a4_hz) as FLOAT);
+    engine.register_fn("cents", |freq: FLOAT, reference: FLOAT| cents(freq as f32, reference as f32) as FLOAT);
+    engine
+}
+
+fn compile(engine: &Engine, path: &Path) -> Result<AST> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| PitchuError::Script(format!("could not read {}: {}", path.display(), e)))?;
+    let ast = engine
+        .compile(&source)
+        .map_err(|e| PitchuError::Script(format!("{}: {}", path.display(), e)))?;
+    if !ast.iter_functions().any(|f| f.name == "map" && f.params.len() == 4) {
+        return Err(PitchuError::Script(format!(
+            "{} does not define fn map(freq, clarity, rms, state)",
+            path.display()
+        )));
+    }
+    Ok(ast)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::DEFAULT_A4_HZ;
+
+    fn script(name: &str, source: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("pitchu-{}-{}.rhai", name, std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        path
+    }
+
+    #[test]
+    fn state_persists_between_calls() {
+        // Start on the third ascending note in a row
+        let path = script(
+            "ascending",
+            r#"
+            fn map(freq, clarity, rms, state) {
+                let rising = freq > (state.last ?? 0.0);
+                state.last = freq;
+                state.run = if rising { (state.run ?? 0) + 1 } else { 1 };
+                if state.run >= 3 { "Return" } else if note_name(freq) == "A3" { "a" } else { () }
+            }
+            "#,
+        );
+        let mut mapper = ScriptMapper::load(&path, DEFAULT_A4_HZ).unwrap();
+        assert_eq!(mapper.map(220.0, 0.9, 0.1), Some(Key::Layout('a')));
+        assert_eq!(mapper.map(180.0, 0.9, 0.1), None);
+        assert_eq!(mapper.map(200.0, 0.9, 0.1), None);
+        assert_eq!(mapper.map(250.0, 0.9, 0.1), Some(Key::Return));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn runaway_scripts_are_cut_off() {
+        let path = script("runaway", "fn map(freq, clarity, rms, state) { loop {} }");
+        let mut mapper = ScriptMapper::load(&path, DEFAULT_A4_HZ).unwrap();
+        assert_eq!(mapper.map(220.0, 0.9, 0.1), None);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn scripts_without_map_are_rejected() {
+        let path = script("no-map", "fn other(x) { x }");
+        assert!(matches!(ScriptMapper::load(&path, DEFAULT_A4_HZ), Err(PitchuError::Script(_))));
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,108 @@
+//! `--play`: a timed sequence of key presses read from a file, played on the output without
+//! listening to the microphone, e.g. to see how a game reacts to exact timings or to reproduce
+//! an input bug.
+
+use crate::config::KeyConfig;
+use crate::error::PitchuError;
+use enigo::Key;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// One key press of a sequence: after `delay`, `key` goes down for `duration`, or is tapped
+/// when `duration` is zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub key: Key,
+    pub delay: Duration,
+    pub duration: Duration,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SequenceFile {
+    #[serde(default)]
+    step: Vec<StepConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StepConfig {
+    key: KeyConfig,
+    #[serde(default)]
+    duration_ms: u64,
+    #[serde(default)]
+    delay_ms: u64,
+}
+
+/// Reads a sequence file: `[[step]]` tables of `key`, `duration_ms` and `delay_ms`, played
+/// in order. Keys are written as in a band.
+pub fn parse_sequence(text: &str) -> Result<Vec<Step>, String> {
+    let file: SequenceFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    if file.step.is_empty() {
+        return Err("no [[step]] tables".to_string());
+    }
+    file.step
+        .iter()
+        .enumerate()
+        .map(|(index, step)| {
+            let key = step.key.to_key().map_err(|e| match e {
+                PitchuError::InvalidConfig(reason) => format!("step {}: {}", index + 1, reason),
+                other => format!("step {}: {}", index + 1, other),
+            })?;
+            Ok(Step {
+                key,
+                delay: Duration::from_millis(step.delay_ms),
+                duration: Duration::from_millis(step.duration_ms),
+            })
+        })
+        .collect()
+}
+
+/// How long the whole sequence takes to play.
+pub fn sequence_length(steps: &[Step]) -> Duration {
+    steps.iter().map(|step| step.delay + step.duration).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_are_read_in_order() {
+        let steps = parse_sequence(
+            r#"
+            [[step]]
+            key = "a"
+            duration_ms = 200
+
+            [[step]]
+            key = "Space"
+            delay_ms = 50
+
+            [[step]]
+            key = { scancode = 44 }
+            duration_ms = 30
+            delay_ms = 100
+            "#,
+        )
+        .unwrap();
+        assert_eq!(steps.len(), 3);
+        assert_eq!(
+            steps[0],
+            Step { key: Key::Layout('a'), delay: Duration::ZERO, duration: Duration::from_millis(200) }
+        );
+        assert_eq!(steps[1].key, Key::Space);
+        assert_eq!(steps[1].duration, Duration::ZERO);
+        assert_eq!(steps[2].delay, Duration::from_millis(100));
+        assert_eq!(sequence_length(&steps), Duration::from_millis(380));
+    }
+
+    #[test]
+    fn bad_sequences_say_which_step() {
+        let error = parse_sequence("[[step]]\nkey = \"a\"\n[[step]]\nkey = \"NotAKey\"\n").unwrap_err();
+        assert!(error.starts_with("step 2:"), "{}", error);
+        assert!(parse_sequence("[[step]]\nduration_ms = 10\n").is_err());
+        assert!(parse_sequence("[[step]]\nkey = \"a\"\nduraton_ms = 10\n").is_err());
+        assert!(parse_sequence("").is_err());
+    }
+}
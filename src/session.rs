@@ -0,0 +1,237 @@
+//! `--record-session` files and `pitchu replay`. A session is one JSON object per line: a
+//! header, then every analysis window as the detector saw it and every key event and text
+//! pitchu acted on, in order, e.g.
+//! `{"type":"window","t_ms":1520.3,"channel":0,"freq":247.3,"clarity":0.91,"power":3.2}`.
+
+use crate::clock::ManualClock;
+use crate::engine::{Measurement, PitchToKeyEngine};
+use crate::keys::{key_name, parse_key, KeyEvent};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Format version written in the header; files of another version are refused.
+pub const SESSION_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyAction {
+    Press,
+    Repeat,
+    Release,
+    Down,
+    Up,
+}
+
+/// One line of a session file. Times are milliseconds since the session started.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionRecord {
+    /// The first line.
+    Session { version: u32, sample_rate: usize, window_size: usize, channels: usize },
+    /// The detector's raw pitch, if it found one, and the window's power.
+    Window {
+        t_ms: f64,
+        channel: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        freq: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        clarity: Option<f32>,
+        power: f32,
+    },
+    Key { t_ms: f64, channel: usize, action: KeyAction, key: String },
+    Text { t_ms: f64, channel: usize, text: String },
+}
+
+impl SessionRecord {
+    pub fn window(t: Duration, channel: usize, measurement: Measurement) -> Self {
+        SessionRecord::Window {
+            t_ms: millis(t),
+            channel,
+            freq: measurement.detection.map(|(freq, _)| freq),
+            clarity: measurement.detection.map(|(_, clarity)| clarity),
+            power: measurement.power,
+        }
+    }
+
+    pub fn key(t: Duration, channel: usize, event: KeyEvent) -> Self {
+        let (action, key) = match event {
+            KeyEvent::Press(key) => (KeyAction::Press, key),
+            KeyEvent::Repeat(key) => (KeyAction::Repeat, key),
+            KeyEvent::Release(key) => (KeyAction::Release, key),
+            KeyEvent::Down(key) => (KeyAction::Down, key),
+            KeyEvent::Up(key) => (KeyAction::Up, key),
+        };
+        SessionRecord::Key { t_ms: millis(t), channel, action, key: key_name(key) }
+    }
+
+    pub fn text(t: Duration, channel: usize, text: String) -> Self {
+        SessionRecord::Text { t_ms: millis(t), channel, text }
+    }
+
+    pub fn t_ms(&self) -> f64 {
+        match self {
+            SessionRecord::Session { .. } => 0.0,
+            SessionRecord::Window { t_ms, .. } | SessionRecord::Key { t_ms, .. } | SessionRecord::Text { t_ms, .. } => {
+                *t_ms
+            }
+        }
+    }
+
+    /// The key event of a `Key` record.
+    pub fn key_event(&self) -> Option<KeyEvent> {
+        let SessionRecord::Key { action, key, .. } = self else {
+            return None;
+        };
+        let key = parse_key(key)?;
+        Some(match action {
+            KeyAction::Press => KeyEvent::Press(key),
+            KeyAction::Repeat => KeyEvent::Repeat(key),
+            KeyAction::Release => KeyEvent::Release(key),
+            KeyAction::Down => KeyEvent::Down(key),
+            KeyAction::Up => KeyEvent::Up(key),
+        })
+    }
+}
+
+// Tenths of a millisecond are plenty and keep the lines short
+fn millis(t: Duration) -> f64 {
+    (t.as_secs_f64() * 10_000.0).round() / 10.0
+}
+
+/// A session file, read back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub sample_rate: usize,
+    pub window_size: usize,
+    pub channels: usize,
+    /// Everything after the header.
+    pub records: Vec<SessionRecord>,
+}
+
+impl Session {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let header = lines.next().ok_or("the file is empty")?;
+        let (version, sample_rate, window_size, channels) = match serde_json::from_str(header.1) {
+            Ok(SessionRecord::Session { version, sample_rate, window_size, channels }) => {
+                (version, sample_rate, window_size, channels)
+            }
+            _ => return Err("line 1 is not a session header; was this file written by --record-session?".to_string()),
+        };
+        if version != SESSION_VERSION {
+            return Err(format!("version {} sessions can't be read, only version {}", version, SESSION_VERSION));
+        }
+        let records = lines
+            .map(|(index, line)| serde_json::from_str(line).map_err(|e| format!("line {}: {}", index + 1, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(Session { sample_rate, window_size, channels, records })
+    }
+
+    /// The recorded `Key` and `Text` records.
+    pub fn decisions(&self) -> impl Iterator<Item = &SessionRecord> {
+        self.records.iter().filter(|record| !matches!(record, SessionRecord::Window { .. }))
+    }
+}
+
+/// Runs recorded windows through engines of its own, one per channel, on a [`ManualClock`]
+/// that follows the recording's timestamps, so a replay decides the same way however fast it
+/// runs.
+pub struct Replay {
+    engines: Vec<PitchToKeyEngine<ManualClock>>,
+    clock: ManualClock,
+    now_ms: f64,
+}
+
+impl Replay {
+    /// `engines` must run on `clock`.
+    pub fn new(engines: Vec<PitchToKeyEngine<ManualClock>>, clock: ManualClock) -> Self {
+        Replay { engines, clock, now_ms: 0.0 }
+    }
+
+    /// Feeds a `Window` record to its channel's engine at its time and returns the `Key` and
+    /// `Text` records that result. Other records, and channels without an engine, give none.
+    pub fn feed(&mut self, record: &SessionRecord) -> Vec<SessionRecord> {
+        let &SessionRecord::Window { t_ms, channel, freq, clarity, power } = record else {
+            return Vec::new();
+        };
+        let Some(engine) = self.engines.get_mut(channel) else {
+            return Vec::new();
+        };
+        if t_ms > self.now_ms {
+            self.clock.advance(Duration::from_secs_f64((t_ms - self.now_ms) / 1000.0));
+            self.now_ms = t_ms;
+        }
+        let detection = freq.zip(clarity);
+        let outcome = engine.process_measured(Measurement { detection, power });
+        let t = Duration::from_secs_f64(t_ms / 1000.0);
+        let mut records: Vec<_> = outcome.events.into_iter().map(|event| SessionRecord::key(t, channel, event)).collect();
+        records.extend(outcome.text.map(|text| SessionRecord::text(t, channel, text)));
+        records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::mapping::{Band, Mapping};
+    use enigo::Key;
+
+    fn session(records: &[SessionRecord]) -> String {
+        let header = SessionRecord::Session { version: SESSION_VERSION, sample_rate: 48_000, window_size: 2048, channels: 1 };
+        std::iter::once(&header).chain(records).map(|record| serde_json::to_string(record).unwrap() + "\n").collect()
+    }
+
+    fn window(ms: u64, detection: Option<(f32, f32)>) -> SessionRecord {
+        SessionRecord::window(Duration::from_millis(ms), 0, Measurement { detection, power: 5.0 })
+    }
+
+    #[test]
+    fn sessions_round_trip() {
+        let records = [
+            window(0, None),
+            window(43, Some((247.3, 0.91))),
+            SessionRecord::key(Duration::from_micros(43_210), 0, KeyEvent::Press(Key::DownArrow)),
+            SessionRecord::text(Duration::from_millis(90), 0, "gg".to_string()),
+        ];
+        let text = session(&records);
+        assert!(text.contains(r#"{"type":"window","t_ms":43.0,"channel":0,"freq":247.3,"clarity":0.91,"power":5.0}"#));
+        let parsed = Session::parse(&text).unwrap();
+        assert_eq!((parsed.sample_rate, parsed.channels), (48_000, 1));
+        assert_eq!(parsed.records, records);
+        assert_eq!(parsed.records[2].key_event(), Some(KeyEvent::Press(Key::DownArrow)));
+        assert_eq!(parsed.decisions().count(), 2);
+
+        assert!(Session::parse("").is_err());
+        assert!(Session::parse(r#"{"type":"window","t_ms":0.0,"channel":0,"power":1.0}"#).is_err());
+    }
+
+    #[test]
+    fn replaying_with_a_stricter_press_clarity_drops_the_doubtful_press() {
+        // A confident note, silence, then a doubtful one
+        let windows: Vec<_> = (0..30)
+            .map(|index| {
+                let detection = match index {
+                    0..10 => Some((110.0, 0.95)),
+                    20..30 => Some((110.0, 0.6)),
+                    _ => None,
+                };
+                window(index * 46, detection)
+            })
+            .collect();
+        let presses = |min_press_clarity| {
+            let clock = ManualClock::new();
+            let config = EngineConfig { min_press_clarity, ..EngineConfig::default() };
+            let mapping = Mapping::new(vec![Band::new(100.0, 120.0, Key::Layout('a'))]);
+            let mut replay = Replay::new(vec![PitchToKeyEngine::new(config, mapping, clock.clone())], clock);
+            windows
+                .iter()
+                .flat_map(|record| replay.feed(record))
+                .filter(|record| matches!(record, SessionRecord::Key { action: KeyAction::Press, .. }))
+                .map(|record| record.t_ms())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(presses(0.0).len(), 2);
+        assert_eq!(presses(0.8), [presses(0.0)[0]]);
+    }
+}
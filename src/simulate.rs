@@ -0,0 +1,156 @@
+//! `--simulate`: drives the mapping and key logic from a CSV of pitches instead of audio, on a
+//! virtual clock taken from the CSV, so the same file always gives the same key events.
+
+use crate::clock::ManualClock;
+use crate::engine::PitchToKeyEngine;
+use crate::keys::{key_name, KeyEvent};
+use std::fmt;
+use std::time::Duration;
+
+/// One analysis window of a simulation: when it ends, and its pitch as `(frequency, clarity)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimulatedWindow {
+    pub t_ms: f64,
+    pub pitch: Option<(f32, f32)>,
+}
+
+/// Reads `time_ms,frequency,clarity` rows. An empty frequency is silence and a missing clarity
+/// counts as 1. Blank lines, `#` comments and a header row are skipped; times must not go
+/// backwards.
+pub fn parse_pitch_csv(text: &str) -> Result<Vec<SimulatedWindow>, String> {
+    let mut windows: Vec<SimulatedWindow> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let error = |what: &str| format!("line {}: {}", index + 1, what);
+        let Ok(t_ms) = fields[0].parse::<f64>() else {
+            if windows.is_empty() && index == 0 {
+                // A header
+                continue;
+            }
+            return Err(error(&format!("'{}' is not a time in milliseconds", fields[0])));
+        };
+        if fields.len() > 3 {
+            return Err(error("expected time_ms,frequency,clarity"));
+        }
+        if windows.last().is_some_and(|last| t_ms < last.t_ms) {
+            return Err(error("the time goes backwards"));
+        }
+        let number = |field: Option<&&str>, name: &str| -> Result<Option<f32>, String> {
+            match field.filter(|field| !field.is_empty()) {
+                Some(field) => field.parse().map(Some).map_err(|_| error(&format!("'{}' is not a {}", field, name))),
+                None => Ok(None),
+            }
+        };
+        let frequency = number(fields.get(1), "frequency")?;
+        let clarity = number(fields.get(2), "clarity")?.unwrap_or(1.0);
+        windows.push(SimulatedWindow { t_ms, pitch: frequency.map(|frequency| (frequency, clarity)) });
+    }
+    Ok(windows)
+}
+
+/// What a simulated window led to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimulatedAction {
+    Key(KeyEvent),
+    Text(String),
+}
+
+/// A [`SimulatedAction`] and the time of the window that caused it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatedEvent {
+    pub t_ms: f64,
+    pub action: SimulatedAction,
+}
+
+/// One CSV row: `time_ms,press,a`, `time_ms,repeat,a`, `time_ms,release,a` or
+/// `time_ms,type,<text>`.
+impl fmt::Display for SimulatedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (kind, value) = match &self.action {
+            SimulatedAction::Key(KeyEvent::Press(key)) => ("press", key_name(*key)),
+            SimulatedAction::Key(KeyEvent::Repeat(key)) => ("repeat", key_name(*key)),
+            SimulatedAction::Key(KeyEvent::Release(key)) => ("release", key_name(*key)),
+            SimulatedAction::Key(KeyEvent::Down(key)) => ("down", key_name(*key)),
+            SimulatedAction::Key(KeyEvent::Up(key)) => ("up", key_name(*key)),
+            SimulatedAction::Text(text) => ("type", text.clone()),
+        };
+        write!(f, "{},{},{}", self.t_ms, kind, csv_field(&value))
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Feeds `windows` to `engine`, moving `clock` (the engine's) to each window's time first.
+pub fn simulate(
+    windows: &[SimulatedWindow],
+    engine: &mut PitchToKeyEngine<ManualClock>,
+    clock: &ManualClock,
+) -> Vec<SimulatedEvent> {
+    let mut events = Vec::new();
+    let mut now_ms = windows.first().map_or(0.0, |window| window.t_ms);
+    for window in windows {
+        clock.advance(Duration::from_secs_f64((window.t_ms - now_ms) / 1000.0));
+        now_ms = window.t_ms;
+        let outcome = engine.process_detection(window.pitch);
+        let t_ms = window.t_ms;
+        events.extend(outcome.events.into_iter().map(|event| SimulatedEvent { t_ms, action: SimulatedAction::Key(event) }));
+        events.extend(outcome.text.map(|text| SimulatedEvent { t_ms, action: SimulatedAction::Text(text) }));
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::mapping::{Band, Mapping};
+    use enigo::Key;
+
+    #[test]
+    fn rows_parse_with_silence_and_a_header() {
+        let windows = parse_pitch_csv("time_ms,frequency,clarity\n0,110,0.9\n\n# a breath\n50,,\n100,110\n").unwrap();
+        assert_eq!(
+            windows,
+            [
+                SimulatedWindow { t_ms: 0.0, pitch: Some((110.0, 0.9)) },
+                SimulatedWindow { t_ms: 50.0, pitch: None },
+                SimulatedWindow { t_ms: 100.0, pitch: Some((110.0, 1.0)) },
+            ]
+        );
+        assert_eq!(parse_pitch_csv("0,110\n10,abc").unwrap_err(), "line 2: 'abc' is not a frequency");
+        assert_eq!(parse_pitch_csv("50,110\n10,110").unwrap_err(), "line 2: the time goes backwards");
+    }
+
+    #[test]
+    fn the_same_csv_always_gives_the_same_events() {
+        // A note held for a second from 1000 ms, sung every 46 ms
+        let csv: String = (0..40)
+            .map(|index| {
+                let t = 1000 + index * 46;
+                if index < 22 { format!("{},110,0.9\n", t) } else { format!("{},,\n", t) }
+            })
+            .collect();
+        let windows = parse_pitch_csv(&csv).unwrap();
+        let run = || {
+            let clock = ManualClock::new();
+            let mapping = Mapping::new(vec![Band::new(100.0, 120.0, Key::Layout('a'))]);
+            let mut engine = PitchToKeyEngine::new(EngineConfig::default(), mapping, clock.clone());
+            simulate(&windows, &mut engine, &clock).iter().map(ToString::to_string).collect::<Vec<_>>()
+        };
+        let events = run();
+        assert_eq!(events, run());
+        assert_eq!(events.first().map(String::as_str), Some("1092,press,a"));
+        assert_eq!(events.last().map(String::as_str), Some("2012,release,a"));
+        assert!(events.iter().filter(|event| event.contains("repeat")).count() >= 5);
+    }
+}
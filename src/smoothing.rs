@@ -0,0 +1,96 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Smooths the detected frequency across consecutive windows in which a pitch was found.
+pub trait Smoother: Send {
+    /// Feed the next detected frequency and get the smoothed value back.
+    fn smooth(&mut self, freq: f32) -> f32;
+    /// Forget all history; called when the pitch is lost.
+    fn reset(&mut self);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmoothingKind {
+    None,
+    Ema,
+    Median,
+}
+
+pub fn build_smoother(kind: SmoothingKind, ema_alpha: f32, median_window: usize) -> Box<dyn Smoother> {
+    match kind {
+        SmoothingKind::None => Box::new(NoSmoothing),
+        SmoothingKind::Ema => Box::new(EmaSmoother::new(ema_alpha)),
+        SmoothingKind::Median => Box::new(MedianSmoother::new(median_window)),
+    }
+}
+
+pub struct NoSmoothing;
+
+impl Smoother for NoSmoothing {
+    fn smooth(&mut self, freq: f32) -> f32 {
+        freq
+    }
+
+    fn reset(&mut self) {}
+}
+
+/// Exponential moving average. Reacts within a window or two, while still damping single-frame errors.
+pub struct EmaSmoother {
+    alpha: f32,
+    value: Option<f32>,
+}
+
+impl EmaSmoother {
+    /// `alpha` is the weight of the newest reading: 1.0 disables smoothing, smaller is smoother.
+    pub fn new(alpha: f32) -> Self {
+        EmaSmoother { alpha: alpha.clamp(0.0, 1.0), value: None }
+    }
+}
+
+impl Smoother for EmaSmoother {
+    fn smooth(&mut self, freq: f32) -> f32 {
+        let value = match self.value {
+            Some(previous) => previous + self.alpha * (freq - previous),
+            None => freq,
+        };
+        self.value = Some(value);
+        value
+    }
+
+    fn reset(&mut self) {
+        self.value = None;
+    }
+}
+
+/// Median of the last `window` readings. Rejects outliers outright, at the cost of
+/// lagging by about half the window.
+pub struct MedianSmoother {
+    window: usize,
+    history: VecDeque<f32>,
+}
+
+impl MedianSmoother {
+    pub fn new(window: usize) -> Self {
+        let window = window.max(1);
+        MedianSmoother { window, history: VecDeque::with_capacity(window) }
+    }
+}
+
+impl Smoother for MedianSmoother {
+    fn smooth(&mut self, freq: f32) -> f32 {
+        if self.history.len() == self.window {
+            self.history.pop_front();
+        }
+        self.history.push_back(freq);
+
+        let mut sorted: Vec<f32> = self.history.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+        sorted[sorted.len() / 2]
+    }
+
+    fn reset(&mut self) {
+        self.history.clear();
+    }
+}
@@ -0,0 +1,125 @@
+//! What a session did, counted window by window as it runs and printed when it ends, to see how
+//! a config behaves: which keys were pressed, how long each band was sung, how clear the pitch
+//! was and how much of the time the gate kept the input out.
+
+use crate::keys::{key_name, KeyEvent};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// The counters of one engine's session.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SessionStats {
+    windows: u64,
+    gated: u64,
+    detections: u64,
+    clarity_sum: f64,
+    // By key name: (presses, repeats)
+    presses: BTreeMap<String, (u64, u64)>,
+    // By band label
+    band_time: BTreeMap<String, Duration>,
+}
+
+impl SessionStats {
+    pub fn new() -> Self {
+        SessionStats::default()
+    }
+
+    /// Counts one window of `length`: whether the gate kept it out, its pitch, the label of the
+    /// band the pitch was in, and the key events that came of it.
+    pub fn record_window(
+        &mut self,
+        length: Duration,
+        gated: bool,
+        pitch: Option<(f32, f32)>,
+        band: Option<String>,
+        events: &[KeyEvent],
+    ) {
+        self.windows += 1;
+        self.gated += gated as u64;
+        if let Some((_, clarity)) = pitch {
+            self.detections += 1;
+            self.clarity_sum += clarity as f64;
+        }
+        if let Some(band) = band {
+            *self.band_time.entry(band).or_default() += length;
+        }
+        for event in events {
+            match *event {
+                KeyEvent::Press(key) | KeyEvent::Down(key) => self.presses.entry(key_name(key)).or_default().0 += 1,
+                KeyEvent::Repeat(key) => self.presses.entry(key_name(key)).or_default().1 += 1,
+                KeyEvent::Release(_) | KeyEvent::Up(_) => {}
+            }
+        }
+    }
+
+    pub fn windows(&self) -> u64 {
+        self.windows
+    }
+
+    /// Average clarity of the windows with a pitch; `None` without any.
+    pub fn mean_clarity(&self) -> Option<f32> {
+        (self.detections > 0).then(|| (self.clarity_sum / self.detections as f64) as f32)
+    }
+
+    /// Presses (and repeats) of `key`, by its name.
+    pub fn presses(&self, key: &str) -> (u64, u64) {
+        self.presses.get(key).copied().unwrap_or_default()
+    }
+
+    /// How long pitches were in the band labelled `band`.
+    pub fn band_time(&self, band: &str) -> Duration {
+        self.band_time.get(band).copied().unwrap_or_default()
+    }
+
+    /// A text summary of everything counted.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let share = |count: u64| if self.windows == 0 { 0.0 } else { count as f32 * 100.0 / self.windows as f32 };
+        let _ = writeln!(
+            out,
+            "Session: {} windows, {} gated ({:.0}%), {} with a pitch ({:.0}%).",
+            self.windows,
+            self.gated,
+            share(self.gated),
+            self.detections,
+            share(self.detections)
+        );
+        if let Some(clarity) = self.mean_clarity() {
+            let _ = writeln!(out, "  Average clarity: {:.2}", clarity);
+        }
+        for (key, (presses, repeats)) in &self.presses {
+            let _ = writeln!(out, "  Key '{}': presses {}, repeats {}", key, presses, repeats);
+        }
+        for (band, time) in &self.band_time {
+            let _ = writeln!(out, "  Band '{}': sung for {:.1}s", band, time.as_secs_f32());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enigo::Key;
+
+    #[test]
+    fn windows_add_up_to_presses_band_time_and_clarity() {
+        let window = Duration::from_millis(50);
+        let a = Key::Layout('a');
+        let mut stats = SessionStats::new();
+        assert_eq!(stats.mean_clarity(), None);
+        stats.record_window(window, true, None, None, &[]);
+        stats.record_window(window, false, Some((220.0, 0.8)), Some("a".to_string()), &[KeyEvent::Press(a)]);
+        stats.record_window(window, false, Some((221.0, 0.9)), Some("a".to_string()), &[KeyEvent::Repeat(a)]);
+        stats.record_window(window, false, Some((80.0, 1.0)), Some("ignore".to_string()), &[KeyEvent::Release(a)]);
+        assert_eq!(stats.windows(), 4);
+        assert!((stats.mean_clarity().unwrap() - 0.9).abs() < 1e-6);
+        assert_eq!(stats.presses("a"), (1, 1));
+        assert_eq!(stats.presses("b"), (0, 0));
+        assert_eq!(stats.band_time("a"), Duration::from_millis(100));
+        let report = stats.report();
+        assert!(report.starts_with("Session: 4 windows, 1 gated (25%), 3 with a pitch (75%)."), "{}", report);
+        assert!(report.contains("Key 'a': presses 1, repeats 1"), "{}", report);
+    }
+}
@@ -0,0 +1,103 @@
+//! `pitchu test-mapping`: a mapping checked pitch by pitch without singing. Each pitch goes
+//! through the same band lookup the engine uses, so a table shows what would be pressed.
+
+use crate::mapping::Mapping;
+use crate::notes::{frequency_to_midi, midi_to_frequency};
+
+/// What one pitch comes to under a mapping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepPoint {
+    pub freq: f32,
+    /// The band the pitch maps to, by index in the mapping's bands.
+    pub band: Option<usize>,
+    /// Other key and text bands that also contain the pitch and lose to `band`.
+    pub shadowed: Vec<usize>,
+    /// Between two bands rather than beyond all of them.
+    pub in_gap: bool,
+}
+
+/// Looks `freq` up in `mapping`.
+pub fn resolve(mapping: &Mapping, freq: f32) -> SweepPoint {
+    let band = mapping.band_index_for(freq);
+    // Behind an ignore band, nothing sounds either way
+    let shadowed = match band {
+        Some(chosen) if !mapping.bands()[chosen].is_ignore() => (0..mapping.bands().len())
+            .filter(|&index| index != chosen)
+            .filter(|&index| !mapping.bands()[index].is_ignore() && mapping.bands()[index].contains(freq))
+            .collect(),
+        _ => Vec::new(),
+    };
+    SweepPoint { freq, band, shadowed, in_gap: mapping.in_gap(freq) }
+}
+
+/// Every semitone of `a4_hz`'s tuning from `low_hz` to `high_hz`.
+pub fn semitones(low_hz: f32, high_hz: f32, a4_hz: f32) -> Vec<f32> {
+    let low = frequency_to_midi(low_hz, a4_hz).ceil() as i32;
+    let high = frequency_to_midi(high_hz, a4_hz).floor() as i32;
+    (low..=high).map(|midi| midi_to_frequency(midi as f32, a4_hz)).collect()
+}
+
+/// From `low_hz` to `high_hz` every `step_hz`.
+pub fn steps(low_hz: f32, high_hz: f32, step_hz: f32) -> Vec<f32> {
+    let count = ((high_hz - low_hz) / step_hz).floor() as usize;
+    (0..=count).map(|index| low_hz + step_hz * index as f32).collect()
+}
+
+/// The ranges between bands that no band covers, lowest first.
+pub fn gaps(mapping: &Mapping) -> Vec<(f32, f32)> {
+    let mut ranges: Vec<(f32, f32)> = mapping.bands().iter().map(|band| (band.low_hz, band.high_hz)).collect();
+    ranges.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut gaps = Vec::new();
+    let mut covered_to = match ranges.first() {
+        Some(&(_, high)) => high,
+        None => return gaps,
+    };
+    for (low, high) in ranges {
+        if low > covered_to {
+            gaps.push((covered_to, low));
+        }
+        covered_to = covered_to.max(high);
+    }
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::Band;
+    use enigo::Key;
+
+    fn mapping() -> Mapping {
+        Mapping::new(vec![
+            Band::new(100.0, 150.0, Key::Layout('a')),
+            Band::new(140.0, 160.0, Key::Layout('b')),
+            Band::ignore(120.0, 130.0),
+            Band::new(200.0, 250.0, Key::Layout('c')),
+        ])
+    }
+
+    #[test]
+    fn pitches_resolve_as_the_engine_maps_them() {
+        let mapping = mapping();
+        assert_eq!(resolve(&mapping, 110.0), SweepPoint { freq: 110.0, band: Some(0), shadowed: vec![], in_gap: false });
+        // The earlier band wins the overlap; the later one is flagged
+        assert_eq!(resolve(&mapping, 145.0).band, Some(0));
+        assert_eq!(resolve(&mapping, 145.0).shadowed, vec![1]);
+        assert_eq!(resolve(&mapping, 125.0).band, Some(2));
+        assert!(resolve(&mapping, 125.0).shadowed.is_empty());
+        assert!(resolve(&mapping, 180.0).in_gap);
+        // Beyond every band is not a gap
+        assert_eq!(resolve(&mapping, 300.0), SweepPoint { freq: 300.0, band: None, shadowed: vec![], in_gap: false });
+    }
+
+    #[test]
+    fn sweeps_and_gaps_cover_the_range() {
+        assert_eq!(gaps(&mapping()), vec![(160.0, 200.0)]);
+        assert!(gaps(&Mapping::new(Vec::new())).is_empty());
+        // A2 to A3: 13 semitones, both ends included
+        let notes = semitones(110.0, 220.0, 440.0);
+        assert_eq!(notes.len(), 13);
+        assert!((notes[0] - 110.0).abs() < 0.01 && (notes[12] - 220.0).abs() < 0.01);
+        assert_eq!(steps(100.0, 130.0, 10.0), vec![100.0, 110.0, 120.0, 130.0]);
+    }
+}
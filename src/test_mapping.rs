@@ -0,0 +1,90 @@
+use pitchu::mapping::Mapping;
+use pitchu::notes::{midi_to_frequency, nearest_note, parse_note_name};
+use pitchu::sweep::{gaps, resolve, semitones, steps, SweepPoint};
+use std::io::BufRead;
+
+/// `pitchu test-mapping`: prints what every semitone (or every `step_hz`) across each mapping
+/// maps to, then the gaps and overlaps between its bands. With `interactive`, then looks up
+/// each note name or frequency read from stdin in the first mapping.
+pub fn run(mappings: &[Mapping], step_hz: Option<f32>, interactive: bool, a4_hz: f32) {
+    for (index, mapping) in mappings.iter().enumerate() {
+        if mappings.len() > 1 {
+            println!("Channel {}:", index);
+        }
+        print_table(mapping, step_hz, a4_hz);
+    }
+    if !interactive {
+        return;
+    }
+    let Some(mapping) = mappings.first() else {
+        return;
+    };
+    println!("Enter a note name (A3, F#4, Bb2) or a frequency in Hz; an empty line quits.");
+    for line in std::io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let entry = line.trim();
+        if entry.is_empty() {
+            break;
+        }
+        match parse_pitch(entry, a4_hz) {
+            Some(freq) => println!("{}", describe(mapping, &resolve(mapping, freq), a4_hz)),
+            None => println!("'{}' is not a note name or a frequency.", entry),
+        }
+    }
+}
+
+fn print_table(mapping: &Mapping, step_hz: Option<f32>, a4_hz: f32) {
+    let Some((low, high)) = mapping.range_hz() else {
+        println!("No bands.");
+        return;
+    };
+    let points = match step_hz {
+        Some(step) => steps(low, high, step),
+        None => semitones(low, high, a4_hz),
+    };
+    for freq in points {
+        println!("{}", describe(mapping, &resolve(mapping, freq), a4_hz));
+    }
+    let bands = mapping.bands();
+    let band = |index: usize| {
+        let band = &bands[index];
+        format!("'{}' ({:.1}-{:.1} Hz)", band.label(), band.low_hz, band.high_hz)
+    };
+    for (low, high) in gaps(mapping) {
+        println!("Gap: {:.1}-{:.1} Hz maps to no band.", low, high);
+    }
+    for (first, second) in mapping.overlapping_bands() {
+        if !bands[first].is_ignore() && !bands[second].is_ignore() {
+            println!("Overlap: {} and {}; where both apply, the first listed wins.", band(first), band(second));
+        }
+    }
+}
+
+// "220.0 Hz  A3  +0c  -> 'a'", with why nothing is pressed when it isn't
+fn describe(mapping: &Mapping, point: &SweepPoint, a4_hz: f32) -> String {
+    let note = nearest_note(point.freq, a4_hz);
+    let result = match point.band {
+        Some(index) => {
+            let band = &mapping.bands()[index];
+            let mut result = if band.is_ignore() { "ignored".to_string() } else { format!("'{}'", band.label()) };
+            for shadowed in &point.shadowed {
+                result.push_str(&format!(" (over '{}')", mapping.bands()[*shadowed].label()));
+            }
+            result
+        }
+        None if point.in_gap => "nothing (gap)".to_string(),
+        None => "nothing (outside every band)".to_string(),
+    };
+    format!("{:8.1} Hz  {:<3} {:+3.0}c  -> {}", point.freq, note.name(), note.cents, result)
+}
+
+// A note name such as A3, or a frequency with or without "Hz"
+fn parse_pitch(entry: &str, a4_hz: f32) -> Option<f32> {
+    if let Some(midi) = parse_note_name(entry) {
+        return Some(midi_to_frequency(midi as f32, a4_hz));
+    }
+    let number = entry.trim_end_matches(|c: char| c.is_ascii_alphabetic()).trim();
+    number.parse::<f32>().ok().filter(|freq| *freq > 0.0)
+}
@@ -0,0 +1,370 @@
+use pitchu::keys::{key_name, KeyEvent};
+use pitchu::level::to_dbfs;
+use pitchu::mapping::Mapping;
+use pitchu::notes::nearest_note;
+use pitchu::scope::{self, PitchTrace, Scope, Spectrogram, SPECTRUM_HIGH_HZ, SPECTRUM_LOW_HZ, TRACE_SPAN};
+use pitchu::tuner;
+use enigo::Key;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::widgets::{Block, Borders, Gauge, List, ListItem, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Events buffered for the UI thread; beyond this the processing loop drops them rather than wait
+const EVENT_QUEUE_DEPTH: usize = 1024;
+// Audio windows buffered for the spectrogram; kept short since each one is a whole window
+const AUDIO_QUEUE_DEPTH: usize = 8;
+// Redraw interval
+const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+const KEY_EVENT_HISTORY: usize = 12;
+const LOG_HISTORY: usize = 200;
+// Level meter range
+const METER_FLOOR_DB: f32 = -60.0;
+// Rows the fixed panels need, and the least the scope is drawn in; below that it is left out
+const FIXED_ROWS: u16 = 18;
+const MIN_SCOPE_ROWS: u16 = 6;
+const MIN_SCOPE_COLUMNS: u16 = 20;
+// Spectra kept for the spectrogram: more than any terminal is wide
+const SPECTROGRAM_HISTORY: usize = 500;
+
+/// What the processing loop reports to the UI.
+pub enum UiEvent {
+    /// One analysed window: detected `(frequency, clarity)` if any, and its RMS and peak levels.
+    Window { pitch: Option<(f32, f32)>, rms: f32, peak: f32 },
+    Key(KeyEvent),
+    Log(String),
+}
+
+/// Handle to the terminal UI thread. Dropping it stops the thread and restores the terminal.
+pub struct Tui {
+    sender: SyncSender<UiEvent>,
+    // Only when the spectrogram is shown
+    audio: Option<SyncSender<(Vec<f32>, usize)>>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Tui {
+    /// Takes over the terminal and starts drawing, naming notes relative to `a4_hz`, with a
+    /// `--scope` panel if `scope` is set. Pressing `q` or Ctrl-C clears `running`.
+    pub fn start(running: Arc<AtomicBool>, mapping: Mapping, a4_hz: f32, scope: Option<Scope>) -> io::Result<Self> {
+        // Installs a panic hook that restores the terminal before the panic message is printed
+        let terminal = ratatui::try_init()?;
+        let (sender, receiver) = mpsc::sync_channel(EVENT_QUEUE_DEPTH);
+        let (audio, audio_receiver) = match scope {
+            Some(Scope::Spectrum) => {
+                let (audio, audio_receiver) = mpsc::sync_channel(AUDIO_QUEUE_DEPTH);
+                (Some(audio), Some(audio_receiver))
+            }
+            _ => (None, None),
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_thread = Arc::clone(&stop);
+        let handle = thread::spawn(move || {
+            let state = UiState::new(mapping, a4_hz, scope);
+            if let Err(e) = ui_loop(terminal, receiver, audio_receiver, state, &stop_thread, &running) {
+                running.store(false, Ordering::SeqCst);
+                ratatui::restore();
+                eprintln!("TUI error: {}", e);
+            }
+        });
+        Ok(Tui { sender, audio, stop, handle: Some(handle) })
+    }
+
+    /// Never blocks: if the UI falls behind, events are dropped.
+    pub fn send(&self, event: UiEvent) {
+        let _ = self.sender.try_send(event);
+    }
+
+    /// Hands an analysed window to the spectrogram, if one is shown. Never blocks; the spectrum
+    /// itself is worked out on the UI thread.
+    pub fn send_audio(&self, window: &[f32], sample_rate: usize) {
+        if let Some(audio) = &self.audio {
+            let _ = audio.try_send((window.to_vec(), sample_rate));
+        }
+    }
+
+    /// A writer for `env_logger` that shows log lines in the UI instead of scribbling over it.
+    pub fn log_writer(&self) -> TuiLogWriter {
+        TuiLogWriter {
+            sender: self.sender.clone(),
+            stopped: Arc::clone(&self.stop),
+            line: Vec::new(),
+        }
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        ratatui::restore();
+    }
+}
+
+/// Once the UI has shut down, log lines go to stderr again.
+pub struct TuiLogWriter {
+    sender: SyncSender<UiEvent>,
+    stopped: Arc<AtomicBool>,
+    line: Vec<u8>,
+}
+
+impl Write for TuiLogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if byte == b'\n' {
+                if self.stopped.load(Ordering::SeqCst) {
+                    self.line.push(b'\n');
+                    io::stderr().write_all(&self.line)?;
+                } else {
+                    let line = String::from_utf8_lossy(&self.line).into_owned();
+                    let _ = self.sender.try_send(UiEvent::Log(line));
+                }
+                self.line.clear();
+            } else {
+                self.line.push(byte);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct UiState {
+    mapping: Mapping,
+    a4_hz: f32,
+    started: Instant,
+    pitch: Option<(f32, f32)>,
+    rms: f32,
+    peak: f32,
+    active: Option<(Key, Instant)>,
+    key_events: VecDeque<(Duration, KeyEvent)>,
+    logs: VecDeque<String>,
+    scope: Option<Scope>,
+    trace: PitchTrace,
+    spectrogram: Spectrogram,
+}
+
+impl UiState {
+    fn new(mapping: Mapping, a4_hz: f32, scope: Option<Scope>) -> Self {
+        UiState {
+            mapping,
+            a4_hz,
+            started: Instant::now(),
+            pitch: None,
+            rms: 0.0,
+            peak: 0.0,
+            active: None,
+            key_events: VecDeque::new(),
+            logs: VecDeque::new(),
+            scope,
+            trace: PitchTrace::default(),
+            spectrogram: Spectrogram::default(),
+        }
+    }
+
+    fn apply(&mut self, event: UiEvent) {
+        match event {
+            UiEvent::Window { pitch, rms, peak } => {
+                self.pitch = pitch;
+                self.rms = rms;
+                self.peak = peak;
+                if self.scope == Some(Scope::Trace) {
+                    self.trace.push(self.started.elapsed(), pitch.map(|(freq, _)| freq));
+                }
+            }
+            UiEvent::Key(key_event) => {
+                match key_event {
+                    KeyEvent::Press(key) | KeyEvent::Down(key) => self.active = Some((key, Instant::now())),
+                    KeyEvent::Repeat(_) => {}
+                    KeyEvent::Release(_) | KeyEvent::Up(_) => self.active = None,
+                }
+                push_bounded(&mut self.key_events, (self.started.elapsed(), key_event), KEY_EVENT_HISTORY);
+            }
+            UiEvent::Log(line) => push_bounded(&mut self.logs, line, LOG_HISTORY),
+        }
+    }
+}
+
+fn push_bounded<T>(queue: &mut VecDeque<T>, item: T, limit: usize) {
+    if queue.len() == limit {
+        queue.pop_front();
+    }
+    queue.push_back(item);
+}
+
+fn ui_loop(
+    mut terminal: DefaultTerminal,
+    receiver: Receiver<UiEvent>,
+    audio: Option<Receiver<(Vec<f32>, usize)>>,
+    mut state: UiState,
+    stop: &AtomicBool,
+    running: &AtomicBool,
+) -> io::Result<()> {
+    while !stop.load(Ordering::SeqCst) {
+        match receiver.recv_timeout(FRAME_INTERVAL) {
+            Ok(event) => {
+                state.apply(event);
+                while let Ok(event) = receiver.try_recv() {
+                    state.apply(event);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if let Some(audio) = &audio {
+            while let Ok((window, sample_rate)) = audio.try_recv() {
+                state.spectrogram.push(scope::spectrum(&window, sample_rate), SPECTROGRAM_HISTORY);
+            }
+        }
+
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.kind == KeyEventKind::Press && (key.code == KeyCode::Char('q') || ctrl_c) {
+                    running.store(false, Ordering::SeqCst);
+                }
+            }
+        }
+
+        terminal.draw(|frame| draw(frame, &state))?;
+    }
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, state: &UiState) {
+    let area = frame.area();
+    // On a small terminal the scope gives way to the panels that were there before it
+    let scope = state
+        .scope
+        .filter(|_| area.height >= FIXED_ROWS + MIN_SCOPE_ROWS + 2 && area.width >= MIN_SCOPE_COLUMNS);
+    let mut constraints = vec![
+        Constraint::Length(4),
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Min(5),
+    ];
+    if scope.is_some() {
+        constraints[4] = Constraint::Fill(1);
+        constraints.insert(4, Constraint::Fill(1));
+    }
+    let mut rows = Layout::default().direction(Direction::Vertical).constraints(constraints).split(area).to_vec();
+    if let Some(scope) = scope {
+        draw_scope(frame, state, scope, rows.remove(4));
+    }
+
+    let pitch_text = match state.pitch {
+        Some((freq, _)) => {
+            let note = nearest_note(freq, state.a4_hz);
+            let reading = tuner::read(&state.mapping, freq).map(|r| r.to_string()).unwrap_or_default();
+            format!("{:8.2} Hz   {} {:+.0}c\n{}", freq, note.name(), note.cents, reading)
+        }
+        None => "      -- Hz   (no pitch)".to_string(),
+    };
+    frame.render_widget(
+        Paragraph::new(pitch_text).block(Block::default().borders(Borders::ALL).title(" Pitch (q to quit) ")),
+        rows[0],
+    );
+
+    let clarity = state.pitch.map_or(0.0, |(_, clarity)| clarity.clamp(0.0, 1.0));
+    frame.render_widget(
+        Gauge::default()
+            .block(Block::default().borders(Borders::ALL).title(" Clarity "))
+            .gauge_style(Style::default().fg(Color::Cyan))
+            .ratio(clarity as f64)
+            .label(format!("{:.2}", clarity)),
+        rows[1],
+    );
+
+    let db = to_dbfs(state.rms).max(METER_FLOOR_DB);
+    let peak_db = to_dbfs(state.peak).max(METER_FLOOR_DB);
+    let level = ((db - METER_FLOOR_DB) / -METER_FLOOR_DB).clamp(0.0, 1.0);
+    let level_color = if peak_db > -1.0 { Color::Red } else { Color::Green };
+    frame.render_widget(
+        Gauge::default()
+            .block(Block::default().borders(Borders::ALL).title(" Input level "))
+            .gauge_style(Style::default().fg(level_color))
+            .ratio(level as f64)
+            .label(format!("RMS {:.1} dBFS, peak {:.1} dBFS", db, peak_db)),
+        rows[2],
+    );
+
+    let active_text = match state.active {
+        Some((key, since)) => format!("{}  (held {:.1}s)", key_name(key), since.elapsed().as_secs_f32()),
+        None => "-".to_string(),
+    };
+    frame.render_widget(
+        Paragraph::new(active_text).block(Block::default().borders(Borders::ALL).title(" Active key ")),
+        rows[3],
+    );
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(35), Constraint::Percentage(65)])
+        .split(rows[4]);
+
+    let key_items: Vec<ListItem> = state
+        .key_events
+        .iter()
+        .rev()
+        .map(|(at, key_event)| {
+            let (label, key) = match key_event {
+                KeyEvent::Press(key) => ("press  ", key),
+                KeyEvent::Repeat(key) => ("repeat ", key),
+                KeyEvent::Release(key) => ("release", key),
+                KeyEvent::Down(key) => ("down   ", key),
+                KeyEvent::Up(key) => ("up     ", key),
+            };
+            ListItem::new(format!("{:8.2}s {} {}", at.as_secs_f32(), label, key_name(*key)))
+        })
+        .collect();
+    frame.render_widget(
+        List::new(key_items).block(Block::default().borders(Borders::ALL).title(" Key events ")),
+        columns[0],
+    );
+
+    let log_height = columns[1].height.saturating_sub(2) as usize;
+    let log_items: Vec<ListItem> = state
+        .logs
+        .iter()
+        .skip(state.logs.len().saturating_sub(log_height))
+        .map(|line| ListItem::new(line.as_str()))
+        .collect();
+    frame.render_widget(
+        List::new(log_items).block(Block::default().borders(Borders::ALL).title(" Log ")),
+        columns[1],
+    );
+}
+
+fn draw_scope(frame: &mut Frame, state: &UiState, scope: Scope, area: Rect) {
+    let width = area.width.saturating_sub(2) as usize;
+    let height = area.height.saturating_sub(2) as usize;
+    let (title, lines) = match scope {
+        Scope::Trace => (
+            format!(" Pitch, last {}s ", TRACE_SPAN.as_secs()),
+            state.trace.render(&state.mapping, state.started.elapsed(), width, height),
+        ),
+        Scope::Spectrum => (
+            format!(" Spectrum, {:.0}-{:.0} Hz ", SPECTRUM_LOW_HZ, SPECTRUM_HIGH_HZ),
+            state.spectrogram.render(width, height),
+        ),
+    };
+    frame.render_widget(
+        Paragraph::new(lines.join("\n")).block(Block::default().borders(Borders::ALL).title(title)),
+        area,
+    );
+}
@@ -0,0 +1,85 @@
+use crate::audio::AudioInput;
+use log::{info, warn};
+use pitchu::clock::SystemClock;
+use pitchu::engine::PitchToKeyEngine;
+use pitchu::gate::window_power;
+use pitchu::notes::nearest_note;
+use pitchu::pitch_stats::PitchStats;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// `pitchu tune`: shows what `engine` detects in each window of `input` on one line that keeps
+/// being rewritten, and sums it all up when `running` goes false. Nothing is pressed; the
+/// engine has no bands.
+pub fn run(
+    input: &mut AudioInput,
+    engine: &mut PitchToKeyEngine<SystemClock>,
+    window_size: usize,
+    mut warmup_samples: usize,
+    a4_hz: f32,
+    running: &AtomicBool,
+) {
+    let mut window = vec![0.0f32; window_size];
+    let mut stats = PitchStats::new();
+    let mut stdout = std::io::stdout();
+    info!("Tune: listening; Ctrl-C stops and prints a summary.");
+    while running.load(Ordering::SeqCst) {
+        if input.has_failed() {
+            warn!("Tune: the audio device stopped.");
+            break;
+        }
+        let consumer = &mut input.consumers[0];
+        consumer.discard_backlog(input.latency_cap, window_size);
+        while consumer.pop_window(&mut window) {
+            if warmup_samples > 0 {
+                warmup_samples = warmup_samples.saturating_sub(window_size);
+                continue;
+            }
+            let rms = (window_power(&window) / window_size as f32).sqrt();
+            let pitch = engine.process(&window).pitch;
+            stats.observe(pitch, rms);
+            let line = match pitch {
+                Some((freq, clarity)) => {
+                    let note = nearest_note(freq, a4_hz);
+                    format!(
+                        "{:7.1} Hz  {:<3} {:+3.0}c  clarity {:.2}  RMS {:.3}",
+                        freq,
+                        note.name(),
+                        note.cents,
+                        clarity,
+                        rms
+                    )
+                }
+                None => format!("   no pitch                        RMS {:.3}", rms),
+            };
+            // Padded so a shorter line covers the one before
+            let _ = write!(stdout, "\r{:<60}", line);
+            let _ = stdout.flush();
+        }
+        input.wait_for_window(POLL_INTERVAL);
+    }
+    println!();
+    print_summary(&stats, a4_hz);
+}
+
+fn print_summary(stats: &PitchStats, a4_hz: f32) {
+    println!("Tune: {} windows, pitch heard in {:.0}% of them.", stats.windows(), stats.voiced() * 100.0);
+    if let Some(level) = stats.level() {
+        println!("RMS: {}", level);
+    }
+    let Some(frequency) = stats.frequency() else {
+        println!("No pitch was detected.");
+        return;
+    };
+    let hz = |freq: f32| {
+        let note = nearest_note(freq, a4_hz);
+        format!("{:.1} Hz ({} {:+.0}c)", freq, note.name(), note.cents)
+    };
+    println!("Pitch: min {}, median {}, max {}", hz(frequency.min), hz(frequency.median), hz(frequency.max));
+    if let Some(clarity) = stats.clarity() {
+        println!("Clarity: {}", clarity);
+    }
+}
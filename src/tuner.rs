@@ -0,0 +1,141 @@
+use crate::mapping::{Band, Mapping};
+use crate::notes::cents;
+use std::fmt;
+
+// Cells on each side of the needle's center mark
+const NEEDLE_HALF_WIDTH: i32 = 5;
+// Cents represented by one needle cell
+const CENTS_PER_CELL: f32 = 10.0;
+
+/// Where a detected pitch sits relative to the configured bands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TunerReading {
+    /// Inside a band; distances to both edges are positive.
+    InBand {
+        /// The band's [`label`](Band::label).
+        label: String,
+        cents_from_center: f32,
+        cents_above_low: f32,
+        cents_below_high: f32,
+    },
+    /// Outside every band; offset from the center of the closest one.
+    Near { label: String, cents_from_center: f32 },
+}
+
+/// How far a detected pitch lands from the center of its band, for `--diagnostics`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CenterDeviation {
+    pub freq: f32,
+    /// The [`label`](Band::label) of the band the pitch is in, or of the closest one.
+    pub label: String,
+    pub center_hz: f32,
+    pub inside: bool,
+}
+
+impl CenterDeviation {
+    pub fn hz(&self) -> f32 {
+        self.freq - self.center_hz
+    }
+
+    pub fn cents(&self) -> f32 {
+        cents(self.freq, self.center_hz)
+    }
+}
+
+impl fmt::Display for CenterDeviation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1} Hz -> '{}' (center {:.1} Hz): {:+.1} Hz, {:+.0}c{}",
+            self.freq,
+            self.label,
+            self.center_hz,
+            self.hz(),
+            self.cents(),
+            if self.inside { "" } else { ", outside the band" }
+        )
+    }
+}
+
+/// Quantizes `freq` to the center of the band it falls in, or of the band whose center is
+/// closest. `None` only when the mapping has no bands.
+pub fn nearest_center(mapping: &Mapping, freq: f32) -> Option<CenterDeviation> {
+    let (band, inside) = match mapping.band_for(freq) {
+        Some(band) => (band, true),
+        None => (closest_band(mapping, freq)?, false),
+    };
+    Some(CenterDeviation { freq, label: band.label(), center_hz: band.center_hz(), inside })
+}
+
+// The band whose center is the fewest cents from `freq`
+fn closest_band(mapping: &Mapping, freq: f32) -> Option<&Band> {
+    let distance = |band: &&Band| cents(freq, band.center_hz()).abs();
+    mapping.bands().iter().min_by(|a, b| distance(a).total_cmp(&distance(b)))
+}
+
+/// Reads `freq` against `mapping`. `None` only when the mapping has no bands.
+pub fn read(mapping: &Mapping, freq: f32) -> Option<TunerReading> {
+    if let Some(band) = mapping.band_for(freq) {
+        return Some(TunerReading::InBand {
+            label: band.label(),
+            cents_from_center: cents(freq, band.center_hz()),
+            cents_above_low: cents(freq, band.low_hz),
+            cents_below_high: cents(band.high_hz, freq),
+        });
+    }
+    closest_band(mapping, freq)
+        .map(|band| TunerReading::Near { label: band.label(), cents_from_center: cents(freq, band.center_hz()) })
+}
+
+impl TunerReading {
+    pub fn cents_from_center(&self) -> f32 {
+        match *self {
+            TunerReading::InBand { cents_from_center, .. } | TunerReading::Near { cents_from_center, .. } => {
+                cents_from_center
+            }
+        }
+    }
+
+    /// A needle like `[     |-->  ]`, the arrow pointing from the band center toward the pitch.
+    pub fn needle(&self) -> String {
+        let offset = (self.cents_from_center() / CENTS_PER_CELL).round() as i32;
+        let offset = offset.clamp(-NEEDLE_HALF_WIDTH, NEEDLE_HALF_WIDTH);
+        let mut cells = String::from("[");
+        for cell in -NEEDLE_HALF_WIDTH..=NEEDLE_HALF_WIDTH {
+            let c = match cell {
+                0 => '|',
+                c if c == offset && c > 0 => '>',
+                c if c == offset && c < 0 => '<',
+                c if (c > 0 && c < offset) || (c < 0 && c > offset) => '-',
+                _ => ' ',
+            };
+            cells.push(c);
+        }
+        cells.push(']');
+        cells
+    }
+}
+
+impl fmt::Display for TunerReading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TunerReading::InBand { label, cents_from_center, cents_above_low, cents_below_high } => write!(
+                f,
+                "{} {:+.0}c in '{}' ({:.0}c above low edge, {:.0}c below high edge)",
+                self.needle(),
+                cents_from_center,
+                label,
+                cents_above_low,
+                cents_below_high
+            ),
+            TunerReading::Near { label, cents_from_center } => write!(
+                f,
+                "{} {:+.0}c off '{}' (sing {})",
+                self.needle(),
+                cents_from_center,
+                label,
+                if *cents_from_center > 0.0 { "lower" } else { "higher" }
+            ),
+        }
+    }
+}
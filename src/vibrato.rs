@@ -0,0 +1,137 @@
+//! `--vibrato-tolerance`: holds a sustained note with vibrato on its center frequency. Vibrato
+//! swings the pitch a few tenths of a semitone either way, several times a second, which can be
+//! enough to cross a band edge on every swing. Smoothing only rounds the swing off; this
+//! recognises it as a swing and reports the note it swings around.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How much recent pitch is looked at: one full cycle of even slow (4 Hz) vibrato.
+pub const VIBRATO_SPAN: Duration = Duration::from_millis(300);
+/// Largest `--vibrato-tolerance`, in cents.
+pub const MAX_VIBRATO_TOLERANCE_CENTS: f32 = 200.0;
+
+// Times the pitch has to pass its center within the span before it counts as vibrato: up and
+// back down again, not a glide
+const MIN_CROSSINGS: usize = 2;
+
+/// Recognises vibrato in a run of detections and reports its center while it lasts.
+pub struct VibratoLock {
+    tolerance_cents: f32,
+    // Recent detections, in cents above 1 Hz
+    history: VecDeque<(Instant, f32)>,
+    locked: bool,
+}
+
+impl VibratoLock {
+    /// Pitch swinging at most `tolerance_cents` either side of a center counts as vibrato.
+    pub fn new(tolerance_cents: f32) -> Self {
+        VibratoLock { tolerance_cents, history: VecDeque::new(), locked: false }
+    }
+
+    /// Feeds the detection at `now` and returns the frequency to use: the center of the
+    /// vibrato while there is one, else `freq` unchanged. A pitch beyond the tolerance of the
+    /// current center starts over at once, so a new note is never held back.
+    pub fn update(&mut self, freq: f32, now: Instant) -> f32 {
+        let cents = 1200.0 * freq.max(f32::MIN_POSITIVE).log2();
+        while self.history.front().is_some_and(|&(at, _)| now.duration_since(at) > VIBRATO_SPAN) {
+            self.history.pop_front();
+        }
+        if let Some((center, _)) = self.extent()
+            && (cents - center).abs() > self.tolerance_cents
+        {
+            self.history.clear();
+        }
+        self.history.push_back((now, cents));
+
+        let (center, swing) = self.extent().unwrap_or((cents, 0.0));
+        let mut crossings = 0;
+        let mut side = 0.0f32;
+        for &(_, value) in &self.history {
+            let offset = value - center;
+            if offset != 0.0 {
+                if side != 0.0 && offset.signum() != side {
+                    crossings += 1;
+                }
+                side = offset.signum();
+            }
+        }
+        self.locked = swing <= self.tolerance_cents && crossings >= MIN_CROSSINGS;
+        if self.locked { 2f32.powf(center / 1200.0) } else { freq }
+    }
+
+    /// Whether the last [`update`](Self::update) reported a vibrato's center.
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Forgets the history; called when the pitch is lost.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.locked = false;
+    }
+
+    // Middle of the recent swing and how far it reaches either side, in cents
+    fn extent(&self) -> Option<(f32, f32)> {
+        let low = self.history.iter().map(|&(_, cents)| cents).reduce(f32::min)?;
+        let high = self.history.iter().map(|&(_, cents)| cents).reduce(f32::max)?;
+        Some(((low + high) / 2.0, (high - low) / 2.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const WINDOW: Duration = Duration::from_millis(20);
+
+    fn cents_from(freq: f32, center: f32) -> f32 {
+        1200.0 * (freq / center).log2()
+    }
+
+    #[test]
+    fn vibrato_locks_to_its_center() {
+        let mut lock = VibratoLock::new(60.0);
+        let start = Instant::now();
+        // 5.5 Hz vibrato, 40 cents either side of 220 Hz, for a second
+        let mut worst = 0.0f32;
+        for n in 0..50 {
+            let t = n as f32 * WINDOW.as_secs_f32();
+            let freq = 220.0 * 2f32.powf(40.0 * (2.0 * PI * 5.5 * t).sin() / 1200.0);
+            let held = lock.update(freq, start + WINDOW * n);
+            // Past the first cycle
+            if n >= 15 {
+                assert!(lock.locked(), "window {}", n);
+                worst = worst.max(cents_from(held, 220.0).abs());
+            }
+        }
+        assert!(worst < 5.0, "{}", worst);
+
+        // Lost pitch forgets the note
+        lock.reset();
+        assert_eq!(lock.update(300.0, start + WINDOW * 60), 300.0);
+        assert!(!lock.locked());
+    }
+
+    #[test]
+    fn glides_and_new_notes_pass_straight_through() {
+        let mut lock = VibratoLock::new(60.0);
+        let start = Instant::now();
+        // A slow rise within the tolerance never swings back, so it isn't vibrato
+        for n in 0..15 {
+            let freq = 220.0 * 2f32.powf(4.0 * n as f32 / 1200.0);
+            assert_eq!(lock.update(freq, start + WINDOW * n), freq);
+        }
+
+        let mut lock = VibratoLock::new(60.0);
+        for n in 0..20 {
+            let cents = if n % 5 < 2 { 30.0 } else { -30.0 };
+            lock.update(220.0 * 2f32.powf(cents / 1200.0), start + WINDOW * n);
+        }
+        assert!(lock.locked());
+        // A fifth up is a new note, reported as sung
+        assert_eq!(lock.update(330.0, start + WINDOW * 20), 330.0);
+        assert!(!lock.locked());
+    }
+}
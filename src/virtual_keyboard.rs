@@ -0,0 +1,222 @@
+use enigo::Key;
+
+/// A keyboard made with uinput, for `--output uinput`: key events go straight to the kernel
+/// and work without a display server, e.g. over SSH or under Wayland. Characters are typed as
+/// on a US QWERTY layout.
+pub struct VirtualKeyboard {
+    inner: platform::Keyboard,
+}
+
+impl VirtualKeyboard {
+    /// Creates the keyboard. Fails (with a reason to show the user) when uinput is missing or
+    /// not permitted.
+    pub fn open() -> Result<Self, String> {
+        Ok(VirtualKeyboard { inner: platform::Keyboard::open()? })
+    }
+
+    /// Whether `key` can be pressed on this keyboard.
+    pub fn supports(key: Key) -> bool {
+        platform::Keyboard::supports(key)
+    }
+
+    /// Presses or releases `key`, with Shift around it for a shifted character.
+    pub fn set(&mut self, key: Key, pressed: bool) -> Result<(), String> {
+        self.inner.set(key, pressed)
+    }
+
+    /// Presses and releases `key`.
+    pub fn click(&mut self, key: Key) -> Result<(), String> {
+        self.set(key, true).and_then(|()| self.set(key, false))
+    }
+
+    /// Types `text` one character at a time. Returns the characters it has no key for.
+    pub fn type_text(&mut self, text: &str) -> Result<Vec<char>, String> {
+        let mut skipped = Vec::new();
+        for c in text.chars() {
+            let key = match c {
+                '\n' => Key::Return,
+                '\t' => Key::Tab,
+                c => Key::Layout(c),
+            };
+            if Self::supports(key) {
+                self.click(key)?;
+            } else {
+                skipped.push(c);
+            }
+        }
+        Ok(skipped)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use enigo::Key;
+    use pitchu::layout::key_scancode;
+    use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+    use evdev::{AttributeSet, EventType, InputEvent, Key as Code};
+
+    pub struct Keyboard {
+        device: VirtualDevice,
+    }
+
+    // Everything the keyboard can press, registered with the device; the letters come first,
+    // in order
+    const CODES: &[Code] = &[
+        Code::KEY_A, Code::KEY_B, Code::KEY_C, Code::KEY_D, Code::KEY_E, Code::KEY_F, Code::KEY_G,
+        Code::KEY_H, Code::KEY_I, Code::KEY_J, Code::KEY_K, Code::KEY_L, Code::KEY_M, Code::KEY_N,
+        Code::KEY_O, Code::KEY_P, Code::KEY_Q, Code::KEY_R, Code::KEY_S, Code::KEY_T, Code::KEY_U,
+        Code::KEY_V, Code::KEY_W, Code::KEY_X, Code::KEY_Y, Code::KEY_Z, Code::KEY_1, Code::KEY_2,
+        Code::KEY_3, Code::KEY_4, Code::KEY_5, Code::KEY_6, Code::KEY_7, Code::KEY_8, Code::KEY_9,
+        Code::KEY_0, Code::KEY_MINUS, Code::KEY_EQUAL, Code::KEY_LEFTBRACE, Code::KEY_RIGHTBRACE,
+        Code::KEY_SEMICOLON, Code::KEY_APOSTROPHE, Code::KEY_GRAVE, Code::KEY_BACKSLASH,
+        Code::KEY_COMMA, Code::KEY_DOT, Code::KEY_SLASH, Code::KEY_SPACE, Code::KEY_ENTER,
+        Code::KEY_TAB, Code::KEY_BACKSPACE, Code::KEY_ESC, Code::KEY_DELETE, Code::KEY_CAPSLOCK,
+        Code::KEY_LEFTSHIFT, Code::KEY_LEFTCTRL, Code::KEY_LEFTALT, Code::KEY_LEFTMETA,
+        Code::KEY_UP, Code::KEY_DOWN, Code::KEY_LEFT, Code::KEY_RIGHT, Code::KEY_HOME, Code::KEY_END,
+        Code::KEY_PAGEUP, Code::KEY_PAGEDOWN, Code::KEY_F1, Code::KEY_F2, Code::KEY_F3, Code::KEY_F4,
+        Code::KEY_F5, Code::KEY_F6, Code::KEY_F7, Code::KEY_F8, Code::KEY_F9, Code::KEY_F10,
+        Code::KEY_F11, Code::KEY_F12,
+    ];
+
+    // The key code for `key` and whether it needs Shift
+    fn code(key: Key) -> Option<(Code, bool)> {
+        let named = match key {
+            Key::Alt => Code::KEY_LEFTALT,
+            Key::Backspace => Code::KEY_BACKSPACE,
+            Key::CapsLock => Code::KEY_CAPSLOCK,
+            Key::Control => Code::KEY_LEFTCTRL,
+            Key::Delete => Code::KEY_DELETE,
+            Key::DownArrow => Code::KEY_DOWN,
+            Key::End => Code::KEY_END,
+            Key::Escape => Code::KEY_ESC,
+            Key::F1 => Code::KEY_F1,
+            Key::F2 => Code::KEY_F2,
+            Key::F3 => Code::KEY_F3,
+            Key::F4 => Code::KEY_F4,
+            Key::F5 => Code::KEY_F5,
+            Key::F6 => Code::KEY_F6,
+            Key::F7 => Code::KEY_F7,
+            Key::F8 => Code::KEY_F8,
+            Key::F9 => Code::KEY_F9,
+            Key::F10 => Code::KEY_F10,
+            Key::F11 => Code::KEY_F11,
+            Key::F12 => Code::KEY_F12,
+            Key::Home => Code::KEY_HOME,
+            Key::LeftArrow => Code::KEY_LEFT,
+            Key::Meta => Code::KEY_LEFTMETA,
+            Key::PageDown => Code::KEY_PAGEDOWN,
+            Key::PageUp => Code::KEY_PAGEUP,
+            Key::Return => Code::KEY_ENTER,
+            Key::RightArrow => Code::KEY_RIGHT,
+            Key::Shift => Code::KEY_LEFTSHIFT,
+            Key::Space => Code::KEY_SPACE,
+            Key::Tab => Code::KEY_TAB,
+            Key::UpArrow => Code::KEY_UP,
+            Key::Layout(c) => return character(c),
+            // A scancode is a Linux key code already
+            Key::Raw(_) => {
+                let scancode = key_scancode(key)?;
+                return CODES.iter().find(|code| code.code() == scancode).map(|&code| (code, false));
+            }
+            _ => return None,
+        };
+        Some((named, false))
+    }
+
+    fn character(c: char) -> Option<(Code, bool)> {
+        if c.is_ascii_alphabetic() {
+            let letter = CODES[(c.to_ascii_lowercase() as u8 - b'a') as usize];
+            return Some((letter, c.is_ascii_uppercase()));
+        }
+        const DIGITS: [Code; 10] = [
+            Code::KEY_0, Code::KEY_1, Code::KEY_2, Code::KEY_3, Code::KEY_4, Code::KEY_5, Code::KEY_6,
+            Code::KEY_7, Code::KEY_8, Code::KEY_9,
+        ];
+        if let Some(digit) = c.to_digit(10) {
+            return Some((DIGITS[digit as usize], false));
+        }
+        // Shifted digits, in the order of the number row
+        if let Some(index) = ")!@#$%^&*(".find(c) {
+            return Some((DIGITS[index], true));
+        }
+        Some(match c {
+            ' ' => (Code::KEY_SPACE, false),
+            '-' => (Code::KEY_MINUS, false),
+            '_' => (Code::KEY_MINUS, true),
+            '=' => (Code::KEY_EQUAL, false),
+            '+' => (Code::KEY_EQUAL, true),
+            '[' => (Code::KEY_LEFTBRACE, false),
+            '{' => (Code::KEY_LEFTBRACE, true),
+            ']' => (Code::KEY_RIGHTBRACE, false),
+            '}' => (Code::KEY_RIGHTBRACE, true),
+            ';' => (Code::KEY_SEMICOLON, false),
+            ':' => (Code::KEY_SEMICOLON, true),
+            '\'' => (Code::KEY_APOSTROPHE, false),
+            '"' => (Code::KEY_APOSTROPHE, true),
+            '`' => (Code::KEY_GRAVE, false),
+            '~' => (Code::KEY_GRAVE, true),
+            '\\' => (Code::KEY_BACKSLASH, false),
+            '|' => (Code::KEY_BACKSLASH, true),
+            ',' => (Code::KEY_COMMA, false),
+            '<' => (Code::KEY_COMMA, true),
+            '.' => (Code::KEY_DOT, false),
+            '>' => (Code::KEY_DOT, true),
+            '/' => (Code::KEY_SLASH, false),
+            '?' => (Code::KEY_SLASH, true),
+            _ => return None,
+        })
+    }
+
+    impl Keyboard {
+        pub fn open() -> Result<Self, String> {
+            let mut keys = AttributeSet::<Code>::new();
+            for &code in CODES {
+                keys.insert(code);
+            }
+            let device = VirtualDeviceBuilder::new()
+                .and_then(|builder| builder.name("pitchu virtual keyboard").with_keys(&keys))
+                .and_then(|builder| builder.build())
+                .map_err(|e| format!("could not create a uinput device ({}); check access to /dev/uinput", e))?;
+            Ok(Keyboard { device })
+        }
+
+        pub fn supports(key: Key) -> bool {
+            code(key).is_some()
+        }
+
+        pub fn set(&mut self, key: Key, pressed: bool) -> Result<(), String> {
+            let Some((code, shifted)) = code(key) else {
+                return Err(format!("no key code for {:?}", key));
+            };
+            let event = |code: Code, down: bool| InputEvent::new(EventType::KEY, code.code(), down as i32);
+            let events = match (shifted, pressed) {
+                (false, _) => vec![event(code, pressed)],
+                (true, true) => vec![event(Code::KEY_LEFTSHIFT, true), event(code, true)],
+                (true, false) => vec![event(code, false), event(Code::KEY_LEFTSHIFT, false)],
+            };
+            // emit() appends the SYN_REPORT
+            self.device.emit(&events).map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use enigo::Key;
+
+    pub struct Keyboard;
+
+    impl Keyboard {
+        pub fn open() -> Result<Self, String> {
+            Err("uinput keyboards are only supported on Linux".to_string())
+        }
+
+        pub fn supports(_key: Key) -> bool {
+            false
+        }
+
+        pub fn set(&mut self, _key: Key, _pressed: bool) -> Result<(), String> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use pitchu::axis::StickAxis;
+use pitchu::gamepad::Button;
+
+/// A virtual game controller: a uinput device on Linux, a ViGEm Xbox 360 pad on Windows.
+pub struct VirtualPad {
+    inner: platform::Pad,
+}
+
+impl VirtualPad {
+    /// Creates the controller. Fails (with a reason to show the user) when the platform backend
+    /// is missing or not permitted.
+    pub fn open() -> Result<Self, String> {
+        Ok(VirtualPad { inner: platform::Pad::open()? })
+    }
+
+    /// Presses or releases `button`.
+    pub fn set(&mut self, button: Button, pressed: bool) -> Result<(), String> {
+        self.inner.set(button, pressed)
+    }
+
+    /// Moves a stick `axis` to `value`, from -1.0 (left or down) to 1.0 (right or up).
+    pub fn set_axis(&mut self, axis: StickAxis, value: f32) -> Result<(), String> {
+        self.inner.set_axis(axis, value.clamp(-1.0, 1.0))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+    use evdev::{AbsInfo, AbsoluteAxisType, AttributeSet, EventType, InputEvent, Key, UinputAbsSetup};
+    use pitchu::axis::StickAxis;
+    use pitchu::gamepad::Button;
+
+    pub struct Pad {
+        device: VirtualDevice,
+        // D-pad directions held; each hat axis reports the difference of its pair
+        up: bool,
+        down: bool,
+        left: bool,
+        right: bool,
+    }
+
+    fn button_code(button: Button) -> Option<Key> {
+        Some(match button {
+            Button::A => Key::BTN_SOUTH,
+            Button::B => Key::BTN_EAST,
+            Button::X => Key::BTN_WEST,
+            Button::Y => Key::BTN_NORTH,
+            Button::L => Key::BTN_TL,
+            Button::R => Key::BTN_TR,
+            Button::L2 => Key::BTN_TL2,
+            Button::R2 => Key::BTN_TR2,
+            Button::L3 => Key::BTN_THUMBL,
+            Button::R3 => Key::BTN_THUMBR,
+            Button::Start => Key::BTN_START,
+            Button::Select => Key::BTN_SELECT,
+            Button::Guide => Key::BTN_MODE,
+            Button::DpadUp | Button::DpadDown | Button::DpadLeft | Button::DpadRight => return None,
+        })
+    }
+
+    impl Pad {
+        pub fn open() -> Result<Self, String> {
+            let mut keys = AttributeSet::<Key>::new();
+            for key in [
+                Key::BTN_SOUTH, Key::BTN_EAST, Key::BTN_WEST, Key::BTN_NORTH, Key::BTN_TL, Key::BTN_TR,
+                Key::BTN_TL2, Key::BTN_TR2, Key::BTN_THUMBL, Key::BTN_THUMBR, Key::BTN_START,
+                Key::BTN_SELECT, Key::BTN_MODE,
+            ] {
+                keys.insert(key);
+            }
+            // The D-pad is a hat, which is how most controllers (and SDL) report it
+            let hat = AbsInfo::new(0, -1, 1, 0, 0, 0);
+            let stick = AbsInfo::new(0, -STICK_MAX, STICK_MAX, 16, 128, 0);
+            let device = VirtualDeviceBuilder::new()
+                .and_then(|builder| builder.name("pitchu virtual gamepad").with_keys(&keys))
+                .and_then(|builder| builder.with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_HAT0X, hat)))
+                .and_then(|builder| builder.with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_HAT0Y, hat)))
+                .and_then(|builder| builder.with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_X, stick)))
+                .and_then(|builder| builder.with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_Y, stick)))
+                .and_then(|builder| builder.with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_RX, stick)))
+                .and_then(|builder| builder.with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_RY, stick)))
+                .and_then(|builder| builder.build())
+                .map_err(|e| format!("could not create a uinput device ({}); check access to /dev/uinput", e))?;
+            Ok(Pad { device, up: false, down: false, left: false, right: false })
+        }
+
+        pub fn set(&mut self, button: Button, pressed: bool) -> Result<(), String> {
+            let event = match button_code(button) {
+                Some(key) => InputEvent::new(EventType::KEY, key.code(), pressed as i32),
+                None => {
+                    match button {
+                        Button::DpadUp => self.up = pressed,
+                        Button::DpadDown => self.down = pressed,
+                        Button::DpadLeft => self.left = pressed,
+                        _ => self.right = pressed,
+                    }
+                    let (axis, value) = match button {
+                        Button::DpadUp | Button::DpadDown => (AbsoluteAxisType::ABS_HAT0Y, self.down as i32 - self.up as i32),
+                        _ => (AbsoluteAxisType::ABS_HAT0X, self.right as i32 - self.left as i32),
+                    };
+                    InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+                }
+            };
+            // emit() appends the SYN_REPORT
+            self.device.emit(&[event]).map_err(|e| e.to_string())
+        }
+
+        pub fn set_axis(&mut self, axis: StickAxis, value: f32) -> Result<(), String> {
+            // Y grows downwards on evdev
+            let (code, value) = match axis {
+                StickAxis::LeftX => (AbsoluteAxisType::ABS_X, value),
+                StickAxis::LeftY => (AbsoluteAxisType::ABS_Y, -value),
+                StickAxis::RightX => (AbsoluteAxisType::ABS_RX, value),
+                StickAxis::RightY => (AbsoluteAxisType::ABS_RY, -value),
+            };
+            let event = InputEvent::new(EventType::ABSOLUTE, code.0, (value * STICK_MAX as f32).round() as i32);
+            self.device.emit(&[event]).map_err(|e| e.to_string())
+        }
+    }
+
+    // Sticks report -32767 to 32767, like an Xbox pad under the xpad driver
+    const STICK_MAX: i32 = 32767;
+}
+
+#[cfg(windows)]
+mod platform {
+    use pitchu::axis::StickAxis;
+    use pitchu::gamepad::Button;
+    use vigem_client::{Client, TargetId, XButtons, XGamepad, Xbox360Wired};
+
+    pub struct Pad {
+        target: Xbox360Wired<Client>,
+        state: XGamepad,
+    }
+
+    impl Pad {
+        pub fn open() -> Result<Self, String> {
+            let client = Client::connect().map_err(|e| format!("could not reach the ViGEmBus driver ({}); is it installed?", e))?;
+            let mut target = Xbox360Wired::new(client, TargetId::XBOX360_WIRED);
+            target.plugin().map_err(|e| format!("could not plug in a virtual Xbox 360 controller: {}", e))?;
+            target.wait_ready().map_err(|e| format!("the virtual controller did not become ready: {}", e))?;
+            Ok(Pad { target, state: XGamepad::default() })
+        }
+
+        pub fn set(&mut self, button: Button, pressed: bool) -> Result<(), String> {
+            let flag = match button {
+                Button::A => XButtons::A,
+                Button::B => XButtons::B,
+                Button::X => XButtons::X,
+                Button::Y => XButtons::Y,
+                Button::L => XButtons::LB,
+                Button::R => XButtons::RB,
+                Button::L3 => XButtons::LTHUMB,
+                Button::R3 => XButtons::RTHUMB,
+                Button::Start => XButtons::START,
+                Button::Select => XButtons::BACK,
+                Button::Guide => XButtons::GUIDE,
+                Button::DpadUp => XButtons::UP,
+                Button::DpadDown => XButtons::DOWN,
+                Button::DpadLeft => XButtons::LEFT,
+                Button::DpadRight => XButtons::RIGHT,
+                // The triggers are analog on an Xbox pad
+                Button::L2 | Button::R2 => {
+                    let value = if pressed { u8::MAX } else { 0 };
+                    if button == Button::L2 {
+                        self.state.left_trigger = value;
+                    } else {
+                        self.state.right_trigger = value;
+                    }
+                    return self.target.update(&self.state).map_err(|e| e.to_string());
+                }
+            };
+            if pressed {
+                self.state.buttons.raw |= flag;
+            } else {
+                self.state.buttons.raw &= !flag;
+            }
+            self.target.update(&self.state).map_err(|e| e.to_string())
+        }
+
+        pub fn set_axis(&mut self, axis: StickAxis, value: f32) -> Result<(), String> {
+            let value = (value * i16::MAX as f32).round() as i16;
+            match axis {
+                StickAxis::LeftX => self.state.thumb_lx = value,
+                StickAxis::LeftY => self.state.thumb_ly = value,
+                StickAxis::RightX => self.state.thumb_rx = value,
+                StickAxis::RightY => self.state.thumb_ry = value,
+            }
+            self.target.update(&self.state).map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod platform {
+    use pitchu::axis::StickAxis;
+    use pitchu::gamepad::Button;
+
+    pub struct Pad;
+
+    impl Pad {
+        pub fn open() -> Result<Self, String> {
+            Err("virtual gamepads are only supported on Linux (uinput) and Windows (ViGEm)".to_string())
+        }
+
+        pub fn set(&mut self, _button: Button, _pressed: bool) -> Result<(), String> {
+            Ok(())
+        }
+
+        pub fn set_axis(&mut self, _axis: StickAxis, _value: f32) -> Result<(), String> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::key_state::{BandTiming, KeyStateMachine, KeyTiming};
+use crate::keys::KeyEvent;
+use crate::mapping::{Band, Mapping};
+use enigo::Key;
+use std::time::{Duration, Instant};
+
+/// One singer's share of the frequency range, with its own mapping.
+#[derive(Debug, Clone)]
+pub struct Voice {
+    pub name: String,
+    pub low_hz: f32,
+    pub high_hz: f32,
+    pub mapping: Mapping,
+}
+
+impl Voice {
+    pub fn contains(&self, freq: f32) -> bool {
+        (self.low_hz..=self.high_hz).contains(&freq)
+    }
+
+    /// The voice's range and bands moved by whole `octaves`.
+    pub fn transposed(&self, octaves: i32) -> Voice {
+        let factor = 2f32.powi(octaves);
+        Voice {
+            name: self.name.clone(),
+            low_hz: self.low_hz * factor,
+            high_hz: self.high_hz * factor,
+            mapping: self.mapping.transposed(octaves),
+        }
+    }
+}
+
+/// Every voice's bands in voice order, as one table for reports and range checks.
+pub fn combined_mapping(voices: &[Voice]) -> Mapping {
+    Mapping::new(voices.iter().flat_map(|voice| voice.mapping.bands().iter().cloned()).collect::<Vec<Band>>())
+}
+
+struct VoiceState {
+    voice: Voice,
+    keys: KeyStateMachine,
+    last_heard: Option<Instant>,
+}
+
+/// Several voices sharing one input, each with its own key state.
+///
+/// The detector hears one pitch per window, so two people singing at once show up as windows
+/// alternating between their ranges. Each pitch goes to the first voice whose range contains
+/// it; the other voices keep their keys (and keep repeating them) until nothing has been
+/// heard in their range for `hold`, so one voice never releases another's key.
+pub struct VoiceSet {
+    voices: Vec<VoiceState>,
+    hold: Duration,
+}
+
+impl VoiceSet {
+    pub fn new(voices: Vec<Voice>, timing: KeyTiming, hold: Duration) -> Self {
+        let voices = voices
+            .into_iter()
+            .map(|voice| VoiceState { voice, keys: KeyStateMachine::new(timing), last_heard: None })
+            .collect();
+        VoiceSet { voices, hold }
+    }
+
+    pub fn voices(&self) -> impl Iterator<Item = &Voice> {
+        self.voices.iter().map(|state| &state.voice)
+    }
+
+    /// The voice whose range contains `freq`, if any.
+    pub fn voice_for(&self, freq: f32) -> Option<&Voice> {
+        self.voices().find(|voice| voice.contains(freq))
+    }
+
+    /// Feed the pitch of one window (`None` when no pitch was trusted) and get the key events
+    /// of every voice, plus the key the pitch mapped to.
+    pub fn update(&mut self, freq: Option<f32>, now: Instant) -> (Option<Key>, Vec<KeyEvent>) {
+        let route = freq.and_then(|freq| self.voices.iter().position(|state| state.voice.contains(freq)));
+        let mut key = None;
+        let mut events = Vec::new();
+        for (index, state) in self.voices.iter_mut().enumerate() {
+            if Some(index) == route
+                && let Some(freq) = freq
+            {
+                let band = state.voice.mapping.band_for(freq);
+                key = band.and_then(Band::key);
+                state.last_heard = Some(now);
+                let timing = band.map_or(BandTiming::GLOBAL, |band| band.timing);
+                events.extend(state.keys.update_band(key, band.and_then(Band::tap_key), timing, now));
+            } else if let Some(active) = state.keys.note_key() {
+                let quiet_for = state.last_heard.map_or(Duration::MAX, |heard| now.duration_since(heard));
+                let sustained = (quiet_for < self.hold).then_some(active);
+                events.extend(state.keys.update(sustained, now));
+            }
+        }
+        (key, events)
+    }
+
+    /// Forgets every voice's held key without reporting releases.
+    pub fn reset(&mut self) {
+        for state in &mut self.voices {
+            state.keys.reset();
+            state.last_heard = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_state::SwitchConsensus;
+
+    const HOLD: Duration = Duration::from_millis(150);
+    const WINDOW: Duration = Duration::from_millis(50);
+
+    fn duet() -> VoiceSet {
+        let low = Voice {
+            name: "low".to_string(),
+            low_hz: 80.0,
+            high_hz: 200.0,
+            mapping: Mapping::new(vec![Band::new(100.0, 150.0, Key::LeftArrow)]),
+        };
+        let high = Voice {
+            name: "high".to_string(),
+            low_hz: 200.1,
+            high_hz: 500.0,
+            mapping: Mapping::new(vec![Band::new(250.0, 300.0, Key::Layout('a'))]),
+        };
+        VoiceSet::new(
+            vec![low, high],
+            KeyTiming { min_note: Duration::ZERO, switch: SwitchConsensus::IMMEDIATE, ..KeyTiming::default() },
+            HOLD,
+        )
+    }
+
+    /// Feeds one pitch per window and collects every event.
+    fn run(voices: &mut VoiceSet, script: &[Option<f32>]) -> Vec<KeyEvent> {
+        let start = Instant::now();
+        script
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &freq)| voices.update(freq, start + WINDOW * i as u32).1)
+            .collect()
+    }
+
+    #[test]
+    fn alternating_pitches_hold_both_voices_keys() {
+        let mut voices = duet();
+        let events = run(&mut voices, &[Some(120.0), Some(280.0), Some(120.0), Some(280.0)]);
+        assert_eq!(events, vec![KeyEvent::Press(Key::LeftArrow), KeyEvent::Press(Key::Layout('a'))]);
+    }
+
+    #[test]
+    fn one_voice_stopping_does_not_release_the_other() {
+        let mut voices = duet();
+        // Both sing, then the high voice stops while the low one carries on
+        let mut script = vec![Some(120.0), Some(280.0)];
+        script.extend([Some(120.0); 3]);
+        let events = run(&mut voices, &script);
+        assert_eq!(
+            events,
+            vec![
+                KeyEvent::Press(Key::LeftArrow),
+                KeyEvent::Press(Key::Layout('a')),
+                KeyEvent::Release(Key::Layout('a')),
+            ]
+        );
+    }
+
+    #[test]
+    fn pitches_outside_every_voice_are_ignored() {
+        let mut voices = duet();
+        assert_eq!(voices.update(Some(60.0), Instant::now()), (None, vec![]));
+        assert_eq!(voices.update(Some(900.0), Instant::now()), (None, vec![]));
+    }
+}
@@ -0,0 +1,18 @@
+use std::ops::RangeInclusive;
+
+/// How far `--whistle` moves the bands up by default: three octaves take a table laid out for
+/// a low voice (100-340 Hz) to 800-2700 Hz, where most people whistle.
+pub const DEFAULT_WHISTLE_OCTAVES: i32 = 3;
+/// Octave shifts accepted by `--whistle-octaves`.
+pub const WHISTLE_OCTAVE_RANGE: RangeInclusive<i32> = 1..=4;
+
+/// Analysis window for whistling. A whistle's period is under 2 ms, so half the usual window
+/// still holds dozens of periods and the key reacts sooner.
+pub const WHISTLE_WINDOW_SIZE: usize = 1024;
+/// Detections below this are voice, breath or room noise rather than a whistle.
+pub const WHISTLE_MIN_HZ: f32 = 500.0;
+/// Detector clarity threshold while whistling, when `--clarity` isn't set. A whistle is close
+/// to a pure sine and comes out far clearer than a sung note.
+pub const WHISTLE_CLARITY: f32 = 0.6;
+/// `--min-clarity-for-press` while whistling, when it isn't set.
+pub const WHISTLE_MIN_PRESS_CLARITY: f32 = 0.8;
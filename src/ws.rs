@@ -0,0 +1,127 @@
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+use tungstenite::{Message, WebSocket};
+
+// Events buffered for the broadcast thread before new ones are dropped
+const EVENT_QUEUE_DEPTH: usize = 256;
+// How often the broadcast thread checks for new connections when no events arrive
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// A client that can't take a message within this time is disconnected
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// One detected pitch, as broadcast to WebSocket clients.
+#[derive(Debug, Clone, Serialize)]
+pub struct PitchEvent {
+    pub freq: f32,
+    pub clarity: f32,
+    pub key: Option<String>,
+    /// Input channel the pitch was detected on; only present with `--dual-channel`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<usize>,
+}
+
+/// A key command, as broadcast with `--output ws`: `action` is `tap`, `press`, `release` or
+/// `text`.
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyMessage {
+    pub action: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+// Pitches keep their original flat shape, so clients that only know pitches still work
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+enum WsEvent {
+    Pitch(PitchEvent),
+    Key(KeyMessage),
+}
+
+/// Handle used by the processing loop (and a `ws` output) to publish events. Never blocks.
+#[derive(Clone)]
+pub struct PitchBroadcaster {
+    sender: SyncSender<WsEvent>,
+}
+
+impl PitchBroadcaster {
+    /// Binds a WebSocket server to `127.0.0.1:port` and starts the broadcast thread.
+    pub fn start(port: u16) -> std::io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        info!("WebSocket: broadcasting pitch events on ws://{}", listener.local_addr()?);
+
+        let (sender, receiver) = mpsc::sync_channel(EVENT_QUEUE_DEPTH);
+        thread::spawn(move || broadcast_loop(listener, receiver));
+        Ok(PitchBroadcaster { sender })
+    }
+
+    pub fn publish(&self, event: PitchEvent) {
+        self.send(WsEvent::Pitch(event));
+    }
+
+    pub fn publish_key(&self, message: KeyMessage) {
+        self.send(WsEvent::Key(message));
+    }
+
+    fn send(&self, event: WsEvent) {
+        match self.sender.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => debug!("WebSocket: broadcast queue full, dropping event."),
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+fn broadcast_loop(listener: TcpListener, receiver: Receiver<WsEvent>) {
+    let mut clients: Vec<WebSocket<TcpStream>> = Vec::new();
+    loop {
+        accept_clients(&listener, &mut clients);
+
+        let event = match receiver.recv_timeout(ACCEPT_POLL_INTERVAL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let payload = match serde_json::to_string(&event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("WebSocket: failed to serialize event: {}", e);
+                continue;
+            }
+        };
+        clients.retain_mut(|client| match client.send(Message::Text(payload.clone())) {
+            Ok(()) => true,
+            Err(e) => {
+                info!("WebSocket: client disconnected ({}).", e);
+                false
+            }
+        });
+    }
+}
+
+fn accept_clients(listener: &TcpListener, clients: &mut Vec<WebSocket<TcpStream>>) {
+    while let Ok((stream, addr)) = listener.accept() {
+        // The handshake is done in blocking mode; after that a short write timeout keeps a
+        // stalled client from holding up everyone else.
+        let setup = stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT)));
+        if let Err(e) = setup {
+            warn!("WebSocket: could not configure connection from {}: {}", addr, e);
+            continue;
+        }
+        match tungstenite::accept(stream) {
+            Ok(client) => {
+                info!("WebSocket: client connected from {}.", addr);
+                clients.push(client);
+            }
+            Err(e) => warn!("WebSocket: handshake with {} failed: {}", addr, e),
+        }
+    }
+}
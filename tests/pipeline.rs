@@ -0,0 +1,168 @@
+//! Drives synthesized audio through the same queue, windowing, detection and mapping path the
+//! binary uses, with a manual clock advancing one window at a time.
+
+use enigo::Key;
+use pitchu::audio_queue::audio_queue;
+use pitchu::clock::ManualClock;
+use pitchu::engine::{EngineConfig, PitchToKeyEngine};
+use pitchu::keys::KeyEvent;
+use pitchu::mapping::Mapping;
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+const SAMPLE_RATE: usize = 44_100;
+const WINDOW: usize = 2048;
+// Pushed per simulated audio callback
+const CALLBACK_FRAMES: usize = 512;
+
+fn samples(seconds: f32) -> usize {
+    (seconds * SAMPLE_RATE as f32) as usize
+}
+
+/// A tone whose frequency moves linearly from `from_hz` to `to_hz`; `wave` maps phase
+/// (0..1) to a sample.
+fn tone(from_hz: f32, to_hz: f32, seconds: f32, wave: fn(f32) -> f32) -> Vec<f32> {
+    let n = samples(seconds);
+    let mut phase = 0.0f32;
+    (0..n)
+        .map(|i| {
+            let freq = from_hz + (to_hz - from_hz) * i as f32 / n as f32;
+            phase = (phase + freq / SAMPLE_RATE as f32).fract();
+            0.4 * wave(phase)
+        })
+        .collect()
+}
+
+fn sine(phase: f32) -> f32 {
+    (TAU * phase).sin()
+}
+
+fn saw(phase: f32) -> f32 {
+    2.0 * phase - 1.0
+}
+
+fn silence(seconds: f32) -> Vec<f32> {
+    vec![0.0; samples(seconds)]
+}
+
+fn config() -> EngineConfig {
+    EngineConfig { window_size: WINDOW, sample_rate: SAMPLE_RATE, ..EngineConfig::default() }
+}
+
+/// Runs `signal` through the pipeline and returns every key event with the time it happened.
+fn run(signal: &[f32]) -> Vec<(Duration, KeyEvent)> {
+    let clock = ManualClock::new();
+    let mut engine = PitchToKeyEngine::new(config(), Mapping::default(), clock.clone());
+    let (mut producer, mut consumer) = audio_queue(4 * WINDOW);
+    let window_duration = Duration::from_secs_f64(WINDOW as f64 / SAMPLE_RATE as f64);
+
+    let mut window = vec![0.0; WINDOW];
+    let mut elapsed = Duration::ZERO;
+    let mut events = Vec::new();
+    for chunk in signal.chunks(CALLBACK_FRAMES) {
+        producer.push(chunk);
+        while consumer.pop_window(&mut window) {
+            for event in engine.process(&window).events {
+                events.push((elapsed, event));
+            }
+            clock.advance(window_duration);
+            elapsed += window_duration;
+        }
+    }
+    events
+}
+
+fn keys(events: &[(Duration, KeyEvent)]) -> Vec<KeyEvent> {
+    events.iter().map(|&(_, event)| event).collect()
+}
+
+#[test]
+fn sustained_saw_presses_once_then_repeats_at_the_repeat_interval() {
+    let events = run(&tone(250.0, 250.0, 3.0, saw));
+    let a = Key::Layout('a');
+
+    assert_eq!(events[0].1, KeyEvent::Press(a));
+    assert!(events[1..].iter().all(|&(_, event)| event == KeyEvent::Repeat(a)), "{:?}", events);
+
+    // The press waits out the 70ms minimum note, two windows after the note's first one.
+    // The first repeat comes once the 250ms hold threshold has passed since that first
+    // window, then one per 100ms interval, rounded up to the next window boundary.
+    let window_duration = Duration::from_secs_f64(WINDOW as f64 / SAMPLE_RATE as f64);
+    let note_start = events[0].0 - 2 * window_duration;
+    let first_repeat = events[1].0 - note_start;
+    assert!(first_repeat >= Duration::from_millis(250) && first_repeat < Duration::from_millis(300));
+    for pair in events[1..].windows(2) {
+        let gap = pair[1].0 - pair[0].0;
+        assert!(gap >= Duration::from_millis(100) && gap < Duration::from_millis(150), "gap {:?}", gap);
+    }
+    assert!((18..=30).contains(&(events.len() - 1)), "{} repeats", events.len() - 1);
+}
+
+#[test]
+fn notes_separated_by_silence_press_and_release_in_order() {
+    let mut signal = tone(110.0, 110.0, 0.5, sine);
+    signal.extend(silence(0.3));
+    signal.extend(tone(320.0, 320.0, 0.2, sine));
+    signal.extend(silence(0.3));
+
+    assert_eq!(
+        keys(&run(&signal)),
+        vec![
+            KeyEvent::Press(Key::DownArrow),
+            KeyEvent::Repeat(Key::DownArrow),
+            KeyEvent::Repeat(Key::DownArrow),
+            KeyEvent::Release(Key::DownArrow),
+            KeyEvent::Press(Key::Return),
+            KeyEvent::Release(Key::Return),
+        ]
+    );
+}
+
+#[test]
+fn glide_across_a_band_boundary_moves_to_the_next_key() {
+    // 140 Hz is in the RightArrow band, 150 Hz in UpArrow. A key change presses the new key
+    // straight away; the old one is only reported released when the pitch is lost.
+    let mut signal = tone(140.0, 140.0, 0.3, saw);
+    signal.extend(tone(140.0, 150.0, 0.4, saw));
+    signal.extend(tone(150.0, 150.0, 0.3, saw));
+    signal.extend(silence(0.3));
+
+    let presses: Vec<KeyEvent> = keys(&run(&signal))
+        .into_iter()
+        .filter(|event| !matches!(event, KeyEvent::Repeat(_)))
+        .collect();
+    assert_eq!(
+        presses,
+        vec![
+            KeyEvent::Press(Key::RightArrow),
+            KeyEvent::Press(Key::UpArrow),
+            KeyEvent::Release(Key::UpArrow),
+        ]
+    );
+}
+
+#[test]
+fn silence_and_out_of_range_tones_press_nothing() {
+    let mut signal = silence(1.0);
+    signal.extend(tone(60.0, 60.0, 0.5, sine));
+    signal.extend(tone(600.0, 600.0, 0.5, sine));
+    assert_eq!(keys(&run(&signal)), vec![]);
+}
+
+#[test]
+fn pushed_samples_are_analysed_in_whole_windows_on_poll() {
+    let clock = ManualClock::new();
+    let mut engine = PitchToKeyEngine::new(config(), Mapping::default(), clock.clone());
+    let window_duration = Duration::from_secs_f64(WINDOW as f64 / SAMPLE_RATE as f64);
+    let mut signal = tone(250.0, 250.0, 0.2, sine);
+    signal.extend(silence(0.2));
+
+    let mut events = Vec::new();
+    // Odd-sized chunks: a window is only analysed once it is complete
+    for chunk in signal.chunks(700) {
+        engine.push_samples(chunk);
+        events.extend(engine.poll());
+        clock.advance(window_duration.mul_f64(700.0 / WINDOW as f64));
+    }
+    assert_eq!(events, vec![KeyEvent::Press(Key::Layout('a')), KeyEvent::Release(Key::Layout('a'))]);
+}